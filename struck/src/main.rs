@@ -1,26 +1,25 @@
-use std::ffi::OsStr;
-use std::io::Read;
+use std::fs;
 use std::path::{Path, PathBuf};
-use std::{fs, io};
 
 use anyhow::Error;
 use argh::FromArgs;
 
-use serde::Deserialize;
-use struckdown::html::to_html;
-use struckdown::processors::BuiltinProcessor;
-use struckdown::{event::AnnotatedEvent, pipeline::Pipeline};
-
-fn read_file<P: AsRef<Path>>(path: &P) -> Result<String, Error> {
-    let path = path.as_ref();
-    if path.as_os_str() == OsStr::new("-") {
-        let mut rv = String::new();
-        io::stdin().read_to_string(&mut rv)?;
-        Ok(rv)
-    } else {
-        Ok(fs::read_to_string(&path)?)
-    }
-}
+use struckdown::anchors::{collect_anchors, compare_anchors, Anchor};
+use struckdown::asciidoc::{AsciiDocRenderer, AsciiDocRendererOptions};
+use struckdown::document::read_source;
+use struckdown::dry_run::DryRunReport;
+use struckdown::html::{HtmlRenderer, HtmlRendererOptions};
+use struckdown::inventory::{Inventory, Registry};
+use struckdown::json::{stream_from_json, stream_to_json};
+use struckdown::lossy::UnknownDirectives;
+use struckdown::markdown::{LineWrapping, LinkStyle, MarkdownRenderer, MarkdownRendererOptions};
+use struckdown::outline::{collect_outline, compare_outlines, Section};
+use struckdown::parser::ParserOptions;
+use struckdown::pipeline::Pipeline;
+use struckdown::processors::{AutoAnchors, External, OnError, Processor, WireFormat};
+use struckdown::protocol::check_conformance;
+use struckdown::sandbox::{install as install_sandbox, Sandbox};
+use struckdown::theme::ThemeProfile;
 
 #[derive(FromArgs, Debug)]
 /// Small utility to play around with struckdown.
@@ -35,6 +34,12 @@ enum Command {
     Parse(ParseCommand),
     Render(RenderCommand),
     Process(ProcessCommand),
+    Format(FormatCommand),
+    CheckAnchors(CheckAnchorsCommand),
+    OutlineDiff(OutlineDiffCommand),
+    Inventory(InventoryCommand),
+    Conformance(ConformanceCommand),
+    DryRun(DryRunCommand),
 }
 
 /// Parses a markdown document.
@@ -46,6 +51,9 @@ struct ParseCommand {
     /// path to the file to read.
     #[argh(positional, default = "PathBuf::from(\"-\")")]
     path: PathBuf,
+    /// record source trivia so that rendering to markdown keeps the source.
+    #[argh(switch)]
+    lossless: bool,
 }
 
 /// Process according a config file.
@@ -57,6 +65,9 @@ struct ProcessCommand {
     /// path to the config file.
     #[argh(positional)]
     config: PathBuf,
+    /// path to a YAML file restricting what processors may do.
+    #[argh(option)]
+    sandbox: Option<PathBuf>,
 }
 
 /// Renders a token stream to HTML.
@@ -65,52 +76,344 @@ struct ProcessCommand {
 struct RenderCommand {
     #[argh(positional, default = "PathBuf::from(\"-\")")]
     path: PathBuf,
+    /// the output format (html, asciidoc or markdown).
+    #[argh(option, default = "String::from(\"html\")")]
+    format: String,
+    /// path to write a JSON report of elements the format cannot represent.
+    #[argh(option)]
+    lossy_report: Option<PathBuf>,
+    /// the theme profile for html (classes or inline_styles).
+    #[argh(option, default = "String::from(\"classes\")")]
+    theme: String,
+    /// what to do with unhandled directives (literal or drop).
+    #[argh(option, default = "String::from(\"literal\")")]
+    unknown_directives: String,
+}
+
+/// Formats a markdown document.
+///
+/// This parses a markdown document and renders it back to markdown.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "format")]
+struct FormatCommand {
+    /// path to the file to format.
+    #[argh(positional, default = "PathBuf::from(\"-\")")]
+    path: PathBuf,
+    /// how to wrap paragraphs (preserve, wrap or sentence-per-line).
+    #[argh(option, default = "String::from(\"preserve\")")]
+    wrap: String,
+    /// the maximum line width when wrapping.
+    #[argh(option, default = "80")]
+    width: usize,
+    /// collect link targets as references at the end of the document.
+    #[argh(switch)]
+    reference_links: bool,
+    /// path to a config file with processors to apply before formatting.
+    #[argh(option)]
+    config: Option<PathBuf>,
+    /// write the result back to the file instead of stdout.
+    #[argh(switch)]
+    write: bool,
+    /// keep the source of blocks the processors did not change.
+    #[argh(switch)]
+    lossless: bool,
+}
+
+/// Compares the anchors of two versions of a document.
+///
+/// This prints a JSON report of removed, renamed and added anchors and
+/// fails if links to the old version would break.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "check-anchors")]
+struct CheckAnchorsCommand {
+    /// path to the old version of the document.
+    #[argh(positional)]
+    old: PathBuf,
+    /// path to the new version of the document.
+    #[argh(positional)]
+    new: PathBuf,
+}
+
+/// Compares the outlines of two versions of a document.
+///
+/// This prints a summary of sections that were added, removed, moved or
+/// renamed.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "outline-diff")]
+struct OutlineDiffCommand {
+    /// path to the old version of the document.
+    #[argh(positional)]
+    old: PathBuf,
+    /// path to the new version of the document.
+    #[argh(positional)]
+    new: PathBuf,
+    /// the output format (json or markdown).
+    #[argh(option, default = "String::from(\"json\")")]
+    format: String,
+}
+
+/// Lists the directives and roles used by documents.
+///
+/// This prints a JSON report of every directive and role with its uses and
+/// fails if names outside of the registry are used.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "inventory")]
+struct InventoryCommand {
+    /// paths to the documents to scan.
+    #[argh(positional)]
+    paths: Vec<PathBuf>,
+    /// path to a YAML file with the known `directives` and `roles`.
+    #[argh(option)]
+    registry: Option<PathBuf>,
+}
+
+/// Checks an external processor against the protocol.
+///
+/// This runs the program against the golden fixtures, prints a JSON report
+/// and fails if the program violates the protocol.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "conformance")]
+struct ConformanceCommand {
+    /// the program to check.
+    #[argh(positional)]
+    cmd: PathBuf,
+    /// arguments for the program (pass after `--`).
+    #[argh(positional)]
+    args: Vec<String>,
+    /// the wire format: json or message_pack.
+    #[argh(option, default = "String::from(\"json\")")]
+    format: String,
+    /// exchange protocol versions before the events.
+    #[argh(switch)]
+    handshake: bool,
+    /// the time in milliseconds after which the program is killed.
+    #[argh(option)]
+    timeout_ms: Option<u64>,
+}
+
+/// Reports what a pipeline would change.
+///
+/// This runs the processors of a config file against documents and prints
+/// a JSON report of the events every processor adds, removes and modifies.
+#[derive(FromArgs, Debug)]
+#[argh(subcommand, name = "dry-run")]
+struct DryRunCommand {
+    /// path to the config file.
+    #[argh(positional)]
+    config: PathBuf,
+    /// paths to the documents to process.
+    #[argh(positional)]
+    paths: Vec<PathBuf>,
+    /// the number of sample changes to report per processor.
+    #[argh(option, default = "5")]
+    samples: usize,
 }
 
 fn parse_cmd(cmd: ParseCommand) -> Result<(), Error> {
-    let source = read_file(&cmd.path)?;
-    for event in struckdown::parser::parse(&source, &Default::default()) {
-        let out = serde_json::to_string(&event)?;
-        println!("{}", out);
-    }
+    let source = read_source(&cmd.path)?;
+    let options = ParserOptions {
+        lossless: cmd.lossless,
+        ..Default::default()
+    };
+    print!(
+        "{}",
+        stream_to_json(struckdown::parser::parse(&source, &options))
+    );
     Ok(())
 }
 
 fn render_cmd(cmd: RenderCommand) -> Result<(), Error> {
-    let source = read_file(&cmd.path)?;
-    let events = source
-        .lines()
-        .map(|line| -> Result<AnnotatedEvent, Error> { Ok(serde_json::from_str(&line)?) })
-        .collect::<Result<Vec<_>, _>>()?;
-    println!("{}", to_html(events.into_iter(), &Default::default()));
+    let source = read_source(&cmd.path)?;
+    let events = stream_from_json(&source)?;
+    let unknown_directives = match cmd.unknown_directives.as_str() {
+        "literal" => UnknownDirectives::Literal,
+        "drop" => UnknownDirectives::Drop,
+        other => anyhow::bail!("unknown directive policy '{}'", other),
+    };
+    let lossy = match cmd.format.as_str() {
+        "html" => {
+            let options = HtmlRendererOptions {
+                theme: ThemeProfile::builtin(&cmd.theme)
+                    .ok_or_else(|| anyhow::anyhow!("unknown theme profile '{}'", cmd.theme))?,
+                unknown_directives,
+                ..Default::default()
+            };
+            let mut renderer = HtmlRenderer::new_buffered(&options);
+            renderer.feed_stream(events.into_iter())?;
+            let lossy = renderer.lossy_conversions().to_vec();
+            println!("{}", renderer.into_string());
+            lossy
+        }
+        "asciidoc" => {
+            let options = AsciiDocRendererOptions {
+                unknown_directives,
+                ..Default::default()
+            };
+            let mut renderer = AsciiDocRenderer::new_buffered(&options);
+            renderer.feed_stream(events.into_iter())?;
+            let lossy = renderer.lossy_conversions().to_vec();
+            print!("{}", renderer.into_string());
+            lossy
+        }
+        "markdown" => {
+            let options = MarkdownRendererOptions {
+                unknown_directives,
+                ..Default::default()
+            };
+            let mut renderer = MarkdownRenderer::new_buffered(&options);
+            renderer.feed_stream(events.into_iter())?;
+            let lossy = renderer.lossy_conversions().to_vec();
+            print!("{}", renderer.into_string());
+            lossy
+        }
+        other => anyhow::bail!("unknown output format '{}'", other),
+    };
+    if let Some(ref path) = cmd.lossy_report {
+        fs::write(path, serde_json::to_string_pretty(&lossy)?)?;
+    }
     Ok(())
 }
 
-#[derive(Debug, Deserialize)]
-pub struct ProcessConfig {
-    processors: Vec<BuiltinProcessor>,
+fn format_cmd(cmd: FormatCommand) -> Result<(), Error> {
+    let source = read_source(&cmd.path)?;
+    let line_wrapping = match cmd.wrap.as_str() {
+        "preserve" => LineWrapping::Preserve,
+        "wrap" => LineWrapping::Wrap,
+        "sentence-per-line" => LineWrapping::SentencePerLine,
+        other => anyhow::bail!("unknown wrapping policy '{}'", other),
+    };
+    let options = MarkdownRendererOptions {
+        line_wrapping,
+        wrap_width: cmd.width,
+        link_style: if cmd.reference_links {
+            LinkStyle::Reference
+        } else {
+            LinkStyle::Inline
+        },
+        ..Default::default()
+    };
+    let mut pipeline: Pipeline = match cmd.config {
+        Some(ref path) => serde_yaml::from_str(&read_source(path)?)?,
+        None => Pipeline::new(),
+    };
+    if cmd.lossless {
+        pipeline.set_parser_options(&ParserOptions {
+            lossless: true,
+            ..pipeline.parser().options().clone()
+        });
+    }
+    let formatted = pipeline.normalize(&source, &options);
+    if cmd.write {
+        fs::write(&cmd.path, formatted)?;
+    } else {
+        print!("{}", formatted);
+    }
+    Ok(())
 }
 
-fn process_cmd(cmd: ProcessCommand) -> Result<(), Error> {
-    let command_source = read_file(&cmd.config)?;
-    let config: ProcessConfig = serde_yaml::from_str(&command_source)?;
+fn read_anchors(path: &Path) -> Result<Vec<Anchor>, Error> {
+    let source = read_source(&path)?;
+    let iter = struckdown::parser::parse(&source, &Default::default());
+    Ok(collect_anchors(
+        Box::new(AutoAnchors::default()).apply(Box::new(iter)),
+    ))
+}
 
-    let mut pipeline = Pipeline::new();
-    for processor in config.processors {
-        pipeline.add_processor(processor);
+fn check_anchors_cmd(cmd: CheckAnchorsCommand) -> Result<(), Error> {
+    let report = compare_anchors(&read_anchors(&cmd.old)?, &read_anchors(&cmd.new)?);
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if report.is_breaking() {
+        anyhow::bail!("anchors were removed or renamed");
     }
+    Ok(())
+}
 
-    let source = read_file(&"-")?;
-    let events = source
-        .lines()
-        .map(|line| -> Result<AnnotatedEvent, Error> { Ok(serde_json::from_str(&line)?) })
-        .collect::<Result<Vec<_>, _>>()?;
+fn read_outline(path: &Path) -> Result<Vec<Section>, Error> {
+    let source = read_source(&path)?;
+    let iter = struckdown::parser::parse(&source, &Default::default());
+    Ok(collect_outline(
+        Box::new(AutoAnchors::default()).apply(Box::new(iter)),
+    ))
+}
 
-    for event in pipeline.apply(events.into_iter()) {
-        let out = serde_json::to_string(&event)?;
-        println!("{}", out);
+fn outline_diff_cmd(cmd: OutlineDiffCommand) -> Result<(), Error> {
+    let report = compare_outlines(&read_outline(&cmd.old)?, &read_outline(&cmd.new)?);
+    match cmd.format.as_str() {
+        "json" => println!("{}", serde_json::to_string_pretty(&report)?),
+        "markdown" => print!("{}", report.to_markdown()),
+        other => anyhow::bail!("unknown output format '{}'", other),
     }
+    Ok(())
+}
 
+fn inventory_cmd(cmd: InventoryCommand) -> Result<(), Error> {
+    let registry: Option<Registry> = match cmd.registry {
+        Some(ref path) => Some(serde_yaml::from_str(&read_source(path)?)?),
+        None => None,
+    };
+    let mut inventory = Inventory::new();
+    for path in &cmd.paths {
+        let source = read_source(path)?;
+        inventory.add_document(
+            &path.display().to_string(),
+            struckdown::parser::parse(&source, &Default::default()),
+        );
+    }
+    let report = inventory.report(registry.as_ref());
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if !report.unknown_directives.is_empty() || !report.unknown_roles.is_empty() {
+        anyhow::bail!("unknown directives or roles are used");
+    }
+    Ok(())
+}
+
+fn conformance_cmd(cmd: ConformanceCommand) -> Result<(), Error> {
+    let format = match cmd.format.as_str() {
+        "json" => WireFormat::Json,
+        "message_pack" => WireFormat::MessagePack,
+        other => anyhow::bail!("unknown wire format '{}'", other),
+    };
+    let report = check_conformance(&External {
+        cmd: cmd.cmd,
+        args: cmd.args,
+        env: Default::default(),
+        cwd: None,
+        format,
+        timeout_ms: cmd.timeout_ms,
+        on_error: OnError::Fail,
+        handshake: cmd.handshake,
+    });
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if !report.is_conformant() {
+        anyhow::bail!("the processor violates the protocol");
+    }
+    Ok(())
+}
+
+fn process_cmd(cmd: ProcessCommand) -> Result<(), Error> {
+    let command_source = read_source(&cmd.config)?;
+    let pipeline: Pipeline = serde_yaml::from_str(&command_source)?;
+    if let Some(ref path) = cmd.sandbox {
+        let sandbox: Sandbox = serde_yaml::from_str(&read_source(path)?)?;
+        install_sandbox(sandbox);
+    }
+
+    let source = read_source(&"-")?;
+    let events = stream_from_json(&source)?;
+
+    print!("{}", stream_to_json(pipeline.apply(events.into_iter())));
+
+    Ok(())
+}
+
+fn dry_run_cmd(cmd: DryRunCommand) -> Result<(), Error> {
+    let pipeline: Pipeline = serde_yaml::from_str(&read_source(&cmd.config)?)?;
+    let mut report = DryRunReport::new(cmd.samples);
+    for path in &cmd.paths {
+        let source = read_source(path)?;
+        pipeline.dry_run(&path.display().to_string(), &source, &mut report);
+    }
+    println!("{}", serde_json::to_string_pretty(&report)?);
     Ok(())
 }
 
@@ -121,6 +424,12 @@ fn run() -> Result<(), Error> {
         Command::Parse(args) => parse_cmd(args)?,
         Command::Render(args) => render_cmd(args)?,
         Command::Process(args) => process_cmd(args)?,
+        Command::Format(args) => format_cmd(args)?,
+        Command::CheckAnchors(args) => check_anchors_cmd(args)?,
+        Command::OutlineDiff(args) => outline_diff_cmd(args)?,
+        Command::Inventory(args) => inventory_cmd(args)?,
+        Command::Conformance(args) => conformance_cmd(args)?,
+        Command::DryRun(args) => dry_run_cmd(args)?,
     }
 
     Ok(())
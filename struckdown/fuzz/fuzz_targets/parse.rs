@@ -0,0 +1,41 @@
+//! Checks that the parser does not panic and emits well formed streams.
+//!
+//! Run with `cargo +nightly fuzz run parse` from the `struckdown` folder.
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use struckdown::event::{EndTagEvent, Event, StartTagEvent};
+use struckdown::parser::{parse, ParserOptions};
+
+fuzz_target!(|data: &[u8]| {
+    let source = match std::str::from_utf8(data) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+
+    let options = ParserOptions {
+        enable_smart_punctuation: true,
+        enable_source_positions: true,
+        ..ParserOptions::default()
+    };
+    // hardened mode only balances tags, so both modes have to survive
+    for hardened in &[false, true] {
+        let options = ParserOptions {
+            hardened: *hardened,
+            ..options.clone()
+        };
+        let mut open_tags = vec![];
+        for (idx, annotated_event) in parse(source, &options).enumerate() {
+            if let Some(ref location) = annotated_event.location {
+                assert!(location.offset + location.len <= source.len());
+            }
+            match annotated_event.event {
+                Event::DocumentStart(..) => assert_eq!(idx, 0),
+                Event::StartTag(StartTagEvent { tag, .. }) => open_tags.push(tag),
+                Event::EndTag(EndTagEvent { tag }) => assert_eq!(open_tags.pop(), Some(tag)),
+                _ => {}
+            }
+        }
+        assert!(open_tags.is_empty());
+    }
+});
@@ -0,0 +1,310 @@
+//! Compares anchors between versions of documents.
+//!
+//! Deep links into documentation break when anchors disappear.  The
+//! functions in this module collect the anchors of processed event streams
+//! and report anchors that were removed or renamed between two versions so
+//! that deploys can fail or redirects can be generated.
+//!
+//! Processors generating ids share an [`AnchorRegistry`] so that colliding
+//! ids are handled the same way everywhere.
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{
+    AnnotatedEvent, Attrs, EndTagEvent, ErrorEvent, Event, Location, StartTagEvent,
+};
+use crate::plain::to_plain_text;
+
+/// What happens when a generated id is already in use.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionPolicy {
+    /// The id is used again.  Where a processor resolves references to its
+    /// ids, they point to the last element with the id.
+    #[default]
+    #[serde(alias = "keep")]
+    Overwrite,
+    /// A `-1`, `-2` etc. suffix is added to make the id unique.
+    Suffix,
+    /// The id is used again and an error is emitted.
+    Error,
+}
+
+/// Keeps track of the ids in use within a document.
+///
+/// ```
+/// use struckdown::anchors::{AnchorRegistry, CollisionPolicy};
+///
+/// let mut registry = AnchorRegistry::new(CollisionPolicy::Suffix);
+/// registry.observe("usage");
+/// assert_eq!(registry.claim("usage"), Ok("usage-1".to_string()));
+/// assert_eq!(registry.claim("setup"), Ok("setup".to_string()));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AnchorRegistry {
+    policy: CollisionPolicy,
+    seen: HashSet<String>,
+}
+
+impl AnchorRegistry {
+    /// Creates an empty registry.
+    pub fn new(policy: CollisionPolicy) -> AnchorRegistry {
+        AnchorRegistry {
+            policy,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Records an id that is used without being claimed.
+    pub fn observe(&mut self, id: &str) {
+        self.seen.insert(id.to_string());
+    }
+
+    /// Records the id of a tag if the event starts one.
+    pub fn observe_event(&mut self, event: &Event<'_>) {
+        if let Event::StartTag(StartTagEvent {
+            attrs: Attrs {
+                id: Some(ref id), ..
+            },
+            ..
+        }) = *event
+        {
+            self.observe(id.as_str());
+        }
+    }
+
+    /// Returns `true` if the id is in use.
+    pub fn contains(&self, id: &str) -> bool {
+        self.seen.contains(id)
+    }
+
+    /// Claims an id and returns the id to use.
+    ///
+    /// With the [`Error`](CollisionPolicy::Error) policy a collision fails
+    /// with the id, which is used nonetheless.
+    pub fn claim(&mut self, id: &str) -> Result<String, String> {
+        if self.seen.insert(id.to_string()) {
+            return Ok(id.to_string());
+        }
+        match self.policy {
+            CollisionPolicy::Overwrite => Ok(id.to_string()),
+            CollisionPolicy::Error => Err(id.to_string()),
+            CollisionPolicy::Suffix => {
+                let mut counter = 1;
+                loop {
+                    let candidate = format!("{}-{}", id, counter);
+                    if self.seen.insert(candidate.clone()) {
+                        return Ok(candidate);
+                    }
+                    counter += 1;
+                }
+            }
+        }
+    }
+
+    /// Creates the error event for an id that collided.
+    pub fn collision_error(id: &str) -> ErrorEvent<'static> {
+        ErrorEvent {
+            title: format!("Duplicate id '{}'", id).into(),
+            description: None,
+            code: Some(Code::DuplicateId),
+        }
+    }
+}
+
+/// An anchor found in a document.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Anchor {
+    /// The id of the anchor.
+    pub id: String,
+    /// The text of the element carrying the anchor.
+    pub title: String,
+    /// The location of the element.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+/// An anchor that changed its id.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RenamedAnchor {
+    /// The anchor in the old version.
+    pub old: Anchor,
+    /// The anchor in the new version.
+    pub new: Anchor,
+}
+
+/// The changes of anchors between two versions of a document.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AnchorReport {
+    /// Anchors that no longer exist.
+    pub removed: Vec<Anchor>,
+    /// Anchors that exist under a new id.
+    ///
+    /// An anchor is considered renamed if an element with the same text
+    /// carries a new id.
+    pub renamed: Vec<RenamedAnchor>,
+    /// Anchors that did not exist before.
+    pub added: Vec<Anchor>,
+}
+
+impl AnchorReport {
+    /// Returns `true` if no anchors changed.
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.renamed.is_empty() && self.added.is_empty()
+    }
+
+    /// Returns `true` if links to the old version would break.
+    pub fn is_breaking(&self) -> bool {
+        !self.removed.is_empty() || !self.renamed.is_empty()
+    }
+
+    /// Returns the redirects (old id to new id) for renamed anchors.
+    pub fn redirects(&self) -> Vec<(&str, &str)> {
+        self.renamed
+            .iter()
+            .map(|x| (x.old.id.as_str(), x.new.id.as_str()))
+            .collect()
+    }
+}
+
+/// Collects all anchors from an event stream.
+///
+/// Anchors are ids attached to tags.  The stream should already have been
+/// processed by processors generating ids such as
+/// [`AutoAnchors`](crate::processors::AutoAnchors).
+pub fn collect_anchors<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(iter: I) -> Vec<Anchor> {
+    let mut rv = vec![];
+    let mut open: Vec<(usize, usize, Anchor, Vec<AnnotatedEvent<'data>>)> = vec![];
+    let mut depth = 0;
+
+    for (idx, annotated_event) in iter.enumerate() {
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { ref attrs, .. }) => {
+                depth += 1;
+                if let Some(ref id) = attrs.id {
+                    open.push((
+                        depth,
+                        idx,
+                        Anchor {
+                            id: id.as_str().to_string(),
+                            title: String::new(),
+                            location: annotated_event.location.clone(),
+                        },
+                        vec![],
+                    ));
+                    continue;
+                }
+            }
+            Event::EndTag(EndTagEvent { .. }) => {
+                if open.last().map(|x| x.0) == Some(depth) {
+                    let (_, idx, mut anchor, events) = open.pop().unwrap();
+                    anchor.title = to_plain_text(events.iter()).as_str().trim().to_string();
+                    rv.push((idx, anchor));
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        for (_, _, _, events) in open.iter_mut() {
+            events.push(annotated_event.clone());
+        }
+    }
+
+    // anchors are reported in document order
+    rv.sort_by_key(|x| x.0);
+    rv.into_iter().map(|x| x.1).collect()
+}
+
+/// Compares the anchors of two versions of a document.
+pub fn compare_anchors(old: &[Anchor], new: &[Anchor]) -> AnchorReport {
+    let old_ids: HashSet<&str> = old.iter().map(|x| x.id.as_str()).collect();
+    let new_ids: HashSet<&str> = new.iter().map(|x| x.id.as_str()).collect();
+    let mut added: Vec<Anchor> = new
+        .iter()
+        .filter(|x| !old_ids.contains(x.id.as_str()))
+        .cloned()
+        .collect();
+
+    let mut report = AnchorReport::default();
+    for anchor in old.iter().filter(|x| !new_ids.contains(x.id.as_str())) {
+        match added
+            .iter()
+            .position(|x| !x.title.is_empty() && x.title == anchor.title)
+        {
+            Some(idx) => report.renamed.push(RenamedAnchor {
+                old: anchor.clone(),
+                new: added.remove(idx),
+            }),
+            None => report.removed.push(anchor.clone()),
+        }
+    }
+    report.added = added;
+    report
+}
+
+/// Compares the anchors of two versions of a site.
+///
+/// Both sides map document paths to their anchors.  Documents that were
+/// removed report all of their anchors as removed.  Only documents with
+/// changes are included in the result.
+pub fn compare_site_anchors(
+    old: &BTreeMap<String, Vec<Anchor>>,
+    new: &BTreeMap<String, Vec<Anchor>>,
+) -> BTreeMap<String, AnchorReport> {
+    let mut rv = BTreeMap::new();
+    for (path, old_anchors) in old {
+        let new_anchors = new.get(path).map_or(&[][..], |x| &x[..]);
+        let report = compare_anchors(old_anchors, new_anchors);
+        if !report.is_empty() {
+            rv.insert(path.clone(), report);
+        }
+    }
+    for (path, new_anchors) in new {
+        if !old.contains_key(path) && !new_anchors.is_empty() {
+            rv.insert(path.clone(), compare_anchors(&[], new_anchors));
+        }
+    }
+    rv
+}
+
+#[test]
+fn test_compare_anchors() {
+    use crate::parser::parse;
+    use crate::processors::{AutoAnchors, Processor};
+
+    let anchors = |source| {
+        collect_anchors(
+            Box::new(AutoAnchors::default()).apply(Box::new(parse(source, &Default::default()))),
+        )
+    };
+    let old = anchors("# Intro\n\n## Setup {#setup}\n\n## Usage\n\n## Legacy\n");
+    let new = anchors("# Intro\n\n## Setup {#installation}\n\n## Usage\n\n## Advanced\n");
+    insta::assert_yaml_snapshot!(compare_anchors(&old, &new));
+}
+
+#[test]
+fn test_collision_policies() {
+    use crate::html::to_html;
+    use crate::parser::parse;
+    use crate::processors::{AutoAnchors, Equations, Processor};
+
+    let render = |duplicates| {
+        let source =
+            "# Eq Euler\n\n```math label=euler\ne^{i\\pi} + 1 = 0\n```\n\nSee {eq}`euler`.";
+        let iter =
+            Box::new(AutoAnchors::default()).apply(Box::new(parse(source, &Default::default())));
+        let iter = Box::new(Equations {
+            duplicates,
+            ..Default::default()
+        })
+        .apply(iter);
+        to_html(iter, &Default::default())
+    };
+    let html = render(CollisionPolicy::Suffix);
+    assert!(html.contains("<h1 id=\"eq-euler\">"));
+    assert!(html.contains("id=\"eq-euler-1\""));
+    assert!(html.contains("href=\"#eq-euler-1\""));
+    assert!(render(CollisionPolicy::Error).contains("Duplicate id &#x27;eq-euler&#x27;"));
+}
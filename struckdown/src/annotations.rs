@@ -0,0 +1,286 @@
+//! Anchors external annotations to the text of a document.
+//!
+//! Review tools store comment threads and highlights as byte ranges of the
+//! source.  [`annotate`] wraps the covered text of a freshly parsed event
+//! stream in spans so that the annotations travel through all processors
+//! that run afterwards and end up next to the right text in the output:
+//!
+//! ```
+//! use struckdown::annotations::{annotate, Annotation};
+//! use struckdown::html::to_html;
+//! use struckdown::parser::parse;
+//!
+//! let source = "Hello brave new world.";
+//! let annotations = vec![Annotation {
+//!     id: "c1".into(),
+//!     start: 6,
+//!     end: 15,
+//!     ..Default::default()
+//! }];
+//! let events = annotate(parse(source, &Default::default()), &annotations);
+//! let html = to_html(events, &Default::default());
+//! assert!(html.contains(">brave new</span> world."));
+//! ```
+//!
+//! Every annotated piece of text is wrapped in a [`Tag::Span`] with the
+//! classes `annotation` and `annotation-{kind}` and a `data-annotation`
+//! attribute holding the id.  The first piece of every annotation also gets
+//! the id `annotation-{id}` so that comment threads can link to it.  Text
+//! is split at the annotation boundaries where the text maps one to one to
+//! the source, otherwise whole events are wrapped.  Code blocks and
+//! directives are wrapped in a [`Tag::Container`].
+//!
+//! At the end of the stream an `annotations` meta data event lists all
+//! annotations with their `data` and whether they could be `anchored`.
+//! Annotations have to be applied before any processor runs as only then
+//! the locations of the events match the source.
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, Event, Location, MetaDataEvent, Tag, TextEvent};
+use crate::value::{value, Value};
+
+/// An annotation of a range of the source.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Annotation {
+    /// The unique id of the annotation.
+    pub id: String,
+    /// The byte offset of the start of the range.
+    pub start: usize,
+    /// The byte offset of the end of the range (exclusive).
+    pub end: usize,
+    /// The kind of annotation (like `comment` or `highlight`).
+    pub kind: String,
+    /// Arbitrary data carried to the meta data event.
+    pub data: Value,
+}
+
+impl Default for Annotation {
+    fn default() -> Annotation {
+        Annotation {
+            id: String::new(),
+            start: 0,
+            end: 0,
+            kind: "comment".into(),
+            data: Value::Null,
+        }
+    }
+}
+
+impl Annotation {
+    fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+/// Wraps annotated parts of an event stream in spans.
+pub fn annotate<'data, 'a, I>(iter: I, annotations: &'a [Annotation]) -> AnnotateIter<'data, 'a, I>
+where
+    I: Iterator<Item = AnnotatedEvent<'data>>,
+{
+    AnnotateIter {
+        source: iter,
+        annotations,
+        buffer: VecDeque::new(),
+        anchored: HashSet::new(),
+        finished: false,
+    }
+}
+
+/// The iterator returned by [`annotate`].
+pub struct AnnotateIter<'data, 'a, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    annotations: &'a [Annotation],
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    anchored: HashSet<usize>,
+    finished: bool,
+}
+
+impl<'data, 'a, I: Iterator<Item = AnnotatedEvent<'data>>> AnnotateIter<'data, 'a, I> {
+    /// Returns the indexes of the annotations overlapping a range.
+    fn overlapping(&self, start: usize, end: usize) -> Vec<usize> {
+        (0..self.annotations.len())
+            .filter(|&idx| self.annotations[idx].overlaps(start, end))
+            .collect()
+    }
+
+    fn attrs(&mut self, idx: usize) -> Attrs<'data> {
+        let annotation = &self.annotations[idx];
+        let mut custom = BTreeMap::new();
+        custom.insert("data-annotation".into(), annotation.id.clone().into());
+        Attrs {
+            id: if self.anchored.insert(idx) {
+                Some(format!("annotation-{}", annotation.id).into())
+            } else {
+                None
+            },
+            class: Some(format!("annotation annotation-{}", annotation.kind).into()),
+            custom: Some(custom),
+            ..Attrs::default()
+        }
+    }
+
+    /// Emits an event wrapped in the tags for the given annotations.
+    fn wrap(&mut self, annotated_event: AnnotatedEvent<'data>, indexes: &[usize], tag: Tag) {
+        for &idx in indexes {
+            let attrs = self.attrs(idx);
+            self.buffer.push_back(tag.start_tag(attrs).into());
+        }
+        self.buffer.push_back(annotated_event);
+        for _ in indexes {
+            self.buffer.push_back(tag.end_tag().into());
+        }
+    }
+
+    /// Splits a text event at the boundaries of the annotations.
+    fn split_text(&mut self, text: &str, location: &Location) {
+        let start = location.offset;
+        let end = start + location.len;
+        let mut boundaries = vec![start, end];
+        for annotation in self.annotations {
+            for &offset in &[annotation.start, annotation.end] {
+                if offset > start && offset < end && text.is_char_boundary(offset - start) {
+                    boundaries.push(offset);
+                }
+            }
+        }
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        for window in boundaries.windows(2) {
+            let (piece_start, piece_end) = (window[0], window[1]);
+            let piece = &text[piece_start - start..piece_end - start];
+            let piece_location = Location {
+                offset: piece_start,
+                len: piece_end - piece_start,
+                line: location.line,
+                column: location.column + text[..piece_start - start].chars().count(),
+            };
+            let annotated_event = AnnotatedEvent::new(
+                TextEvent {
+                    text: piece.to_string().into(),
+                },
+                Some(piece_location),
+            );
+            let indexes = self.overlapping(piece_start, piece_end);
+            self.wrap(annotated_event, &indexes, Tag::Span);
+        }
+    }
+
+    fn process(&mut self, annotated_event: AnnotatedEvent<'data>) {
+        let (start, end) = match annotated_event.location {
+            Some(ref location) => (location.offset, location.offset + location.len),
+            None => return self.buffer.push_back(annotated_event),
+        };
+        let indexes = self.overlapping(start, end);
+        if indexes.is_empty() {
+            return self.buffer.push_back(annotated_event);
+        }
+
+        match annotated_event.event {
+            Event::Text(TextEvent { ref text }) if text.as_str().len() == end - start => {
+                let location = annotated_event.location.clone().unwrap();
+                let text = text.as_str().to_string();
+                self.split_text(&text, &location);
+            }
+            Event::Text(..)
+            | Event::InlineCode(..)
+            | Event::InlineMath(..)
+            | Event::InterpretedText(..)
+            | Event::Image(..)
+            | Event::FootnoteReference(..) => self.wrap(annotated_event, &indexes, Tag::Span),
+            Event::CodeBlock(..) | Event::Directive(..) | Event::MathBlock(..) => {
+                self.wrap(annotated_event, &indexes, Tag::Container)
+            }
+            _ => self.buffer.push_back(annotated_event),
+        }
+    }
+
+    fn metadata(&self) -> MetaDataEvent<'data> {
+        let annotations: Vec<Value> = self
+            .annotations
+            .iter()
+            .enumerate()
+            .map(|(idx, annotation)| {
+                value!({
+                    "id": annotation.id,
+                    "kind": annotation.kind,
+                    "data": annotation.data,
+                    "anchored": self.anchored.contains(&idx),
+                })
+            })
+            .collect();
+        MetaDataEvent {
+            key: "annotations".into(),
+            value: Value::Array(annotations),
+        }
+    }
+}
+
+impl<'data, 'a, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator for AnnotateIter<'data, 'a, I> {
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+            if self.finished {
+                return None;
+            }
+            match self.source.next() {
+                Some(annotated_event) => self.process(annotated_event),
+                None => {
+                    self.finished = true;
+                    if !self.annotations.is_empty() {
+                        let metadata = self.metadata();
+                        self.buffer.push_back(metadata.into());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_annotate() {
+    use crate::html::to_html;
+    use crate::parser::parse;
+    use crate::processors::{Typography, TypographyIter};
+    use std::borrow::Cow;
+
+    let source = "# Title\n\nSome (c) 1/2 *emphasized* text with `code`.\n\n```\nlet x = 1;\n```\n";
+    let annotations = vec![
+        Annotation {
+            id: "1".into(),
+            start: 14,
+            end: 26,
+            data: value!({"author": "jane", "text": "Rephrase this"}),
+            ..Default::default()
+        },
+        Annotation {
+            id: "2".into(),
+            start: 25,
+            end: 50,
+            kind: "highlight".into(),
+            ..Default::default()
+        },
+        Annotation {
+            id: "3".into(),
+            start: 60,
+            end: 65,
+            ..Default::default()
+        },
+        Annotation {
+            id: "4".into(),
+            start: 500,
+            end: 510,
+            ..Default::default()
+        },
+    ];
+    let events = annotate(parse(source, &Default::default()), &annotations);
+    let events = TypographyIter::new(events, Cow::Owned(Typography::default()));
+    insta::assert_snapshot!(to_html(events, &Default::default()));
+}
@@ -0,0 +1,843 @@
+//! Renders event streams into AsciiDoc.
+//!
+//! This allows struckdown to be used as authoring and validation front end
+//! for AsciiDoc based toolchains such as Antora:
+//!
+//! ```
+//! use struckdown::asciidoc::to_asciidoc;
+//! use struckdown::parser::parse;
+//!
+//! let source = "# Setup\n\n```{note}\nPress {kbd}`Ctrl+C` to *stop*.\n```";
+//! let asciidoc = to_asciidoc(parse(source, &Default::default()), &Default::default());
+//! assert_eq!(asciidoc, "== Setup\n\n[NOTE]\n====\nPress kbd:[Ctrl+C] to __stop__.\n====\n");
+//! ```
+//!
+//! Directives listed in [`AsciiDocRendererOptions::admonitions`] become
+//! admonition blocks with their parsed body, all other directives are
+//! written as literal blocks with the directive name as role.  Roles become
+//! inline macros (`{kbd}`Ctrl+C`` turns into `kbd:[Ctrl+C]`).
+//!
+//! Elements without an AsciiDoc equivalent are recorded as
+//! [`lossy_conversions`](AsciiDocRenderer::lossy_conversions).
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    Alignment, AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, DirectiveEvent,
+    DocumentStartEvent, EndTagEvent, Event, FootnoteReferenceEvent, ImageEvent, InlineCodeEvent,
+    InlineMathEvent, InterpretedTextEvent, Location, MathBlockEvent, RawHtmlEvent, StartTagEvent,
+    Tag, TextEvent,
+};
+use crate::lossy::{DirectiveOutcome, Fallback, LossyConversion, UnknownDirectives};
+use crate::parser::parse;
+use crate::value::Value;
+
+/// Customizes the AsciiDoc rendering.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct AsciiDocRendererOptions {
+    /// Maps directive names to admonition types (`NOTE`, `TIP`,
+    /// `IMPORTANT`, `WARNING` or `CAUTION`).
+    pub admonitions: BTreeMap<String, String>,
+    /// Maps roles to the names of the inline macros they are written as.
+    ///
+    /// Roles not listed here use their own name as macro name.
+    pub role_macros: BTreeMap<String, String>,
+    /// What happens to directives that are not admonitions.
+    pub unknown_directives: UnknownDirectives,
+}
+
+impl Default for AsciiDocRendererOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsciiDocRendererOptions {
+    /// Creates the default configuration for the renderer options.
+    pub fn new() -> AsciiDocRendererOptions {
+        let admonitions = [
+            ("note", "NOTE"),
+            ("tip", "TIP"),
+            ("hint", "TIP"),
+            ("important", "IMPORTANT"),
+            ("attention", "IMPORTANT"),
+            ("warning", "WARNING"),
+            ("caution", "CAUTION"),
+            ("danger", "CAUTION"),
+        ];
+        AsciiDocRendererOptions {
+            admonitions: admonitions
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            role_macros: BTreeMap::new(),
+            unknown_directives: UnknownDirectives::default(),
+        }
+    }
+}
+
+/// Buffers a table until it can be rendered.
+#[derive(Default)]
+struct TableState {
+    alignments: Vec<Alignment>,
+    has_header: bool,
+    rows: Vec<Vec<String>>,
+}
+
+/// Escapes text so that it does not turn into markup.
+fn escape(text: &str) -> String {
+    let mut rv = String::with_capacity(text.len());
+    for c in text.chars() {
+        if let '\\' | '*' | '_' | '`' | '#' | '{' | '+' = c {
+            rv.push('\\');
+        }
+        rv.push(c);
+    }
+    rv
+}
+
+/// Returns a block delimiter that does not occur as a line in the body.
+fn delimiter(c: char, body: &str) -> String {
+    let mut rv = c.to_string().repeat(4);
+    while body.lines().any(|line| line.trim_end() == rv) {
+        rv.push(c);
+    }
+    rv
+}
+
+/// Object capable of rendering events to AsciiDoc.
+pub struct AsciiDocRenderer<'options, F> {
+    out: F,
+    options: &'options AsciiDocRendererOptions,
+    lists: Vec<Option<u32>>,
+    items: Vec<bool>,
+    saved_items: Vec<Vec<bool>>,
+    marker: Option<String>,
+    list_attrs: Option<String>,
+    list_ended: bool,
+    quotes: usize,
+    links: Vec<String>,
+    inline: String,
+    heading: Option<(usize, Option<String>)>,
+    table: Option<TableState>,
+    blank_pending: bool,
+    wrote_any: bool,
+    in_html: bool,
+    location: Option<Location>,
+    lossy: Vec<LossyConversion>,
+}
+
+impl<'options, F: Write> AsciiDocRenderer<'options, F> {
+    /// Creates a new renderer that writes into a writer.
+    pub fn new(
+        out: F,
+        options: &'options AsciiDocRendererOptions,
+    ) -> AsciiDocRenderer<'options, F> {
+        AsciiDocRenderer {
+            out,
+            options,
+            lists: vec![],
+            items: vec![],
+            saved_items: vec![],
+            marker: None,
+            list_attrs: None,
+            list_ended: false,
+            quotes: 0,
+            links: vec![],
+            inline: String::new(),
+            heading: None,
+            table: None,
+            blank_pending: false,
+            wrote_any: false,
+            in_html: false,
+            location: None,
+            lossy: vec![],
+        }
+    }
+
+    /// Consumes the writer and returns the inner file.
+    pub fn into_writer(self) -> F {
+        self.out
+    }
+
+    /// Returns the elements that could not be represented so far.
+    pub fn lossy_conversions(&self) -> &[LossyConversion] {
+        &self.lossy
+    }
+
+    fn report_tag(&mut self, tag: Tag, fallback: Fallback) {
+        self.lossy.push(LossyConversion::tag(
+            "asciidoc",
+            tag,
+            fallback,
+            self.location.clone(),
+        ));
+    }
+
+    fn report(&mut self, element: String, fallback: Fallback) {
+        self.lossy.push(LossyConversion {
+            format: "asciidoc".into(),
+            element,
+            fallback,
+            location: self.location.clone(),
+        });
+    }
+
+    fn write_lines(&mut self, lines: &[String]) -> Result<(), io::Error> {
+        for line in lines {
+            writeln!(self.out, "{}", line)?;
+        }
+        self.wrote_any = true;
+        Ok(())
+    }
+
+    /// Writes a block, attaching it to the current list item if needed.
+    fn write_block(&mut self, lines: &[String]) -> Result<(), io::Error> {
+        self.start_block()?;
+        self.write_lines(lines)
+    }
+
+    /// Separates a new block from the previous one.
+    fn start_block(&mut self) -> Result<(), io::Error> {
+        self.flush_inline()?;
+        self.write_item_marker()?;
+        match self.items.last_mut() {
+            Some(has_content) if *has_content => self.write_lines(&["+".into()])?,
+            Some(has_content) => *has_content = true,
+            None if self.blank_pending && self.wrote_any => self.write_lines(&["".into()])?,
+            None => {}
+        }
+        self.blank_pending = false;
+        self.list_ended = false;
+        Ok(())
+    }
+
+    /// Writes the marker of a list item that has no text of its own.
+    fn write_item_marker(&mut self) -> Result<(), io::Error> {
+        if let Some(marker) = self.marker.take() {
+            self.write_item_line(format!("{}{{empty}}", marker))?;
+        }
+        Ok(())
+    }
+
+    fn write_item_line(&mut self, line: String) -> Result<(), io::Error> {
+        if self.blank_pending && self.wrote_any {
+            self.write_lines(&["".into()])?;
+        }
+        self.blank_pending = false;
+        if let Some(attrs) = self.list_attrs.take() {
+            self.write_lines(&[attrs])?;
+        }
+        if let Some(has_content) = self.items.last_mut() {
+            *has_content = true;
+        }
+        self.write_lines(&[line])
+    }
+
+    /// Writes out buffered inline text as a paragraph.
+    fn flush_inline(&mut self) -> Result<(), io::Error> {
+        if self.inline.trim().is_empty() {
+            self.inline.clear();
+            return Ok(());
+        }
+        let text = std::mem::take(&mut self.inline);
+        let mut lines: Vec<String> = text.trim().lines().map(|x| x.trim().to_string()).collect();
+        match self.marker.take() {
+            Some(marker) => {
+                let first = format!("{}{}", marker, lines.remove(0));
+                self.write_item_line(first)?;
+                self.write_lines(&lines)
+            }
+            None => self.write_block(&lines),
+        }
+    }
+
+    /// Isolates the contents of a delimited block from enclosing lists.
+    fn enter_delimited_block(&mut self) {
+        let items = std::mem::take(&mut self.items);
+        self.saved_items.push(items);
+    }
+
+    fn leave_delimited_block(&mut self) -> Result<(), io::Error> {
+        self.flush_inline()?;
+        self.items = self.saved_items.pop().unwrap_or_default();
+        self.blank_pending = false;
+        Ok(())
+    }
+
+    fn start_tag(&mut self, tag: Tag, attrs: &Attrs) -> Result<(), io::Error> {
+        match tag {
+            Tag::Paragraph | Tag::Figure => self.flush_inline()?,
+            Tag::DefinitionList => {
+                self.flush_inline()?;
+                self.start_block()?;
+            }
+            // a term without definition is followed by another term
+            Tag::DefinitionTerm => {
+                self.flush_inline()?;
+                if let Some(marker) = self.marker.take() {
+                    self.write_item_line(marker.trim_end().to_string())?;
+                }
+            }
+            Tag::DefinitionDetails => {}
+            Tag::FigureCaption => {
+                self.inline.push_str(" +\n_");
+            }
+            Tag::Heading1
+            | Tag::Heading2
+            | Tag::Heading3
+            | Tag::Heading4
+            | Tag::Heading5
+            | Tag::Heading6 => {
+                self.flush_inline()?;
+                self.heading = Some((
+                    tag.header_level().unwrap(),
+                    attrs.id.as_ref().map(|x| x.as_str().to_string()),
+                ));
+            }
+            Tag::BlockQuote => {
+                self.quotes += 1;
+                let fence = "_".repeat(3 + self.quotes);
+                self.write_block(&[fence])?;
+                self.enter_delimited_block();
+            }
+            Tag::OrderedList | Tag::UnorderedList => {
+                self.flush_inline()?;
+                self.write_item_marker()?;
+                if self.lists.is_empty() {
+                    // adjacent lists would otherwise be merged
+                    if self.list_ended {
+                        self.write_lines(&["".into(), "//-".into()])?;
+                    }
+                    self.blank_pending = !self.list_ended;
+                }
+                if tag == Tag::OrderedList {
+                    let start = attrs.start.unwrap_or(1);
+                    if start != 1 {
+                        self.list_attrs = Some(format!("[start={}]", start));
+                    }
+                    self.lists.push(Some(start));
+                } else {
+                    self.lists.push(None);
+                }
+            }
+            Tag::ListItem => {
+                self.flush_inline()?;
+                self.write_item_marker()?;
+                let depth = self.lists.len().max(1);
+                self.marker = Some(match self.lists.last() {
+                    Some(Some(_)) => format!("{} ", ".".repeat(depth)),
+                    _ => format!("{} ", "*".repeat(depth)),
+                });
+                self.items.push(false);
+            }
+            Tag::FootnoteDefinition => {
+                let id = attrs.id.as_ref().map_or("", |x| x.as_str());
+                self.write_block(&[format!("[[fn-{}]]", id)])?;
+                self.items.push(false);
+                self.marker = Some(format!("^{}^ ", id));
+            }
+            Tag::Table => {
+                self.flush_inline()?;
+                self.table = Some(TableState::default());
+            }
+            Tag::TableHeader | Tag::TableRow => {
+                if let Some(ref mut table) = self.table {
+                    table.has_header |= tag == Tag::TableHeader;
+                    table.rows.push(vec![]);
+                }
+            }
+            Tag::TableHead => {
+                if let Some(ref mut table) = self.table {
+                    table.alignments.push(attrs.alignment);
+                }
+                self.inline.clear();
+            }
+            Tag::TableCell => self.inline.clear(),
+            Tag::TableBody => {}
+            Tag::Emphasis | Tag::EmphasisAlt => self.inline.push_str("__"),
+            Tag::Strong => self.inline.push_str("**"),
+            Tag::Strikethrough => self.inline.push_str("[.line-through]##"),
+            Tag::Link => {
+                let target = attrs.target.as_ref().map_or("", |x| x.as_str());
+                match target.strip_prefix('#') {
+                    Some(id) => {
+                        self.inline.push_str(&format!("<<{},", id));
+                        self.links.push(">>".into());
+                    }
+                    None => {
+                        self.inline
+                            .push_str(&format!("link:{}[", target.replace(' ', "%20")));
+                        self.links.push("]".into());
+                    }
+                }
+            }
+            // synthetic tags have no AsciiDoc syntax, only their contents
+            // are rendered.
+            Tag::Container | Tag::Span => {
+                if !attrs.is_empty() {
+                    self.report_tag(tag, Fallback::ContentsOnly);
+                }
+            }
+            Tag::Details
+            | Tag::Summary
+            | Tag::Spoiler
+            | Tag::Abbreviation
+            | Tag::Insertion
+            | Tag::Deletion
+            | Tag::Sidenote => self.report_tag(tag, Fallback::ContentsOnly),
+        }
+        Ok(())
+    }
+
+    fn end_tag(&mut self, tag: Tag) -> Result<(), io::Error> {
+        match tag {
+            Tag::Paragraph | Tag::Figure => {
+                self.flush_inline()?;
+                self.blank_pending = true;
+            }
+            Tag::FigureCaption => self.inline.push('_'),
+            Tag::DefinitionList => {
+                self.flush_inline()?;
+                self.write_item_marker()?;
+                self.blank_pending = true;
+            }
+            Tag::DefinitionTerm => {
+                let term = std::mem::take(&mut self.inline);
+                self.marker = Some(format!("{}:: ", term.replace('\n', " ").trim()));
+            }
+            Tag::DefinitionDetails => {
+                let text = std::mem::take(&mut self.inline);
+                let mut lines: Vec<String> =
+                    text.trim().lines().map(|x| x.trim().to_string()).collect();
+                match self.marker.take() {
+                    Some(marker) => {
+                        let first = if lines.is_empty() {
+                            "{empty}".into()
+                        } else {
+                            lines.remove(0)
+                        };
+                        self.write_item_line(format!("{}{}", marker, first))?;
+                    }
+                    // further definitions are attached to the first one
+                    None => self.write_lines(&["+".into()])?,
+                }
+                self.write_lines(&lines)?;
+            }
+            Tag::Heading1
+            | Tag::Heading2
+            | Tag::Heading3
+            | Tag::Heading4
+            | Tag::Heading5
+            | Tag::Heading6 => {
+                let text = std::mem::take(&mut self.inline);
+                let text = text.replace(" +\n", " ").replace('\n', " ");
+                let (level, id) = self.heading.take().unwrap_or((1, None));
+                let mut lines = vec![];
+                if let Some(id) = id {
+                    lines.push(format!("[[{}]]", id));
+                }
+                lines.push(format!("{} {}", "=".repeat(level + 1), text.trim()));
+                self.write_block(&lines)?;
+                self.blank_pending = true;
+            }
+            Tag::BlockQuote => {
+                self.leave_delimited_block()?;
+                let fence = "_".repeat(3 + self.quotes);
+                self.quotes -= 1;
+                self.write_lines(&[fence])?;
+                self.blank_pending = true;
+            }
+            Tag::OrderedList | Tag::UnorderedList => {
+                self.flush_inline()?;
+                self.lists.pop();
+                self.list_ended = self.lists.is_empty();
+                self.blank_pending = true;
+            }
+            Tag::ListItem | Tag::FootnoteDefinition => {
+                self.flush_inline()?;
+                self.write_item_marker()?;
+                self.items.pop();
+                self.blank_pending = tag == Tag::FootnoteDefinition;
+            }
+            Tag::Table => {
+                if let Some(table) = self.table.take() {
+                    let lines = render_table(table);
+                    self.write_block(&lines)?;
+                    self.blank_pending = true;
+                }
+            }
+            Tag::TableHead | Tag::TableCell => {
+                let cell = std::mem::take(&mut self.inline)
+                    .replace(" +\n", " ")
+                    .replace('\n', " ")
+                    .replace('|', "\\|");
+                if let Some(row) = self.table.as_mut().and_then(|x| x.rows.last_mut()) {
+                    row.push(cell.trim().to_string());
+                }
+            }
+            Tag::TableHeader | Tag::TableRow | Tag::TableBody => {}
+            Tag::Emphasis | Tag::EmphasisAlt => self.inline.push_str("__"),
+            Tag::Strong => self.inline.push_str("**"),
+            Tag::Strikethrough => self.inline.push_str("##"),
+            Tag::Link => {
+                let suffix = self.links.pop().unwrap_or_else(|| "]".into());
+                self.inline.push_str(&suffix);
+            }
+            Tag::Container
+            | Tag::Span
+            | Tag::Details
+            | Tag::Summary
+            | Tag::Spoiler
+            | Tag::Abbreviation
+            | Tag::Insertion
+            | Tag::Deletion
+            | Tag::Sidenote => {}
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, directive: &DirectiveEvent<'_>) -> Result<(), io::Error> {
+        let name = directive.name.as_str();
+        let argument = directive.argument.as_ref().map(|x| x.as_str());
+        let body = directive.body.as_str();
+        let kind = match self.options.admonitions.get(name) {
+            Some(kind) => kind,
+            None => return self.unknown_directive(directive),
+        };
+        let events: Vec<_> = parse(body, &Default::default()).skip(1).collect();
+        let fence = delimiter('=', body);
+        let mut lines = vec![format!("[{}]", kind)];
+        if let Some(title) = argument {
+            lines.push(format!(".{}", title));
+        }
+        lines.push(fence.clone());
+        self.write_block(&lines)?;
+        self.enter_delimited_block();
+        // locations within the body are reported as the directive's
+        let location = self.location.clone();
+        for mut event in events {
+            event.location = location.clone();
+            self.feed_event(&event)?;
+        }
+        self.leave_delimited_block()?;
+        self.write_lines(&[fence])?;
+        self.blank_pending = true;
+        Ok(())
+    }
+
+    fn unknown_directive(&mut self, directive: &DirectiveEvent<'_>) -> Result<(), io::Error> {
+        let name = directive.name.as_str();
+        match self.options.unknown_directives.resolve(directive) {
+            DirectiveOutcome::Replace(events) => {
+                let location = self.location.clone();
+                for event in events {
+                    match event {
+                        Event::Directive(ref directive) => self.literal_directive(directive)?,
+                        event => self.feed_event(&AnnotatedEvent::new(event, location.clone()))?,
+                    }
+                }
+            }
+            DirectiveOutcome::Fallback(Fallback::Literal) => self.literal_directive(directive)?,
+            DirectiveOutcome::Fallback(fallback) => {
+                self.report(format!("directive {}", name), fallback)
+            }
+        }
+        Ok(())
+    }
+
+    fn literal_directive(&mut self, directive: &DirectiveEvent<'_>) -> Result<(), io::Error> {
+        let name = directive.name.as_str();
+        let body = directive.body.as_str();
+        self.report(format!("directive {}", name), Fallback::Literal);
+        let fence = delimiter('.', body);
+        let mut lines = vec![format!("[.{}]", name)];
+        if let Some(ref argument) = directive.argument {
+            lines.push(format!(".{}", argument));
+        }
+        lines.push(fence.clone());
+        lines.extend(body.lines().map(|x| x.to_string()));
+        lines.push(fence);
+        self.write_block(&lines)?;
+        self.blank_pending = true;
+        Ok(())
+    }
+
+    /// Feeds a single event into the renderer.
+    pub fn feed_event(&mut self, event: &AnnotatedEvent<'_>) -> Result<(), io::Error> {
+        self.location = event.location.clone();
+        if self.in_html && !matches!(event.event, Event::RawHtml(..)) {
+            self.in_html = false;
+            self.write_lines(&["++++".into()])?;
+            self.blank_pending = true;
+        }
+
+        match event.event {
+            Event::DocumentStart(DocumentStartEvent { ref front_matter }) => {
+                if let Some(Value::Object(front_matter)) = front_matter {
+                    let mut lines = vec![];
+                    for (key, value) in front_matter.iter() {
+                        let value = match value {
+                            Value::String(value) => value.clone(),
+                            Value::Number(_) | Value::Bool(_) => value.to_string(),
+                            _ => continue,
+                        };
+                        if key == "title" {
+                            lines.insert(0, format!("= {}", value));
+                        } else {
+                            lines.push(format!(":{}: {}", key, value));
+                        }
+                    }
+                    if !lines.is_empty() {
+                        self.write_block(&lines)?;
+                        self.blank_pending = true;
+                    }
+                }
+            }
+            Event::StartTag(StartTagEvent { tag, ref attrs }) => self.start_tag(tag, attrs)?,
+            Event::EndTag(EndTagEvent { tag }) => self.end_tag(tag)?,
+            Event::Text(TextEvent { ref text }) => self.inline.push_str(&escape(text.as_str())),
+            Event::InterpretedText(InterpretedTextEvent {
+                ref role, ref text, ..
+            }) => {
+                let name = self
+                    .options
+                    .role_macros
+                    .get(role.as_str())
+                    .map_or(role.as_str(), |x| x.as_str());
+                self.inline
+                    .push_str(&format!("{}:[{}]", name, text.as_str().replace(']', "\\]")));
+            }
+            Event::InlineCode(InlineCodeEvent { ref code }) => {
+                self.inline.push_str(&format!("`+{}+`", code.as_str()));
+            }
+            Event::InlineMath(InlineMathEvent { ref math, .. }) => {
+                self.inline.push_str(&format!(
+                    "latexmath:[{}]",
+                    math.as_str().replace(']', "\\]")
+                ));
+            }
+            Event::MathBlock(MathBlockEvent { ref math, .. }) => {
+                let fence = delimiter('+', math.as_str());
+                let mut lines = vec!["[latexmath]".to_string(), fence.clone()];
+                lines.extend(math.as_str().lines().map(|x| x.to_string()));
+                lines.push(fence);
+                self.write_block(&lines)?;
+                self.blank_pending = true;
+            }
+            Event::CodeBlock(CodeBlockEvent {
+                ref language,
+                ref code,
+                ..
+            }) => {
+                let fence = delimiter('-', code.as_str());
+                let mut lines = vec![];
+                if let Some(language) = language {
+                    lines.push(format!("[source,{}]", language.as_str()));
+                }
+                lines.push(fence.clone());
+                lines.extend(code.as_str().lines().map(|x| x.to_string()));
+                lines.push(fence);
+                self.write_block(&lines)?;
+                self.blank_pending = true;
+            }
+            Event::Directive(ref directive) => {
+                self.directive(directive)?;
+            }
+            Event::Image(ImageEvent {
+                ref target,
+                ref alt,
+                ref title,
+                width,
+                height,
+            }) => {
+                let alt = alt.as_ref().map_or("", |x| x.as_str()).replace(']', "\\]");
+                let mut attrs = String::new();
+                if let Some(width) = width {
+                    attrs.push_str(&format!(",width={}", width));
+                }
+                if let Some(height) = height {
+                    attrs.push_str(&format!(",height={}", height));
+                }
+                if let Some(title) = title {
+                    attrs.push_str(&format!(
+                        ",title=\"{}\"",
+                        title.as_str().replace('"', "\\\"")
+                    ));
+                }
+                self.inline
+                    .push_str(&format!("image:{}[{}{}]", target.as_str(), alt, attrs));
+            }
+            Event::RawHtml(RawHtmlEvent { ref html }) => {
+                self.report("raw_html".into(), Fallback::Passthrough);
+                if self.heading.is_some() || self.table.is_some() || !self.inline.is_empty() {
+                    self.inline.push_str(&format!("+++{}+++", html.as_str()));
+                } else {
+                    if !self.in_html {
+                        self.write_block(&["++++".into()])?;
+                    }
+                    self.in_html = true;
+                    let lines: Vec<String> = html.as_str().lines().map(|x| x.to_string()).collect();
+                    self.write_lines(&lines)?;
+                }
+            }
+            Event::SoftBreak => self.inline.push('\n'),
+            Event::HardBreak => self.inline.push_str(" +\n"),
+            Event::Rule => {
+                self.write_block(&["'''".into()])?;
+                self.blank_pending = true;
+            }
+            Event::Checkbox(CheckboxEvent { checked }) => {
+                self.inline.push_str(if checked { "[x] " } else { "[ ] " });
+            }
+            Event::FootnoteReference(FootnoteReferenceEvent { ref target }) => {
+                self.inline
+                    .push_str(&format!("^<<fn-{},{}>>^", target.as_str(), target.as_str()));
+            }
+            Event::MetaData(..) | Event::Error(..) | Event::Diagnostic(..) => {}
+        }
+        Ok(())
+    }
+
+    /// Feeds an event stream into the renderer.
+    pub fn feed_stream<'data, I>(&mut self, iter: I) -> Result<(), io::Error>
+    where
+        I: Iterator<Item = AnnotatedEvent<'data>>,
+    {
+        for event in iter {
+            self.feed_event(&event)?;
+        }
+        self.finish()
+    }
+
+    /// Writes out everything that is still buffered.
+    ///
+    /// This is automatically called by [`feed_stream`](Self::feed_stream).
+    pub fn finish(&mut self) -> Result<(), io::Error> {
+        if self.in_html {
+            self.in_html = false;
+            self.write_lines(&["++++".into()])?;
+        }
+        self.flush_inline()
+    }
+}
+
+fn render_table(table: TableState) -> Vec<String> {
+    let mut attrs = vec![];
+    if table.has_header {
+        attrs.push("%header".to_string());
+    }
+    if table.alignments.iter().any(|x| *x != Alignment::None) {
+        let cols: Vec<&str> = table
+            .alignments
+            .iter()
+            .map(|x| match x {
+                Alignment::None => "1",
+                Alignment::Left => "<1",
+                Alignment::Center => "^1",
+                Alignment::Right => ">1",
+            })
+            .collect();
+        attrs.push(format!("cols=\"{}\"", cols.join(",")));
+    }
+
+    let mut lines = vec![];
+    if !attrs.is_empty() {
+        lines.push(format!("[{}]", attrs.join(",")));
+    }
+    lines.push("|===".into());
+    for (idx, row) in table.rows.iter().enumerate() {
+        if row.is_empty() {
+            continue;
+        }
+        let cells: Vec<String> = row.iter().map(|x| format!("| {}", x)).collect();
+        lines.push(cells.join(" "));
+        if idx == 0 && table.has_header {
+            lines.push(String::new());
+        }
+    }
+    lines.push("|===".into());
+    lines
+}
+
+impl<'options> AsciiDocRenderer<'options, Vec<u8>> {
+    /// Creates a new AsciiDoc renderer writing into a buffer.
+    pub fn new_buffered(options: &'options AsciiDocRendererOptions) -> Self {
+        AsciiDocRenderer::new(Vec::new(), options)
+    }
+
+    /// Converts the renderer into a string.
+    pub fn into_string(self) -> String {
+        String::from_utf8(self.into_writer()).expect("renderer produced invalid utf-8")
+    }
+}
+
+/// Convenience shortcut that renders an event stream into AsciiDoc.
+pub fn to_asciidoc<'a, I: Iterator<Item = AnnotatedEvent<'a>>>(
+    iter: I,
+    options: &AsciiDocRendererOptions,
+) -> String {
+    let mut renderer = AsciiDocRenderer::new_buffered(options);
+    renderer.feed_stream(iter).unwrap();
+    renderer.into_string()
+}
+
+#[test]
+fn test_to_asciidoc() {
+    let source = r#"---
+title: Getting Started
+author: Jane
+---
+# Installation {#install}
+
+Install *the* **tool** with `cargo install` and read [the docs](https://example.com)
+or the [setup section](#install).\
+A {kbd}`Ctrl+C` stops it, see {ref}`usage`.
+
+- [x] first
+- second
+  1. nested
+  2. items
+
+3. Loose items
+
+   with a second paragraph.
+
+   ```rust
+   fn main() {}
+   ```
+
+> Quoted text
+>
+> > nested
+
+| Name | Value |
+|:-----|------:|
+| a    | ~~1~~ |
+
+```{warning} Careful
+This *deletes* data.
+
+- really
+```
+
+```{graphviz}
+digraph { a -> b }
+```
+
+<div>raw</div>
+
+---
+
+Text[^1] with a footnote.
+
+[^1]: The footnote.
+"#;
+    insta::assert_snapshot!(to_asciidoc(
+        parse(source, &Default::default()),
+        &Default::default()
+    ));
+}
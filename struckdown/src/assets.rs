@@ -0,0 +1,244 @@
+//! Caches files generated by processors.
+//!
+//! Processors that render diagrams, charts or images invoke external tools
+//! which are often slow.  An [`AssetStore`] names generated files after a
+//! hash of everything that went into them, so a file that already exists on
+//! disk does not have to be generated again:
+//!
+//! ```
+//! use struckdown::assets::AssetStore;
+//!
+//! let output_dir = std::env::temp_dir().join("struckdown-assets-doctest");
+//! let store = AssetStore::new(&output_dir, "/assets/");
+//! let asset = store
+//!     .get_or_create("graph", &[b"digraph { a -> b }"], "svg", |path| {
+//!         std::fs::write(path, "<svg/>").map_err(|err| err.to_string())
+//!     })
+//!     .unwrap();
+//! assert!(asset.url.starts_with("/assets/graph-"));
+//! # std::fs::remove_dir_all(&output_dir).ok();
+//! ```
+//!
+//! All generated files are recorded in a [`AssetManifest`] stored as
+//! `manifest.json` in the output folder.  It lets a build tool find the
+//! assets a site references and remove stale ones.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// The name of the manifest file in the output folder.
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+/// Returns a content hash of some inputs as hex string.
+///
+/// The hash is a 64 bit FNV-1a hash which is stable across platforms and
+/// releases.  Every input is prefixed with its length so that moving bytes
+/// from one input to the next changes the hash.  It is not suitable for
+/// anything security relevant.
+pub fn content_hash(inputs: &[&[u8]]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for &byte in bytes {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    for input in inputs {
+        feed(&(input.len() as u64).to_le_bytes());
+        feed(input);
+    }
+    format!("{:016x}", hash)
+}
+
+/// A generated file.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Asset {
+    /// The path of the file on disk.
+    pub path: PathBuf,
+    /// The URL the file is served under.
+    pub url: String,
+    /// The content hash of the inputs of the file.
+    pub hash: String,
+    /// Whether the file already existed and was not generated again.
+    pub cached: bool,
+}
+
+/// An entry of the [`AssetManifest`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AssetManifestEntry {
+    /// The name of what generated the file (for instance `graphviz`).
+    pub generator: String,
+    /// The content hash of the inputs of the file.
+    pub hash: String,
+}
+
+/// Lists the files generated into an output folder by file name.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct AssetManifest {
+    pub assets: BTreeMap<String, AssetManifestEntry>,
+}
+
+impl AssetManifest {
+    /// Loads the manifest of an output folder.
+    ///
+    /// A missing manifest is treated as empty.
+    pub fn load(output_dir: &Path) -> Result<AssetManifest, io::Error> {
+        match fs::read(output_dir.join(MANIFEST_FILENAME)) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(AssetManifest::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the manifest into an output folder.
+    pub fn save(&self, output_dir: &Path) -> Result<(), io::Error> {
+        let contents = serde_json::to_vec_pretty(self)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        fs::write(output_dir.join(MANIFEST_FILENAME), contents)
+    }
+
+    /// Returns the files in the output folder not listed in the manifest.
+    pub fn find_stale(&self, output_dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+        let mut rv = vec![];
+        for entry in fs::read_dir(output_dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name != MANIFEST_FILENAME && !self.assets.contains_key(name.as_ref()) {
+                rv.push(entry.path());
+            }
+        }
+        rv.sort();
+        Ok(rv)
+    }
+}
+
+/// Stores generated files under content hashed names.
+#[derive(Debug, Clone)]
+pub struct AssetStore {
+    output_dir: PathBuf,
+    url_prefix: String,
+}
+
+impl AssetStore {
+    /// Creates a store writing into `output_dir` served under `url_prefix`.
+    pub fn new<P: AsRef<Path>>(output_dir: P, url_prefix: &str) -> AssetStore {
+        AssetStore {
+            output_dir: output_dir.as_ref().to_path_buf(),
+            url_prefix: url_prefix.to_string(),
+        }
+    }
+
+    /// Returns the folder files are written to.
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// Returns the file name for a generated file.
+    ///
+    /// The name is made of `stem`, the content hash of `inputs` and the
+    /// extension.  The inputs must include everything that affects the
+    /// output, like the source and the command line of the tool.
+    pub fn filename(&self, stem: &str, inputs: &[&[u8]], extension: &str) -> String {
+        format!("{}-{}.{}", stem, content_hash(inputs), extension)
+    }
+
+    /// Returns a generated file, generating it if necessary.
+    ///
+    /// If no file for the inputs exists yet, `create` is invoked with the
+    /// path to write to.  The file is first written to a temporary path and
+    /// moved into place on success, so an interrupted generator never
+    /// leaves a broken file in the cache.  The file is recorded in the
+    /// manifest either way.
+    pub fn get_or_create<F>(
+        &self,
+        stem: &str,
+        inputs: &[&[u8]],
+        extension: &str,
+        create: F,
+    ) -> Result<Asset, String>
+    where
+        F: FnOnce(&Path) -> Result<(), String>,
+    {
+        let hash = content_hash(inputs);
+        let filename = format!("{}-{}.{}", stem, hash, extension);
+        let path = self.output_dir.join(&filename);
+        let cached = path.is_file();
+
+        if !cached {
+            fs::create_dir_all(&self.output_dir).map_err(|err| err.to_string())?;
+            let tmp_path = self
+                .output_dir
+                .join(format!(".{}-{}.tmp.{}", stem, hash, extension));
+            if let Err(err) = create(&tmp_path) {
+                fs::remove_file(&tmp_path).ok();
+                return Err(err);
+            }
+            fs::rename(&tmp_path, &path).map_err(|err| err.to_string())?;
+        }
+
+        self.record(
+            &filename,
+            AssetManifestEntry {
+                generator: stem.to_string(),
+                hash: hash.clone(),
+            },
+        )
+        .map_err(|err| err.to_string())?;
+
+        Ok(Asset {
+            path,
+            url: format!("{}{}", self.url_prefix, filename),
+            hash,
+            cached,
+        })
+    }
+
+    fn record(&self, filename: &str, entry: AssetManifestEntry) -> Result<(), io::Error> {
+        let mut manifest = AssetManifest::load(&self.output_dir)?;
+        if manifest.assets.get(filename) != Some(&entry) {
+            manifest.assets.insert(filename.to_string(), entry);
+            manifest.save(&self.output_dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_asset_store() {
+    let output_dir = std::env::temp_dir().join("struckdown-assets-test");
+    fs::remove_dir_all(&output_dir).ok();
+    let store = AssetStore::new(&output_dir, "/assets/");
+
+    let mut calls = 0;
+    for _ in 0..2 {
+        let asset = store
+            .get_or_create("chart", &[b"a", b"b"], "svg", |path| {
+                calls += 1;
+                fs::write(path, "<svg/>").map_err(|err| err.to_string())
+            })
+            .unwrap();
+        assert_eq!(asset.url, format!("/assets/chart-{}.svg", asset.hash));
+        assert!(asset.path.is_file());
+    }
+    assert_eq!(calls, 1);
+
+    // inputs are length prefixed
+    assert_ne!(content_hash(&[b"ab", b""]), content_hash(&[b"a", b"b"]));
+
+    let failed = store.get_or_create("chart", &[b"c"], "svg", |_| Err("boom".into()));
+    assert_eq!(failed.unwrap_err(), "boom");
+
+    fs::write(output_dir.join("old.svg"), "").unwrap();
+    let manifest = AssetManifest::load(&output_dir).unwrap();
+    assert_eq!(manifest.assets.len(), 1);
+    assert_eq!(
+        manifest.find_stale(&output_dir).unwrap(),
+        vec![output_dir.join("old.svg")]
+    );
+    fs::remove_dir_all(&output_dir).ok();
+}
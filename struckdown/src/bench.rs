@@ -0,0 +1,254 @@
+//! Measures the throughput of pipelines.
+//!
+//! This is only available with the `bench` feature.  [`run`] parses all
+//! markdown files of a corpus folder and runs them through the configured
+//! processors one stage at a time, measuring how long every stage takes:
+//!
+//! ```no_run
+//! use struckdown::bench::{run, BenchConfig};
+//!
+//! let config: BenchConfig = serde_yaml::from_str("
+//! iterations: 5
+//! processors:
+//!   - processor: auto_anchors
+//!   - processor: typography
+//! ").unwrap();
+//! let report = run("docs".as_ref(), &config).unwrap();
+//! println!("{}", serde_json::to_string_pretty(&report).unwrap());
+//! ```
+//!
+//! To also count allocations the program running the benchmark has to
+//! install the [`CountingAllocator`] as its global allocator:
+//!
+//! ```no_run
+//! #[global_allocator]
+//! static ALLOCATOR: struckdown::bench::CountingAllocator = struckdown::bench::CountingAllocator;
+//! ```
+//!
+//! Every stage consumes the fully collected output of the previous stage,
+//! so the numbers do not include the cost of the stages before it.  The
+//! report is serializable to compare runs in CI.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::AnnotatedEvent;
+use crate::html::to_html;
+use crate::parser::parse;
+use crate::processors::{BuiltinProcessor, Processor};
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// A global allocator counting allocations for the report.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        INSTALLED.store(true, Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size as u64, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+fn allocation_counters() -> Option<(u64, u64)> {
+    if INSTALLED.load(Ordering::Relaxed) {
+        Some((
+            ALLOCATIONS.load(Ordering::Relaxed),
+            ALLOCATED_BYTES.load(Ordering::Relaxed),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Configures a benchmark run.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BenchConfig {
+    /// The processors to run in order.
+    pub processors: Vec<BuiltinProcessor>,
+    /// How often the corpus is processed.
+    pub iterations: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> BenchConfig {
+        BenchConfig {
+            processors: vec![],
+            iterations: 3,
+        }
+    }
+}
+
+/// The measurements of one stage.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StageReport {
+    /// The name of the stage (`parse`, the processor or `html`).
+    pub name: String,
+    /// The total time spent in the stage in seconds.
+    pub seconds: f64,
+    /// The source bytes processed per second.
+    pub bytes_per_second: f64,
+    /// The number of events the stage emitted.
+    pub events: usize,
+    /// The number of allocations if the [`CountingAllocator`] is installed.
+    pub allocations: Option<u64>,
+    /// The number of bytes allocated if the [`CountingAllocator`] is
+    /// installed.
+    pub allocated_bytes: Option<u64>,
+}
+
+/// The result of a benchmark run.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BenchReport {
+    /// The number of documents in the corpus.
+    pub documents: usize,
+    /// The size of the corpus in bytes.
+    pub bytes: usize,
+    /// How often the corpus was processed.
+    pub iterations: usize,
+    /// The measurements per stage in pipeline order.
+    pub stages: Vec<StageReport>,
+}
+
+#[derive(Default)]
+struct Measurement {
+    duration: Duration,
+    events: usize,
+    allocations: Option<(u64, u64)>,
+}
+
+impl Measurement {
+    fn measure<T, F: FnOnce() -> T>(&mut self, f: F) -> T {
+        let allocations_before = allocation_counters();
+        let start = Instant::now();
+        let rv = f();
+        self.duration += start.elapsed();
+        if let (Some(before), Some(after)) = (allocations_before, allocation_counters()) {
+            let (count, bytes) = self.allocations.unwrap_or_default();
+            self.allocations = Some((count + after.0 - before.0, bytes + after.1 - before.1));
+        }
+        rv
+    }
+
+    fn into_report(self, name: String, bytes: usize) -> StageReport {
+        let seconds = self.duration.as_secs_f64();
+        StageReport {
+            name,
+            seconds,
+            bytes_per_second: if seconds > 0.0 {
+                bytes as f64 / seconds
+            } else {
+                0.0
+            },
+            events: self.events,
+            allocations: self.allocations.map(|x| x.0),
+            allocated_bytes: self.allocations.map(|x| x.1),
+        }
+    }
+}
+
+fn find_documents(path: &Path, rv: &mut Vec<PathBuf>) -> Result<(), io::Error> {
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_documents(&path, rv)?;
+        } else if matches!(path.extension(), Some(ext) if ext == "md") {
+            rv.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name of a processor as used in configs.
+fn config_name(processor: &BuiltinProcessor) -> String {
+    let mut rv = String::new();
+    for c in processor.type_name().chars() {
+        if c.is_ascii_uppercase() && !rv.is_empty() {
+            rv.push('_');
+        }
+        rv.push(c.to_ascii_lowercase());
+    }
+    rv
+}
+
+/// Benchmarks a pipeline against all `.md` files in a folder.
+///
+/// Folders are searched recursively.
+pub fn run(corpus: &Path, config: &BenchConfig) -> Result<BenchReport, io::Error> {
+    let mut paths = vec![];
+    find_documents(corpus, &mut paths)?;
+    paths.sort();
+    let sources = paths
+        .iter()
+        .map(fs::read_to_string)
+        .collect::<Result<Vec<_>, _>>()?;
+    let bytes = sources.iter().map(|x| x.len()).sum::<usize>() * config.iterations;
+
+    let mut parse_stage = Measurement::default();
+    let mut processor_stages: Vec<Measurement> = config
+        .processors
+        .iter()
+        .map(|_| Default::default())
+        .collect();
+    let mut html_stage = Measurement::default();
+
+    for _ in 0..config.iterations {
+        for source in &sources {
+            let mut events: Vec<AnnotatedEvent<'_>> =
+                parse_stage.measure(|| parse(source, &Default::default()).collect());
+            parse_stage.events += events.len();
+            for (processor, stage) in config.processors.iter().zip(processor_stages.iter_mut()) {
+                let processor = Box::new(processor.clone());
+                events = stage.measure(|| processor.apply(Box::new(events.into_iter())).collect());
+                stage.events += events.len();
+            }
+            html_stage.measure(|| to_html(events.into_iter(), &Default::default()));
+        }
+    }
+
+    let mut stages = vec![parse_stage.into_report("parse".into(), bytes)];
+    for (processor, stage) in config.processors.iter().zip(processor_stages) {
+        stages.push(stage.into_report(config_name(processor), bytes));
+    }
+    stages.push(html_stage.into_report("html".into(), bytes));
+
+    Ok(BenchReport {
+        documents: sources.len(),
+        bytes: bytes / config.iterations.max(1),
+        iterations: config.iterations,
+        stages,
+    })
+}
+
+#[test]
+fn test_run() {
+    let config: BenchConfig = serde_yaml::from_str(
+        "iterations: 2\nprocessors:\n  - processor: auto_anchors\n  - processor: typography\n",
+    )
+    .unwrap();
+    let report = run("tests/inputs".as_ref(), &config).unwrap();
+    assert!(report.documents > 0);
+    assert_eq!(report.iterations, 2);
+    let names: Vec<_> = report.stages.iter().map(|x| x.name.as_str()).collect();
+    assert_eq!(names, vec!["parse", "auto_anchors", "typography", "html"]);
+    assert!(report.stages[0].events > 0);
+}
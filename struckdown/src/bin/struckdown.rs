@@ -0,0 +1,646 @@
+//! Parses, processes and renders documents on the command line.
+//!
+//! This is only available with the `cli` feature.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+
+use anyhow::Error;
+use argh::FromArgs;
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
+
+use struckdown::diagnostics::{collect_diagnostics, Diagnostic};
+use struckdown::document::read_source;
+use struckdown::event::{AnnotatedEvent, MetaDataEvent};
+use struckdown::html::{HtmlRenderer, HtmlRendererOptions};
+use struckdown::json::stream_to_json;
+use struckdown::locales::{LocaleOptions, LocaleSite};
+use struckdown::markdown::{MarkdownRenderer, MarkdownRendererOptions};
+use struckdown::pipeline::Pipeline;
+use struckdown::sandbox::track_reads;
+use struckdown::search::{collect_search_records, SearchIndex, SearchOptions, SearchRecord};
+
+/// Renders struckdown documents.
+///
+/// The documents are read from the given paths or stdin, processed with
+/// the pipeline of the config and written to stdout or the output folder.
+#[derive(FromArgs, Debug)]
+struct Cli {
+    /// paths to the documents (defaults to stdin).
+    #[argh(positional)]
+    paths: Vec<PathBuf>,
+    /// path to a YAML file configuring the pipeline.
+    #[argh(option)]
+    config: Option<PathBuf>,
+    /// the output format: html, json or markdown.
+    #[argh(option, default = "String::from(\"html\")")]
+    format: String,
+    /// folder to write the rendered documents to instead of stdout.
+    #[argh(option)]
+    out_dir: Option<PathBuf>,
+    /// re-render documents when they or the files they read change.
+    #[argh(switch)]
+    watch: bool,
+    /// path to write a search index of all documents to.
+    #[argh(option)]
+    search_index: Option<PathBuf>,
+    /// include the titles of enclosing sections in the search index.
+    #[argh(switch)]
+    search_hierarchy: bool,
+    /// include the positions of tokens in the search index.
+    #[argh(switch)]
+    search_positions: bool,
+    /// comma separated languages of the site, each in a folder of the same
+    /// name.  The first one is the default language.
+    #[argh(option)]
+    locales: Option<String>,
+}
+
+/// How long the stages of rendering a document took.
+struct Timings {
+    /// Parsing and processing the document.
+    process: Duration,
+    render: Duration,
+}
+
+/// The result of rendering a document.
+struct Rendered {
+    output: String,
+    diagnostics: Vec<Diagnostic>,
+    records: Vec<SearchRecord>,
+    timings: Timings,
+}
+
+/// A document rendered by the command.
+struct Document {
+    path: PathBuf,
+    /// The files read while processing the document.
+    dependencies: BTreeSet<PathBuf>,
+    /// The search records of the document.
+    records: Vec<SearchRecord>,
+}
+
+impl Document {
+    fn new(path: PathBuf) -> Document {
+        Document {
+            path,
+            dependencies: BTreeSet::new(),
+            records: vec![],
+        }
+    }
+}
+
+/// Makes a path absolute without requiring the file to exist.
+///
+/// Watchers report changes with absolute paths, including for files that
+/// were just removed.
+fn normalize_path(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    match (parent.canonicalize(), path.file_name()) {
+        (Ok(parent), Some(file_name)) => parent.join(file_name),
+        _ => path.to_path_buf(),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn load_pipeline(config: Option<&Path>) -> Result<Pipeline, Error> {
+    Ok(match config {
+        Some(path) => serde_yaml::from_str(&read_source(path)?)?,
+        None => Pipeline::new(),
+    })
+}
+
+/// Renders a document.
+///
+/// Search records are collected if the document has a name in the search
+/// index.
+fn render(
+    pipeline: &Pipeline,
+    source: &str,
+    format: &str,
+    search: Option<(&str, &SearchOptions)>,
+    localization: Option<MetaDataEvent<'static>>,
+) -> Result<Rendered, Error> {
+    let start = Instant::now();
+    let mut events: Vec<_> = pipeline.process(source).collect();
+    events.extend(localization.map(AnnotatedEvent::from));
+    let processed = Instant::now();
+    let mut diagnostics = collect_diagnostics(&events);
+    let records = match search {
+        Some((document, options)) => collect_search_records(document, &events, options),
+        None => vec![],
+    };
+    let (output, lossy) = match format {
+        "html" => {
+            let options = HtmlRendererOptions::default();
+            let mut renderer = HtmlRenderer::new_buffered(&options);
+            renderer.feed_stream(events.into_iter())?;
+            let lossy = renderer.lossy_conversions().to_vec();
+            (renderer.into_string() + "\n", lossy)
+        }
+        "json" => (stream_to_json(events.into_iter()), vec![]),
+        "markdown" => {
+            let options = MarkdownRendererOptions::default();
+            let mut renderer = MarkdownRenderer::new_buffered(&options);
+            renderer.feed_stream(events.into_iter())?;
+            let lossy = renderer.lossy_conversions().to_vec();
+            (renderer.into_string(), lossy)
+        }
+        other => anyhow::bail!("unknown output format '{}'", other),
+    };
+    // renderers report what they could not represent
+    diagnostics.extend(
+        lossy
+            .iter()
+            .filter_map(|x| Diagnostic::from_event(&x.to_diagnostic_event())),
+    );
+    Ok(Rendered {
+        output,
+        diagnostics,
+        records,
+        timings: Timings {
+            process: processed - start,
+            render: processed.elapsed(),
+        },
+    })
+}
+
+/// Returns the innermost folder containing all documents.
+fn input_root(cli: &Cli) -> PathBuf {
+    let mut parents = cli
+        .paths
+        .iter()
+        .filter(|x| x.as_os_str() != "-")
+        .filter_map(|x| normalize_path(x).parent().map(Path::to_path_buf));
+    let mut root = match parents.next() {
+        Some(root) => root,
+        None => return PathBuf::new(),
+    };
+    for parent in parents {
+        while !parent.starts_with(&root) && root.pop() {}
+    }
+    root
+}
+
+/// Returns where the rendered document is written to.
+///
+/// The folders of the documents below their common folder are mirrored in
+/// the output folder.
+fn output_path(cli: &Cli, path: &Path) -> Option<PathBuf> {
+    let extension = match cli.format.as_str() {
+        "json" => "json",
+        "markdown" => "md",
+        _ => "html",
+    };
+    let relative = if path.as_os_str() == "-" {
+        PathBuf::from("stdin")
+    } else {
+        let path = normalize_path(path);
+        match path.strip_prefix(input_root(cli)) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => path.file_name().map_or("stdin".into(), PathBuf::from),
+        }
+    };
+    cli.out_dir
+        .as_ref()
+        .map(|out_dir| out_dir.join(relative).with_extension(extension))
+}
+
+/// Fails if two documents would be written to the same output file.
+fn check_output_paths(cli: &Cli) -> Result<(), Error> {
+    let mut seen = BTreeMap::new();
+    for path in &cli.paths {
+        if let Some(output_path) = output_path(cli, path) {
+            if let Some(other) = seen.insert(output_path.clone(), path) {
+                anyhow::bail!(
+                    "'{}' and '{}' would both be written to '{}'",
+                    other.display(),
+                    path.display(),
+                    output_path.display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the path of a document within a multi-language site.
+fn site_path(cli: &Cli, path: &Path) -> String {
+    format!("/{}", document_name(cli, path))
+}
+
+/// Registers all documents in a site if languages are configured.
+fn load_locale_site(cli: &Cli) -> Result<Option<LocaleSite>, Error> {
+    let locales: Vec<String> = match cli.locales {
+        Some(ref locales) => locales
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(String::from)
+            .collect(),
+        None => return Ok(None),
+    };
+    let default_locale = match locales.first() {
+        Some(default_locale) => default_locale.clone(),
+        None => anyhow::bail!("--locales requires at least one language"),
+    };
+    if cli.out_dir.is_none() {
+        anyhow::bail!("--locales requires --out-dir");
+    }
+    let mut site = LocaleSite::new(LocaleOptions {
+        default_locale,
+        locales,
+    });
+    for path in &cli.paths {
+        site.add_document(&site_path(cli, path));
+    }
+    Ok(Some(site))
+}
+
+/// Writes the pages for missing translations and reports which documents
+/// are not translated.
+fn write_fallback_pages(
+    cli: &Cli,
+    pipeline: &Pipeline,
+    site: Option<&LocaleSite>,
+    documents: &[Document],
+) -> Result<(), Error> {
+    let (site, out_dir) = match (site, cli.out_dir.as_ref()) {
+        (Some(site), Some(out_dir)) => (site, out_dir),
+        _ => return Ok(()),
+    };
+    for page in site.fallback_pages() {
+        let document = match documents
+            .iter()
+            .find(|x| site_path(cli, &x.path) == page.source)
+        {
+            Some(document) => document,
+            None => continue,
+        };
+        let source = read_source(&document.path)?;
+        let rendered = render(
+            pipeline,
+            &source,
+            &cli.format,
+            None,
+            site.metadata_event(&page.url),
+        )?;
+        let output_path = out_dir.join(page.url.trim_start_matches('/'));
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(output_path, rendered.output)?;
+    }
+    for report in site.untranslated() {
+        if !report.missing.is_empty() {
+            eprintln!(
+                "warning: {} of {} documents are not translated to {}",
+                report.missing.len(),
+                report.missing.len() + report.translated,
+                report.locale
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns the options for the search index if one is written.
+fn search_options(cli: &Cli) -> Option<SearchOptions> {
+    cli.search_index.as_ref().map(|_| SearchOptions {
+        hierarchy: cli.search_hierarchy,
+        positions: cli.search_positions,
+    })
+}
+
+/// Returns the name of a document in the search index.
+///
+/// This is the path of the rendered document within the output folder or
+/// the path of the source if it's written to stdout.
+fn document_name(cli: &Cli, path: &Path) -> String {
+    match (output_path(cli, path), cli.out_dir.as_ref()) {
+        (Some(output_path), Some(out_dir)) => match output_path.strip_prefix(out_dir) {
+            Ok(relative) => relative
+                .components()
+                .map(|x| x.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+            Err(_) => output_path.display().to_string(),
+        },
+        _ => path.display().to_string(),
+    }
+}
+
+/// Writes the search records of all documents into a single file.
+fn write_search_index(cli: &Cli, documents: &[Document]) -> Result<(), Error> {
+    if let Some(ref search_index) = cli.search_index {
+        let index = SearchIndex {
+            records: documents
+                .iter()
+                .flat_map(|x| x.records.iter().cloned())
+                .collect(),
+        };
+        fs::write(search_index, serde_json::to_string(&index)?)?;
+    }
+    Ok(())
+}
+
+/// Renders a document and stores the files it depends on and its search
+/// records.
+fn build(
+    cli: &Cli,
+    pipeline: &Pipeline,
+    site: Option<&LocaleSite>,
+    document: &mut Document,
+) -> Result<(), Error> {
+    let path = document.path.as_path();
+    let source = read_source(path)?;
+    let name = document_name(cli, path);
+    let options = search_options(cli);
+    let search = options.as_ref().map(|options| (name.as_str(), options));
+    let localization = site.and_then(|site| site.metadata_event(&site_path(cli, path)));
+    let (rv, reads) = track_reads(|| render(pipeline, &source, &cli.format, search, localization));
+    let Rendered {
+        output,
+        diagnostics,
+        records,
+        timings,
+    } = rv?;
+    for diagnostic in diagnostics {
+        eprintln!("{}:{}", path.display(), diagnostic);
+    }
+    match output_path(cli, path) {
+        Some(output_path) => {
+            if normalize_path(&output_path) == normalize_path(path) {
+                anyhow::bail!("'{}' would overwrite itself", path.display());
+            }
+            if let Some(parent) = output_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&output_path, output)?;
+        }
+        None => print!("{}", output),
+    }
+    if cli.watch {
+        eprintln!(
+            "rendered {} in {} (process {}, render {})",
+            path.display(),
+            format_duration(timings.process + timings.render),
+            format_duration(timings.process),
+            format_duration(timings.render),
+        );
+    }
+    document.dependencies = reads.iter().map(|x| normalize_path(x)).collect();
+    document.records = records;
+    Ok(())
+}
+
+/// Rebuilds documents whenever they, their dependencies or the config change.
+fn watch(cli: &Cli, mut pipeline: Pipeline, site: Option<LocaleSite>) -> Result<(), Error> {
+    let config = cli.config.as_deref().map(normalize_path);
+    let mut documents: Vec<Document> = cli
+        .paths
+        .iter()
+        .map(|path| Document::new(path.clone()))
+        .collect();
+    let mut rebuild: Vec<usize> = (0..documents.len()).collect();
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::watcher(tx, Duration::from_millis(100))?;
+    // folders are watched rather than files as editors often replace files
+    let mut watched_dirs = BTreeSet::new();
+
+    loop {
+        for idx in rebuild.drain(..) {
+            let document = &mut documents[idx];
+            if let Err(err) = build(cli, &pipeline, site.as_ref(), document) {
+                eprintln!("error: {}: {}", document.path.display(), err);
+            }
+        }
+        if let Err(err) = write_search_index(cli, &documents) {
+            eprintln!("error: failed to write search index: {}", err);
+        }
+        if let Err(err) = write_fallback_pages(cli, &pipeline, site.as_ref(), &documents) {
+            eprintln!("error: failed to write fallback pages: {}", err);
+        }
+
+        let files = documents
+            .iter()
+            .flat_map(|x| {
+                Some(normalize_path(&x.path))
+                    .into_iter()
+                    .chain(x.dependencies.iter().cloned())
+            })
+            .chain(config.clone());
+        for file in files {
+            if let Some(dir) = file.parent() {
+                if watched_dirs.insert(dir.to_path_buf()) {
+                    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+                }
+            }
+        }
+
+        let mut changed = BTreeSet::new();
+        let mut event = Some(rx.recv()?);
+        while let Some(current) = event {
+            match current {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path)
+                | DebouncedEvent::Rename(_, path) => {
+                    changed.insert(normalize_path(&path));
+                }
+                DebouncedEvent::Error(err, _) => eprintln!("error: {}", err),
+                _ => {}
+            }
+            event = rx.try_recv().ok();
+        }
+
+        if config.iter().any(|x| changed.contains(x)) {
+            match load_pipeline(cli.config.as_deref()) {
+                Ok(new_pipeline) => {
+                    pipeline = new_pipeline;
+                    rebuild.extend(0..documents.len());
+                    continue;
+                }
+                Err(err) => eprintln!("error: failed to load config: {}", err),
+            }
+        }
+        rebuild.extend(documents.iter().enumerate().filter_map(|(idx, document)| {
+            if changed.contains(&normalize_path(&document.path))
+                || !document.dependencies.is_disjoint(&changed)
+            {
+                Some(idx)
+            } else {
+                None
+            }
+        }));
+    }
+}
+
+fn run(mut cli: Cli) -> Result<(), Error> {
+    if cli.paths.is_empty() {
+        cli.paths.push(PathBuf::from("-"));
+    }
+    if cli.paths.len() > 1 && cli.out_dir.is_none() {
+        anyhow::bail!("rendering multiple documents requires --out-dir");
+    }
+    check_output_paths(&cli)?;
+    let site = load_locale_site(&cli)?;
+    let pipeline = load_pipeline(cli.config.as_deref())?;
+    if let Some(ref out_dir) = cli.out_dir {
+        fs::create_dir_all(out_dir)?;
+    }
+
+    if cli.watch {
+        if cli.paths.iter().any(|x| x.as_os_str() == "-") {
+            anyhow::bail!("cannot watch stdin");
+        }
+        return watch(&cli, pipeline, site);
+    }
+    let mut documents = vec![];
+    for path in &cli.paths {
+        let mut document = Document::new(path.clone());
+        build(&cli, &pipeline, site.as_ref(), &mut document)?;
+        documents.push(document);
+    }
+    write_search_index(&cli, &documents)?;
+    write_fallback_pages(&cli, &pipeline, site.as_ref(), &documents)
+}
+
+fn main() -> Result<(), Error> {
+    run(argh::from_env())
+}
+
+#[test]
+fn test_multiple_documents() {
+    let dir = std::env::temp_dir().join("struckdown-cli-test");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("first.md"), "# First\n\nHello").unwrap();
+    fs::write(dir.join("second.md"), "# Second\n\nWorld").unwrap();
+
+    run(Cli {
+        paths: vec![dir.join("first.md"), dir.join("second.md")],
+        config: None,
+        format: "html".into(),
+        out_dir: Some(dir.join("out")),
+        watch: false,
+        search_index: Some(dir.join("search.json")),
+        search_hierarchy: false,
+        search_positions: false,
+        locales: None,
+    })
+    .unwrap();
+
+    let first = fs::read_to_string(dir.join("out/first.html")).unwrap();
+    assert!(first.contains("<p>Hello</p>"));
+    let second = fs::read_to_string(dir.join("out/second.html")).unwrap();
+    assert!(second.contains("<p>World</p>"));
+    let index = fs::read_to_string(dir.join("search.json")).unwrap();
+    assert!(index.contains("first.html") && index.contains("second.html"));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_output_folders() {
+    let dir = std::env::temp_dir().join("struckdown-cli-folder-test");
+    for folder in &["a", "b"] {
+        fs::create_dir_all(dir.join(folder)).unwrap();
+        fs::write(dir.join(folder).join("index.md"), folder).unwrap();
+    }
+    fs::write(dir.join("a/index.markdown"), "Hello").unwrap();
+
+    run(Cli {
+        paths: vec![dir.join("a/index.md"), dir.join("b/index.md")],
+        config: None,
+        format: "html".into(),
+        out_dir: Some(dir.join("out")),
+        watch: false,
+        search_index: Some(dir.join("search.json")),
+        search_hierarchy: false,
+        search_positions: false,
+        locales: None,
+    })
+    .unwrap();
+    let a = fs::read_to_string(dir.join("out/a/index.html")).unwrap();
+    assert!(a.contains("<p>a</p>"));
+    let b = fs::read_to_string(dir.join("out/b/index.html")).unwrap();
+    assert!(b.contains("<p>b</p>"));
+    let index = fs::read_to_string(dir.join("search.json")).unwrap();
+    assert!(index.contains("a/index.html") && index.contains("b/index.html"));
+
+    let err = run(Cli {
+        paths: vec![dir.join("a/index.md"), dir.join("a/index.markdown")],
+        config: None,
+        format: "html".into(),
+        out_dir: Some(dir.join("out")),
+        watch: false,
+        search_index: None,
+        search_hierarchy: false,
+        search_positions: false,
+        locales: None,
+    })
+    .unwrap_err();
+    assert!(err.to_string().contains("would both be written to"));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_locales() {
+    let dir = std::env::temp_dir().join("struckdown-cli-locales-test");
+    for (path, source) in &[
+        ("en/index.md", "# Welcome"),
+        ("en/guide.md", "# Guide"),
+        ("de/index.md", "# Willkommen"),
+    ] {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, source).unwrap();
+    }
+
+    run(Cli {
+        paths: vec![
+            dir.join("en/index.md"),
+            dir.join("en/guide.md"),
+            dir.join("de/index.md"),
+        ],
+        config: None,
+        format: "json".into(),
+        out_dir: Some(dir.join("out")),
+        watch: false,
+        search_index: None,
+        search_hierarchy: false,
+        search_positions: false,
+        locales: Some("en,de".into()),
+    })
+    .unwrap();
+
+    let index = fs::read_to_string(dir.join("out/de/index.json")).unwrap();
+    assert!(index.contains("\"localization\"") && index.contains("/en/index.json"));
+    let fallback = fs::read_to_string(dir.join("out/de/guide.json")).unwrap();
+    assert!(fallback.contains("Guide"));
+    assert!(fallback.contains("\"fallback\":\"/en/guide.json\""));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_unhandled_directives() {
+    use struckdown::codes::Code;
+
+    let source = "```{graphviz}\ndigraph { a -> b }\n```\n";
+    let rendered = render(&Pipeline::new(), source, "html", None, None).unwrap();
+    assert!(rendered.output.contains("directive-graphviz"));
+    assert_eq!(rendered.diagnostics.len(), 1);
+    assert_eq!(rendered.diagnostics[0].code, Some(Code::UnhandledDirective));
+    assert_eq!(rendered.diagnostics[0].location.as_ref().unwrap().line, 1);
+}
@@ -0,0 +1,185 @@
+//! Stable codes for errors and diagnostics.
+//!
+//! Every [`ErrorEvent`](crate::event::ErrorEvent) and
+//! [`DiagnosticEvent`](crate::event::DiagnosticEvent) emitted by the parser
+//! and the builtin processors carries a [`Code`].  Unlike the messages, which
+//! are meant for humans and can change, codes are stable.  Tools should
+//! match on them to suppress diagnostics or to link to documentation:
+//!
+//! ```
+//! use struckdown::codes::Code;
+//! use struckdown::diagnostics::collect_diagnostics;
+//! use struckdown::parser::parse;
+//!
+//! let source = "```{note}\n---\ntitle: [unclosed\n---\nBody\n```\n";
+//! let events: Vec<_> = parse(source, &Default::default()).collect();
+//! let diagnostics = collect_diagnostics(&events);
+//! assert_eq!(diagnostics[0].code, Some(Code::InvalidDirectiveFrontMatter));
+//! assert_eq!(Code::InvalidDirectiveFrontMatter.as_str(), "invalid-directive-front-matter");
+//! ```
+//!
+//! Codes are serialized as strings.  Codes not in the catalog (for instance
+//! from external processors) are kept as [`Code::Custom`].
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+macro_rules! codes {
+    ($($name:ident => $code:literal, $description:literal;)*) => {
+        /// A stable code identifying a kind of error or diagnostic.
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum Code {
+            $(#[doc = $description] $name,)*
+            /// A code that is not part of the catalog.
+            Custom(String),
+        }
+
+        impl Code {
+            /// Returns all codes of the catalog.
+            pub fn catalog() -> Vec<Code> {
+                vec![$(Code::$name,)*]
+            }
+
+            /// Returns the string form of the code.
+            pub fn as_str(&self) -> &str {
+                match *self {
+                    $(Code::$name => $code,)*
+                    Code::Custom(ref code) => code,
+                }
+            }
+
+            /// Returns the description of a code from the catalog.
+            pub fn description(&self) -> Option<&'static str> {
+                match *self {
+                    $(Code::$name => Some($description),)*
+                    Code::Custom(..) => None,
+                }
+            }
+
+            /// Looks up a code by its string form.
+            ///
+            /// Codes not in the catalog become [`Code::Custom`].
+            pub fn parse(code: &str) -> Code {
+                match code {
+                    $($code => Code::$name,)*
+                    other => Code::Custom(other.to_string()),
+                }
+            }
+        }
+    };
+}
+
+codes! {
+    InvalidDirectiveFrontMatter => "invalid-directive-front-matter",
+        "The front matter of a directive is not valid YAML.";
+    UnknownRole => "unknown-role",
+        "Interpreted text uses a role that is not registered.";
+    DirectiveNestingTooDeep => "directive-nesting-too-deep",
+        "Directives are nested deeper than allowed.";
+    DuplicateId => "duplicate-id",
+        "An id is used by more than one element.";
+    InputTooLarge => "input-too-large",
+        "The source exceeds the configured size limit.";
+    TooManyEvents => "too-many-events",
+        "The document exceeds the configured number of events.";
+    NestingTooDeep => "nesting-too-deep",
+        "The document exceeds the configured nesting depth.";
+    DirectiveTooLarge => "directive-too-large",
+        "The body of a directive exceeds the configured size limit.";
+    UnknownLayout => "unknown-layout",
+        "A document selects a layout that is not registered.";
+    IncludeFailure => "include-failure",
+        "An included file cannot be read.";
+    IncludeWithoutPath => "include-without-path",
+        "An include directive has no path.";
+    IncludeOutsideRoot => "include-outside-root",
+        "An included file is outside of the include root.";
+    IncludeCycle => "include-cycle",
+        "Includes include each other.";
+    IncludeNestingTooDeep => "include-nesting-too-deep",
+        "Includes are nested deeper than allowed.";
+    SandboxViolation => "sandbox-violation",
+        "A processor tried to do something the sandbox forbids.";
+    UnknownEquation => "unknown-equation",
+        "An equation reference points to an unknown label.";
+    InvalidDate => "invalid-date",
+        "A date is not in the `YYYY-MM-DD` format.";
+    ContentExpired => "content-expired",
+        "Timed content is past its end date.";
+    ContentScheduled => "content-scheduled",
+        "Timed content is before its start date.";
+    HighlightingLoadFailure => "highlighting-load-failure",
+        "Themes or syntax definitions for highlighting cannot be loaded.";
+    InvalidLinkPattern => "invalid-link-pattern",
+        "A link rewrite pattern is not a valid regular expression.";
+    InvalidMetadata => "invalid-metadata",
+        "A meta directive does not hold valid YAML.";
+    InvalidSlugPattern => "invalid-slug-pattern",
+        "A slug pattern is not a valid regular expression.";
+    MathRenderFailure => "math-render-failure",
+        "A formula cannot be rendered.";
+    QuizWithoutOptions => "quiz-without-options",
+        "A quiz has no answer options.";
+    InvalidCodeSource => "invalid-code-source",
+        "The source of a code block is not a valid reference.";
+    CodeSourceFetchFailure => "code-source-fetch-failure",
+        "The source of a code block cannot be fetched.";
+    GlossaryLoadFailure => "glossary-load-failure",
+        "A glossary file cannot be loaded.";
+    ExternalProcessorFailure => "external-processor-failure",
+        "An external processor failed or violated the protocol.";
+    WasmProcessorFailure => "wasm-processor-failure",
+        "A wasm processor failed.";
+    PluginFailure => "plugin-failure",
+        "A plugin failed.";
+    SocialCardFailure => "social-card-failure",
+        "A social card cannot be rendered.";
+    AmbiguousReference => "ambiguous-reference",
+        "A cross-reference matches more than one target.";
+    UnresolvedReference => "unresolved-reference",
+        "A cross-reference matches no target.";
+    UnhandledDirective => "unhandled-directive",
+        "A directive reached a renderer without being handled by a processor.";
+    LossyConversion => "lossy-conversion",
+        "A renderer cannot represent an element faithfully.";
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Code {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Code {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Code, D::Error> {
+        Ok(Code::parse(&String::deserialize(deserializer)?))
+    }
+}
+
+#[test]
+fn test_codes() {
+    let catalog = Code::catalog();
+    for code in &catalog {
+        assert_eq!(&Code::parse(code.as_str()), code);
+        assert!(code.description().is_some());
+        let value = serde_json::to_value(code).unwrap();
+        assert_eq!(serde_json::from_value::<Code>(value).unwrap(), *code);
+    }
+    let mut strings: Vec<_> = catalog.iter().map(|x| x.as_str()).collect();
+    strings.sort_unstable();
+    strings.dedup();
+    assert_eq!(strings.len(), catalog.len());
+
+    let custom: Code = serde_json::from_str("\"my-processor-failure\"").unwrap();
+    assert_eq!(custom, Code::Custom("my-processor-failure".into()));
+    assert_eq!(
+        serde_json::to_string(&custom).unwrap(),
+        "\"my-processor-failure\""
+    );
+}
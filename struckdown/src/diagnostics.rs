@@ -0,0 +1,140 @@
+//! Collects problems reported in event streams.
+//!
+//! The parser and processors report problems with the source in the event
+//! stream itself: [`DiagnosticEvent`]s are not rendered and carry warnings
+//! such as unknown roles or directive front matter that fails to parse,
+//! [`ErrorEvent`]s are rendered in place of the content that failed (like a
+//! missing include).  [`collect_diagnostics`] gathers both with the
+//! location of the source they point at so that tools can report them:
+//!
+//! ```
+//! use struckdown::diagnostics::collect_diagnostics;
+//! use struckdown::event::Severity;
+//! use struckdown::parser::parse;
+//!
+//! let source = "Text\n\n```{note}\n---\ntitle: [unclosed\n---\nBody\n```\n";
+//! let events: Vec<_> = parse(source, &Default::default()).collect();
+//! let diagnostics = collect_diagnostics(&events);
+//! assert_eq!(diagnostics[0].severity, Severity::Warning);
+//! assert_eq!(diagnostics[0].message, "Invalid directive front matter");
+//! assert_eq!(diagnostics[0].location.as_ref().unwrap().line, 6);
+//! ```
+//!
+//! Diagnostics carry the stable [`Code`] of the problem if it is known.
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, DiagnosticEvent, ErrorEvent, Event, Location, Severity};
+
+/// A problem with the source of a document.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Diagnostic {
+    /// How severe the problem is.
+    pub severity: Severity,
+    /// The code of the problem.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Code>,
+    /// A short description of the problem.
+    pub message: String,
+    /// Details on the problem.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The location of the problem in the source.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic from a diagnostic or error event.
+    ///
+    /// Error events are reported with the severity `error`.
+    pub fn from_event(annotated_event: &AnnotatedEvent<'_>) -> Option<Diagnostic> {
+        let (severity, message, description, code) = match annotated_event.event {
+            Event::Diagnostic(DiagnosticEvent {
+                severity,
+                ref message,
+                ref description,
+                ref code,
+            }) => (severity, message, description, code),
+            Event::Error(ErrorEvent {
+                ref title,
+                ref description,
+                ref code,
+            }) => (Severity::Error, title, description, code),
+            _ => return None,
+        };
+        Some(Diagnostic {
+            severity,
+            code: code.clone(),
+            message: message.as_str().to_string(),
+            description: description.as_ref().map(|x| x.as_str().to_string()),
+            location: annotated_event.location.clone(),
+        })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref location) = self.location {
+            write!(f, "{}:{}: ", location.line, location.column)?;
+        }
+        write!(f, "{}", self.severity)?;
+        if let Some(ref code) = self.code {
+            write!(f, "[{}]", code)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(ref description) = self.description {
+            write!(f, ": {}", description)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the diagnostics and errors of an event stream in stream order.
+pub fn collect_diagnostics<'a, 'data: 'a, I>(iter: I) -> Vec<Diagnostic>
+where
+    I: IntoIterator<Item = &'a AnnotatedEvent<'data>>,
+{
+    iter.into_iter()
+        .filter_map(Diagnostic::from_event)
+        .collect()
+}
+
+#[test]
+fn test_collect_diagnostics() {
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+    use crate::processors::{Include, RoleProcessor, UnknownRolePolicy};
+
+    let source = "\
+Press {kbd}`Ctrl+C`.
+
+```{include} missing.md
+```
+
+> ```{note}
+> ---
+> title: Note
+> tags: [one, two
+> ---
+> Body
+> ```
+";
+    let mut roles = RoleProcessor::new();
+    roles.set_unknown_role_policy(UnknownRolePolicy::Warn);
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(roles);
+    pipeline.add_processor(Include::default());
+    let events: Vec<_> = pipeline.process(source).collect();
+    let diagnostics = collect_diagnostics(&events);
+    insta::assert_snapshot!(diagnostics
+        .iter()
+        .map(|x| format!("{}\n", x))
+        .collect::<String>());
+
+    let html = to_html(events.into_iter(), &Default::default());
+    assert!(html.contains("<p>Press <span class=\"role-kbd\">Ctrl+C</span>.</p>"));
+    assert!(!html.contains("Invalid directive front matter"));
+}
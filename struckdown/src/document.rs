@@ -0,0 +1,355 @@
+//! Ties an event stream to the source it was parsed from.
+//!
+//! Locations of events are byte ranges into the source document.  A
+//! [`Document`] keeps the source next to the events so that the exact text
+//! of an event can be looked up, for instance to show the offending snippet
+//! in a diagnostic.
+//!
+//! The document also gives access to the [`FrontMatter`] with typed getters
+//! for the common keys:
+//!
+//! ```
+//! use struckdown::document::{Date, Document};
+//!
+//! let source = "---\ntitle: Hello\ntags: [rust, docs]\ndate: 2021-03-14\n---\nText";
+//! let document = Document::parse(source, &Default::default());
+//! let front_matter = document.front_matter().unwrap();
+//! assert_eq!(front_matter.title(), Some("Hello"));
+//! assert_eq!(front_matter.tags(), vec!["rust", "docs"]);
+//! assert_eq!(front_matter.date(), Some(Date::new(2021, 3, 14)));
+//! assert_eq!(document.iter().count(), 4);
+//! ```
+use std::fmt;
+use std::io::{self, Read};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, DocumentStartEvent, Event, Location};
+use crate::parser::{parse, ParserOptions};
+use crate::value::{from_value, Value};
+
+/// Reads the source of a document from a file.
+///
+/// The path `-` reads from stdin instead.
+pub fn read_source<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let path = path.as_ref();
+    if path.as_os_str() == "-" {
+        let mut rv = String::new();
+        io::stdin().read_to_string(&mut rv)?;
+        Ok(rv)
+    } else {
+        std::fs::read_to_string(path)
+    }
+}
+
+/// A calendar date as used in front matter (`YYYY-MM-DD`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    /// The year.
+    pub year: i32,
+    /// The month (1 to 12).
+    pub month: u32,
+    /// The day of the month (1 to 31).
+    pub day: u32,
+}
+
+impl Date {
+    /// Creates a date.
+    pub fn new(year: i32, month: u32, day: u32) -> Date {
+        Date { year, month, day }
+    }
+
+    /// Parses a `YYYY-MM-DD` date.
+    ///
+    /// A time following the date (`2021-03-14T12:00:00Z` or
+    /// `2021-03-14 12:00`) is ignored.
+    pub fn parse(s: &str) -> Option<Date> {
+        let s = s.trim();
+        let date = s.get(..10)?;
+        if !s[10..].is_empty() && !s[10..].starts_with(&['T', ' '][..]) {
+            return None;
+        }
+        let mut parts = date.split('-');
+        let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+        if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+            return None;
+        }
+        let date = Date::new(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+        if (1..=12).contains(&date.month) && (1..=date.days_in_month()).contains(&date.day) {
+            Some(date)
+        } else {
+            None
+        }
+    }
+
+    fn days_in_month(&self) -> u32 {
+        match self.month {
+            2 if self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// The front matter of a document.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct FrontMatter {
+    value: Value,
+}
+
+impl FrontMatter {
+    /// Wraps a front matter value.
+    pub fn new(value: Value) -> FrontMatter {
+        FrontMatter { value }
+    }
+
+    /// Returns the front matter as value.
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// Returns the value of a key.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.value.get(key)
+    }
+
+    /// Returns the `title` key.
+    pub fn title(&self) -> Option<&str> {
+        self.get("title")?.as_str()
+    }
+
+    /// Returns the `tags` key.
+    ///
+    /// The key can hold a single string or a list of strings.
+    pub fn tags(&self) -> Vec<&str> {
+        match self.get("tags") {
+            Some(Value::String(tag)) => vec![tag.as_str()],
+            Some(Value::Array(tags)) => tags.iter().filter_map(|x| x.as_str()).collect(),
+            _ => vec![],
+        }
+    }
+
+    /// Returns the `date` key if it holds a valid date.
+    pub fn date(&self) -> Option<Date> {
+        Date::parse(self.get("date")?.as_str()?)
+    }
+
+    /// Deserializes the front matter into a custom type.
+    pub fn to_typed<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        from_value(self.value.clone())
+    }
+}
+
+/// A parsed document together with its source.
+#[derive(Debug, Clone)]
+pub struct Document<'data> {
+    source: &'data str,
+    events: Vec<AnnotatedEvent<'data>>,
+    front_matter: Option<FrontMatter>,
+}
+
+fn find_front_matter(events: &[AnnotatedEvent<'_>]) -> Option<FrontMatter> {
+    events.iter().find_map(|x| match x.event {
+        Event::DocumentStart(DocumentStartEvent {
+            front_matter: Some(ref front_matter),
+        }) => Some(FrontMatter::new(front_matter.clone())),
+        _ => None,
+    })
+}
+
+impl<'data> Document<'data> {
+    /// Parses a document.
+    pub fn parse(source: &'data str, options: &ParserOptions) -> Document<'data> {
+        Document::from_events(source, parse(source, options))
+    }
+
+    /// Creates a document from events that were parsed from `source`.
+    ///
+    /// The events can already have been processed.  Processors keep the
+    /// locations of the events they pass through but synthetic events
+    /// usually have none.
+    pub fn from_events<I: IntoIterator<Item = AnnotatedEvent<'data>>>(
+        source: &'data str,
+        events: I,
+    ) -> Document<'data> {
+        let events: Vec<_> = events.into_iter().collect();
+        Document {
+            source,
+            front_matter: find_front_matter(&events),
+            events,
+        }
+    }
+
+    /// Returns the source of the document.
+    pub fn source(&self) -> &'data str {
+        self.source
+    }
+
+    /// Returns the events of the document.
+    pub fn events(&self) -> &[AnnotatedEvent<'data>] {
+        &self.events
+    }
+
+    /// Iterates over the events of the document.
+    pub fn iter(&self) -> std::slice::Iter<'_, AnnotatedEvent<'data>> {
+        self.events.iter()
+    }
+
+    /// Returns the front matter of the document.
+    ///
+    /// This is the front matter the document was created with, edits of
+    /// the events do not change it.
+    pub fn front_matter(&self) -> Option<&FrontMatter> {
+        self.front_matter.as_ref()
+    }
+
+    /// Returns the events of the document for editing.
+    ///
+    /// See [`edit`](crate::edit) for helpers performing common edits.
+    pub fn events_mut(&mut self) -> &mut Vec<AnnotatedEvent<'data>> {
+        &mut self.events
+    }
+
+    /// Converts the document into its events.
+    pub fn into_events(self) -> Vec<AnnotatedEvent<'data>> {
+        self.events
+    }
+
+    /// Returns the source text covered by a location.
+    ///
+    /// Returns `None` if the location is not within the source.
+    pub fn source_at(&self, location: &Location) -> Option<&'data str> {
+        self.source
+            .get(location.offset..location.offset.checked_add(location.len)?)
+    }
+
+    /// Returns the source text an event was created from.
+    ///
+    /// Returns `None` for events without location information.
+    pub fn source_for(&self, annotated_event: &AnnotatedEvent<'_>) -> Option<&'data str> {
+        self.source_at(annotated_event.location.as_ref()?)
+    }
+
+    /// Returns the full source lines an event was created from.
+    ///
+    /// This is useful for diagnostics that want to show the context around
+    /// a snippet.
+    pub fn lines_for(&self, annotated_event: &AnnotatedEvent<'_>) -> Option<&'data str> {
+        let location = annotated_event.location.as_ref()?;
+        self.source_at(location)?;
+        let start = self.source[..location.offset]
+            .rfind('\n')
+            .map_or(0, |x| x + 1);
+        let end = location.offset + location.len;
+        let end = match self.source[end..].find('\n') {
+            Some(nl) if !self.source[..end].ends_with('\n') => end + nl,
+            _ => end,
+        };
+        Some(self.source[start..end].trim_end_matches(&['\r', '\n'][..]))
+    }
+}
+
+impl<'data> IntoIterator for Document<'data> {
+    type Item = AnnotatedEvent<'data>;
+    type IntoIter = std::vec::IntoIter<AnnotatedEvent<'data>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.events.into_iter()
+    }
+}
+
+impl<'a, 'data> IntoIterator for &'a Document<'data> {
+    type Item = &'a AnnotatedEvent<'data>;
+    type IntoIter = std::slice::Iter<'a, AnnotatedEvent<'data>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.events.iter()
+    }
+}
+
+#[test]
+fn test_source_for() {
+    use crate::event::{Event, StartTagEvent, Tag};
+
+    let source = "---\ntitle: Test\n---\n\n# Hello {#hello}\n\nSome *emphasized* text.\n";
+    let document = Document::parse(source, &Default::default());
+    let snippets: Vec<_> = document
+        .events()
+        .iter()
+        .filter(|x| match x.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => tag != Tag::Paragraph,
+            _ => false,
+        })
+        .map(|x| (document.source_for(x), document.lines_for(x)))
+        .collect();
+    insta::assert_debug_snapshot!(snippets);
+}
+
+#[test]
+fn test_front_matter() {
+    use crate::html::to_html;
+
+    #[derive(Deserialize)]
+    struct Page {
+        title: String,
+        draft: bool,
+    }
+
+    let source =
+        "---\ntitle: Hello\ntags: single\ndate: 2021-03-14T10:00:00Z\ndraft: true\n---\n# Body\n";
+    let document = Document::parse(source, &Default::default());
+    let front_matter = document.front_matter().unwrap();
+    assert_eq!(front_matter.title(), Some("Hello"));
+    assert_eq!(front_matter.tags(), vec!["single"]);
+    assert_eq!(
+        front_matter.date().map(|x| x.to_string()).as_deref(),
+        Some("2021-03-14")
+    );
+    let page: Page = front_matter.to_typed().unwrap();
+    assert_eq!((page.title.as_str(), page.draft), ("Hello", true));
+    assert_eq!(
+        to_html(document.into_iter(), &Default::default()),
+        "<h1>Body</h1>\n"
+    );
+
+    let document = Document::parse("---\ndate: 2021-13-01\n---\n", &Default::default());
+    let front_matter = document.front_matter().unwrap();
+    assert_eq!((front_matter.title(), front_matter.date()), (None, None));
+    assert!(front_matter.tags().is_empty());
+    assert!(Document::parse("No front matter", &Default::default())
+        .front_matter()
+        .is_none());
+
+    assert_eq!(
+        Date::parse("2021-03-14 12:00"),
+        Some(Date::new(2021, 3, 14))
+    );
+    for valid in &["2020-02-29", "2000-02-29", "2021-04-30", "2021-12-31"] {
+        assert_eq!(
+            Date::parse(valid).map(|x| x.to_string()).as_deref(),
+            Some(*valid)
+        );
+    }
+    for invalid in &[
+        "2021-3-14",
+        "2021-03-14x",
+        "14.03.2021",
+        "2021-02-32",
+        "2021-02-29",
+        "1900-02-29",
+        "2021-02-30",
+        "2021-04-31",
+    ] {
+        assert_eq!(Date::parse(invalid), None);
+    }
+}
@@ -0,0 +1,260 @@
+//! Reports what the processors of a pipeline change.
+//!
+//! Before a changed pipeline configuration is rolled out it can be run in
+//! dry-run mode against the documents it will be applied to.
+//! [`Pipeline::dry_run`](crate::pipeline::Pipeline::dry_run) applies the
+//! processors one after another and records for each processor how many
+//! events it added, removed and modified, together with a few sample
+//! changes and their locations.  No output is produced:
+//!
+//! ```
+//! use struckdown::dry_run::DryRunReport;
+//! use struckdown::pipeline::Pipeline;
+//!
+//! let pipeline: Pipeline = serde_yaml::from_str("
+//! processors:
+//!   - processor: auto_anchors
+//!   - processor: typography
+//! ").unwrap();
+//! let mut report = DryRunReport::new(3);
+//! pipeline.dry_run("index.md", "# Hello\n\n(c) 2021", &mut report);
+//! assert_eq!(report.processors[0].processor, "AutoAnchors");
+//! assert_eq!(report.processors[0].modified, 1);
+//! assert_eq!(report.processors[1].modified, 1);
+//! ```
+//!
+//! The same report can be passed for all documents of a corpus.
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Location};
+use crate::value::{to_value, Value};
+
+// larger differences are reported as a removal and an addition of all
+// events in between the common prefix and suffix
+const MAX_DIFF_CELLS: usize = 1 << 22;
+
+/// The kind of a change.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// An event was added.
+    Added,
+    /// An event was removed.
+    Removed,
+    /// An event was replaced by another.
+    Modified,
+}
+
+/// A change a processor made to a stream.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Change {
+    /// The document the change was made in.
+    pub document: String,
+    /// The kind of the change.
+    pub kind: ChangeKind,
+    /// The location of the changed event.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+    /// The event before the change.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    /// The event after the change.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// The changes made by a single processor.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProcessorReport {
+    /// The name of the processor.
+    pub processor: String,
+    /// The number of documents the processor changed.
+    pub changed_documents: usize,
+    /// The number of added events.
+    pub added: usize,
+    /// The number of removed events.
+    pub removed: usize,
+    /// The number of modified events.
+    pub modified: usize,
+    /// The first changes made by the processor.
+    pub samples: Vec<Change>,
+}
+
+/// The changes made by the processors of a pipeline.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DryRunReport {
+    /// The maximum number of sample changes per processor.
+    #[serde(skip)]
+    pub max_samples: usize,
+    /// The number of documents processed.
+    pub documents: usize,
+    /// The reports of the processors in pipeline order.
+    pub processors: Vec<ProcessorReport>,
+}
+
+impl DryRunReport {
+    /// Creates an empty report keeping up to `max_samples` samples per
+    /// processor.
+    pub fn new(max_samples: usize) -> DryRunReport {
+        DryRunReport {
+            max_samples,
+            documents: 0,
+            processors: vec![],
+        }
+    }
+
+    /// Returns `true` if no processor changed anything.
+    pub fn is_unchanged(&self) -> bool {
+        self.processors.iter().all(|x| x.changed_documents == 0)
+    }
+
+    /// Records the changes a processor made to a document.
+    ///
+    /// `stage` is the position of the processor in the pipeline.
+    pub fn record(
+        &mut self,
+        stage: usize,
+        processor: &str,
+        document: &str,
+        before: &[AnnotatedEvent<'_>],
+        after: &[AnnotatedEvent<'_>],
+    ) {
+        while self.processors.len() <= stage {
+            self.processors.push(ProcessorReport {
+                processor: String::new(),
+                changed_documents: 0,
+                added: 0,
+                removed: 0,
+                modified: 0,
+                samples: vec![],
+            });
+        }
+        let max_samples = self.max_samples;
+        let report = &mut self.processors[stage];
+        report.processor = processor.to_string();
+        let changes = compare_events(before, after);
+        if !changes.is_empty() {
+            report.changed_documents += 1;
+        }
+        for (kind, old, new) in changes {
+            match kind {
+                ChangeKind::Added => report.added += 1,
+                ChangeKind::Removed => report.removed += 1,
+                ChangeKind::Modified => report.modified += 1,
+            }
+            if report.samples.len() < max_samples {
+                let old = old.map(|x| &before[x]);
+                let new = new.map(|x| &after[x]);
+                report.samples.push(Change {
+                    document: document.to_string(),
+                    kind,
+                    location: old
+                        .and_then(|x| x.location.clone())
+                        .or_else(|| new.and_then(|x| x.location.clone())),
+                    before: old.and_then(|x| to_value(&x.event).ok()),
+                    after: new.and_then(|x| to_value(&x.event).ok()),
+                });
+            }
+        }
+    }
+}
+
+/// Compares two streams by their events ignoring locations.
+///
+/// Returns the changes with the indexes of the events in `before` and
+/// `after`.  Removals directly followed by additions are paired up as
+/// modifications.
+fn compare_events(
+    before: &[AnnotatedEvent<'_>],
+    after: &[AnnotatedEvent<'_>],
+) -> Vec<(ChangeKind, Option<usize>, Option<usize>)> {
+    let key = |x: &AnnotatedEvent<'_>| serde_json::to_string(&x.event).unwrap_or_default();
+    let old: Vec<String> = before.iter().map(key).collect();
+    let new: Vec<String> = after.iter().map(key).collect();
+
+    let prefix = old
+        .iter()
+        .zip(new.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    // removed and added events in order, `None` separates runs of changes
+    let mut ops: Vec<Option<(Option<usize>, Option<usize>)>> = vec![];
+    if old_mid.len().saturating_mul(new_mid.len()) > MAX_DIFF_CELLS {
+        ops.extend((0..old_mid.len()).map(|i| Some((Some(prefix + i), None))));
+        ops.extend((0..new_mid.len()).map(|j| Some((None, Some(prefix + j)))));
+    } else {
+        let (n, m) = (old_mid.len(), new_mid.len());
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * (m + 1) + j] = if old_mid[i] == new_mid[j] {
+                    lcs[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_mid[i] == new_mid[j] {
+                ops.push(None);
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1]) {
+                ops.push(Some((Some(prefix + i), None)));
+                i += 1;
+            } else {
+                ops.push(Some((None, Some(prefix + j))));
+                j += 1;
+            }
+        }
+    }
+
+    let mut rv = vec![];
+    for run in ops.split(|x| x.is_none()) {
+        let removed: Vec<usize> = run.iter().filter_map(|x| x.and_then(|x| x.0)).collect();
+        let added: Vec<usize> = run.iter().filter_map(|x| x.and_then(|x| x.1)).collect();
+        for idx in 0..removed.len().max(added.len()) {
+            rv.push(match (removed.get(idx), added.get(idx)) {
+                (Some(&old), Some(&new)) => (ChangeKind::Modified, Some(old), Some(new)),
+                (Some(&old), None) => (ChangeKind::Removed, Some(old), None),
+                (None, new) => (ChangeKind::Added, None, new.copied()),
+            });
+        }
+    }
+    rv
+}
+
+#[test]
+fn test_dry_run() {
+    use crate::pipeline::Pipeline;
+
+    let pipeline: Pipeline = serde_yaml::from_str(
+        "
+processors:
+  - processor: auto_anchors
+  - processor: typography
+  - processor: code_container
+",
+    )
+    .unwrap();
+    let mut report = DryRunReport::new(2);
+    pipeline.dry_run("a.md", "# Setup\n\nUse it (c) 2021.\n", &mut report);
+    pipeline.dry_run("b.md", "Plain text.\n\n```\ncode\n```\n", &mut report);
+    assert_eq!(report.documents, 2);
+    assert!(!report.is_unchanged());
+    insta::assert_yaml_snapshot!(&report);
+
+    let mut report = DryRunReport::new(2);
+    pipeline.dry_run("c.md", "Nothing to do.", &mut report);
+    assert!(report.is_unchanged());
+}
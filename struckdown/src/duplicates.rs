@@ -0,0 +1,223 @@
+//! Detects duplicated sections across documents.
+//!
+//! Copy-pasted content drifts apart over time.  The [`DuplicateFinder`]
+//! splits documents into sections at headings, fingerprints the words of
+//! every section with overlapping word shingles and reports pairs of
+//! sections that are similar enough to be consolidated into a shared
+//! include or snippet.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, Location, StartTagEvent};
+use crate::plain::to_plain_text;
+
+/// Configures the [`DuplicateFinder`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct DuplicateOptions {
+    /// The minimum similarity (between 0 and 1) to report.
+    pub threshold: f64,
+    /// Sections with fewer words are ignored.
+    pub min_words: usize,
+    /// The number of words per shingle.
+    pub shingle_size: usize,
+}
+
+impl Default for DuplicateOptions {
+    fn default() -> DuplicateOptions {
+        DuplicateOptions {
+            threshold: 0.8,
+            min_words: 20,
+            shingle_size: 4,
+        }
+    }
+}
+
+/// Refers to a section of a document.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SectionRef {
+    /// The document the section is in.
+    pub document: String,
+    /// The text of the heading starting the section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The location of the start of the section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+/// A pair of near-duplicate sections.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DuplicateSection {
+    /// The section seen first.
+    pub first: SectionRef,
+    /// The section duplicating it.
+    pub second: SectionRef,
+    /// The similarity of the two sections (between 0 and 1).
+    pub similarity: f64,
+}
+
+#[derive(Debug)]
+struct Section {
+    section: SectionRef,
+    words: Vec<String>,
+    fingerprint: HashSet<u64>,
+}
+
+/// Collects sections of documents and finds duplicates among them.
+#[derive(Debug, Default)]
+pub struct DuplicateFinder {
+    options: DuplicateOptions,
+    sections: Vec<Section>,
+}
+
+impl DuplicateFinder {
+    /// Creates a new finder.
+    pub fn new(options: DuplicateOptions) -> DuplicateFinder {
+        DuplicateFinder {
+            options,
+            sections: vec![],
+        }
+    }
+
+    fn push_section(&mut self, mut section: Section) {
+        if section.words.len() < self.options.min_words {
+            return;
+        }
+        let size = self.options.shingle_size.max(1);
+        section.fingerprint = section
+            .words
+            .windows(size.min(section.words.len()))
+            .map(|shingle| {
+                let mut hasher = DefaultHasher::new();
+                shingle.hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect();
+        self.sections.push(section);
+    }
+
+    /// Adds a document to the set of analyzed documents.
+    ///
+    /// The document is split into sections at every heading.  Content before
+    /// the first heading forms a section without a title.
+    pub fn add_document<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(
+        &mut self,
+        path: &str,
+        iter: I,
+    ) {
+        let new_section = |title, location| Section {
+            section: SectionRef {
+                document: path.to_string(),
+                title,
+                location,
+            },
+            words: vec![],
+            fingerprint: HashSet::new(),
+        };
+        let mut current = new_section(None, None);
+        let mut heading: Option<(usize, Option<Location>, Vec<AnnotatedEvent<'data>>)> = None;
+        let mut depth = 0;
+
+        for annotated_event in iter {
+            match annotated_event.event {
+                Event::StartTag(StartTagEvent { tag, .. }) => {
+                    depth += 1;
+                    if heading.is_none() && tag.header_level().is_some() {
+                        heading = Some((depth, annotated_event.location, vec![]));
+                        continue;
+                    }
+                }
+                Event::EndTag(_) => {
+                    depth -= 1;
+                    if heading.as_ref().map(|x| x.0) == Some(depth + 1) {
+                        if let Some((_, location, events)) = heading.take() {
+                            let title = to_plain_text(events.iter()).as_str().trim().to_string();
+                            let previous =
+                                std::mem::replace(&mut current, new_section(Some(title), location));
+                            self.push_section(previous);
+                            continue;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            match heading {
+                Some((_, _, ref mut events)) => events.push(annotated_event),
+                None => {
+                    if let Some(text) = annotated_event.event.raw_text() {
+                        current.words.extend(
+                            text.as_str()
+                                .split(|c: char| !c.is_alphanumeric())
+                                .filter(|x| !x.is_empty())
+                                .map(|x| x.to_lowercase()),
+                        );
+                    }
+                }
+            }
+        }
+        self.push_section(current);
+    }
+
+    /// Returns all pairs of sections at or above the similarity threshold.
+    ///
+    /// The result is sorted by descending similarity.
+    pub fn find(&self) -> Vec<DuplicateSection> {
+        let mut rv = vec![];
+        for (idx, a) in self.sections.iter().enumerate() {
+            for b in &self.sections[idx + 1..] {
+                let shared = a.fingerprint.intersection(&b.fingerprint).count();
+                let total = a.fingerprint.len() + b.fingerprint.len() - shared;
+                if total == 0 {
+                    continue;
+                }
+                let similarity = shared as f64 / total as f64;
+                if similarity >= self.options.threshold {
+                    rv.push(DuplicateSection {
+                        first: a.section.clone(),
+                        second: b.section.clone(),
+                        similarity: (similarity * 100.0).round() / 100.0,
+                    });
+                }
+            }
+        }
+        rv.sort_by(|a, b| b.similarity.partial_cmp(&a.similarity).unwrap());
+        rv
+    }
+}
+
+#[test]
+fn test_find_duplicates() {
+    use crate::parser::parse;
+
+    let install = "Install the package with cargo and make sure that a recent \
+                   Rust compiler is available on your system before continuing \
+                   with the setup of the project.";
+    let mut finder = DuplicateFinder::new(DuplicateOptions {
+        threshold: 0.5,
+        ..DuplicateOptions::default()
+    });
+    finder.add_document(
+        "intro.md",
+        parse(
+            &format!("# Intro\n\nWelcome.\n\n## Installation\n\n{}\n", install),
+            &Default::default(),
+        ),
+    );
+    finder.add_document(
+        "guide.md",
+        parse(
+            &format!(
+                "# Guide\n\n## Setup\n\n{} Then run the tests.\n\n## Usage\n\n{}\n",
+                install,
+                "Usage is explained in a different chapter of this guide which covers \
+                 all of the available command line flags and configuration files."
+            ),
+            &Default::default(),
+        ),
+    );
+    insta::assert_yaml_snapshot!(finder.find());
+}
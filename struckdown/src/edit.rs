@@ -0,0 +1,259 @@
+//! Helpers for editing event streams.
+//!
+//! Tools that change documents (link checkers fixing targets, linters
+//! adding classes, …) work on a buffered stream.  The functions in this
+//! module perform the common edits on the event at an index and take care
+//! of keeping start and end tags paired and of merging attributes:
+//!
+//! ```
+//! use struckdown::edit::{add_class, set_link_target};
+//! use struckdown::event::{Event, StartTagEvent, Tag};
+//! use struckdown::html::to_html;
+//! use struckdown::parser::parse;
+//!
+//! let mut events: Vec<_> = parse("See [the docs](/old/).", &Default::default()).collect();
+//! let link = events
+//!     .iter()
+//!     .position(|x| matches!(x.event, Event::StartTag(StartTagEvent { tag: Tag::Link, .. })))
+//!     .unwrap();
+//! assert!(set_link_target(&mut events, link, "/new/"));
+//! assert!(add_class(&mut events, link, "internal"));
+//! let html = to_html(events.into_iter(), &Default::default());
+//! assert!(html.contains(r#"href="&#x2f;new&#x2f;" class="internal">the docs</a>"#));
+//! ```
+//!
+//! Edits return `false` (or `None`) and leave the stream untouched if the
+//! event at the index does not support them.  Only [`add_class`],
+//! [`unwrap_node`] and [`remove_node`] add or remove events, which shifts
+//! the indexes of the events after the edited one.
+use crate::event::{AnnotatedEvent, Attrs, Event, ImageEvent, StartTagEvent, Str, Tag};
+
+/// Returns the index of the last event of the node starting at `idx`.
+///
+/// For a start tag this is the matching end tag, other events are nodes of
+/// their own.  Returns `None` for end tags and unbalanced start tags.
+pub fn node_end(events: &[AnnotatedEvent<'_>], idx: usize) -> Option<usize> {
+    match events.get(idx)?.event {
+        Event::StartTag(..) => {}
+        Event::EndTag(..) => return None,
+        _ => return Some(idx),
+    }
+    let mut depth = 0;
+    for (offset, annotated_event) in events[idx..].iter().enumerate() {
+        match annotated_event.event {
+            Event::StartTag(..) => depth += 1,
+            Event::EndTag(..) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx + offset);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Sets the target of a link or image.
+pub fn set_link_target<'data, S: Into<Str<'data>>>(
+    events: &mut [AnnotatedEvent<'data>],
+    idx: usize,
+    target: S,
+) -> bool {
+    match events.get_mut(idx).map(|x| &mut x.event) {
+        Some(Event::StartTag(StartTagEvent {
+            tag: Tag::Link,
+            attrs,
+        })) => attrs.target = Some(target.into()),
+        Some(Event::Image(image)) => image.target = target.into(),
+        _ => return false,
+    }
+    true
+}
+
+/// Sets or removes the title of a link or image.
+pub fn set_link_title<'data, S: Into<Str<'data>>>(
+    events: &mut [AnnotatedEvent<'data>],
+    idx: usize,
+    title: Option<S>,
+) -> bool {
+    match events.get_mut(idx).map(|x| &mut x.event) {
+        Some(Event::StartTag(StartTagEvent {
+            tag: Tag::Link,
+            attrs,
+        })) => attrs.title = title.map(Into::into),
+        Some(Event::Image(image)) => image.title = title.map(Into::into),
+        _ => return false,
+    }
+    true
+}
+
+/// Sets or removes the alt text of an image.
+pub fn set_image_alt<'data, S: Into<Str<'data>>>(
+    events: &mut [AnnotatedEvent<'data>],
+    idx: usize,
+    alt: Option<S>,
+) -> bool {
+    match events.get_mut(idx).map(|x| &mut x.event) {
+        Some(Event::Image(ImageEvent { alt: image_alt, .. })) => {
+            *image_alt = alt.map(Into::into);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Merges attributes into a start tag (see [`Attrs::merge`]).
+pub fn merge_attrs<'data>(
+    events: &mut [AnnotatedEvent<'data>],
+    idx: usize,
+    attrs: Attrs<'data>,
+) -> bool {
+    match events.get_mut(idx).map(|x| &mut x.event) {
+        Some(Event::StartTag(StartTagEvent {
+            attrs: ref mut existing,
+            ..
+        })) => {
+            existing.merge(attrs);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Returns `true` if the event at `idx` sits within inline content.
+fn in_inline_context(events: &[AnnotatedEvent<'_>], idx: usize) -> bool {
+    let mut depth = 0;
+    for annotated_event in events[..idx].iter().rev() {
+        match annotated_event.event {
+            Event::EndTag(..) => depth += 1,
+            Event::StartTag(StartTagEvent { tag, .. }) if depth == 0 => {
+                return !matches!(
+                    tag,
+                    Tag::BlockQuote
+                        | Tag::OrderedList
+                        | Tag::UnorderedList
+                        | Tag::FootnoteDefinition
+                        | Tag::DefinitionList
+                        | Tag::DefinitionDetails
+                        | Tag::Figure
+                        | Tag::Container
+                        | Tag::Details
+                        | Tag::Sidenote
+                );
+            }
+            Event::StartTag(..) => depth -= 1,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn class_attrs(class: &str) -> Attrs<'static> {
+    let mut attrs = Attrs::default();
+    attrs.add_class(class);
+    attrs
+}
+
+/// Adds whitespace separated classes to a node.
+///
+/// Classes are added to the attributes of start tags.  Other nodes are
+/// wrapped in a [`Tag::Span`] (inline content) or a [`Tag::Container`]
+/// (blocks) carrying the classes.  Wrapping is not supported for end tags
+/// and events outside of the content such as the document start.
+pub fn add_class(events: &mut Vec<AnnotatedEvent<'_>>, idx: usize, class: &str) -> bool {
+    let tag = match events.get(idx).map(|x| &x.event) {
+        Some(Event::StartTag(..)) => return merge_attrs(events, idx, class_attrs(class)),
+        Some(Event::CodeBlock(..))
+        | Some(Event::Directive(..))
+        | Some(Event::MathBlock(..))
+        | Some(Event::Rule) => Tag::Container,
+        Some(Event::RawHtml(..)) if !in_inline_context(events, idx) => Tag::Container,
+        Some(Event::Text(..))
+        | Some(Event::InterpretedText(..))
+        | Some(Event::InlineCode(..))
+        | Some(Event::InlineMath(..))
+        | Some(Event::Image(..))
+        | Some(Event::RawHtml(..))
+        | Some(Event::FootnoteReference(..))
+        | Some(Event::Checkbox(..)) => Tag::Span,
+        _ => return false,
+    };
+    let location = events[idx].location.clone();
+    events.insert(
+        idx + 1,
+        AnnotatedEvent::new(tag.end_tag(), location.clone()),
+    );
+    events.insert(
+        idx,
+        AnnotatedEvent::new(tag.start_tag(class_attrs(class)), location),
+    );
+    true
+}
+
+/// Removes a start tag and its end tag but keeps the contents.
+///
+/// This turns a link into its text for instance.
+pub fn unwrap_node(events: &mut Vec<AnnotatedEvent<'_>>, idx: usize) -> bool {
+    match (events.get(idx).map(|x| &x.event), node_end(events, idx)) {
+        (Some(Event::StartTag(..)), Some(end)) => {
+            events.remove(end);
+            events.remove(idx);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Removes a node with all of its contents and returns the removed events.
+pub fn remove_node<'data>(
+    events: &mut Vec<AnnotatedEvent<'data>>,
+    idx: usize,
+) -> Option<Vec<AnnotatedEvent<'data>>> {
+    let end = node_end(events, idx)?;
+    Some(events.drain(idx..=end).collect())
+}
+
+#[test]
+fn test_edits() {
+    use crate::html::to_html;
+    use crate::parser::parse;
+
+    let source = "A [link](/a) with ![an image](a.png) and `code`.\n\n```\nblock\n```\n";
+    let mut events: Vec<_> = parse(source, &Default::default()).collect();
+    let find = |events: &[AnnotatedEvent<'_>], f: &dyn Fn(&Event<'_>) -> bool| {
+        events.iter().position(|x| f(&x.event)).unwrap()
+    };
+
+    let link = find(&events, &|x| {
+        matches!(x, Event::StartTag(StartTagEvent { tag: Tag::Link, .. }))
+    });
+    assert_eq!(node_end(&events, link), Some(link + 2));
+    assert!(set_link_target(&mut events, link, "/b"));
+    assert!(set_link_title(&mut events, link, Some("B")));
+    assert!(add_class(&mut events, link, "one two"));
+    assert!(add_class(&mut events, link, "two three"));
+    assert!(!set_image_alt(&mut events, link, Some("nope")));
+
+    let image = find(&events, &|x| matches!(x, Event::Image(..)));
+    assert!(set_image_alt(&mut events, image, Some("a new image")));
+    assert!(set_link_target(&mut events, image, "b.png"));
+    assert!(add_class(&mut events, image, "wide"));
+
+    let code = find(&events, &|x| matches!(x, Event::CodeBlock(..)));
+    assert!(add_class(&mut events, code, "example"));
+    let end = find(&events, &|x| matches!(x, Event::EndTag(..)));
+    assert!(!add_class(&mut events, end, "nope"));
+
+    insta::assert_snapshot!(to_html(events.clone().into_iter(), &Default::default()));
+
+    let link = find(&events, &|x| {
+        matches!(x, Event::StartTag(StartTagEvent { tag: Tag::Link, .. }))
+    });
+    assert!(unwrap_node(&mut events, link));
+    let code = find(&events, &|x| matches!(x, Event::InlineCode(..)));
+    assert_eq!(remove_node(&mut events, code).map(|x| x.len()), Some(1));
+    let html = to_html(events.into_iter(), &Default::default());
+    assert!(html.starts_with("<p>A link with <span class=\"wide\">"));
+    assert!(html.contains(" and .</p>"));
+}
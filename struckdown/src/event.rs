@@ -7,11 +7,12 @@ use std::fmt::{self, Debug, Display};
 use std::marker::PhantomData;
 
 use pulldown_cmark as cm;
-use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
-use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
 use serde::ser::SerializeTuple;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use crate::codes::Code;
 use crate::value::Value;
 
 /// An internal string type.
@@ -95,6 +96,17 @@ impl<'data> Str<'data> {
         &self.inner
     }
 
+    /// Converts the string into an owned string.
+    pub fn into_owned(self) -> Str<'static> {
+        match self.inner {
+            cm::CowStr::Borrowed(val) => Str::from(val.to_string()),
+            cm::CowStr::Boxed(val) => Str::from(val),
+            cm::CowStr::Inlined(val) => Str {
+                inner: cm::CowStr::Inlined(val),
+            },
+        }
+    }
+
     /// Creates a string from a cmark string.
     pub(crate) fn from_cm_str(value: cm::CowStr<'data>) -> Str<'data> {
         Str { inner: value }
@@ -153,6 +165,153 @@ pub struct Location {
     pub column: usize,
 }
 
+/// Maps locations within a directive body back into the source.
+///
+/// Directive bodies are parsed on their own, so the locations of their
+/// events point into the body.  The mapper moves them to where the body is
+/// in the source so that diagnostics point at the right lines:
+///
+/// ```
+/// use struckdown::event::{Event, LocationMapper};
+/// use struckdown::parser::parse;
+///
+/// let source = "# Title\n\n```{note}\nA *body*.\n```\n";
+/// for annotated_event in parse(source, &Default::default()) {
+///     if let Event::Directive(ref directive) = annotated_event.event {
+///         let mapper = LocationMapper::for_directive(directive).unwrap();
+///         let events: Vec<_> = parse(directive.body.as_str(), &Default::default())
+///             .map(|x| mapper.map_event(x))
+///             .collect();
+///         assert_eq!(events[1].location.as_ref().unwrap().line, 4);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LocationMapper {
+    base: Location,
+}
+
+impl LocationMapper {
+    /// Creates a mapper for a body at the given location.
+    pub fn new(base: Location) -> LocationMapper {
+        LocationMapper { base }
+    }
+
+    /// Creates a mapper for the body of a directive.
+    ///
+    /// Returns `None` if the location of the body is not known.
+    pub fn for_directive(directive: &DirectiveEvent<'_>) -> Option<LocationMapper> {
+        directive.body_location.clone().map(LocationMapper::new)
+    }
+
+    /// Maps a location relative to the body into the source.
+    pub fn map(&self, location: &Location) -> Location {
+        Location {
+            offset: self.base.offset + location.offset,
+            len: location.len,
+            line: self.base.line + location.line - 1,
+            column: if location.line == 1 {
+                self.base.column + location.column
+            } else {
+                location.column
+            },
+        }
+    }
+
+    /// Maps the locations of an event parsed from the body.
+    ///
+    /// This includes the body locations of nested directives as well as the
+    /// spans of ids and roles.
+    pub fn map_event<'data>(
+        &self,
+        mut annotated_event: AnnotatedEvent<'data>,
+    ) -> AnnotatedEvent<'data> {
+        annotated_event.location = annotated_event.location.as_ref().map(|x| self.map(x));
+        let span = match annotated_event.event {
+            Event::Directive(DirectiveEvent {
+                ref mut body_location,
+                ..
+            }) => body_location,
+            Event::StartTag(StartTagEvent { ref mut attrs, .. }) => &mut attrs.id_span,
+            Event::InterpretedText(InterpretedTextEvent {
+                ref mut role_span, ..
+            }) => role_span,
+            _ => return annotated_event,
+        };
+        *span = span.as_ref().map(|x| self.map(x));
+        annotated_event
+    }
+}
+
+/// Source trivia recorded by the lossless parse mode.
+///
+/// With [`lossless`](crate::parser::ParserOptions::lossless) enabled the
+/// parser records the syntax that the events do not capture.  The first
+/// event of every top level block carries the exact source of the block in
+/// `raw`, everything up to the next block in `trailing` and a `fingerprint`
+/// of the block's events as parsed.  The markdown renderer writes blocks
+/// whose events still match the fingerprint back as they were in the
+/// source.  The document start carries the front matter and what precedes
+/// the first block.
+///
+/// List items and fenced blocks at any depth record their `marker` and
+/// `fence` so that blocks which were changed are written in the style of
+/// the source.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Trivia<'data> {
+    /// The exact source of the block.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<Str<'data>>,
+    /// The source between the block and the next one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trailing: Option<Str<'data>>,
+    /// The fingerprint of the block's events (see [`fingerprint`]).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// The opening fence of a code block or directive (like `~~~~`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fence: Option<Str<'data>>,
+    /// The marker of a list item including the whitespace up to its
+    /// content (like `*   ` or `1) `).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marker: Option<Str<'data>>,
+}
+
+impl<'data> Trivia<'data> {
+    /// Converts the trivia into owned trivia.
+    pub fn into_owned(self) -> Trivia<'static> {
+        Trivia {
+            raw: self.raw.map(|x| x.into_owned()),
+            trailing: self.trailing.map(|x| x.into_owned()),
+            fingerprint: self.fingerprint,
+            fence: self.fence.map(|x| x.into_owned()),
+            marker: self.marker.map(|x| x.into_owned()),
+        }
+    }
+}
+
+/// Computes the fingerprint of a sequence of events.
+///
+/// The fingerprint only covers the events themselves, locations and trivia
+/// are ignored.  It is stable across processes so that it survives passing
+/// the stream through external processors.
+pub fn fingerprint<'a, 'data: 'a, I>(iter: I) -> String
+where
+    I: IntoIterator<Item = &'a AnnotatedEvent<'data>>,
+{
+    // 64 bit FNV-1a over the serialized events
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for annotated_event in iter {
+        let serialized = serde_json::to_vec(&annotated_event.event).unwrap_or_default();
+        for byte in serialized.into_iter().chain(Some(b'\n')) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
 /// Event with annotations.
 ///
 /// An annotated event is generally the same as an [`Event`] but it contains
@@ -164,6 +323,8 @@ pub struct AnnotatedEvent<'data> {
     pub event: Event<'data>,
     /// The optional location.
     pub location: Option<Location>,
+    /// The source trivia recorded in lossless mode.
+    pub trivia: Option<Box<Trivia<'data>>>,
 }
 
 impl<'data> AnnotatedEvent<'data> {
@@ -175,6 +336,21 @@ impl<'data> AnnotatedEvent<'data> {
         AnnotatedEvent {
             event: value.into(),
             location,
+            trivia: None,
+        }
+    }
+}
+
+impl<'data> AnnotatedEvent<'data> {
+    /// Converts the event into an owned event.
+    ///
+    /// This is useful when events are created from a temporary source
+    /// such as a directive body that is parsed during processing.
+    pub fn into_owned(self) -> AnnotatedEvent<'static> {
+        AnnotatedEvent {
+            event: self.event.into_owned(),
+            location: self.location,
+            trivia: self.trivia.map(|x| Box::new(x.into_owned())),
         }
     }
 }
@@ -190,7 +366,13 @@ impl<'data> Serialize for AnnotatedEvent<'data> {
     where
         S: Serializer,
     {
-        if let Some(ref location) = self.location {
+        if let Some(ref trivia) = self.trivia {
+            let mut s = serializer.serialize_tuple(3)?;
+            s.serialize_element(&self.event)?;
+            s.serialize_element(&self.location)?;
+            s.serialize_element(trivia)?;
+            s.end()
+        } else if let Some(ref location) = self.location {
             let mut s = serializer.serialize_tuple(2)?;
             s.serialize_element(&self.event)?;
             s.serialize_element(location)?;
@@ -215,12 +397,20 @@ impl<'de, 'data> Deserialize<'de> for AnnotatedEvent<'data> {
                 formatter.write_str("array or map")
             }
 
-            fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
             where
                 A: SeqAccess<'de>,
             {
-                Deserialize::deserialize(SeqAccessDeserializer::new(seq))
-                    .map(|(event, location): (Event, _)| AnnotatedEvent::new(event, location))
+                let event: Event = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let location = seq.next_element::<Option<Location>>()?.flatten();
+                let trivia = seq.next_element::<Option<Trivia>>()?.flatten();
+                Ok(AnnotatedEvent {
+                    event,
+                    location,
+                    trivia: trivia.map(Box::new),
+                })
             }
 
             fn visit_map<M>(self, map: M) -> Result<Self::Value, M::Error>
@@ -284,6 +474,12 @@ pub enum Tag {
     ListItem,
     /// Defines a footnote.
     FootnoteDefinition,
+    /// `<dl>` equivalent.
+    DefinitionList,
+    /// `<dt>` equivalent.
+    DefinitionTerm,
+    /// `<dd>` equivalent.
+    DefinitionDetails,
     /// `<table>` equivalent.
     Table,
     /// `<thead>` equivalent.
@@ -310,6 +506,24 @@ pub enum Tag {
     Container,
     /// `<span>` equivalent. Not used in syntax.
     Span,
+    /// `<details>` equivalent. Not used in syntax.
+    Details,
+    /// `<summary>` equivalent. Not used in syntax.
+    Summary,
+    /// Inline text hidden until revealed. Not used in syntax.
+    Spoiler,
+    /// `<abbr>` equivalent. Not used in syntax.
+    Abbreviation,
+    /// `<ins>` equivalent. Not used in syntax.
+    Insertion,
+    /// `<del>` equivalent. Not used in syntax.
+    Deletion,
+    /// Inline note rendered in the margin. Not used in syntax.
+    Sidenote,
+    /// `<figure>` equivalent. Not used in syntax.
+    Figure,
+    /// `<figcaption>` equivalent. Not used in syntax.
+    FigureCaption,
 }
 
 impl Tag {
@@ -330,6 +544,21 @@ impl Tag {
         }
     }
 
+    /// Returns the heading tag for a header level.
+    ///
+    /// This is the inverse of [`header_level`](Self::header_level).
+    pub fn from_header_level(level: usize) -> Option<Tag> {
+        match level {
+            1 => Some(Tag::Heading1),
+            2 => Some(Tag::Heading2),
+            3 => Some(Tag::Heading3),
+            4 => Some(Tag::Heading4),
+            5 => Some(Tag::Heading5),
+            6 => Some(Tag::Heading6),
+            _ => None,
+        }
+    }
+
     /// Creates a start tag event.
     pub fn start_tag(self, attrs: Attrs<'_>) -> StartTagEvent<'_> {
         StartTagEvent { tag: self, attrs }
@@ -351,6 +580,12 @@ pub struct Attrs<'data> {
     /// Holds the start for a list.  
     #[serde(skip_serializing_if = "Option::is_none")]
     pub start: Option<u32>,
+    /// Holds whether a list is tight.
+    ///
+    /// The items of tight lists hold their contents directly whereas the
+    /// contents of items in loose lists are wrapped in paragraphs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tight: Option<bool>,
     /// Alignment information
     #[serde(default, skip_serializing_if = "is_default")]
     pub alignment: Alignment,
@@ -369,18 +604,108 @@ pub struct Attrs<'data> {
     /// Custom attributes.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub custom: Option<BTreeMap<Cow<'static, str>, Str<'data>>>,
+    /// The location of the `{#id}` trailer the id was defined with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_span: Option<Location>,
 }
 
 impl<'data> Attrs<'data> {
     /// Returns `true` if all attrs are empty.
     pub fn is_empty(&self) -> bool {
         self.start.is_none()
+            && self.tight.is_none()
             && self.alignment == Alignment::None
             && self.id.is_none()
             && self.class.is_none()
             && self.title.is_none()
             && self.target.is_none()
             && self.custom.is_none()
+            && self.id_span.is_none()
+    }
+
+    /// Returns `true` if the class is in the list of classes.
+    pub fn has_class(&self, class: &str) -> bool {
+        self.class
+            .as_ref()
+            .iter()
+            .any(|x| x.as_str().split_whitespace().any(|x| x == class))
+    }
+
+    /// Adds whitespace separated classes unless they are already there.
+    pub fn add_class(&mut self, class: &str) {
+        for class in class.split_whitespace() {
+            if self.has_class(class) {
+                continue;
+            }
+            self.class = Some(match self.class.take() {
+                Some(existing) if !existing.as_str().trim().is_empty() => {
+                    format!("{} {}", existing.as_str().trim(), class).into()
+                }
+                _ => class.to_string().into(),
+            });
+        }
+    }
+
+    /// Removes a class.
+    ///
+    /// The class attribute is removed entirely once no class is left.
+    pub fn remove_class(&mut self, class: &str) {
+        if let Some(existing) = self.class.take() {
+            let rest: Vec<&str> = existing
+                .as_str()
+                .split_whitespace()
+                .filter(|x| *x != class)
+                .collect();
+            if !rest.is_empty() {
+                self.class = Some(rest.join(" ").into());
+            }
+        }
+    }
+
+    /// Merges other attributes into these.
+    ///
+    /// Attributes set on `other` win, classes are combined and custom
+    /// attributes are merged key by key.
+    pub fn merge(&mut self, other: Attrs<'data>) {
+        self.start = other.start.or(self.start);
+        self.tight = other.tight.or(self.tight);
+        if other.alignment != Alignment::None {
+            self.alignment = other.alignment;
+        }
+        if other.id.is_some() {
+            self.id = other.id;
+            self.id_span = other.id_span;
+        }
+        if let Some(class) = other.class {
+            self.add_class(class.as_str());
+        }
+        self.title = other.title.or(self.title.take());
+        self.target = other.target.or(self.target.take());
+        if let Some(custom) = other.custom {
+            self.custom
+                .get_or_insert_with(Default::default)
+                .extend(custom);
+        }
+    }
+
+    /// Converts the attributes into owned attributes.
+    pub fn into_owned(self) -> Attrs<'static> {
+        Attrs {
+            start: self.start,
+            tight: self.tight,
+            alignment: self.alignment,
+            id: self.id.map(Str::into_owned),
+            class: self.class.map(Str::into_owned),
+            title: self.title.map(Str::into_owned),
+            target: self.target.map(Str::into_owned),
+            custom: self.custom.map(|custom| {
+                custom
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into_owned()))
+                    .collect()
+            }),
+            id_span: self.id_span,
+        }
     }
 }
 
@@ -420,6 +745,9 @@ pub struct InterpretedTextEvent<'data> {
     pub role: Str<'data>,
     /// Text to the interpreted with that role.
     pub text: Str<'data>,
+    /// The location of the `{role}` prefix if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role_span: Option<Location>,
 }
 
 /// Code block
@@ -444,6 +772,13 @@ pub struct DirectiveEvent<'data> {
     pub front_matter: Option<Value>,
     /// The directive body.
     pub body: Str<'data>,
+    /// The location of the body in the source.
+    ///
+    /// This is only set if the body is verbatim source, which is not the
+    /// case for directives indented within lists or block quotes.  See
+    /// [`LocationMapper`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_location: Option<Location>,
 }
 
 /// Inline code
@@ -453,6 +788,25 @@ pub struct InlineCodeEvent<'data> {
     pub code: Str<'data>,
 }
 
+/// Inline math
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InlineMathEvent<'data> {
+    /// The TeX source of the formula.
+    pub math: Str<'data>,
+    /// Indicates display math within a paragraph (`$$...$$`).
+    #[serde(default)]
+    pub display: bool,
+}
+
+/// A block of display math
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MathBlockEvent<'data> {
+    /// Arguments to the math block.
+    pub args: Option<BTreeMap<Str<'data>, Str<'data>>>,
+    /// The TeX source of the formula.
+    pub math: Str<'data>,
+}
+
 /// An embedded image
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ImageEvent<'data> {
@@ -462,6 +816,12 @@ pub struct ImageEvent<'data> {
     pub alt: Option<Str<'data>>,
     /// The optional title of the image
     pub title: Option<Str<'data>>,
+    /// The width of the image in pixels if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    /// The height of the image in pixels if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
 }
 
 /// Embedded raw HTML
@@ -499,6 +859,42 @@ pub struct MetaDataEvent<'data> {
 pub struct ErrorEvent<'data> {
     pub title: Str<'data>,
     pub description: Option<Str<'data>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Code>,
+}
+
+/// The severity of a diagnostic.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// An event reporting a problem with the source.
+///
+/// Unlike an [`ErrorEvent`] a diagnostic is not rendered.  It points at the
+/// source through the location of the annotated event so that tools can
+/// report it (see [`diagnostics`](crate::diagnostics)).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiagnosticEvent<'data> {
+    pub severity: Severity,
+    pub message: Str<'data>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<Str<'data>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Code>,
 }
 
 /// A event in a struckdown stream.
@@ -521,6 +917,8 @@ pub enum Event<'data> {
     CodeBlock(CodeBlockEvent<'data>),
     Directive(DirectiveEvent<'data>),
     InlineCode(InlineCodeEvent<'data>),
+    InlineMath(InlineMathEvent<'data>),
+    MathBlock(MathBlockEvent<'data>),
     Image(ImageEvent<'data>),
     RawHtml(RawHtmlEvent<'data>),
     SoftBreak,
@@ -530,6 +928,7 @@ pub enum Event<'data> {
     FootnoteReference(FootnoteReferenceEvent<'data>),
     MetaData(MetaDataEvent<'data>),
     Error(ErrorEvent<'data>),
+    Diagnostic(DiagnosticEvent<'data>),
 }
 
 macro_rules! impl_from_event_type {
@@ -557,14 +956,133 @@ impl_from_event_type!(InterpretedText, InterpretedTextEvent<'data>, 'data);
 impl_from_event_type!(CodeBlock, CodeBlockEvent<'data>, 'data);
 impl_from_event_type!(Directive, DirectiveEvent<'data>, 'data);
 impl_from_event_type!(InlineCode, InlineCodeEvent<'data>, 'data);
+impl_from_event_type!(InlineMath, InlineMathEvent<'data>, 'data);
+impl_from_event_type!(MathBlock, MathBlockEvent<'data>, 'data);
 impl_from_event_type!(Image, ImageEvent<'data>, 'data);
 impl_from_event_type!(RawHtml, RawHtmlEvent<'data>, 'data);
 impl_from_event_type!(Checkbox, CheckboxEvent);
 impl_from_event_type!(FootnoteReference, FootnoteReferenceEvent<'data>, 'data);
 impl_from_event_type!(MetaData, MetaDataEvent<'data>, 'data);
 impl_from_event_type!(Error, ErrorEvent<'data>, 'data);
+impl_from_event_type!(Diagnostic, DiagnosticEvent<'data>, 'data);
 
 impl<'data> Event<'data> {
+    /// Converts the event into an owned event.
+    pub fn into_owned(self) -> Event<'static> {
+        match self {
+            Event::DocumentStart(event) => Event::DocumentStart(event),
+            Event::StartTag(StartTagEvent { tag, attrs }) => Event::StartTag(StartTagEvent {
+                tag,
+                attrs: attrs.into_owned(),
+            }),
+            Event::EndTag(event) => Event::EndTag(event),
+            Event::Text(TextEvent { text }) => Event::Text(TextEvent {
+                text: text.into_owned(),
+            }),
+            Event::InterpretedText(InterpretedTextEvent {
+                role,
+                text,
+                role_span,
+            }) => Event::InterpretedText(InterpretedTextEvent {
+                role: role.into_owned(),
+                text: text.into_owned(),
+                role_span,
+            }),
+            Event::CodeBlock(CodeBlockEvent {
+                language,
+                args,
+                code,
+            }) => Event::CodeBlock(CodeBlockEvent {
+                language: language.map(Str::into_owned),
+                args: args.map(|args| {
+                    args.into_iter()
+                        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                        .collect()
+                }),
+                code: code.into_owned(),
+            }),
+            Event::Directive(DirectiveEvent {
+                name,
+                argument,
+                front_matter,
+                body,
+                body_location,
+            }) => Event::Directive(DirectiveEvent {
+                name: name.into_owned(),
+                argument: argument.map(Str::into_owned),
+                body_location,
+                front_matter,
+                body: body.into_owned(),
+            }),
+            Event::InlineCode(InlineCodeEvent { code }) => Event::InlineCode(InlineCodeEvent {
+                code: code.into_owned(),
+            }),
+            Event::InlineMath(InlineMathEvent { math, display }) => {
+                Event::InlineMath(InlineMathEvent {
+                    math: math.into_owned(),
+                    display,
+                })
+            }
+            Event::MathBlock(MathBlockEvent { args, math }) => Event::MathBlock(MathBlockEvent {
+                args: args.map(|args| {
+                    args.into_iter()
+                        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+                        .collect()
+                }),
+                math: math.into_owned(),
+            }),
+            Event::Image(ImageEvent {
+                target,
+                alt,
+                title,
+                width,
+                height,
+            }) => Event::Image(ImageEvent {
+                target: target.into_owned(),
+                alt: alt.map(Str::into_owned),
+                title: title.map(Str::into_owned),
+                width,
+                height,
+            }),
+            Event::RawHtml(RawHtmlEvent { html }) => Event::RawHtml(RawHtmlEvent {
+                html: html.into_owned(),
+            }),
+            Event::SoftBreak => Event::SoftBreak,
+            Event::HardBreak => Event::HardBreak,
+            Event::Rule => Event::Rule,
+            Event::Checkbox(event) => Event::Checkbox(event),
+            Event::FootnoteReference(FootnoteReferenceEvent { target }) => {
+                Event::FootnoteReference(FootnoteReferenceEvent {
+                    target: target.into_owned(),
+                })
+            }
+            Event::MetaData(MetaDataEvent { key, value }) => Event::MetaData(MetaDataEvent {
+                key: key.into_owned(),
+                value,
+            }),
+            Event::Error(ErrorEvent {
+                title,
+                description,
+                code,
+            }) => Event::Error(ErrorEvent {
+                title: title.into_owned(),
+                description: description.map(Str::into_owned),
+                code,
+            }),
+            Event::Diagnostic(DiagnosticEvent {
+                severity,
+                message,
+                description,
+                code,
+            }) => Event::Diagnostic(DiagnosticEvent {
+                severity,
+                message: message.into_owned(),
+                description: description.map(Str::into_owned),
+                code,
+            }),
+        }
+    }
+
     /// Returns the contents as raw text.
     pub fn raw_text(&self) -> Option<&Str<'data>> {
         static NEWLINE: Str<'static> = Str::new("\n");
@@ -573,7 +1091,9 @@ impl<'data> Event<'data> {
             Event::Text(TextEvent { ref text })
             | Event::InterpretedText(InterpretedTextEvent { ref text, .. })
             | Event::InlineCode(InlineCodeEvent { code: ref text, .. })
-            | Event::CodeBlock(CodeBlockEvent { code: ref text, .. }) => Some(text),
+            | Event::CodeBlock(CodeBlockEvent { code: ref text, .. })
+            | Event::InlineMath(InlineMathEvent { math: ref text, .. })
+            | Event::MathBlock(MathBlockEvent { math: ref text, .. }) => Some(text),
             Event::Directive(DirectiveEvent { body: ref text, .. }) => Some(text),
             Event::SoftBreak => Some(&NEWLINE),
             Event::HardBreak => Some(&DOUBLE_NEWLINE),
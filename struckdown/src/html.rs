@@ -1,4 +1,24 @@
 //! Implements an HTML renderer.
+//!
+//! [`to_html`] renders an entire event stream into a string.  For larger
+//! documents the [`HtmlRenderer`] can instead stream into any [`Write`]
+//! implementation:
+//!
+//! ~~~
+//! use struckdown::html::HtmlRenderer;
+//! use struckdown::parser::parse;
+//!
+//! let options = Default::default();
+//! let mut renderer = HtmlRenderer::new(std::io::stdout(), &options);
+//! renderer
+//!     .feed_stream(parse("| a | b |\n|---|---|\n| 1 | 2 |", &Default::default()))
+//!     .unwrap();
+//! ~~~
+//!
+//! All tags are supported including the synthetic ones that have no
+//! markdown syntax such as [`Tag::TableBody`].  Directives no processor
+//! handled are recorded as
+//! [`lossy_conversions`](HtmlRenderer::lossy_conversions).
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::io::{self, Write};
@@ -8,9 +28,13 @@ use v_htmlescape::escape;
 
 use crate::event::{
     Alignment, AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, DirectiveEvent, EndTagEvent,
-    ErrorEvent, Event, FootnoteReferenceEvent, ImageEvent, InlineCodeEvent, InterpretedTextEvent,
-    RawHtmlEvent, StartTagEvent, Str, Tag, TextEvent,
+    ErrorEvent, Event, FootnoteReferenceEvent, ImageEvent, InlineCodeEvent, InlineMathEvent,
+    InterpretedTextEvent, Location, MathBlockEvent, MetaDataEvent, RawHtmlEvent, StartTagEvent,
+    Str, Tag, TextEvent,
 };
+use crate::lossy::{DirectiveOutcome, Fallback, LossyConversion, UnknownDirectives};
+use crate::theme::ThemeProfile;
+use crate::value::{value, Value};
 
 /// Customizes the HTML rendering.
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -22,8 +46,19 @@ pub struct HtmlRendererOptions {
     pub footnote_reference_class: String,
     /// The class to emit for footnote definitions.
     pub footnote_definition_class: String,
+    /// The class to emit for spoilers.
+    pub spoiler_class: String,
+    /// The class to emit for sidenotes.
+    pub sidenote_class: String,
     /// The initial level for headlines
     pub initial_headline_level: usize,
+    /// When enabled the entries of all `faq` meta data are rendered as a
+    /// single FAQPage JSON-LD block at the end of the output.
+    pub emit_faq_json_ld: bool,
+    /// Maps theme tokens to classes and inline styles.
+    pub theme: ThemeProfile,
+    /// What happens to directives no processor handled.
+    pub unknown_directives: UnknownDirectives,
 }
 
 impl Default for HtmlRendererOptions {
@@ -39,7 +74,12 @@ impl HtmlRendererOptions {
             render_underlines: false,
             footnote_reference_class: "footnote-reference".into(),
             footnote_definition_class: "footnote-definition".into(),
+            spoiler_class: "spoiler".into(),
+            sidenote_class: "sidenote".into(),
             initial_headline_level: 1,
+            emit_faq_json_ld: false,
+            theme: ThemeProfile::classes(),
+            unknown_directives: UnknownDirectives::default(),
         }
     }
 }
@@ -49,6 +89,8 @@ pub struct HtmlRenderer<'data, 'options, F> {
     out: F,
     footnotes: HashMap<Str<'data>, usize>,
     options: &'options HtmlRendererOptions,
+    lossy: Vec<LossyConversion>,
+    faq: Vec<Value>,
 }
 
 impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
@@ -58,6 +100,8 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             out,
             footnotes: HashMap::new(),
             options,
+            lossy: vec![],
+            faq: vec![],
         }
     }
 
@@ -66,6 +110,39 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
         self.out
     }
 
+    /// Returns the elements that could not be represented so far.
+    pub fn lossy_conversions(&self) -> &[LossyConversion] {
+        &self.lossy
+    }
+
+    fn report_directive(
+        &mut self,
+        directive: &DirectiveEvent<'_>,
+        fallback: Fallback,
+        location: Option<&Location>,
+    ) {
+        self.lossy.push(LossyConversion {
+            format: "html".into(),
+            element: format!("directive {}", directive.name.as_str()),
+            fallback,
+            location: location.cloned(),
+        });
+    }
+
+    fn literal_directive(
+        &mut self,
+        directive: &DirectiveEvent<'_>,
+        location: Option<&Location>,
+    ) -> Result<(), io::Error> {
+        self.report_directive(directive, Fallback::Literal, location);
+        write!(
+            self.out,
+            "<div class=\"directive-{}\"><pre>{}</pre></div>",
+            escape(directive.name.as_str()),
+            escape(directive.body.as_str()),
+        )
+    }
+
     fn newline_after_start_tag(&self, tag: Tag) -> bool {
         match tag {
             Tag::Paragraph => false,
@@ -80,6 +157,9 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Tag::UnorderedList => true,
             Tag::ListItem => false,
             Tag::FootnoteDefinition => true,
+            Tag::DefinitionList => true,
+            Tag::DefinitionTerm => false,
+            Tag::DefinitionDetails => false,
             Tag::Table => true,
             Tag::TableHead => true,
             Tag::TableRow => true,
@@ -93,6 +173,15 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Tag::TableBody => true,
             Tag::Container => true,
             Tag::Span => false,
+            Tag::Details => true,
+            Tag::Summary => false,
+            Tag::Spoiler => false,
+            Tag::Abbreviation => false,
+            Tag::Insertion => false,
+            Tag::Deletion => false,
+            Tag::Sidenote => false,
+            Tag::Figure => true,
+            Tag::FigureCaption => false,
         }
     }
 
@@ -110,6 +199,9 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Tag::UnorderedList => true,
             Tag::ListItem => true,
             Tag::FootnoteDefinition => true,
+            Tag::DefinitionList => true,
+            Tag::DefinitionTerm => true,
+            Tag::DefinitionDetails => true,
             Tag::Table => true,
             Tag::TableHead => true,
             Tag::TableRow => true,
@@ -123,6 +215,15 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Tag::TableBody => true,
             Tag::Container => true,
             Tag::Span => false,
+            Tag::Details => true,
+            Tag::Summary => true,
+            Tag::Spoiler => false,
+            Tag::Abbreviation => false,
+            Tag::Insertion => false,
+            Tag::Deletion => false,
+            Tag::Sidenote => false,
+            Tag::Figure => true,
+            Tag::FigureCaption => true,
         }
     }
 
@@ -151,6 +252,9 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Tag::UnorderedList => "ul",
             Tag::ListItem => "li",
             Tag::FootnoteDefinition => "div",
+            Tag::DefinitionList => "dl",
+            Tag::DefinitionTerm => "dt",
+            Tag::DefinitionDetails => "dd",
             Tag::Table => "table",
             Tag::TableHeader => "thead",
             Tag::TableBody => "tbody",
@@ -170,6 +274,15 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Tag::Link => "a",
             Tag::Container => "div",
             Tag::Span => "span",
+            Tag::Details => "details",
+            Tag::Summary => "summary",
+            Tag::Spoiler => "span",
+            Tag::Abbreviation => "abbr",
+            Tag::Insertion => "ins",
+            Tag::Deletion => "del",
+            Tag::Sidenote => "span",
+            Tag::Figure => "figure",
+            Tag::FigureCaption => "figcaption",
         }
     }
 
@@ -219,17 +332,36 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             }
         }
 
-        if tag == Tag::FootnoteDefinition {
+        let tag_class = match tag {
+            Tag::FootnoteDefinition => Some(&self.options.footnote_definition_class),
+            Tag::Spoiler => {
+                // spoilers need to be focusable so they can be revealed
+                // without a pointer.
+                write!(self.out, " tabindex=\"0\"")?;
+                Some(&self.options.spoiler_class)
+            }
+            Tag::Sidenote => Some(&self.options.sidenote_class),
+            _ => None,
+        };
+        if let Some(tag_class) = tag_class {
             combined_class = if combined_class.is_empty() {
-                Cow::Borrowed(&self.options.footnote_definition_class)
+                Cow::Borrowed(tag_class)
             } else {
-                Cow::Owned(format!(
-                    "{} {}",
-                    combined_class, &self.options.footnote_definition_class
-                ))
+                Cow::Owned(format!("{} {}", combined_class, tag_class))
             };
         }
 
+        if !self.options.theme.is_empty() {
+            let (class, style) = self.options.theme.apply(&combined_class);
+            if !style.is_empty() {
+                if !combined_style.is_empty() {
+                    combined_style.push_str("; ");
+                }
+                combined_style.push_str(&style);
+            }
+            combined_class = Cow::Owned(class);
+        }
+
         if !combined_class.is_empty() {
             write!(self.out, " class=\"{}\"", escape(&combined_class))?;
         }
@@ -268,10 +400,38 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
         Ok(())
     }
 
+    fn faq_json_ld(&mut self) -> Result<(), io::Error> {
+        let entries = std::mem::take(&mut self.faq);
+        let json_ld = value!({
+            "@context": "https://schema.org",
+            "@type": "FAQPage",
+            "mainEntity": entries.iter().map(|entry| value!({
+                "@type": "Question",
+                "name": entry.get("question"),
+                "acceptedAnswer": {
+                    "@type": "Answer",
+                    "text": entry.get("answer"),
+                }
+            })).collect::<Vec<_>>(),
+        });
+        writeln!(
+            self.out,
+            "<script type=\"application/ld+json\">{}</script>",
+            json_ld.to_string().replace("</", "<\\/")
+        )
+    }
+
     /// Feeds a single event into the renderer.
     pub fn feed_event(&mut self, event: &AnnotatedEvent<'data>) -> Result<(), io::Error> {
         match event.event {
-            Event::DocumentStart(_) | Event::MetaData(_) => {}
+            Event::DocumentStart(_) => {}
+            Event::MetaData(MetaDataEvent { ref key, ref value }) => {
+                if self.options.emit_faq_json_ld && key.as_str() == "faq" {
+                    if let Some(entries) = value.as_array() {
+                        self.faq.extend(entries.iter().cloned());
+                    }
+                }
+            }
             Event::StartTag(StartTagEvent { tag, ref attrs }) => {
                 self.start_tag(tag, attrs)?;
             }
@@ -292,17 +452,33 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
                 }
                 writeln!(self.out, ">{}</code></pre>", escape(code.as_str()))?;
             }
-            Event::Directive(DirectiveEvent {
-                ref name, ref body, ..
-            }) => {
-                write!(
-                    self.out,
-                    "<div class=\"directive-{}\"><pre>{}</pre></div>",
-                    escape(name.as_str()),
-                    escape(body.as_str()),
-                )?;
+            Event::Directive(ref directive) => {
+                let location = event.location.as_ref();
+                match self.options.unknown_directives.resolve(directive) {
+                    DirectiveOutcome::Replace(events) => {
+                        for replacement in events {
+                            match replacement {
+                                Event::Directive(ref directive) => {
+                                    self.literal_directive(directive, location)?
+                                }
+                                replacement => self.feed_event(&AnnotatedEvent::new(
+                                    replacement,
+                                    location.cloned(),
+                                ))?,
+                            }
+                        }
+                    }
+                    DirectiveOutcome::Fallback(Fallback::Literal) => {
+                        self.literal_directive(directive, location)?
+                    }
+                    DirectiveOutcome::Fallback(fallback) => {
+                        self.report_directive(directive, fallback, location)
+                    }
+                }
             }
-            Event::InterpretedText(InterpretedTextEvent { ref text, ref role }) => {
+            Event::InterpretedText(InterpretedTextEvent {
+                ref text, ref role, ..
+            }) => {
                 write!(
                     self.out,
                     "<span class=\"role-{}\">{}</span>",
@@ -313,18 +489,49 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Event::InlineCode(InlineCodeEvent { ref code }) => {
                 write!(self.out, "<code>{}</code>", escape(code.as_str()))?;
             }
+            Event::InlineMath(InlineMathEvent { ref math, display }) => {
+                if display {
+                    write!(
+                        self.out,
+                        "<span class=\"math display\">\\[{}\\]</span>",
+                        escape(math.as_str())
+                    )?;
+                } else {
+                    write!(
+                        self.out,
+                        "<span class=\"math inline\">\\({}\\)</span>",
+                        escape(math.as_str())
+                    )?;
+                }
+            }
+            Event::MathBlock(MathBlockEvent { ref math, .. }) => {
+                writeln!(
+                    self.out,
+                    "<div class=\"math display\">\\[{}\\]</div>",
+                    escape(math.as_str())
+                )?;
+            }
             Event::Image(ImageEvent {
                 ref target,
                 ref alt,
                 ref title,
+                width,
+                height,
             }) => {
                 write!(
                     self.out,
-                    "<img src=\"{}\" alt=\"{}\" title=\"{}\">",
+                    "<img src=\"{}\" alt=\"{}\" title=\"{}\"",
                     target,
                     alt.as_ref().map_or("", |x| x.as_str()),
                     title.as_ref().map_or("", |x| x.as_str()),
                 )?;
+                if let Some(width) = width {
+                    write!(self.out, " width={}", width)?;
+                }
+                if let Some(height) = height {
+                    write!(self.out, " height={}", height)?;
+                }
+                write!(self.out, ">")?;
             }
             Event::RawHtml(RawHtmlEvent { ref html }) => {
                 write!(self.out, "{}", html)?;
@@ -342,14 +549,7 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
                 )?;
             }
             Event::FootnoteReference(FootnoteReferenceEvent { ref target }) => {
-                let number = match self.footnotes.get(target) {
-                    Some(&num) => num,
-                    None => {
-                        let next_number = self.footnotes.len() + 1;
-                        self.footnotes.insert(target.clone(), next_number);
-                        next_number
-                    }
-                };
+                let number = self.footnote_number(target);
                 write!(
                     self.out,
                     "<sup class=\"{}\"><a href=\"#{}\">{}</a></sup>",
@@ -361,18 +561,54 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
             Event::Error(ErrorEvent {
                 ref title,
                 ref description,
+                ref code,
             }) => {
+                write!(self.out, "<div class=\"error\"")?;
+                if let Some(ref code) = code {
+                    write!(self.out, " data-code=\"{}\"", escape(code.as_str()))?;
+                }
                 write!(
                     self.out,
-                    "<div class=\"error\">\n<h3>{}</h3>\n<p>{}</p>\n</div>",
+                    ">\n<h3>{}</h3>\n<p>{}</p>\n</div>",
                     escape(title.as_str()),
                     escape(description.as_ref().map_or("No details", |x| x.as_str())),
                 )?;
             }
+            Event::Diagnostic(..) => {}
         }
         Ok(())
     }
 
+    fn footnote_number(&mut self, target: &Str<'data>) -> usize {
+        match self.footnotes.get(target) {
+            Some(&num) => num,
+            None => {
+                let next_number = self.footnotes.len() + 1;
+                self.footnotes.insert(target.clone(), next_number);
+                next_number
+            }
+        }
+    }
+
+    /// Numbers footnotes in the order they are referenced by the events.
+    ///
+    /// When only a part of a document is rendered the entire document can
+    /// be passed here first so that the numbers match the ones of the full
+    /// rendering.
+    pub fn number_footnotes<'a, I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = &'a AnnotatedEvent<'data>>,
+        'data: 'a,
+    {
+        for annotated_event in iter {
+            if let Event::FootnoteReference(FootnoteReferenceEvent { ref target }) =
+                annotated_event.event
+            {
+                self.footnote_number(target);
+            }
+        }
+    }
+
     /// Feeds an event stream into the renderer.
     ///
     /// In this case the iterator is consumed.  Alternatively you can/have
@@ -385,6 +621,17 @@ impl<'data, 'options, F: Write> HtmlRenderer<'data, 'options, F> {
         for event in iter {
             self.feed_event(&event)?;
         }
+        self.finish()
+    }
+
+    /// Writes out everything that is still buffered.
+    ///
+    /// This is automatically called by [`feed_stream`](Self::feed_stream)
+    /// and writes the FAQPage JSON-LD collected from `faq` meta data.
+    pub fn finish(&mut self) -> Result<(), io::Error> {
+        if !self.faq.is_empty() {
+            self.faq_json_ld()?;
+        }
         Ok(())
     }
 }
@@ -410,3 +657,24 @@ pub fn to_html<'a, I: Iterator<Item = AnnotatedEvent<'a>>>(
     renderer.feed_stream(iter).unwrap();
     renderer.into_string()
 }
+
+#[test]
+fn test_faq_json_ld() {
+    use crate::pipeline::Pipeline;
+    use crate::processors::Faq;
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Faq::default());
+    let options = HtmlRendererOptions {
+        emit_faq_json_ld: true,
+        ..Default::default()
+    };
+    insta::assert_snapshot!(to_html(
+        pipeline.process(
+            "```{faq}\n# Is it free?\nYes, it is **free**.\n```\n\n\
+             Some text.\n\n\
+             ```{faq}\n# Can I use it offline?\nYes.\n```"
+        ),
+        &options
+    ));
+}
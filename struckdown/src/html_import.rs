@@ -0,0 +1,620 @@
+//! Converts HTML into an event stream.
+//!
+//! Only the subset of HTML that WYSIWYG editors and legacy content
+//! management systems commonly produce is understood.  Elements without a
+//! struckdown equivalent (`div`, `span`, `font` and the like) are dropped
+//! while their contents are kept; `script`, `style` and the document head
+//! are dropped entirely.
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::states::RawKind;
+use html5ever::tokenizer::{
+    BufferQueue, Tag as HtmlTag, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer,
+    TokenizerOpts,
+};
+
+use crate::event::{
+    AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, DocumentStartEvent, Event, ImageEvent,
+    InlineCodeEvent, Str, Tag, TextEvent,
+};
+
+/// Elements that never have contents.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// Elements that implicitly close an open paragraph.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// Elements whose contents are dropped.
+const IGNORED_ELEMENTS: &[&str] = &["head", "script", "style", "template", "title"];
+
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child_elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Element(element) => Some(element),
+            Node::Text(..) => None,
+        })
+    }
+
+    fn text_content(&self, rv: &mut String) {
+        for child in &self.children {
+            match child {
+                Node::Text(text) => rv.push_str(text),
+                Node::Element(element) if element.name == "br" => rv.push('\n'),
+                Node::Element(element) => element.text_content(rv),
+            }
+        }
+    }
+}
+
+/// Checks if a node is a block or a container holding blocks (such as
+/// `body` or a `span` wrapping paragraphs).
+fn is_block(node: &Node) -> bool {
+    match node {
+        Node::Element(element) => {
+            BLOCK_ELEMENTS.contains(&element.name.as_str())
+                || element.name == "body"
+                || element.children.iter().any(is_block)
+        }
+        Node::Text(..) => false,
+    }
+}
+
+/// Builds a tree of elements from tokens with just enough of the HTML
+/// rules for implied end tags to handle sloppy editor output.
+struct TreeBuilder {
+    stack: Vec<Element>,
+}
+
+impl TreeBuilder {
+    fn append(&mut self, node: Node) {
+        self.stack
+            .last_mut()
+            .expect("the root element is never popped")
+            .children
+            .push(node);
+    }
+
+    fn pop(&mut self) {
+        if self.stack.len() > 1 {
+            let element = self.stack.pop().unwrap();
+            self.append(Node::Element(element));
+        }
+    }
+
+    /// Closes the innermost open `name` unless one of `boundaries` is
+    /// open within it.
+    fn close(&mut self, name: &str, boundaries: &[&str]) {
+        for idx in (1..self.stack.len()).rev() {
+            let open = self.stack[idx].name.as_str();
+            if open == name {
+                while self.stack.len() > idx {
+                    self.pop();
+                }
+                return;
+            }
+            if boundaries.contains(&open) {
+                return;
+            }
+        }
+    }
+
+    fn start_tag(&mut self, tag: HtmlTag) {
+        let name = tag.name.to_string();
+        if BLOCK_ELEMENTS.contains(&name.as_str()) {
+            self.close("p", &["blockquote", "li", "td", "th", "div"]);
+        }
+        match name.as_str() {
+            "li" => self.close("li", &["ul", "ol"]),
+            "dt" | "dd" => {
+                self.close("dt", &["dl"]);
+                self.close("dd", &["dl"]);
+            }
+            "tr" => self.close("tr", &["table"]),
+            "td" | "th" => {
+                self.close("td", &["tr", "table"]);
+                self.close("th", &["tr", "table"]);
+            }
+            "thead" | "tbody" | "tfoot" => {
+                self.close("thead", &["table"]);
+                self.close("tbody", &["table"]);
+            }
+            _ => {}
+        }
+        let element = Element {
+            name,
+            attrs: tag
+                .attrs
+                .into_iter()
+                .map(|attr| (attr.name.local.to_string(), attr.value.to_string()))
+                .collect(),
+            children: vec![],
+        };
+        if tag.self_closing || VOID_ELEMENTS.contains(&element.name.as_str()) {
+            self.append(Node::Element(element));
+        } else {
+            self.stack.push(element);
+        }
+    }
+
+    fn end_tag(&mut self, tag: HtmlTag) {
+        let name = tag.name.to_string();
+        if name == "br" {
+            // `</br>` is treated like `<br>` by browsers
+            self.start_tag(HtmlTag {
+                kind: TagKind::StartTag,
+                ..tag
+            });
+        } else {
+            self.close(&name, &[]);
+        }
+    }
+}
+
+impl TokenSink for TreeBuilder {
+    type Handle = ();
+
+    fn process_token(&mut self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        match token {
+            Token::TagToken(tag) => match tag.kind {
+                TagKind::StartTag => {
+                    let raw_kind = match &*tag.name {
+                        "script" => Some(RawKind::ScriptData),
+                        "style" => Some(RawKind::Rawtext),
+                        "title" | "textarea" => Some(RawKind::Rcdata),
+                        _ => None,
+                    };
+                    self.start_tag(tag);
+                    if let Some(raw_kind) = raw_kind {
+                        return TokenSinkResult::RawData(raw_kind);
+                    }
+                }
+                TagKind::EndTag => self.end_tag(tag),
+            },
+            Token::CharacterTokens(text) => {
+                if let Some(Node::Text(last)) = self.stack.last_mut().unwrap().children.last_mut() {
+                    last.push_str(&text);
+                } else {
+                    self.append(Node::Text(text.to_string()));
+                }
+            }
+            _ => {}
+        }
+        TokenSinkResult::Continue
+    }
+}
+
+fn parse_tree(html: &str) -> Element {
+    let builder = TreeBuilder {
+        stack: vec![Element {
+            name: "#root".into(),
+            attrs: vec![],
+            children: vec![],
+        }],
+    };
+    let mut tokenizer = Tokenizer::new(builder, TokenizerOpts::default());
+    let mut queue = BufferQueue::new();
+    queue.push_back(StrTendril::from_slice(html));
+    let _ = tokenizer.feed(&mut queue);
+    tokenizer.end();
+    let mut builder = tokenizer.sink;
+    while builder.stack.len() > 1 {
+        builder.pop();
+    }
+    builder.stack.pop().unwrap()
+}
+
+/// Emits events for a tree.
+struct Converter {
+    events: Vec<AnnotatedEvent<'static>>,
+    // whether the last emitted text ended in whitespace
+    after_space: bool,
+}
+
+impl Converter {
+    fn push<E: Into<Event<'static>>>(&mut self, event: E) {
+        self.events.push(AnnotatedEvent::new(event, None));
+    }
+
+    fn start(&mut self, tag: Tag, attrs: Attrs<'static>) {
+        self.push(tag.start_tag(attrs));
+    }
+
+    fn end(&mut self, tag: Tag) {
+        self.push(tag.end_tag());
+    }
+
+    fn text(&mut self, text: &str) {
+        let mut collapsed = String::new();
+        for c in text.chars() {
+            if c.is_ascii_whitespace() {
+                if !self.after_space {
+                    collapsed.push(' ');
+                    self.after_space = true;
+                }
+            } else {
+                collapsed.push(c);
+                self.after_space = false;
+            }
+        }
+        if !collapsed.is_empty() {
+            self.push(TextEvent {
+                text: collapsed.into(),
+            });
+        }
+    }
+
+    /// Emits inline contents wrapped in `tag`.
+    fn inline_block(&mut self, tag: Tag, attrs: Attrs<'static>, nodes: &[Node]) {
+        self.start(tag, attrs);
+        self.inline_contents(nodes);
+        self.end(tag);
+    }
+
+    /// Emits inline contents without leading and trailing whitespace.
+    fn inline_contents(&mut self, nodes: &[Node]) {
+        self.after_space = true;
+        for node in nodes {
+            self.inline(node);
+        }
+        if let Some(AnnotatedEvent {
+            event: Event::Text(TextEvent { text }),
+            ..
+        }) = self.events.last_mut()
+        {
+            if text.as_str().ends_with(' ') {
+                let trimmed = text.as_str().trim_end().to_string();
+                if trimmed.is_empty() {
+                    self.events.pop();
+                } else {
+                    *text = trimmed.into();
+                }
+            }
+        }
+    }
+
+    fn inline(&mut self, node: &Node) {
+        let element = match node {
+            Node::Text(text) => return self.text(text),
+            Node::Element(element) => element,
+        };
+        let wrapping = match element.name.as_str() {
+            "em" | "i" | "cite" | "dfn" => Some(Tag::Emphasis),
+            "strong" | "b" => Some(Tag::Strong),
+            "s" | "del" | "strike" => Some(Tag::Strikethrough),
+            "u" => Some(Tag::EmphasisAlt),
+            "a" if element.attr("href").is_some() => Some(Tag::Link),
+            "code" | "kbd" | "samp" | "tt" => {
+                let mut code = String::new();
+                element.text_content(&mut code);
+                self.push(InlineCodeEvent { code: code.into() });
+                self.after_space = false;
+                return;
+            }
+            "img" => {
+                if let Some(src) = element.attr("src") {
+                    self.push(ImageEvent {
+                        target: src.to_string().into(),
+                        alt: element.attr("alt").map(|x| Str::from(x.to_string())),
+                        title: element.attr("title").map(|x| Str::from(x.to_string())),
+                        width: element.attr("width").and_then(|x| x.parse().ok()),
+                        height: element.attr("height").and_then(|x| x.parse().ok()),
+                    });
+                    self.after_space = false;
+                }
+                return;
+            }
+            "br" => {
+                self.push(Event::HardBreak);
+                self.after_space = true;
+                return;
+            }
+            "input" if element.attr("type") == Some("checkbox") => {
+                self.push(CheckboxEvent {
+                    checked: element.attr("checked").is_some(),
+                });
+                self.after_space = true;
+                return;
+            }
+            name if IGNORED_ELEMENTS.contains(&name) => return,
+            _ => None,
+        };
+        match wrapping {
+            Some(tag) => {
+                let attrs = Attrs {
+                    target: element.attr("href").map(|x| x.to_string().into()),
+                    title: element.attr("title").map(|x| x.to_string().into()),
+                    ..Attrs::default()
+                };
+                self.start(tag, attrs);
+                for child in &element.children {
+                    self.inline(child);
+                }
+                self.end(tag);
+            }
+            None => {
+                for child in &element.children {
+                    self.inline(child);
+                }
+            }
+        }
+    }
+
+    /// Emits block contents.
+    ///
+    /// Runs of inline contents are wrapped in paragraphs if `wrap` is set.
+    fn blocks(&mut self, nodes: &[Node], wrap: bool) {
+        let mut run_start = 0;
+        for (idx, node) in nodes.iter().enumerate() {
+            if is_block(node) {
+                self.inline_run(&nodes[run_start..idx], wrap);
+                run_start = idx + 1;
+                if let Node::Element(element) = node {
+                    self.block(element);
+                }
+            }
+        }
+        self.inline_run(&nodes[run_start..], wrap);
+    }
+
+    fn inline_run(&mut self, nodes: &[Node], wrap: bool) {
+        let significant = nodes.iter().any(|node| match node {
+            Node::Text(text) => !text.trim().is_empty(),
+            Node::Element(element) => !IGNORED_ELEMENTS.contains(&element.name.as_str()),
+        });
+        if !significant {
+            return;
+        }
+        if wrap {
+            self.inline_block(Tag::Paragraph, Attrs::default(), nodes);
+        } else {
+            self.inline_contents(nodes);
+        }
+    }
+
+    fn block(&mut self, element: &Element) {
+        let heading = match element.name.as_str() {
+            "h1" => Some(Tag::Heading1),
+            "h2" => Some(Tag::Heading2),
+            "h3" => Some(Tag::Heading3),
+            "h4" => Some(Tag::Heading4),
+            "h5" => Some(Tag::Heading5),
+            "h6" => Some(Tag::Heading6),
+            _ => None,
+        };
+        if let Some(tag) = heading {
+            let attrs = Attrs {
+                id: element.attr("id").map(|x| x.to_string().into()),
+                ..Attrs::default()
+            };
+            return self.inline_block(tag, attrs, &element.children);
+        }
+
+        match element.name.as_str() {
+            "p" => self.inline_block(Tag::Paragraph, Attrs::default(), &element.children),
+            "blockquote" => {
+                self.start(Tag::BlockQuote, Attrs::default());
+                self.blocks(&element.children, true);
+                self.end(Tag::BlockQuote);
+            }
+            "ul" | "ol" => self.list(element),
+            "li" => self.list_item(element, true),
+            "dl" => {
+                self.start(Tag::DefinitionList, Attrs::default());
+                for item in element.child_elements() {
+                    let tag = match item.name.as_str() {
+                        "dt" => Tag::DefinitionTerm,
+                        "dd" => Tag::DefinitionDetails,
+                        _ => continue,
+                    };
+                    self.start(tag, Attrs::default());
+                    self.blocks(&item.children, false);
+                    self.end(tag);
+                }
+                self.end(Tag::DefinitionList);
+            }
+            "pre" => {
+                let mut code = String::new();
+                element.text_content(&mut code);
+                if !code.ends_with('\n') {
+                    code.push('\n');
+                }
+                let language = element
+                    .child_elements()
+                    .chain(Some(element))
+                    .filter_map(|x| x.attr("class"))
+                    .flat_map(|x| x.split_whitespace())
+                    .find_map(|x| {
+                        x.strip_prefix("language-")
+                            .or_else(|| x.strip_prefix("lang-"))
+                    })
+                    .map(|x| Str::from(x.to_string()));
+                self.push(CodeBlockEvent {
+                    language,
+                    args: None,
+                    code: code.into(),
+                });
+            }
+            "hr" => self.push(Event::Rule),
+            "table" => self.table(element),
+            _ => self.blocks(&element.children, true),
+        }
+    }
+
+    fn list(&mut self, element: &Element) {
+        let (tag, start) = if element.name == "ol" {
+            let start = element.attr("start").and_then(|x| x.parse().ok());
+            (Tag::OrderedList, Some(start.unwrap_or(1)))
+        } else {
+            (Tag::UnorderedList, None)
+        };
+        // nested lists do not make a list loose
+        let tight = !element.child_elements().any(|item| {
+            item.child_elements().any(|x| {
+                x.name != "ul" && x.name != "ol" && BLOCK_ELEMENTS.contains(&x.name.as_str())
+            })
+        });
+        self.start(
+            tag,
+            Attrs {
+                start,
+                tight: Some(tight),
+                ..Attrs::default()
+            },
+        );
+        for item in element.child_elements() {
+            self.list_item(item, tight);
+        }
+        self.end(tag);
+    }
+
+    fn list_item(&mut self, element: &Element, tight: bool) {
+        self.start(Tag::ListItem, Attrs::default());
+        self.blocks(&element.children, !tight);
+        self.end(Tag::ListItem);
+    }
+
+    fn table(&mut self, element: &Element) {
+        let mut header = None;
+        let mut rows = vec![];
+        for child in element.child_elements() {
+            match child.name.as_str() {
+                "thead" => header = child.child_elements().find(|x| x.name == "tr"),
+                "tbody" | "tfoot" => rows.extend(child.child_elements().filter(|x| x.name == "tr")),
+                "tr" => rows.push(child),
+                _ => {}
+            }
+        }
+        let is_cell = |x: &&Element| x.name == "td" || x.name == "th";
+        if header.is_none()
+            && matches!(rows.first(), Some(row) if row.child_elements().filter(is_cell).all(|x| x.name == "th"))
+        {
+            header = Some(rows.remove(0));
+        }
+
+        self.start(Tag::Table, Attrs::default());
+        self.start(Tag::TableHeader, Attrs::default());
+        if let Some(header) = header {
+            for cell in header.child_elements().filter(is_cell) {
+                self.inline_block(Tag::TableHead, Attrs::default(), &cell.children);
+            }
+        }
+        self.end(Tag::TableHeader);
+        self.start(Tag::TableBody, Attrs::default());
+        for row in rows {
+            self.start(Tag::TableRow, Attrs::default());
+            for cell in row.child_elements().filter(is_cell) {
+                self.inline_block(Tag::TableCell, Attrs::default(), &cell.children);
+            }
+            self.end(Tag::TableRow);
+        }
+        self.end(Tag::TableBody);
+        self.end(Tag::Table);
+    }
+}
+
+/// Converts HTML into an event stream.
+///
+/// This is only available with the `html-import` feature.  The events carry
+/// no locations.  Together with [`to_markdown`](crate::markdown::to_markdown)
+/// this turns HTML into markdown:
+///
+/// ```
+/// use struckdown::markdown::to_markdown;
+/// use struckdown::parser::from_html;
+///
+/// let markdown = to_markdown(
+///     from_html("<p>Hello <b>World</b>!</p>"),
+///     &Default::default(),
+/// );
+/// assert_eq!(markdown, "Hello **World**!\n");
+/// ```
+pub fn from_html(html: &str) -> impl Iterator<Item = AnnotatedEvent<'static>> {
+    let root = parse_tree(html);
+    let mut converter = Converter {
+        events: vec![DocumentStartEvent { front_matter: None }.into()],
+        after_space: true,
+    };
+    converter.blocks(&root.children, true);
+    converter.events.into_iter()
+}
+
+#[test]
+fn test_from_html() {
+    use crate::markdown::to_markdown;
+
+    let html = r#"<html><head><title>Old CMS</title><style>p { color: red }</style></head>
+<body>
+<h1 id="intro">Welcome   to the <i>new</i> site</h1>
+<div>Some loose text<br>on two lines
+<p>A <a href="https://example.com" title="Example">link</a>, <code>code</code> and
+<img src="logo.png" alt="Logo">.
+<p><span style="font-weight: bold"><strong>Unclosed</strong> paragraphs</span>
+</div>
+<ul>
+  <li>one
+  <li><input type="checkbox" checked> two
+    <ul><li>nested</li></ul>
+</ul>
+<ol start="3"><li><p>loose</p></li><li><p>items</p></li></ol>
+<blockquote><p>Quoted</p></blockquote>
+<pre><code class="language-rust">fn main() {
+    println!("&lt;hi&gt;");
+}</code></pre>
+<table>
+  <tr><th>Name</th><th>Value</th></tr>
+  <tr><td>a</td><td><em>1</em></td></tr>
+</table>
+<dl><dt>Term<dd>Its <em>definition</em><dt>Other<dd>More</dl>
+<hr>
+<script>alert("<p>nope</p>")</script>
+</body></html>"#;
+    insta::assert_snapshot!(to_markdown(from_html(html), &Default::default()));
+}
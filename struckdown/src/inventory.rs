@@ -0,0 +1,200 @@
+//! Inventories the directives and roles used by documents.
+//!
+//! Before a pipeline is upgraded or an extension is deprecated it has to be
+//! known which documents use it.  The [`Inventory`] collects every
+//! directive and role of a set of documents together with their locations
+//! and [`Inventory::report`] lists them with counts.  Given a [`Registry`]
+//! of the names a pipeline handles, names outside of it are reported as
+//! unknown.
+//!
+//! ```
+//! use struckdown::inventory::{Inventory, Registry};
+//! use struckdown::parser::parse;
+//!
+//! let mut inventory = Inventory::default();
+//! inventory.add_document("intro.md", parse("Press {kbd}`Ctrl+C`.", &Default::default()));
+//! inventory.add_document("usage.md", parse("```{note}\nSee {ref}`intro`.\n```", &Default::default()));
+//!
+//! let mut registry = Registry::default();
+//! registry.roles.insert("kbd".into());
+//! registry.directives.insert("note".into());
+//! let report = inventory.report(Some(&registry));
+//! assert_eq!(report.roles.len(), 2);
+//! assert_eq!(report.unknown_roles, vec!["ref".to_string()]);
+//! ```
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, InterpretedTextEvent, Location, LocationMapper};
+use crate::parser::parse;
+
+/// The directive and role names a pipeline knows about.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Registry {
+    /// The known directive names.
+    pub directives: BTreeSet<String>,
+    /// The known role names.
+    pub roles: BTreeSet<String>,
+}
+
+/// A single use of a directive or role.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Usage {
+    /// The document it's used in.
+    pub document: String,
+    /// The location within the document.
+    ///
+    /// Uses within the body of a directive have the location of the
+    /// directive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+/// All uses of a directive or role name.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NameUsage {
+    /// The name of the directive or role.
+    pub name: String,
+    /// How often the name is used.
+    pub count: usize,
+    /// Where the name is used.
+    pub usages: Vec<Usage>,
+}
+
+/// The directives and roles used by a set of documents.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InventoryReport {
+    /// The used directives sorted by name.
+    pub directives: Vec<NameUsage>,
+    /// The used roles sorted by name.
+    pub roles: Vec<NameUsage>,
+    /// Used directives not in the registry.
+    pub unknown_directives: Vec<String>,
+    /// Used roles not in the registry.
+    pub unknown_roles: Vec<String>,
+}
+
+/// Collects the directives and roles of documents.
+///
+/// Documents must be added as parsed as processors usually replace the
+/// directives and roles they handle.  The bodies of directives are parsed
+/// as well so that directives and roles within admonitions and similar
+/// directives are found.
+#[derive(Debug, Default)]
+pub struct Inventory {
+    directives: BTreeMap<String, Vec<Usage>>,
+    roles: BTreeMap<String, Vec<Usage>>,
+}
+
+impl Inventory {
+    /// Creates an empty inventory.
+    pub fn new() -> Inventory {
+        Inventory::default()
+    }
+
+    /// Adds the directives and roles of a document.
+    pub fn add_document<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(
+        &mut self,
+        path: &str,
+        iter: I,
+    ) {
+        self.scan(path, iter, None);
+    }
+
+    fn scan<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(
+        &mut self,
+        path: &str,
+        iter: I,
+        outer_location: Option<&Location>,
+    ) {
+        for annotated_event in iter {
+            let location = outer_location.or(annotated_event.location.as_ref());
+            let usage = || Usage {
+                document: path.to_string(),
+                location: location.cloned(),
+            };
+            match annotated_event.event {
+                Event::Directive(ref directive) => {
+                    self.directives
+                        .entry(directive.name.as_str().to_string())
+                        .or_default()
+                        .push(usage());
+                    let events = parse(directive.body.as_str(), &Default::default());
+                    // uses within bodies are reported at their own location
+                    // if it is known and at the directive otherwise
+                    match LocationMapper::for_directive(directive) {
+                        Some(mapper) => {
+                            let events: Vec<_> = events.map(|x| mapper.map_event(x)).collect();
+                            self.scan(path, events.into_iter(), outer_location)
+                        }
+                        None => self.scan(path, events, location),
+                    }
+                }
+                Event::InterpretedText(InterpretedTextEvent { ref role, .. }) => {
+                    self.roles
+                        .entry(role.as_str().to_string())
+                        .or_default()
+                        .push(usage());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the report of all uses.
+    ///
+    /// If a registry is given, names not in it are listed as unknown.
+    pub fn report(&self, registry: Option<&Registry>) -> InventoryReport {
+        let names = |usages: &BTreeMap<String, Vec<Usage>>| -> Vec<NameUsage> {
+            usages
+                .iter()
+                .map(|(name, usages)| NameUsage {
+                    name: name.clone(),
+                    count: usages.len(),
+                    usages: usages.clone(),
+                })
+                .collect()
+        };
+        let unknown =
+            |usages: &BTreeMap<String, Vec<Usage>>, known: Option<&BTreeSet<String>>| match known {
+                Some(known) => usages
+                    .keys()
+                    .filter(|name| !known.contains(*name))
+                    .cloned()
+                    .collect(),
+                None => vec![],
+            };
+        InventoryReport {
+            directives: names(&self.directives),
+            roles: names(&self.roles),
+            unknown_directives: unknown(&self.directives, registry.map(|x| &x.directives)),
+            unknown_roles: unknown(&self.roles, registry.map(|x| &x.roles)),
+        }
+    }
+}
+
+#[test]
+fn test_inventory() {
+    let mut inventory = Inventory::new();
+    inventory.add_document(
+        "intro.md",
+        parse(
+            "# Intro\n\nPress {kbd}`Ctrl+C` or {kbd}`Esc`.\n\n```{youtube} dQw4w9WgXcQ\n```\n",
+            &Default::default(),
+        ),
+    );
+    inventory.add_document(
+        "usage.md",
+        parse(
+            "```{note}\nSee {doc}`intro` and press {kbd}`q`.\n```\n",
+            &Default::default(),
+        ),
+    );
+    let registry = Registry {
+        directives: ["note".to_string()].iter().cloned().collect(),
+        roles: ["kbd".to_string()].iter().cloned().collect(),
+    };
+    insta::assert_yaml_snapshot!(inventory.report(Some(&registry)));
+}
@@ -0,0 +1,55 @@
+//! Serializes event streams to and from JSON.
+//!
+//! Streams are serialized as JSON lines: every event is a JSON value on a
+//! line of its own.  This is the same format the
+//! [`External`](crate::processors::External) processor speaks, so it can be
+//! used for fixtures and for processors written in other languages.
+//!
+//! Events with a location are serialized as a two element array of the
+//! event and its location, events without one as just the event:
+//!
+//! ```json
+//! [{"type":"text","text":"Hello"},{"offset":0,"len":5,"line":1,"column":0}]
+//! {"type":"soft_break"}
+//! ```
+//!
+//! Events with [`Trivia`](crate::event::Trivia) from a lossless parse are a
+//! three element array of the event, its location (or `null`) and the
+//! trivia.
+//!
+//! Deserializing a serialized stream yields the same events again.
+use crate::event::AnnotatedEvent;
+
+/// Serializes an event stream into JSON lines.
+pub fn stream_to_json<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(iter: I) -> String {
+    let mut rv = String::new();
+    for annotated_event in iter {
+        rv.push_str(
+            &serde_json::to_string(&annotated_event).expect("Serializing events should never fail"),
+        );
+        rv.push('\n');
+    }
+    rv
+}
+
+/// Deserializes an event stream from JSON lines.
+///
+/// Empty lines are ignored.
+pub fn stream_from_json(json: &str) -> Result<Vec<AnnotatedEvent<'static>>, serde_json::Error> {
+    json.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect()
+}
+
+#[test]
+fn test_json_roundtrip() {
+    use crate::parser::parse;
+
+    let source =
+        "---\ntitle: Hello\n---\n# Hello {role}`x`\n\n- [x] *done*\n\n```{note} arg\nbody\n```\n";
+    let json = stream_to_json(parse(source, &Default::default()));
+    let events = stream_from_json(&json).unwrap();
+    assert_eq!(stream_to_json(events.into_iter()), json);
+    insta::assert_snapshot!(json);
+}
@@ -0,0 +1,218 @@
+//! Routes documents through different pipelines.
+//!
+//! A site usually has different kinds of content: guides, API references,
+//! changelogs.  Each of them wants its own processors and sometimes its own
+//! output format.  [`Layouts`] holds a [`Layout`] per kind and selects it
+//! by a front matter key so that all documents can be built in one run:
+//!
+//! ```
+//! use struckdown::layouts::Layouts;
+//!
+//! let layouts: Layouts = serde_yaml::from_str("
+//! default:
+//!   processors:
+//!     - processor: auto_anchors
+//! layouts:
+//!   api-reference:
+//!     processors:
+//!       - processor: api_signatures
+//!   notes:
+//!     renderer:
+//!       format: markdown
+//! ").unwrap();
+//! let html = layouts.render("---\nlayout: api-reference\n---\n# Reference");
+//! let markdown = layouts.render("---\nlayout: notes\n---\n# Notes");
+//! ```
+//!
+//! Documents without the key use the default layout.  Documents naming an
+//! unknown layout use it as well and get an error.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::asciidoc::{to_asciidoc, AsciiDocRendererOptions};
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, DocumentStartEvent, ErrorEvent, Event};
+use crate::html::{to_html, HtmlRendererOptions};
+use crate::markdown::{to_markdown, MarkdownRendererOptions};
+use crate::pipeline::Pipeline;
+
+/// The output format of a layout.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "format", rename_all = "snake_case")]
+pub enum Renderer {
+    /// Renders HTML.
+    Html(HtmlRendererOptions),
+    /// Renders markdown.
+    Markdown(MarkdownRendererOptions),
+    /// Renders AsciiDoc.
+    Asciidoc(AsciiDocRendererOptions),
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer::Html(Default::default())
+    }
+}
+
+impl Renderer {
+    /// Renders an event stream.
+    pub fn render<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(&self, iter: I) -> String {
+        match *self {
+            Renderer::Html(ref options) => to_html(iter, options),
+            Renderer::Markdown(ref options) => to_markdown(iter, options),
+            Renderer::Asciidoc(ref options) => to_asciidoc(iter, options),
+        }
+    }
+}
+
+/// The processors and output format for a kind of document.
+///
+/// This deserializes like a [`Pipeline`] with an additional `renderer`.
+#[derive(Deserialize, Default)]
+pub struct Layout {
+    /// The pipeline documents are processed with.
+    #[serde(flatten)]
+    pub pipeline: Pipeline,
+    /// The output format.
+    #[serde(default)]
+    pub renderer: Renderer,
+}
+
+/// Selects the layout of documents by their front matter.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Layouts {
+    /// The front matter key naming the layout.
+    pub key: String,
+    /// The layout of documents that do not name one.
+    pub default: Layout,
+    /// The layouts by name.
+    pub layouts: BTreeMap<String, Layout>,
+}
+
+impl Default for Layouts {
+    fn default() -> Layouts {
+        Layouts {
+            key: "layout".into(),
+            default: Layout::default(),
+            layouts: BTreeMap::new(),
+        }
+    }
+}
+
+impl Layouts {
+    /// Creates layouts with a default layout.
+    pub fn new(default: Layout) -> Layouts {
+        Layouts {
+            default,
+            ..Layouts::default()
+        }
+    }
+
+    /// Adds a named layout.
+    pub fn add_layout<S: Into<String>>(&mut self, name: S, layout: Layout) {
+        self.layouts.insert(name.into(), layout);
+    }
+
+    /// Returns the name of the layout a document asks for.
+    ///
+    /// The front matter is parsed with the parser options of the default
+    /// layout.
+    pub fn layout_name(&self, source: &str) -> Option<String> {
+        match self.default.pipeline.parser().parse(source).next()?.event {
+            Event::DocumentStart(DocumentStartEvent {
+                front_matter: Some(ref front_matter),
+            }) => front_matter
+                .get(&self.key)
+                .and_then(|x| x.as_str())
+                .map(|x| x.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Selects the layout of a document.
+    ///
+    /// Fails with the name of the layout if it is unknown.
+    pub fn select(&self, source: &str) -> Result<&Layout, String> {
+        match self.layout_name(source) {
+            Some(name) => self.layouts.get(&name).ok_or(name),
+            None => Ok(&self.default),
+        }
+    }
+
+    /// Parses and processes a document with its layout.
+    pub fn process<'data, 'options: 'data>(
+        &'options self,
+        source: &'data str,
+    ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
+        match self.select(source) {
+            Ok(layout) => layout.pipeline.process(source),
+            Err(name) => {
+                let mut iter = self.default.pipeline.process(source);
+                let error = ErrorEvent {
+                    title: format!("Unknown layout '{}'", name).into(),
+                    description: Some(
+                        format!("known layouts are: {}", self.known_layouts()).into(),
+                    ),
+                    code: Some(Code::UnknownLayout),
+                };
+                // the error goes right after the document start
+                Box::new(
+                    iter.next()
+                        .into_iter()
+                        .chain(Some(error.into()))
+                        .chain(iter),
+                )
+            }
+        }
+    }
+
+    /// Processes and renders a document with its layout.
+    pub fn render(&self, source: &str) -> String {
+        let renderer = match self.select(source) {
+            Ok(layout) => &layout.renderer,
+            Err(_) => &self.default.renderer,
+        };
+        renderer.render(self.process(source))
+    }
+
+    fn known_layouts(&self) -> String {
+        self.layouts
+            .keys()
+            .map(|x| x.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[test]
+fn test_layouts() {
+    let layouts: Layouts = serde_yaml::from_str(
+        "
+key: kind
+default:
+  processors:
+    - processor: auto_anchors
+layouts:
+  plain: {}
+  notes:
+    renderer:
+      format: markdown
+",
+    )
+    .unwrap();
+
+    assert_eq!(layouts.layout_name("# Guide"), None);
+    assert_eq!(
+        layouts.layout_name("---\nkind: notes\n---\n").as_deref(),
+        Some("notes")
+    );
+    insta::assert_snapshot!([
+        layouts.render("# Guide"),
+        layouts.render("---\nkind: plain\n---\n# Plain"),
+        layouts.render("---\nkind: notes\n---\n# Notes\n\n* one"),
+        layouts.render("---\nkind: missing\n---\n# Missing"),
+    ]
+    .join("\n---\n"));
+}
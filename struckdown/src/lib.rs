@@ -28,13 +28,50 @@
 //! // render to html
 //! let html = to_html(stream, &Default::default());
 //! ~~~
+pub mod anchors;
+pub mod annotations;
+pub mod asciidoc;
+pub mod assets;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod codes;
+pub mod diagnostics;
+pub mod document;
+pub mod dry_run;
+pub mod duplicates;
+pub mod edit;
 pub mod event;
 pub mod html;
+pub mod inventory;
+pub mod json;
+pub mod layouts;
+pub mod limits;
+pub mod locales;
+pub mod lossy;
+pub mod markdown;
+pub mod navigation;
+pub mod outline;
 pub mod parser;
+pub mod partial;
 pub mod pipeline;
+pub mod print;
 pub mod processors;
+#[cfg(feature = "external-processor")]
+pub mod protocol;
+pub mod redirects;
+pub mod sandbox;
+pub mod search;
+pub mod tasks;
+pub mod theme;
+pub mod tree;
 
 // internal only for now
+#[cfg(feature = "html-import")]
+mod html_import;
+#[cfg(feature = "notebook-import")]
+mod notebook_import;
+#[cfg(feature = "org-import")]
+mod org_import;
 mod plain;
 
 /// Gives access to [`serde_json`] value functionality.
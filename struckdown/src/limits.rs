@@ -0,0 +1,270 @@
+//! Bounds the resources spent on a document.
+//!
+//! Services rendering untrusted content need to bound memory and CPU time
+//! per document.  [`Limits`] are part of the
+//! [`ParserOptions`](crate::parser::ParserOptions) so they are under the
+//! control of the host rather than the document.  The parser refuses
+//! oversized input and enforces the limits on the parsed stream, a
+//! [`Pipeline`](crate::pipeline::Pipeline) enforces them again after its
+//! processors ran:
+//!
+//! ```
+//! use struckdown::event::Event;
+//! use struckdown::limits::Limits;
+//! use struckdown::parser::{parse, ParserOptions};
+//!
+//! let options = ParserOptions {
+//!     limits: Limits {
+//!         max_nesting_depth: Some(4),
+//!         ..Limits::default()
+//!     },
+//!     ..ParserOptions::default()
+//! };
+//! let events: Vec<_> = parse("> > > > > deep", &options).collect();
+//! assert!(events.iter().any(|x| matches!(x.event, Event::Error(..))));
+//! ```
+//!
+//! When a limit is exceeded an error is emitted.  Oversized directives are
+//! replaced by the error, otherwise the stream ends after the error with
+//! all open tags closed so that it stays well formed.
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, DirectiveEvent, ErrorEvent, Event, StartTagEvent, Tag};
+
+/// Limits for the size and complexity of documents.
+///
+/// The default does not limit anything.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Limits {
+    /// The maximum size of the source in bytes.
+    pub max_input_size: Option<usize>,
+    /// The maximum number of nested tags.
+    pub max_nesting_depth: Option<usize>,
+    /// The maximum number of events.
+    pub max_events: Option<usize>,
+    /// The maximum size of the body of a directive in bytes.
+    pub max_directive_body_size: Option<usize>,
+}
+
+fn limit_error(code: Code, title: &str, description: String) -> ErrorEvent<'static> {
+    ErrorEvent {
+        title: title.to_string().into(),
+        description: Some(description.into()),
+        code: Some(code),
+    }
+}
+
+impl Limits {
+    /// Returns `true` if nothing is limited.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Limits::default()
+    }
+
+    /// Checks the size of the source.
+    pub fn check_input(&self, source: &str) -> Result<(), ErrorEvent<'static>> {
+        match self.max_input_size {
+            Some(max) if source.len() > max => Err(limit_error(
+                Code::InputTooLarge,
+                "Input too large",
+                format!(
+                    "the document has {} bytes, the limit is {} bytes",
+                    source.len(),
+                    max
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Enforces the limits on a stream.
+    pub fn apply<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>(
+        &'options self,
+        iter: I,
+    ) -> LimitsIter<'data, 'options, I> {
+        LimitsIter::new(iter, Cow::Borrowed(self))
+    }
+}
+
+/// The iterator enforcing [`Limits`].
+pub struct LimitsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    limits: Cow<'options, Limits>,
+    events: usize,
+    open_tags: Vec<Tag>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    done: bool,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> LimitsIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Limits>>>(iterator: I, limits: O) -> Self {
+        Self {
+            source: iterator,
+            limits: limits.into(),
+            events: 0,
+            open_tags: vec![],
+            buffer: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Emits the error and ends the stream after closing all open tags.
+    fn abort(&mut self, error: ErrorEvent<'static>, annotated_event: AnnotatedEvent<'data>) {
+        self.done = true;
+        self.buffer.extend(
+            self.open_tags
+                .drain(..)
+                .rev()
+                .map(|tag| AnnotatedEvent::from(tag.end_tag())),
+        );
+        self.buffer
+            .push_back(AnnotatedEvent::new(error, annotated_event.location));
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for LimitsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+        if self.done {
+            return None;
+        }
+
+        let annotated_event = self.source.next()?;
+        self.events += 1;
+        if let Some(max) = self.limits.max_events {
+            if self.events > max {
+                let error = limit_error(
+                    Code::TooManyEvents,
+                    "Too many events",
+                    format!("the document exceeds the limit of {} events", max),
+                );
+                self.abort(error, annotated_event);
+                return self.buffer.pop_front();
+            }
+        }
+
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => {
+                if let Some(max) = self.limits.max_nesting_depth {
+                    if self.open_tags.len() >= max {
+                        let error = limit_error(
+                            Code::NestingTooDeep,
+                            "Nesting too deep",
+                            format!("the document exceeds the limit of {} nested tags", max),
+                        );
+                        self.abort(error, annotated_event);
+                        return self.buffer.pop_front();
+                    }
+                }
+                self.open_tags.push(tag);
+            }
+            Event::EndTag(..) => {
+                self.open_tags.pop();
+            }
+            Event::Directive(DirectiveEvent {
+                ref name, ref body, ..
+            }) => {
+                if let Some(max) = self.limits.max_directive_body_size {
+                    if body.as_str().len() > max {
+                        return Some(AnnotatedEvent::new(
+                            limit_error(
+                                Code::DirectiveTooLarge,
+                                "Directive too large",
+                                format!(
+                                    "the body of the {} directive has {} bytes, the limit is {} bytes",
+                                    name.as_str(),
+                                    body.as_str().len(),
+                                    max
+                                ),
+                            ),
+                            annotated_event.location,
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Some(annotated_event)
+    }
+}
+
+#[test]
+fn test_limits() {
+    use crate::parser::{parse, ParserOptions};
+
+    let parse_with = |source: &str, limits: Limits| -> Vec<Event<'static>> {
+        let options = ParserOptions {
+            limits,
+            ..ParserOptions::default()
+        };
+        parse(source, &options)
+            .map(|x| x.event.into_owned())
+            .collect()
+    };
+    let error = |events: &[Event<'_>]| -> String {
+        events
+            .iter()
+            .find_map(|x| match x {
+                Event::Error(ErrorEvent { title, .. }) => Some(title.as_str().to_string()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    };
+
+    let events = parse_with(
+        "Hello World!",
+        Limits {
+            max_input_size: Some(5),
+            ..Limits::default()
+        },
+    );
+    assert_eq!(events.len(), 2);
+    assert_eq!(error(&events), "Input too large");
+
+    let events = parse_with(
+        "- a\n  - b\n    - c\n      - d\n",
+        Limits {
+            max_nesting_depth: Some(3),
+            ..Limits::default()
+        },
+    );
+    assert_eq!(error(&events), "Nesting too deep");
+    let depth = events.iter().fold(0, |depth, event| match event {
+        Event::StartTag(..) => depth + 1,
+        Event::EndTag(..) => depth - 1,
+        _ => depth,
+    });
+    assert_eq!(depth, 0);
+
+    let events = parse_with(
+        "a\n\nb\n\nc\n\nd\n",
+        Limits {
+            max_events: Some(5),
+            ..Limits::default()
+        },
+    );
+    assert_eq!(error(&events), "Too many events");
+    assert_eq!(events.len(), 7);
+    assert!(matches!(events[5], Event::EndTag(..)));
+
+    let events = parse_with(
+        "```{note}\nA long note.\n```\n\nAfter",
+        Limits {
+            max_directive_body_size: Some(4),
+            ..Limits::default()
+        },
+    );
+    assert_eq!(error(&events), "Directive too large");
+    assert!(!events.iter().any(|x| matches!(x, Event::Directive(..))));
+    assert!(events.iter().any(|x| matches!(x, Event::Text(..))));
+}
@@ -0,0 +1,388 @@
+//! Supports sites with one subtree per language.
+//!
+//! Multi-language sites keep the translations of a document in language
+//! subtrees (`/en/guide/`, `/de/guide/`).  A [`LocaleSite`] is filled with
+//! the documents of all subtrees and knows for every document which
+//! translations exist.  From that it provides:
+//!
+//! * the alternate versions of a document for `hreflang` links,
+//! * fallback pages rendering the default language where a translation is
+//!   missing,
+//! * one reference database per language so that cross-references stay
+//!   within the language of the document,
+//! * a report of the documents that are not translated yet.
+//!
+//! ```
+//! use struckdown::locales::{LocaleOptions, LocaleSite};
+//!
+//! let mut site = LocaleSite::new(LocaleOptions {
+//!     default_locale: "en".into(),
+//!     locales: vec!["en".into(), "de".into()],
+//! });
+//! site.add_document("/en/");
+//! site.add_document("/en/guide/");
+//! site.add_document("/de/");
+//! assert_eq!(site.resolve("de", "/guide/"), Some(("/en/guide/", true)));
+//! assert_eq!(site.untranslated()[0].missing, vec!["/en/guide/".to_string()]);
+//! ```
+//!
+//! Paths outside of the language subtrees are not localized.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use v_htmlescape::escape;
+
+use crate::event::{AnnotatedEvent, MetaDataEvent};
+use crate::processors::{Reference, ReferenceDatabase};
+use crate::value::to_value;
+
+/// Configures the languages of a site.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LocaleOptions {
+    /// The language used where a translation is missing.
+    pub default_locale: String,
+    /// All languages of the site, each in a subtree of the same name.
+    pub locales: Vec<String>,
+}
+
+impl Default for LocaleOptions {
+    fn default() -> LocaleOptions {
+        LocaleOptions {
+            default_locale: "en".into(),
+            locales: vec!["en".into()],
+        }
+    }
+}
+
+/// A version of a document in a language.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Alternate {
+    /// The language of the version or `x-default` for the version shown to
+    /// users whose language is not available.
+    pub hreflang: String,
+    /// The path of the version.
+    pub url: String,
+}
+
+/// The localization of a single document.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Localization {
+    /// The language of the document.
+    pub locale: String,
+    /// The path of the document relative to the language subtree.
+    pub path: String,
+    /// The path of the default language document if this is a fallback
+    /// page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+    /// The translations of the document.
+    pub alternates: Vec<Alternate>,
+}
+
+/// A page rendering a default language document in place of a missing
+/// translation.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FallbackPage {
+    /// The language the translation is missing in.
+    pub locale: String,
+    /// The path the translation would have.
+    pub url: String,
+    /// The path of the default language document to render.
+    pub source: String,
+}
+
+/// The translation status of a language.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UntranslatedReport {
+    /// The language.
+    pub locale: String,
+    /// The number of default language documents that are translated.
+    pub translated: usize,
+    /// The default language documents without translation.
+    pub missing: Vec<String>,
+}
+
+/// The documents of a site with language subtrees.
+#[derive(Debug, Clone, Default)]
+pub struct LocaleSite {
+    options: LocaleOptions,
+    // relative path -> locale -> path
+    documents: BTreeMap<String, BTreeMap<String, String>>,
+    references: BTreeMap<String, ReferenceDatabase>,
+}
+
+impl LocaleSite {
+    /// Creates an empty site.
+    pub fn new(options: LocaleOptions) -> LocaleSite {
+        LocaleSite {
+            options,
+            documents: BTreeMap::new(),
+            references: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the options of the site.
+    pub fn options(&self) -> &LocaleOptions {
+        &self.options
+    }
+
+    /// Splits a path into language and path within the language subtree.
+    ///
+    /// `/de/guide/` is split into `de` and `/guide/`.  Returns `None` for
+    /// paths outside of the language subtrees.
+    pub fn split_path<'p>(&self, path: &'p str) -> Option<(&'p str, &'p str)> {
+        let rest = path.strip_prefix('/')?;
+        let (locale, rest) = match rest.find('/') {
+            Some(idx) => (&rest[..idx], &rest[idx..]),
+            None => (rest, "/"),
+        };
+        if self.options.locales.iter().any(|x| x == locale) {
+            Some((locale, rest))
+        } else {
+            None
+        }
+    }
+
+    /// Registers a document by path.
+    ///
+    /// Returns `false` if the path is outside of the language subtrees.
+    pub fn add_document(&mut self, path: &str) -> bool {
+        let (locale, relative) = match self.split_path(path) {
+            Some(rv) => rv,
+            None => return false,
+        };
+        self.documents
+            .entry(relative.to_string())
+            .or_default()
+            .insert(locale.to_string(), path.to_string());
+        true
+    }
+
+    /// Registers a processed document and adds its title and anchors to
+    /// the reference database of its language.
+    pub fn add_events(&mut self, path: &str, events: &[AnnotatedEvent<'_>]) -> bool {
+        if !self.add_document(path) {
+            return false;
+        }
+        let locale = self.split_path(path).unwrap().0.to_string();
+        self.references
+            .entry(locale)
+            .or_default()
+            .add_document(path, events);
+        true
+    }
+
+    /// Finds the document to show for a path within a language subtree.
+    ///
+    /// Returns the path of the document and whether it is a fallback to the
+    /// default language.
+    pub fn resolve(&self, locale: &str, path: &str) -> Option<(&str, bool)> {
+        let translations = self.documents.get(path)?;
+        if let Some(rv) = translations.get(locale) {
+            return Some((rv.as_str(), false));
+        }
+        translations
+            .get(&self.options.default_locale)
+            .map(|x| (x.as_str(), true))
+    }
+
+    /// Returns the translations of a document including the `x-default`
+    /// version.
+    ///
+    /// Fallback pages are not listed as they do not hold a translation.
+    pub fn alternates(&self, path: &str) -> Vec<Alternate> {
+        let translations = match self
+            .split_path(path)
+            .and_then(|(_, relative)| self.documents.get(relative))
+        {
+            Some(translations) => translations,
+            None => return vec![],
+        };
+        let mut rv: Vec<Alternate> = self
+            .options
+            .locales
+            .iter()
+            .filter_map(|locale| {
+                translations.get(locale).map(|url| Alternate {
+                    hreflang: locale.clone(),
+                    url: url.clone(),
+                })
+            })
+            .collect();
+        if let Some(url) = translations.get(&self.options.default_locale) {
+            rv.push(Alternate {
+                hreflang: "x-default".into(),
+                url: url.clone(),
+            });
+        }
+        rv
+    }
+
+    /// Returns the localization of a document or fallback page.
+    pub fn get(&self, path: &str) -> Option<Localization> {
+        let (locale, relative) = self.split_path(path)?;
+        let (source, fallback) = self.resolve(locale, relative)?;
+        Some(Localization {
+            locale: locale.to_string(),
+            path: relative.to_string(),
+            fallback: if fallback {
+                Some(source.to_string())
+            } else {
+                None
+            },
+            alternates: self.alternates(source),
+        })
+    }
+
+    /// Returns a `localization` meta data event for a document.
+    pub fn metadata_event(&self, path: &str) -> Option<MetaDataEvent<'static>> {
+        Some(MetaDataEvent {
+            key: "localization".into(),
+            value: to_value(&self.get(path)?).expect("bad localization"),
+        })
+    }
+
+    /// Renders the `<link rel="alternate">` tags for a document.
+    pub fn render_alternate_links(&self, path: &str) -> String {
+        self.alternates(path)
+            .iter()
+            .map(|alternate| {
+                format!(
+                    "<link rel=\"alternate\" hreflang=\"{}\" href=\"{}\">\n",
+                    escape(&alternate.hreflang),
+                    escape(&alternate.url)
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the pages to generate for missing translations.
+    ///
+    /// Every default language document without translation gets a page in
+    /// each other language rendering the default language document.
+    pub fn fallback_pages(&self) -> Vec<FallbackPage> {
+        let mut rv = vec![];
+        for locale in &self.options.locales {
+            if *locale == self.options.default_locale {
+                continue;
+            }
+            for (relative, translations) in &self.documents {
+                if let (None, Some(source)) = (
+                    translations.get(locale),
+                    translations.get(&self.options.default_locale),
+                ) {
+                    rv.push(FallbackPage {
+                        locale: locale.clone(),
+                        url: format!("/{}{}", locale, relative),
+                        source: source.clone(),
+                    });
+                }
+            }
+        }
+        rv
+    }
+
+    /// Returns the reference database for a language.
+    ///
+    /// It holds the references of the documents in the language and of the
+    /// default language documents shown on its fallback pages, pointing to
+    /// the fallback pages.
+    pub fn reference_database(&self, locale: &str) -> ReferenceDatabase {
+        let mut rv = self.references.get(locale).cloned().unwrap_or_default();
+        if locale == self.options.default_locale {
+            return rv;
+        }
+        if let Some(default) = self.references.get(&self.options.default_locale) {
+            let fallbacks: BTreeMap<String, String> = self
+                .fallback_pages()
+                .into_iter()
+                .filter(|x| x.locale == locale)
+                .map(|x| (x.source, x.url))
+                .collect();
+            for reference in default.references() {
+                if let Some(url) = fallbacks.get(&reference.document) {
+                    rv.insert(Reference {
+                        document: url.clone(),
+                        ..reference.clone()
+                    });
+                }
+            }
+        }
+        rv
+    }
+
+    /// Reports the default language documents missing in the other
+    /// languages.
+    pub fn untranslated(&self) -> Vec<UntranslatedReport> {
+        self.options
+            .locales
+            .iter()
+            .filter(|x| **x != self.options.default_locale)
+            .map(|locale| {
+                let mut translated = 0;
+                let mut missing = vec![];
+                for translations in self.documents.values() {
+                    if let Some(source) = translations.get(&self.options.default_locale) {
+                        if translations.contains_key(locale) {
+                            translated += 1;
+                        } else {
+                            missing.push(source.clone());
+                        }
+                    }
+                }
+                UntranslatedReport {
+                    locale: locale.clone(),
+                    translated,
+                    missing,
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_locale_site() {
+    use crate::parser::parse;
+    use crate::processors::{AutoAnchors, Processor};
+
+    let sources = [
+        ("/en/", "# Welcome"),
+        ("/en/guide/", "# Guide\n\n## Setup"),
+        ("/en/faq/", "# FAQ\n\n## Licensing"),
+        ("/de/", "# Willkommen"),
+        ("/de/guide/", "# Anleitung\n\n## Einrichtung"),
+        ("/fr/guide/", "# Guide\n\n## Installation"),
+        ("/about/", "# About"),
+    ];
+    let mut site = LocaleSite::new(LocaleOptions {
+        default_locale: "en".into(),
+        locales: vec!["en".into(), "de".into(), "fr".into()],
+    });
+    for (path, source) in sources.iter() {
+        let events: Vec<_> = Box::new(AutoAnchors::default())
+            .apply(Box::new(parse(source, &Default::default())))
+            .collect();
+        assert_eq!(site.add_events(path, &events), *path != "/about/");
+    }
+
+    assert_eq!(site.split_path("/de"), Some(("de", "/")));
+    assert_eq!(site.split_path("/design/"), None);
+    assert_eq!(site.resolve("de", "/guide/"), Some(("/de/guide/", false)));
+    assert_eq!(site.resolve("fr", "/faq/"), Some(("/en/faq/", true)));
+    assert_eq!(site.resolve("fr", "/missing/"), None);
+    assert_eq!(site.alternates("/about/").len(), 0);
+    insta::assert_snapshot!(site.render_alternate_links("/de/guide/"));
+    insta::assert_yaml_snapshot!(&site.get("/fr/faq/"));
+    insta::assert_yaml_snapshot!(&site.fallback_pages());
+    insta::assert_yaml_snapshot!(&site.untranslated());
+
+    let database = site.reference_database("de");
+    assert_eq!(
+        database.lookup("einrichtung")[0].href(),
+        "/de/guide/#einrichtung"
+    );
+    assert_eq!(database.lookup("licensing")[0].href(), "/de/faq/#licensing");
+    assert!(database.lookup("setup").is_empty());
+    assert_eq!(site.reference_database("en").references().len(), 8);
+}
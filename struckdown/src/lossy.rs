@@ -0,0 +1,309 @@
+//! Reports content that renderers cannot represent.
+//!
+//! Not every output format can express everything an event stream holds:
+//! AsciiDoc has no spoilers and markdown has no way to keep the width of
+//! an image.  Renderers fall back to something simpler in such cases and
+//! record a [`LossyConversion`] so that the degradation per output format
+//! can be audited instead of being discovered visually:
+//!
+//! ```
+//! use struckdown::asciidoc::AsciiDocRenderer;
+//! use struckdown::lossy::Fallback;
+//! use struckdown::parser::parse;
+//!
+//! let options = Default::default();
+//! let mut renderer = AsciiDocRenderer::new_buffered(&options);
+//! let source = "Text\n\n```{graphviz}\ndigraph { a -> b }\n```";
+//! renderer.feed_stream(parse(source, &Default::default())).unwrap();
+//! let lossy = renderer.lossy_conversions();
+//! assert_eq!(lossy[0].element, "directive graphviz");
+//! assert_eq!(lossy[0].fallback, Fallback::Literal);
+//! assert_eq!(lossy[0].location.as_ref().unwrap().line, 3);
+//! ```
+//!
+//! Directives that reach a renderer were not handled by any processor.
+//! What the renderers do with them is configured by the same
+//! [`UnknownDirectives`] policy in all renderer options: render them as a
+//! literal block (the default), drop them or hand them to a hook.  Literal
+//! and dropped directives are recorded as lossy conversions, which
+//! [`LossyConversion::to_diagnostic_event`] turns into a diagnostic with
+//! the [`UnhandledDirective`](Code::UnhandledDirective) code:
+//!
+//! ```
+//! use struckdown::codes::Code;
+//! use struckdown::diagnostics::Diagnostic;
+//! use struckdown::event::{Event, TextEvent};
+//! use struckdown::html::{HtmlRenderer, HtmlRendererOptions};
+//! use struckdown::lossy::UnknownDirectives;
+//! use struckdown::parser::parse;
+//!
+//! let options = HtmlRendererOptions {
+//!     unknown_directives: UnknownDirectives::hook(|directive| {
+//!         if directive.name.as_str() != "todo" {
+//!             return None;
+//!         }
+//!         Some(vec![Event::Text(TextEvent { text: "TODO".into() })])
+//!     }),
+//!     ..Default::default()
+//! };
+//! let mut renderer = HtmlRenderer::new_buffered(&options);
+//! let source = "```{todo}\nWrite this\n```\n\n```{graphviz}\ndigraph { a -> b }\n```";
+//! renderer.feed_stream(parse(source, &Default::default())).unwrap();
+//! assert_eq!(renderer.lossy_conversions().len(), 1);
+//! let event = renderer.lossy_conversions()[0].to_diagnostic_event();
+//! let diagnostic = Diagnostic::from_event(&event).unwrap();
+//! assert_eq!(diagnostic.code, Some(Code::UnhandledDirective));
+//! assert!(renderer.into_string().starts_with("TODO<div class=\"directive-graphviz\">"));
+//! ```
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{
+    AnnotatedEvent, DiagnosticEvent, DirectiveEvent, Event, Location, Severity, Tag,
+};
+
+/// What a renderer did instead of representing an element.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Fallback {
+    /// The element was left out.
+    Dropped,
+    /// Only the contents of the element were rendered.
+    ContentsOnly,
+    /// The source of the element was rendered as a literal block.
+    Literal,
+    /// The raw markup was passed through to the output.
+    Passthrough,
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Fallback::Dropped => "dropped it",
+            Fallback::ContentsOnly => "rendered its contents only",
+            Fallback::Literal => "rendered it as literal block",
+            Fallback::Passthrough => "passed it through",
+        })
+    }
+}
+
+/// An element a renderer could not represent faithfully.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LossyConversion {
+    /// The output format (`html`, `asciidoc` or `markdown`).
+    pub format: String,
+    /// The element that was not represented (`spoiler`, `raw_html`,
+    /// `directive graphviz`).
+    pub element: String,
+    /// What was done instead.
+    pub fallback: Fallback,
+    /// The location of the element in the source.
+    pub location: Option<Location>,
+}
+
+impl LossyConversion {
+    /// Creates a lossy conversion for a tag.
+    pub(crate) fn tag(
+        format: &str,
+        tag: Tag,
+        fallback: Fallback,
+        location: Option<Location>,
+    ) -> LossyConversion {
+        LossyConversion {
+            format: format.into(),
+            element: serde_json::to_value(tag)
+                .ok()
+                .and_then(|x| x.as_str().map(|x| x.to_string()))
+                .unwrap_or_default(),
+            fallback,
+            location,
+        }
+    }
+
+    /// Returns a warning event reporting the conversion.
+    ///
+    /// Directives no processor handled are reported with the
+    /// [`UnhandledDirective`](Code::UnhandledDirective) code, everything
+    /// else with [`LossyConversion`](Code::LossyConversion).
+    pub fn to_diagnostic_event(&self) -> AnnotatedEvent<'static> {
+        let code = if self.element.starts_with("directive ") {
+            Code::UnhandledDirective
+        } else {
+            Code::LossyConversion
+        };
+        AnnotatedEvent::new(
+            DiagnosticEvent {
+                severity: Severity::Warning,
+                message: format!("{} cannot represent {}", self.format, self.element).into(),
+                description: Some(self.fallback.to_string().into()),
+                code: Some(code),
+            },
+            self.location.clone(),
+        )
+    }
+}
+
+impl fmt::Display for LossyConversion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref location) = self.location {
+            write!(f, "{}:{}: ", location.line, location.column)?;
+        }
+        write!(
+            f,
+            "{} cannot represent {}, {}",
+            self.format, self.element, self.fallback
+        )
+    }
+}
+
+/// A function rendering directives no processor handled.
+///
+/// It returns the events to render in place of the directive, or `None` to
+/// render it as literal block.
+pub type DirectiveHook =
+    Arc<dyn Fn(&DirectiveEvent<'_>) -> Option<Vec<Event<'static>>> + Send + Sync + 'static>;
+
+/// What renderers do with directives that reach them unhandled.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownDirectives {
+    /// The directive is rendered as literal block with its name marked.
+    #[default]
+    Literal,
+    /// The directive is left out.
+    Drop,
+    /// The directive is passed to a hook.
+    ///
+    /// This cannot be configured from serialized options.  Directives the
+    /// hook returns are rendered as literal blocks.
+    #[serde(skip)]
+    Hook(DirectiveHook),
+}
+
+impl fmt::Debug for UnknownDirectives {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnknownDirectives::Literal => write!(f, "Literal"),
+            UnknownDirectives::Drop => write!(f, "Drop"),
+            UnknownDirectives::Hook(..) => write!(f, "Hook(..)"),
+        }
+    }
+}
+
+/// What a renderer renders for an unhandled directive.
+pub(crate) enum DirectiveOutcome {
+    /// The directive is rendered as literal block or dropped.
+    Fallback(Fallback),
+    /// The events are rendered in place of the directive.
+    Replace(Vec<Event<'static>>),
+}
+
+impl UnknownDirectives {
+    /// Creates a policy passing directives to a hook.
+    pub fn hook<F>(hook: F) -> UnknownDirectives
+    where
+        F: Fn(&DirectiveEvent<'_>) -> Option<Vec<Event<'static>>> + Send + Sync + 'static,
+    {
+        UnknownDirectives::Hook(Arc::new(hook))
+    }
+
+    /// Returns `true` if directives are rendered as literal blocks.
+    pub(crate) fn is_literal(&self) -> bool {
+        matches!(self, UnknownDirectives::Literal)
+    }
+
+    /// Decides what to render for a directive.
+    pub(crate) fn resolve(&self, directive: &DirectiveEvent<'_>) -> DirectiveOutcome {
+        match self {
+            UnknownDirectives::Literal => DirectiveOutcome::Fallback(Fallback::Literal),
+            UnknownDirectives::Drop => DirectiveOutcome::Fallback(Fallback::Dropped),
+            UnknownDirectives::Hook(hook) => match hook(directive) {
+                Some(events) => DirectiveOutcome::Replace(events),
+                None => DirectiveOutcome::Fallback(Fallback::Literal),
+            },
+        }
+    }
+}
+
+#[test]
+fn test_unknown_directives() {
+    use crate::asciidoc::{AsciiDocRenderer, AsciiDocRendererOptions};
+    use crate::diagnostics::Diagnostic;
+    use crate::event::{Attrs, TextEvent};
+    use crate::html::{HtmlRenderer, HtmlRendererOptions};
+    use crate::markdown::{MarkdownRenderer, MarkdownRendererOptions};
+    use crate::parser::parse;
+
+    let source = "\
+Before
+
+```{graphviz}
+digraph { a -> b }
+```
+
+```{todo} Later
+Write this
+```
+
+```{note}
+Noted
+```
+";
+    let hook = UnknownDirectives::hook(|directive| match directive.name.as_str() {
+        "todo" => Some(vec![
+            Tag::Paragraph.start_tag(Attrs::default()).into(),
+            Event::Text(TextEvent {
+                text: format!("TODO: {}", directive.body.as_str().trim()).into(),
+            }),
+            Tag::Paragraph.end_tag().into(),
+        ]),
+        _ => None,
+    });
+
+    let mut rendered = String::new();
+    for policy in [UnknownDirectives::Drop, hook] {
+        let events = || parse(source, &Default::default());
+        let mut lossy = vec![];
+
+        let options = HtmlRendererOptions {
+            unknown_directives: policy.clone(),
+            ..Default::default()
+        };
+        let mut renderer = HtmlRenderer::new_buffered(&options);
+        renderer.feed_stream(events()).unwrap();
+        lossy.extend(renderer.lossy_conversions().to_vec());
+        rendered.push_str(&renderer.into_string());
+        rendered.push_str("---\n");
+
+        let options = AsciiDocRendererOptions {
+            unknown_directives: policy.clone(),
+            ..Default::default()
+        };
+        let mut renderer = AsciiDocRenderer::new_buffered(&options);
+        renderer.feed_stream(events()).unwrap();
+        lossy.extend(renderer.lossy_conversions().to_vec());
+        rendered.push_str(&renderer.into_string());
+        rendered.push_str("---\n");
+
+        let options = MarkdownRendererOptions {
+            unknown_directives: policy,
+            ..Default::default()
+        };
+        let mut renderer = MarkdownRenderer::new_buffered(&options);
+        renderer.feed_stream(events()).unwrap();
+        lossy.extend(renderer.lossy_conversions().to_vec());
+        rendered.push_str(&renderer.into_string());
+        rendered.push_str("---\n");
+
+        for conversion in lossy {
+            let event = conversion.to_diagnostic_event();
+            let diagnostic = Diagnostic::from_event(&event).unwrap();
+            rendered.push_str(&format!("{}\n", diagnostic));
+        }
+        rendered.push_str("===\n");
+    }
+    insta::assert_snapshot!(rendered);
+}
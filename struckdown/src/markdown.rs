@@ -0,0 +1,1293 @@
+//! Renders event streams back into markdown.
+//!
+//! Elements without a markdown equivalent are recorded as
+//! [`lossy_conversions`](MarkdownRenderer::lossy_conversions).
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    fingerprint, Alignment, AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, DirectiveEvent,
+    DocumentStartEvent, EndTagEvent, Event, FootnoteReferenceEvent, ImageEvent, InlineCodeEvent,
+    InlineMathEvent, InterpretedTextEvent, Location, MathBlockEvent, RawHtmlEvent, StartTagEvent,
+    Tag, TextEvent, Trivia,
+};
+use crate::lossy::{DirectiveOutcome, Fallback, LossyConversion, UnknownDirectives};
+
+lazy_static! {
+    static ref BLOCK_START_RE: Regex = Regex::new(r"^(?:[-+*>=]+|#{1,6}|\d{1,9}[.)])$").unwrap();
+    static ref SENTENCE_END_RE: Regex = Regex::new(r#"[.!?]["'’”)\]*_]*$"#).unwrap();
+}
+
+/// Marks a hard break in buffered inline text.
+const HARD_BREAK: char = '\u{0}';
+
+/// Controls how paragraphs are wrapped.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LineWrapping {
+    /// Keeps the line breaks of the source.
+    Preserve,
+    /// Reflows paragraphs to fit into `wrap_width` columns.
+    Wrap,
+    /// Puts every sentence on its own line.
+    SentencePerLine,
+}
+
+/// Controls how links and images are written.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkStyle {
+    /// Writes targets inline (`[text](target)`).
+    Inline,
+    /// Writes numbered references (`[text][1]`) and collects the
+    /// definitions at the end of the document.
+    Reference,
+}
+
+/// Customizes the markdown rendering.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct MarkdownRendererOptions {
+    /// How paragraphs are wrapped.
+    pub line_wrapping: LineWrapping,
+    /// The maximum line width for [`LineWrapping::Wrap`].
+    pub wrap_width: usize,
+    /// How links and images are written.
+    pub link_style: LinkStyle,
+    /// What happens to directives.
+    ///
+    /// Markdown can hold directives, so by default they are written as they
+    /// are.  Processors should have handled them when rendering for other
+    /// markdown implementations.
+    pub unknown_directives: UnknownDirectives,
+}
+
+impl Default for MarkdownRendererOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MarkdownRendererOptions {
+    /// Creates the default configuration for the renderer options.
+    pub fn new() -> MarkdownRendererOptions {
+        MarkdownRendererOptions {
+            line_wrapping: LineWrapping::Preserve,
+            wrap_width: 80,
+            link_style: LinkStyle::Inline,
+            unknown_directives: UnknownDirectives::default(),
+        }
+    }
+}
+
+/// A line prefix contributed by a container such as a block quote.
+struct Prefix {
+    first: String,
+    rest: String,
+    used: bool,
+}
+
+/// Buffers a table until it can be rendered.
+#[derive(Default)]
+struct TableState {
+    alignments: Vec<Alignment>,
+    rows: Vec<Vec<String>>,
+}
+
+/// A top level block buffered until it is known if it changed.
+struct Verbatim {
+    trivia: Trivia<'static>,
+    depth: usize,
+    events: Vec<AnnotatedEvent<'static>>,
+}
+
+/// Escapes text so that it does not turn into markup.
+fn escape(text: &str) -> String {
+    let mut rv = String::with_capacity(text.len());
+    for c in text.chars() {
+        if let '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '$' = c {
+            rv.push('\\');
+        }
+        rv.push(c);
+    }
+    rv
+}
+
+/// Escapes a block marker at the start of a line of paragraph text.
+///
+/// Text that ends up at the start of a line after wrapping would otherwise
+/// turn into a heading, list item, quote or fence when parsed again.
+fn escape_block_marker(line: String) -> String {
+    let trimmed = line.trim_end();
+    let first = match line.chars().next() {
+        Some(first) => first,
+        None => return line,
+    };
+    let rest = &line[first.len_utf8()..];
+    let is_marker = match first {
+        '>' => true,
+        '#' => {
+            let after = line.trim_start_matches('#');
+            line.len() - after.len() <= 6 && (after.is_empty() || after.starts_with(' '))
+        }
+        '+' => rest.is_empty() || rest.starts_with(' '),
+        '-' => rest.is_empty() || rest.starts_with(' ') || trimmed.chars().all(|c| c == '-'),
+        '=' => trimmed.chars().all(|c| c == '='),
+        '~' => line.starts_with("~~~"),
+        '0'..='9' => {
+            let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let after = &line[digits..];
+            if digits <= 9
+                && (after.starts_with('.') || after.starts_with(')'))
+                && (after.len() == 1 || after[1..].starts_with(' '))
+            {
+                return format!("{}\\{}", &line[..digits], after);
+            }
+            false
+        }
+        _ => false,
+    };
+    if is_marker {
+        format!("\\{}", line)
+    } else {
+        line
+    }
+}
+
+/// Formats a link destination, using the `<…>` form where needed.
+fn link_destination(target: &str) -> Cow<'_, str> {
+    if target.is_empty() || target.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+        Cow::Owned(format!(
+            "<{}>",
+            target.replace('<', "\\<").replace('>', "\\>")
+        ))
+    } else {
+        Cow::Borrowed(target)
+    }
+}
+
+/// Returns a backtick fence that is longer than any run in the text.
+fn backtick_fence(text: &str, min: usize) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    "`".repeat((longest + 1).max(min))
+}
+
+/// Returns the fence recorded in the trivia if it still fits the text.
+fn source_fence(trivia: Option<&Trivia<'_>>, text: &str, min: usize) -> String {
+    let fence = backtick_fence(text, min);
+    match trivia.and_then(|x| x.fence.as_ref()).map(|x| x.as_str()) {
+        Some(source) if source.starts_with('`') && source.len() >= fence.len() => source.into(),
+        Some(source)
+            if source.starts_with('~')
+                && !text.lines().any(|x| x.trim_start().starts_with(source)) =>
+        {
+            source.into()
+        }
+        _ => fence,
+    }
+}
+
+/// Formats a span of inline code.
+fn inline_code(code: &str) -> String {
+    let fence = backtick_fence(code, 1);
+    if code.starts_with('`') || code.ends_with('`') {
+        format!("{} {} {}", fence, code, fence)
+    } else {
+        format!("{}{}{}", fence, code, fence)
+    }
+}
+
+/// Appends a word to the last line unless it would start a block there.
+fn push_word(lines: &mut Vec<String>, word: &str, new_line: bool) {
+    if lines.is_empty() || (new_line && !BLOCK_START_RE.is_match(word)) {
+        lines.push(word.to_string());
+    } else {
+        let line = lines.last_mut().unwrap();
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+}
+
+/// Breaks a paragraph into lines.
+fn reflow(text: &str, wrapping: LineWrapping, width: usize) -> Vec<String> {
+    let mut rv = vec![];
+    let segments: Vec<&str> = text.trim_matches(' ').split(HARD_BREAK).collect();
+
+    for (idx, segment) in segments.iter().enumerate() {
+        let mut lines = vec![];
+        match wrapping {
+            LineWrapping::Preserve => {
+                lines.extend(segment.split('\n').map(|x| x.trim().to_string()));
+            }
+            LineWrapping::Wrap => {
+                for word in segment.split_whitespace() {
+                    let fits = match lines.last() {
+                        Some(line) => line.chars().count() + 1 + word.chars().count() <= width,
+                        None => false,
+                    };
+                    push_word(&mut lines, word, !fits);
+                }
+            }
+            LineWrapping::SentencePerLine => {
+                let mut sentence_ended = false;
+                for word in segment.split_whitespace() {
+                    let starts_sentence = match word.chars().find(|c| c.is_alphanumeric()) {
+                        Some(c) => c.is_uppercase() || c.is_numeric(),
+                        None => false,
+                    };
+                    push_word(&mut lines, word, sentence_ended && starts_sentence);
+                    sentence_ended = SENTENCE_END_RE.is_match(word);
+                }
+            }
+        }
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        if idx + 1 < segments.len() {
+            lines.last_mut().unwrap().push('\\');
+        }
+        rv.extend(lines);
+    }
+
+    rv
+}
+
+/// Object capable of rendering events back to markdown.
+pub struct MarkdownRenderer<'options, F> {
+    out: F,
+    options: &'options MarkdownRendererOptions,
+    prefixes: Vec<Prefix>,
+    blocks: Vec<Tag>,
+    lists: Vec<Option<u32>>,
+    loose_items: Vec<bool>,
+    links: Vec<String>,
+    references: Vec<(String, Option<String>)>,
+    inline: String,
+    heading: Option<(usize, Option<String>)>,
+    table: Option<TableState>,
+    blank_pending: bool,
+    wrote_any: bool,
+    in_html: bool,
+    open_line: bool,
+    location: Option<Location>,
+    trivia: Option<Trivia<'static>>,
+    verbatim: Option<Verbatim>,
+    lossy: Vec<LossyConversion>,
+}
+
+impl<'options, F: Write> MarkdownRenderer<'options, F> {
+    /// Creates a new renderer that writes into a writer.
+    pub fn new(
+        out: F,
+        options: &'options MarkdownRendererOptions,
+    ) -> MarkdownRenderer<'options, F> {
+        MarkdownRenderer {
+            out,
+            options,
+            prefixes: vec![],
+            blocks: vec![],
+            lists: vec![],
+            loose_items: vec![],
+            links: vec![],
+            references: vec![],
+            inline: String::new(),
+            heading: None,
+            table: None,
+            blank_pending: false,
+            wrote_any: false,
+            in_html: false,
+            open_line: false,
+            location: None,
+            trivia: None,
+            verbatim: None,
+            lossy: vec![],
+        }
+    }
+
+    /// Consumes the writer and returns the inner file.
+    pub fn into_writer(self) -> F {
+        self.out
+    }
+
+    /// Returns the elements that could not be represented so far.
+    pub fn lossy_conversions(&self) -> &[LossyConversion] {
+        &self.lossy
+    }
+
+    fn report_tag(&mut self, tag: Tag, fallback: Fallback) {
+        self.lossy.push(LossyConversion::tag(
+            "markdown",
+            tag,
+            fallback,
+            self.location.clone(),
+        ));
+    }
+
+    /// Ends a line left open by verbatim source.
+    fn close_line(&mut self) -> Result<(), io::Error> {
+        if self.open_line {
+            writeln!(self.out)?;
+            self.open_line = false;
+        }
+        Ok(())
+    }
+
+    fn write_blank_if_pending(&mut self) -> Result<(), io::Error> {
+        self.close_line()?;
+        if self.blank_pending && self.wrote_any {
+            let prefix: String = self
+                .prefixes
+                .iter()
+                .filter(|x| x.used)
+                .map(|x| x.rest.as_str())
+                .collect();
+            writeln!(self.out, "{}", prefix.trim_end())?;
+        }
+        self.blank_pending = false;
+        Ok(())
+    }
+
+    fn push_prefix(&mut self, first: String, rest: String) -> Result<(), io::Error> {
+        self.flush_inline()?;
+        self.write_blank_if_pending()?;
+        self.prefixes.push(Prefix {
+            first,
+            rest,
+            used: false,
+        });
+        Ok(())
+    }
+
+    fn pop_prefix(&mut self) -> Result<(), io::Error> {
+        self.flush_inline()?;
+        if let Some(prefix) = self.prefixes.pop() {
+            // containers without content still need their marker
+            if !prefix.used {
+                self.prefixes.push(prefix);
+                self.write_lines(&[String::new()])?;
+                self.prefixes.pop();
+            }
+        }
+        Ok(())
+    }
+
+    fn write_lines(&mut self, lines: &[String]) -> Result<(), io::Error> {
+        self.close_line()?;
+        for line in lines {
+            let mut prefix = String::new();
+            for item in self.prefixes.iter_mut() {
+                if item.used {
+                    prefix.push_str(&item.rest);
+                } else {
+                    prefix.push_str(&item.first);
+                    item.used = true;
+                }
+            }
+            if line.is_empty() {
+                writeln!(self.out, "{}", prefix.trim_end())?;
+            } else {
+                writeln!(self.out, "{}{}", prefix, line)?;
+            }
+        }
+        self.wrote_any = true;
+        Ok(())
+    }
+
+    fn write_block(&mut self, lines: &[String]) -> Result<(), io::Error> {
+        self.flush_inline()?;
+        self.write_blank_if_pending()?;
+        self.write_lines(lines)
+    }
+
+    /// Writes out inline text that is not wrapped in a paragraph.
+    fn flush_inline(&mut self) -> Result<(), io::Error> {
+        if self.inline.trim().is_empty() {
+            self.inline.clear();
+            return Ok(());
+        }
+        let text = std::mem::take(&mut self.inline);
+        let lines: Vec<_> = reflow(&text, self.options.line_wrapping, self.available_width())
+            .into_iter()
+            .map(escape_block_marker)
+            .collect();
+        self.write_blank_if_pending()?;
+        self.write_lines(&lines)
+    }
+
+    /// Returns the markup closing a link or image.
+    fn link_suffix(&mut self, target: &str, title: Option<&str>) -> String {
+        match self.options.link_style {
+            LinkStyle::Inline => match title {
+                Some(title) => format!(
+                    "]({} \"{}\")",
+                    link_destination(target),
+                    title.replace('"', "\\\"")
+                ),
+                None => format!("]({})", link_destination(target)),
+            },
+            LinkStyle::Reference => {
+                let reference = (target.to_string(), title.map(|x| x.to_string()));
+                let idx = match self.references.iter().position(|x| *x == reference) {
+                    Some(idx) => idx,
+                    None => {
+                        self.references.push(reference);
+                        self.references.len() - 1
+                    }
+                };
+                format!("][{}]", idx + 1)
+            }
+        }
+    }
+
+    fn available_width(&self) -> usize {
+        let used: usize = self.prefixes.iter().map(|x| x.rest.chars().count()).sum();
+        self.options.wrap_width.saturating_sub(used).max(20)
+    }
+
+    fn in_inline_context(&self) -> bool {
+        self.heading.is_some()
+            || self.table.is_some()
+            || matches!(
+                self.blocks.last(),
+                Some(Tag::Paragraph) | Some(Tag::Figure) | Some(Tag::DefinitionTerm)
+            )
+            || !self.inline.is_empty()
+    }
+
+    fn start_tag(&mut self, tag: Tag, attrs: &Attrs) -> Result<(), io::Error> {
+        match tag {
+            Tag::Paragraph | Tag::Figure => {
+                self.flush_inline()?;
+                if let (Some(Tag::ListItem), Some(loose)) =
+                    (self.blocks.last(), self.loose_items.last_mut())
+                {
+                    *loose = true;
+                }
+                self.blocks.push(tag);
+            }
+            // captions are written as an emphasized line below the image
+            Tag::FigureCaption => {
+                self.flush_inline()?;
+                self.inline.push('*');
+            }
+            Tag::Heading1
+            | Tag::Heading2
+            | Tag::Heading3
+            | Tag::Heading4
+            | Tag::Heading5
+            | Tag::Heading6 => {
+                self.flush_inline()?;
+                self.heading = Some((
+                    tag.header_level().unwrap(),
+                    attrs.id.as_ref().map(|x| x.as_str().to_string()),
+                ));
+            }
+            Tag::BlockQuote => {
+                self.push_prefix("> ".into(), "> ".into())?;
+                self.blocks.push(tag);
+            }
+            Tag::OrderedList => {
+                self.flush_inline()?;
+                self.lists.push(Some(attrs.start.unwrap_or(1)));
+                self.blocks.push(tag);
+            }
+            Tag::UnorderedList => {
+                self.flush_inline()?;
+                self.lists.push(None);
+                self.blocks.push(tag);
+            }
+            Tag::ListItem => {
+                // the bullet or delimiter and the padding follow the source
+                let source = self
+                    .trivia
+                    .as_ref()
+                    .and_then(|x| x.marker.as_ref())
+                    .map_or("", |x| x.as_str());
+                let symbol = source.trim_end().chars().last();
+                let padding = &source[source.trim_end().len()..];
+                let padding = if padding.is_empty() { " " } else { padding };
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        let delimiter = match symbol {
+                            Some(')') => ')',
+                            _ => '.',
+                        };
+                        format!("{}{}{}", *number - 1, delimiter, padding)
+                    }
+                    _ => match symbol {
+                        Some(bullet @ '*') | Some(bullet @ '+') => format!("{}{}", bullet, padding),
+                        _ => format!("-{}", padding),
+                    },
+                };
+                let rest = " ".repeat(marker.len());
+                self.push_prefix(marker, rest)?;
+                self.loose_items.push(false);
+                self.blocks.push(tag);
+            }
+            Tag::FootnoteDefinition => {
+                let id = attrs.id.as_ref().map_or("", |x| x.as_str());
+                self.push_prefix(format!("[^{}]: ", id), "    ".into())?;
+                self.blocks.push(tag);
+            }
+            Tag::DefinitionList | Tag::DefinitionTerm => {
+                self.flush_inline()?;
+                self.blocks.push(tag);
+            }
+            // definitions directly follow their term
+            Tag::DefinitionDetails => {
+                self.flush_inline()?;
+                self.blank_pending = false;
+                self.push_prefix(": ".into(), "  ".into())?;
+                self.blocks.push(tag);
+            }
+            Tag::Table => {
+                self.flush_inline()?;
+                self.table = Some(TableState::default());
+            }
+            Tag::TableHeader | Tag::TableRow => {
+                if let Some(ref mut table) = self.table {
+                    table.rows.push(vec![]);
+                }
+            }
+            Tag::TableHead => {
+                if let Some(ref mut table) = self.table {
+                    table.alignments.push(attrs.alignment);
+                }
+                self.inline.clear();
+            }
+            Tag::TableCell => self.inline.clear(),
+            Tag::TableBody => {}
+            Tag::Emphasis => self.inline.push('*'),
+            Tag::EmphasisAlt => self.inline.push('_'),
+            Tag::Strong => self.inline.push_str("**"),
+            Tag::Strikethrough => self.inline.push_str("~~"),
+            Tag::Link => {
+                self.inline.push('[');
+                let target = attrs.target.as_ref().map_or("", |x| x.as_str());
+                let title = attrs.title.as_ref().map(|x| x.as_str());
+                let suffix = self.link_suffix(target, title);
+                self.links.push(suffix);
+            }
+            // synthetic tags have no markdown syntax, only their contents
+            // are rendered.
+            Tag::Container | Tag::Span => {
+                if !attrs.is_empty() {
+                    self.report_tag(tag, Fallback::ContentsOnly);
+                }
+            }
+            Tag::Details
+            | Tag::Summary
+            | Tag::Spoiler
+            | Tag::Abbreviation
+            | Tag::Insertion
+            | Tag::Deletion
+            | Tag::Sidenote => self.report_tag(tag, Fallback::ContentsOnly),
+        }
+        Ok(())
+    }
+
+    fn end_tag(&mut self, tag: Tag) -> Result<(), io::Error> {
+        match tag {
+            Tag::Paragraph | Tag::Figure => {
+                self.flush_inline()?;
+                self.blocks.pop();
+                self.blank_pending = true;
+            }
+            Tag::FigureCaption => self.inline.push('*'),
+            Tag::Heading1
+            | Tag::Heading2
+            | Tag::Heading3
+            | Tag::Heading4
+            | Tag::Heading5
+            | Tag::Heading6 => {
+                let text = std::mem::take(&mut self.inline);
+                let text = text.replace(&[HARD_BREAK, '\n'][..], " ");
+                let (level, id) = self.heading.take().unwrap_or((1, None));
+                let mut line = format!("{} {}", "#".repeat(level), text.trim());
+                if let Some(id) = id {
+                    line.push_str(&format!(" {{#{}}}", id));
+                }
+                self.write_block(&[line])?;
+                self.blank_pending = true;
+            }
+            Tag::BlockQuote | Tag::FootnoteDefinition | Tag::DefinitionDetails => {
+                self.pop_prefix()?;
+                self.blocks.pop();
+                self.blank_pending = true;
+            }
+            Tag::DefinitionList => {
+                self.flush_inline()?;
+                self.blocks.pop();
+                self.blank_pending = true;
+            }
+            Tag::DefinitionTerm => {
+                let text = std::mem::take(&mut self.inline);
+                let text = text.replace(&[HARD_BREAK, '\n'][..], " ");
+                self.write_block(&[text.trim().to_string()])?;
+                self.blocks.pop();
+            }
+            Tag::OrderedList | Tag::UnorderedList => {
+                self.flush_inline()?;
+                self.lists.pop();
+                self.blocks.pop();
+                self.blank_pending = true;
+            }
+            Tag::ListItem => {
+                self.pop_prefix()?;
+                self.blocks.pop();
+                self.blank_pending = self.loose_items.pop().unwrap_or(false);
+            }
+            Tag::Table => {
+                if let Some(table) = self.table.take() {
+                    let lines = render_table(table);
+                    self.write_block(&lines)?;
+                    self.blank_pending = true;
+                }
+            }
+            Tag::TableHead | Tag::TableCell => {
+                let cell = std::mem::take(&mut self.inline)
+                    .replace(&[HARD_BREAK, '\n'][..], " ")
+                    .replace('|', "\\|");
+                if let Some(row) = self.table.as_mut().and_then(|x| x.rows.last_mut()) {
+                    row.push(cell.trim().to_string());
+                }
+            }
+            Tag::TableHeader | Tag::TableRow | Tag::TableBody => {}
+            Tag::Emphasis => self.inline.push('*'),
+            Tag::EmphasisAlt => self.inline.push('_'),
+            Tag::Strong => self.inline.push_str("**"),
+            Tag::Strikethrough => self.inline.push_str("~~"),
+            Tag::Link => {
+                let suffix = self.links.pop().unwrap_or_else(|| "]()".into());
+                self.inline.push_str(&suffix);
+            }
+            Tag::Container
+            | Tag::Span
+            | Tag::Details
+            | Tag::Summary
+            | Tag::Spoiler
+            | Tag::Abbreviation
+            | Tag::Insertion
+            | Tag::Deletion
+            | Tag::Sidenote => {}
+        }
+        Ok(())
+    }
+
+    /// Feeds a single event into the renderer.
+    ///
+    /// Top level blocks carrying [`Trivia`] from a lossless parse are
+    /// buffered until their end.  If their events still match the recorded
+    /// fingerprint the source of the block is written unchanged.
+    pub fn feed_event(&mut self, event: &AnnotatedEvent<'_>) -> Result<(), io::Error> {
+        if let Some(ref mut verbatim) = self.verbatim {
+            verbatim.events.push(event.clone().into_owned());
+            match event.event {
+                Event::StartTag(..) => verbatim.depth += 1,
+                Event::EndTag(..) => verbatim.depth = verbatim.depth.saturating_sub(1),
+                _ => {}
+            }
+            if verbatim.depth == 0 {
+                let verbatim = self.verbatim.take().unwrap();
+                self.finish_verbatim(verbatim)?;
+            }
+            return Ok(());
+        }
+
+        let trivia = event.trivia.as_ref().filter(|x| x.raw.is_some());
+        if let (Some(trivia), true) = (trivia, self.at_top_level()) {
+            let verbatim = Verbatim {
+                trivia: (**trivia).clone().into_owned(),
+                depth: if let Event::StartTag(..) = event.event {
+                    1
+                } else {
+                    0
+                },
+                events: vec![event.clone().into_owned()],
+            };
+            if verbatim.depth == 0 {
+                return self.finish_verbatim(verbatim);
+            }
+            self.verbatim = Some(verbatim);
+            return Ok(());
+        }
+
+        self.render_event(event)
+    }
+
+    fn at_top_level(&self) -> bool {
+        self.prefixes.is_empty()
+            && self.blocks.is_empty()
+            && self.heading.is_none()
+            && self.table.is_none()
+            && self.inline.trim().is_empty()
+    }
+
+    /// Writes a buffered block as source or renders it if it changed.
+    fn finish_verbatim(&mut self, verbatim: Verbatim) -> Result<(), io::Error> {
+        let trivia = verbatim.trivia;
+        let raw = trivia.raw.as_ref().map_or("", |x| x.as_str());
+        let trailing = trivia.trailing.as_ref().map_or("", |x| x.as_str());
+        // directives are only kept as source if they are written as they are
+        let rewrites_directives = !self.options.unknown_directives.is_literal()
+            && verbatim
+                .events
+                .iter()
+                .any(|x| matches!(x.event, Event::Directive(..)));
+        if rewrites_directives
+            || trivia.fingerprint.as_deref() != Some(fingerprint(&verbatim.events).as_str())
+        {
+            for event in &verbatim.events {
+                self.render_event(event)?;
+            }
+            // the trailing source holds link reference definitions
+            if !trailing.trim().is_empty() {
+                self.flush_inline()?;
+                self.in_html = false;
+                let lines: Vec<String> = trailing.trim().lines().map(|x| x.to_string()).collect();
+                self.write_block(&lines)?;
+                self.blank_pending = true;
+            }
+            return Ok(());
+        }
+
+        let source = format!("{}{}", raw, trailing);
+        if source.is_empty() {
+            return Ok(());
+        }
+        self.flush_inline()?;
+        let ends_html = matches!(
+            verbatim.events.last().map(|x| &x.event),
+            Some(Event::RawHtml(..))
+        );
+        if !(self.in_html && ends_html) {
+            self.in_html = false;
+            self.write_blank_if_pending()?;
+        }
+        self.close_line()?;
+        self.out.write_all(source.as_bytes())?;
+        self.wrote_any = true;
+        self.open_line = !source.ends_with('\n');
+        // blocks after the source need a blank line unless it has one
+        let blank = source.ends_with("\n\n");
+        self.in_html = ends_html && !blank;
+        self.blank_pending = !blank && !self.in_html;
+        Ok(())
+    }
+
+    fn write_directive(&mut self, directive: &DirectiveEvent<'_>) -> Result<(), io::Error> {
+        let DirectiveEvent {
+            ref name,
+            ref argument,
+            ref front_matter,
+            ref body,
+            ..
+        } = *directive;
+        let fence = source_fence(self.trivia.as_ref(), body.as_str(), 3);
+        let mut lines = vec![match argument {
+            Some(argument) => format!("{}{{{}}} {}", fence, name.as_str(), argument),
+            None => format!("{}{{{}}}", fence, name.as_str()),
+        }];
+        if let Some(front_matter) = front_matter {
+            let yaml = serde_yaml::to_string(front_matter).unwrap_or_default();
+            lines.push("---".into());
+            lines.extend(
+                yaml.trim_start_matches("---\n")
+                    .trim_end()
+                    .lines()
+                    .map(|x| x.to_string()),
+            );
+            lines.push("---".into());
+        }
+        lines.extend(body.as_str().lines().map(|x| x.to_string()));
+        lines.push(fence);
+        self.write_block(&lines)?;
+        self.blank_pending = true;
+        Ok(())
+    }
+
+    fn render_event(&mut self, event: &AnnotatedEvent<'_>) -> Result<(), io::Error> {
+        self.location = event.location.clone();
+        self.trivia = event.trivia.as_ref().map(|x| (**x).clone().into_owned());
+        if self.in_html && !matches!(event.event, Event::RawHtml(..)) {
+            self.in_html = false;
+            self.blank_pending = true;
+        }
+
+        match event.event {
+            Event::DocumentStart(DocumentStartEvent { ref front_matter }) => {
+                if let Some(front_matter) = front_matter {
+                    let yaml = serde_yaml::to_string(front_matter).unwrap_or_default();
+                    let yaml = yaml.trim_start_matches("---\n").trim_end();
+                    let mut lines = vec!["---".to_string()];
+                    lines.extend(yaml.lines().map(|x| x.to_string()));
+                    lines.push("---".into());
+                    self.write_block(&lines)?;
+                    self.blank_pending = true;
+                }
+            }
+            Event::StartTag(StartTagEvent { tag, ref attrs }) => self.start_tag(tag, attrs)?,
+            Event::EndTag(EndTagEvent { tag }) => self.end_tag(tag)?,
+            Event::Text(TextEvent { ref text }) => self.inline.push_str(&escape(text.as_str())),
+            Event::InterpretedText(InterpretedTextEvent {
+                ref role, ref text, ..
+            }) => {
+                self.inline.push_str(&format!(
+                    "{{{}}}{}",
+                    role.as_str(),
+                    inline_code(text.as_str())
+                ));
+            }
+            Event::InlineCode(InlineCodeEvent { ref code }) => {
+                self.inline.push_str(&inline_code(code.as_str()));
+            }
+            Event::InlineMath(InlineMathEvent { ref math, display }) => {
+                let math = math.as_str();
+                if display {
+                    self.inline.push_str(&format!("$${}$$", math));
+                } else if math.is_empty()
+                    || math.contains('$')
+                    || math.starts_with(char::is_whitespace)
+                    || math.ends_with(char::is_whitespace)
+                {
+                    self.inline
+                        .push_str(&format!("{{math}}{}", inline_code(math)));
+                } else {
+                    self.inline.push_str(&format!("${}$", math));
+                }
+            }
+            Event::MathBlock(MathBlockEvent { ref args, ref math }) => {
+                let fence = source_fence(self.trivia.as_ref(), math.as_str(), 3);
+                let mut info = "math".to_string();
+                if let Some(args) = args {
+                    for (key, value) in args.iter() {
+                        info.push_str(&format!(" {}=\"{}\"", key.as_str(), value.as_str()));
+                    }
+                }
+                let mut lines = vec![format!("{}{}", fence, info)];
+                lines.extend(math.as_str().lines().map(|x| x.to_string()));
+                lines.push(fence);
+                self.write_block(&lines)?;
+                self.blank_pending = true;
+            }
+            Event::CodeBlock(CodeBlockEvent {
+                ref language,
+                ref args,
+                ref code,
+            }) => {
+                let fence = source_fence(self.trivia.as_ref(), code.as_str(), 3);
+                let mut info = language.as_ref().map_or("", |x| x.as_str()).to_string();
+                if let Some(args) = args {
+                    for (key, value) in args.iter() {
+                        info.push_str(&format!(" {}=\"{}\"", key.as_str(), value.as_str()));
+                    }
+                }
+                let mut lines = vec![format!("{}{}", fence, info)];
+                lines.extend(code.as_str().lines().map(|x| x.to_string()));
+                lines.push(fence);
+                self.write_block(&lines)?;
+                self.blank_pending = true;
+            }
+            Event::Directive(ref directive) => {
+                match self.options.unknown_directives.resolve(directive) {
+                    DirectiveOutcome::Replace(events) => {
+                        let location = self.location.clone();
+                        for event in events {
+                            match event {
+                                Event::Directive(ref directive) => {
+                                    self.write_directive(directive)?
+                                }
+                                event => self
+                                    .render_event(&AnnotatedEvent::new(event, location.clone()))?,
+                            }
+                        }
+                    }
+                    DirectiveOutcome::Fallback(Fallback::Literal) => {
+                        self.write_directive(directive)?
+                    }
+                    DirectiveOutcome::Fallback(fallback) => self.lossy.push(LossyConversion {
+                        format: "markdown".into(),
+                        element: format!("directive {}", directive.name.as_str()),
+                        fallback,
+                        location: self.location.clone(),
+                    }),
+                }
+            }
+            Event::Image(ImageEvent {
+                ref target,
+                ref alt,
+                ref title,
+                width,
+                height,
+            }) => {
+                if width.is_some() || height.is_some() {
+                    self.lossy.push(LossyConversion {
+                        format: "markdown".into(),
+                        element: "image dimensions".into(),
+                        fallback: Fallback::Dropped,
+                        location: self.location.clone(),
+                    });
+                }
+                let alt = escape(alt.as_ref().map_or("", |x| x.as_str()));
+                let suffix = self.link_suffix(target.as_str(), title.as_ref().map(|x| x.as_str()));
+                self.inline.push_str(&format!("![{}{}", alt, suffix));
+            }
+            Event::RawHtml(RawHtmlEvent { ref html }) => {
+                if self.in_inline_context() {
+                    self.inline.push_str(html.as_str());
+                } else {
+                    if !self.in_html {
+                        self.flush_inline()?;
+                        self.write_blank_if_pending()?;
+                    }
+                    self.in_html = true;
+                    let lines: Vec<String> = html.as_str().lines().map(|x| x.to_string()).collect();
+                    self.write_lines(&lines)?;
+                }
+            }
+            Event::SoftBreak => self.inline.push('\n'),
+            Event::HardBreak => self.inline.push(HARD_BREAK),
+            Event::Rule => {
+                self.write_block(&["---".into()])?;
+                self.blank_pending = true;
+            }
+            Event::Checkbox(CheckboxEvent { checked }) => {
+                self.inline.push_str(if checked { "[x] " } else { "[ ] " });
+            }
+            Event::FootnoteReference(FootnoteReferenceEvent { ref target }) => {
+                self.inline.push_str(&format!("[^{}]", target.as_str()));
+            }
+            Event::MetaData(..) | Event::Error(..) | Event::Diagnostic(..) => {}
+        }
+        Ok(())
+    }
+
+    /// Feeds an event stream into the renderer.
+    pub fn feed_stream<'data, I>(&mut self, iter: I) -> Result<(), io::Error>
+    where
+        I: Iterator<Item = AnnotatedEvent<'data>>,
+    {
+        for event in iter {
+            self.feed_event(&event)?;
+        }
+        self.finish()
+    }
+
+    /// Writes out everything that is still buffered.
+    ///
+    /// This is automatically called by [`feed_stream`](Self::feed_stream)
+    /// and writes the collected link reference definitions.
+    pub fn finish(&mut self) -> Result<(), io::Error> {
+        if let Some(verbatim) = self.verbatim.take() {
+            self.finish_verbatim(verbatim)?;
+        }
+        self.flush_inline()?;
+        let references = std::mem::take(&mut self.references);
+        if !references.is_empty() {
+            self.prefixes.clear();
+            self.blank_pending = true;
+            let lines: Vec<String> = references
+                .into_iter()
+                .enumerate()
+                .map(|(idx, (target, title))| match title {
+                    Some(title) => format!(
+                        "[{}]: {} \"{}\"",
+                        idx + 1,
+                        link_destination(&target),
+                        title.replace('"', "\\\"")
+                    ),
+                    None => format!("[{}]: {}", idx + 1, link_destination(&target)),
+                })
+                .collect();
+            self.write_block(&lines)?;
+        }
+        Ok(())
+    }
+}
+
+fn render_table(table: TableState) -> Vec<String> {
+    let columns = table.alignments.len().max(1);
+    let mut widths = vec![3; columns];
+    for row in &table.rows {
+        for (idx, cell) in row.iter().enumerate().take(columns) {
+            widths[idx] = widths[idx].max(cell.chars().count());
+        }
+    }
+
+    let format_row = |row: &[String]| {
+        let mut line = String::from("|");
+        for (idx, width) in widths.iter().enumerate() {
+            let cell = row.get(idx).map_or("", |x| x.as_str());
+            let padding = width - cell.chars().count();
+            line.push_str(&format!(" {}{} |", cell, " ".repeat(padding)));
+        }
+        line
+    };
+
+    let mut lines = vec![];
+    let mut rows = table.rows.iter();
+    if let Some(header) = rows.next() {
+        lines.push(format_row(header));
+    }
+    let mut separator = String::from("|");
+    for (idx, width) in widths.iter().enumerate() {
+        let alignment = table
+            .alignments
+            .get(idx)
+            .copied()
+            .unwrap_or(Alignment::None);
+        let (left, right) = match alignment {
+            Alignment::None => ("-", "-"),
+            Alignment::Left => (":", "-"),
+            Alignment::Center => (":", ":"),
+            Alignment::Right => ("-", ":"),
+        };
+        separator.push_str(&format!(" {}{}{} |", left, "-".repeat(width - 2), right));
+    }
+    lines.push(separator);
+    lines.extend(rows.map(|row| format_row(row)));
+    lines
+}
+
+impl<'options> MarkdownRenderer<'options, Vec<u8>> {
+    /// Creates a new markdown renderer writing into a buffer.
+    pub fn new_buffered(options: &'options MarkdownRendererOptions) -> Self {
+        MarkdownRenderer::new(Vec::new(), options)
+    }
+
+    /// Converts the renderer into a string.
+    pub fn into_string(self) -> String {
+        String::from_utf8(self.into_writer()).expect("renderer produced invalid utf-8")
+    }
+}
+
+/// Convenience shortcut that renders an event stream into markdown.
+pub fn to_markdown<'a, I: Iterator<Item = AnnotatedEvent<'a>>>(
+    iter: I,
+    options: &MarkdownRendererOptions,
+) -> String {
+    let mut renderer = MarkdownRenderer::new_buffered(options);
+    renderer.feed_stream(iter).unwrap();
+    renderer.into_string()
+}
+
+#[test]
+fn test_line_wrapping() {
+    use crate::parser::parse;
+
+    let source = "\
+# Wrapping
+
+This is a rather long paragraph. It contains several sentences which are
+spread over multiple
+lines! Formatting should take care of it - even with *emphasis*,
+`inline code` and [links](https://example.com).
+
+> A quote with a hard break\\
+> inside of it. And another sentence.
+
+Wrapping must not turn text into markup - so a dash stays a dash in the outputs
+\\+ and a plus sign stays a plus sign too # and hashes never start a new heading,
+\\> quotes stay in the paragraph and step 1. is not a list. 2. Sentences can start with numbers.
+
+1\\. This is not a list either.
+
+- A list item that is long enough to be wrapped when the width is small enough.
+- Short item.
+";
+
+    let mut output = String::new();
+    for &line_wrapping in &[
+        LineWrapping::Preserve,
+        LineWrapping::Wrap,
+        LineWrapping::SentencePerLine,
+    ] {
+        let options = MarkdownRendererOptions {
+            line_wrapping,
+            wrap_width: 40,
+            ..Default::default()
+        };
+        output.push_str(&format!("<!-- {:?} -->\n", line_wrapping));
+        let rendered = to_markdown(parse(source, &Default::default()), &options);
+        // the output parses back into the same document
+        assert_eq!(
+            to_markdown(parse(&rendered, &Default::default()), &options),
+            rendered
+        );
+        output.push_str(&rendered);
+    }
+    insta::assert_snapshot!("line_wrapping", output);
+}
+
+#[test]
+fn test_reference_links() {
+    use crate::parser::parse;
+
+    let source = "\
+See [the docs](https://example.com/docs \"Docs\") and [the
+code](https://example.com/code).  Again [the docs](https://example.com/docs \"Docs\").
+
+![A logo](logo.png)
+
+Open [my notes](<my notes.md>) or [Rust](https://en.wikipedia.org/wiki/Rust_(language)).
+";
+
+    let mut output = String::new();
+    for &link_style in &[LinkStyle::Inline, LinkStyle::Reference] {
+        let options = MarkdownRendererOptions {
+            link_style,
+            ..Default::default()
+        };
+        let rendered = to_markdown(parse(source, &Default::default()), &options);
+        // the targets survive parsing the output again
+        assert_eq!(
+            to_markdown(parse(&rendered, &Default::default()), &options),
+            rendered
+        );
+        output.push_str(&format!("<!-- {:?} -->\n", link_style));
+        output.push_str(&rendered);
+    }
+    insta::assert_snapshot!("reference_links", output);
+}
+
+#[test]
+fn test_figures() {
+    use crate::parser::parse;
+    use crate::processors::{Figures, FiguresIter};
+    use std::borrow::Cow;
+
+    let source = "\
+![A cat](cat.png)
+*The cat in its natural habitat.*
+
+![A dog](dog.png)
+";
+
+    let events = FiguresIter::new(
+        parse(source, &Default::default()),
+        Cow::Owned(Figures::default()),
+    );
+    insta::assert_snapshot!("figures", to_markdown(events, &Default::default()));
+}
+
+#[test]
+fn test_lossy_conversions() {
+    use crate::parser::parse;
+    use crate::processors::{Spoiler, SpoilerIter};
+    use std::borrow::Cow;
+
+    let source = "Spoiler: {spoiler}`the butler did it`\n\n![Logo](logo.png)\n";
+    let mut events: Vec<_> = SpoilerIter::new(
+        parse(source, &Default::default()),
+        Cow::Owned(Spoiler::default()),
+    )
+    .collect();
+    for annotated_event in events.iter_mut() {
+        if let Event::Image(ref mut image) = annotated_event.event {
+            image.width = Some(120);
+        }
+    }
+
+    let options = MarkdownRendererOptions::default();
+    let mut renderer = MarkdownRenderer::new_buffered(&options);
+    renderer.feed_stream(events.into_iter()).unwrap();
+    let lossy: Vec<_> = renderer
+        .lossy_conversions()
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    assert_eq!(
+        lossy,
+        vec![
+            "1:9: markdown cannot represent spoiler, rendered its contents only",
+            "3:0: markdown cannot represent image dimensions, dropped it",
+        ]
+    );
+}
+
+#[test]
+fn test_lossless() {
+    use crate::json::{stream_from_json, stream_to_json};
+    use crate::parser::{parse, ParserOptions};
+
+    let source = "\
+---
+title:   Lossless
+---
+
+Setext  heading
+===============
+
+*  first
+*  second
+
+1) one
+2) two
+
+~~~~ python
+print(  'hi'  )
+~~~~
+
+<div>
+  html
+</div>
+| a | b |
+|--|:-:|
+| 1 | 2 |
+
+See [the docs][docs] and __this__.
+
+[docs]: https://example.com/docs
+
+Last line without newline \\
+continued";
+    let options = ParserOptions {
+        lossless: true,
+        ..Default::default()
+    };
+    let events: Vec<_> = parse(source, &options).collect();
+    assert_eq!(
+        to_markdown(events.clone().into_iter(), &Default::default()),
+        source
+    );
+
+    // trivia survives serialization
+    let json = stream_to_json(events.clone().into_iter());
+    let events_from_json = stream_from_json(&json).unwrap();
+    assert_eq!(
+        to_markdown(events_from_json.into_iter(), &Default::default()),
+        source
+    );
+
+    // changed blocks are rendered, the rest stays as it was
+    let mut events = events;
+    for annotated_event in events.iter_mut() {
+        match annotated_event.event {
+            Event::Text(ref mut text) if text.text.as_str() == "second" => {
+                text.text = "changed".into();
+            }
+            Event::CodeBlock(ref mut code_block) => {
+                code_block.code = "print('changed')\n".into();
+            }
+            Event::Text(ref mut text) if text.text.as_str() == "See " => {
+                text.text = "Read ".into();
+            }
+            _ => {}
+        }
+    }
+    insta::assert_snapshot!(
+        "lossless",
+        to_markdown(events.into_iter(), &Default::default())
+    );
+}
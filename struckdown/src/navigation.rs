@@ -0,0 +1,192 @@
+//! Computes navigation between the documents of a site.
+//!
+//! A [`SiteNavigation`] is created from the documents of a site in their
+//! configured order.  For every document it knows the previous and next
+//! document, the parent (the closest document whose path contains the
+//! document's path) and the breadcrumbs leading to it.  The result can be
+//! attached to the document as meta data so themes can render pagination
+//! and breadcrumbs without maintaining their own site model.
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, DocumentStartEvent, Event, MetaDataEvent, StartTagEvent};
+use crate::plain::to_plain_text;
+use crate::value::to_value;
+
+/// A link to a document.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NavLink {
+    /// The title of the document.
+    pub title: String,
+    /// The path of the document.
+    pub url: String,
+}
+
+/// The navigation of a single document.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Navigation {
+    /// The previous document in order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<NavLink>,
+    /// The next document in order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<NavLink>,
+    /// The parent document.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<NavLink>,
+    /// All ancestors from the root down to the document itself.
+    pub breadcrumbs: Vec<NavLink>,
+}
+
+/// The navigation of all documents of a site.
+#[derive(Debug, Clone, Default)]
+pub struct SiteNavigation {
+    documents: Vec<NavLink>,
+    parents: Vec<Option<usize>>,
+    index: HashMap<String, usize>,
+}
+
+/// Finds the title of a document.
+///
+/// This is the `title` key of the front matter or the text of the first
+/// level 1 heading.
+pub fn document_title<'a, 'data: 'a, I>(iter: I) -> Option<String>
+where
+    I: IntoIterator<Item = &'a AnnotatedEvent<'data>>,
+{
+    let mut heading = None;
+    let mut depth = 0;
+    for annotated_event in iter {
+        match annotated_event.event {
+            Event::DocumentStart(DocumentStartEvent {
+                front_matter: Some(ref front_matter),
+            }) => {
+                if let Some(title) = front_matter.get("title").and_then(|x| x.as_str()) {
+                    return Some(title.to_string());
+                }
+            }
+            Event::StartTag(StartTagEvent { tag, .. }) => {
+                if heading.is_some() {
+                    depth += 1;
+                } else if tag.header_level() == Some(1) {
+                    heading = Some(vec![]);
+                    continue;
+                }
+            }
+            Event::EndTag(_) if heading.is_some() => {
+                if depth == 0 {
+                    let events = heading.unwrap();
+                    return Some(
+                        to_plain_text(events.into_iter())
+                            .as_str()
+                            .trim()
+                            .to_string(),
+                    );
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        if let Some(ref mut events) = heading {
+            events.push(annotated_event);
+        }
+    }
+    None
+}
+
+fn path_segments(path: &str) -> Vec<&str> {
+    path.split('/').filter(|x| !x.is_empty()).collect()
+}
+
+impl SiteNavigation {
+    /// Creates the navigation from `(path, title)` pairs in document order.
+    ///
+    /// Paths are `/` separated.  The parent of a document is the document
+    /// with the longest path that is a prefix of its path, so `/guide/` is
+    /// the parent of `/guide/install/` and `/` the parent of both.
+    pub fn new<I, P, T>(documents: I) -> SiteNavigation
+    where
+        I: IntoIterator<Item = (P, T)>,
+        P: Into<String>,
+        T: Into<String>,
+    {
+        let documents: Vec<NavLink> = documents
+            .into_iter()
+            .map(|(url, title)| NavLink {
+                title: title.into(),
+                url: url.into(),
+            })
+            .collect();
+        let segments: Vec<Vec<&str>> = documents.iter().map(|x| path_segments(&x.url)).collect();
+        let parents = segments
+            .iter()
+            .map(|own| {
+                segments
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| other.len() < own.len() && own.starts_with(other))
+                    .max_by_key(|(_, other)| other.len())
+                    .map(|(idx, _)| idx)
+            })
+            .collect();
+        let index = documents
+            .iter()
+            .enumerate()
+            .map(|(idx, x)| (x.url.clone(), idx))
+            .collect();
+        SiteNavigation {
+            documents,
+            parents,
+            index,
+        }
+    }
+
+    /// Returns the navigation for the document with the given path.
+    pub fn get(&self, path: &str) -> Option<Navigation> {
+        let idx = *self.index.get(path)?;
+        let mut breadcrumbs = vec![self.documents[idx].clone()];
+        let mut current = idx;
+        while let Some(parent) = self.parents[current] {
+            breadcrumbs.push(self.documents[parent].clone());
+            current = parent;
+        }
+        breadcrumbs.reverse();
+        Some(Navigation {
+            previous: idx.checked_sub(1).map(|x| self.documents[x].clone()),
+            next: self.documents.get(idx + 1).cloned(),
+            parent: self.parents[idx].map(|x| self.documents[x].clone()),
+            breadcrumbs,
+        })
+    }
+
+    /// Returns a `navigation` meta data event for a document.
+    pub fn metadata_event(&self, path: &str) -> Option<MetaDataEvent<'static>> {
+        Some(MetaDataEvent {
+            key: "navigation".into(),
+            value: to_value(&self.get(path)?).expect("bad navigation"),
+        })
+    }
+}
+
+#[test]
+fn test_navigation() {
+    use crate::parser::parse;
+
+    let sources = [
+        ("/", "# Welcome"),
+        ("/guide/", "---\ntitle: User Guide\n---\n# Guide"),
+        ("/guide/install/", "# Installing *struckdown*"),
+        ("/guide/usage/", "Text\n\n# Usage"),
+        ("/about/", "no title"),
+    ];
+    let nav = SiteNavigation::new(sources.iter().map(|(path, source)| {
+        let events: Vec<_> = parse(source, &Default::default()).collect();
+        (
+            *path,
+            document_title(&events).unwrap_or_else(|| path.to_string()),
+        )
+    }));
+    insta::assert_yaml_snapshot!(&nav.get("/guide/install/"));
+    insta::assert_yaml_snapshot!(&nav.get("/about/"));
+}
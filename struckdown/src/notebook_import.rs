@@ -0,0 +1,243 @@
+//! Converts Jupyter notebooks into an event stream.
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::event::{
+    AnnotatedEvent, CodeBlockEvent, DirectiveEvent, DocumentStartEvent, RawHtmlEvent, Str,
+};
+use crate::parser::{parse, ParserOptions};
+use crate::value::{value, Value};
+
+/// The mime types of rich outputs in order of preference.
+const MIME_TYPES: &[&str] = &[
+    "text/markdown",
+    "text/html",
+    "image/svg+xml",
+    "image/png",
+    "image/jpeg",
+    "text/latex",
+    "application/json",
+    "text/plain",
+];
+
+/// A string that notebooks store either whole or split into lines.
+#[derive(Deserialize, Default)]
+#[serde(from = "Value")]
+struct MultilineString(String);
+
+impl From<Value> for MultilineString {
+    fn from(value: Value) -> MultilineString {
+        MultilineString(match value {
+            Value::String(value) => value,
+            Value::Array(values) => values
+                .iter()
+                .map(|x| x.as_str().unwrap_or_default())
+                .collect(),
+            Value::Null => String::new(),
+            other => other.to_string(),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct Notebook {
+    cells: Vec<Cell>,
+    #[serde(default)]
+    metadata: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "cell_type", rename_all = "snake_case")]
+enum Cell {
+    Markdown {
+        source: MultilineString,
+    },
+    Code {
+        source: MultilineString,
+        #[serde(default)]
+        outputs: Vec<Output>,
+        execution_count: Option<u64>,
+    },
+    Raw {
+        source: MultilineString,
+        #[serde(default)]
+        metadata: Value,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "output_type", rename_all = "snake_case")]
+enum Output {
+    Stream {
+        name: String,
+        text: MultilineString,
+    },
+    ExecuteResult {
+        data: BTreeMap<String, MultilineString>,
+    },
+    DisplayData {
+        data: BTreeMap<String, MultilineString>,
+    },
+    Error {
+        ename: String,
+        evalue: String,
+        #[serde(default)]
+        traceback: Vec<String>,
+    },
+}
+
+fn output_event(output: Output) -> Option<DirectiveEvent<'static>> {
+    let (kind, front_matter, body) = match output {
+        Output::Stream { name, text } => ("stream", value!({ "name": name }), text.0),
+        Output::ExecuteResult { mut data } | Output::DisplayData { mut data } => {
+            let mime_type = MIME_TYPES.iter().find(|x| data.contains_key(**x))?;
+            let body = data.remove(*mime_type).unwrap_or_default().0;
+            ("data", value!({ "mime_type": mime_type }), body)
+        }
+        Output::Error {
+            ename,
+            evalue,
+            traceback,
+        } => (
+            "error",
+            value!({ "ename": ename, "evalue": evalue }),
+            traceback.join("\n"),
+        ),
+    };
+    Some(DirectiveEvent {
+        name: "output".into(),
+        argument: Some(kind.into()),
+        front_matter: Some(front_matter),
+        body: body.into(),
+        body_location: None,
+    })
+}
+
+/// Converts a Jupyter notebook into an event stream.
+///
+/// This is only available with the `notebook-import` feature.  The
+/// notebook metadata becomes the front matter.  Markdown cells are parsed,
+/// code cells become code blocks in the language of the notebook's kernel
+/// with an `execution_count` argument and HTML raw cells become raw HTML.
+///
+/// Every output of a code cell follows it as an `output` directive with
+/// the kind of output as argument:
+///
+/// * `stream`: text written to `stdout` or `stderr` (the `name` in the
+///   front matter).
+/// * `data`: results and displayed data.  Only the preferred
+///   representation is kept and its `mime_type` is in the front matter.
+///   Binary data such as images stays base64 encoded.
+/// * `error`: the traceback of an exception with its `ename` and `evalue`
+///   in the front matter.
+///
+/// The events carry no locations.
+pub fn from_notebook(
+    json: &str,
+) -> Result<impl Iterator<Item = AnnotatedEvent<'static>>, serde_json::Error> {
+    let notebook: Notebook = serde_json::from_str(json)?;
+    let language = notebook
+        .metadata
+        .pointer("/language_info/name")
+        .or_else(|| notebook.metadata.pointer("/kernelspec/language"))
+        .and_then(|x| x.as_str())
+        .map(|x| Str::from(x.to_string()));
+    let options = ParserOptions {
+        enable_frontmatter: false,
+        ..Default::default()
+    };
+
+    let mut events: Vec<AnnotatedEvent<'static>> = vec![DocumentStartEvent {
+        front_matter: match notebook.metadata {
+            Value::Null => None,
+            metadata => Some(metadata),
+        },
+    }
+    .into()];
+
+    for cell in notebook.cells {
+        match cell {
+            Cell::Markdown { source } => events.extend(
+                parse(&source.0, &options)
+                    .skip(1)
+                    .map(|x| AnnotatedEvent::new(x.event.into_owned(), None)),
+            ),
+            Cell::Code {
+                source,
+                outputs,
+                execution_count,
+            } => {
+                let mut code = source.0;
+                if !code.ends_with('\n') {
+                    code.push('\n');
+                }
+                events.push(
+                    CodeBlockEvent {
+                        language: language.clone(),
+                        args: execution_count.map(|count| {
+                            let mut args = BTreeMap::new();
+                            args.insert("execution_count".into(), count.to_string().into());
+                            args
+                        }),
+                        code: code.into(),
+                    }
+                    .into(),
+                );
+                events.extend(outputs.into_iter().filter_map(output_event).map(Into::into));
+            }
+            Cell::Raw { source, metadata } => {
+                let format = metadata
+                    .get("format")
+                    .or_else(|| metadata.get("raw_mimetype"))
+                    .and_then(|x| x.as_str());
+                if format == Some("text/html") {
+                    events.push(
+                        RawHtmlEvent {
+                            html: source.0.into(),
+                        }
+                        .into(),
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(events.into_iter())
+}
+
+#[test]
+fn test_from_notebook() {
+    let notebook = r##"{
+  "cells": [
+    {"cell_type": "markdown", "metadata": {}, "source": ["# Analysis\n", "\n", "Some *text*."]},
+    {
+      "cell_type": "code",
+      "execution_count": 1,
+      "metadata": {},
+      "source": ["print('hi')\n", "1 + 1"],
+      "outputs": [
+        {"output_type": "stream", "name": "stdout", "text": ["hi\n"]},
+        {"output_type": "execute_result", "execution_count": 1, "metadata": {},
+         "data": {"text/plain": ["2"], "text/html": ["<b>2</b>"]}}
+      ]
+    },
+    {
+      "cell_type": "code",
+      "execution_count": null,
+      "metadata": {},
+      "source": "1 / 0",
+      "outputs": [
+        {"output_type": "error", "ename": "ZeroDivisionError", "evalue": "division by zero",
+         "traceback": ["Traceback", "ZeroDivisionError: division by zero"]}
+      ]
+    },
+    {"cell_type": "raw", "metadata": {"format": "text/html"}, "source": "<hr>"}
+  ],
+  "metadata": {"kernelspec": {"language": "python", "name": "python3"}},
+  "nbformat": 4,
+  "nbformat_minor": 5
+}"##;
+    let events: Vec<_> = from_notebook(notebook).unwrap().collect();
+    insta::assert_yaml_snapshot!(&events);
+}
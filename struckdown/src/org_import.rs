@@ -0,0 +1,578 @@
+//! Converts Org documents into an event stream.
+//!
+//! Only a pragmatic subset of Org is understood: headings (with
+//! `CUSTOM_ID` properties as ids), plain and checkbox lists, tables,
+//! source, example and quote blocks, horizontal rules, `#+KEY:` keywords
+//! and the inline markup for emphasis, code and links.  Everything else is
+//! treated as paragraph text.
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::event::{
+    AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, DirectiveEvent, DocumentStartEvent,
+    Event, InlineCodeEvent, InterpretedTextEvent, Str, Tag, TextEvent,
+};
+use crate::value::Value;
+
+lazy_static! {
+    static ref HEADING_RE: Regex =
+        Regex::new(r"^(\*+)\s+(?:(?:TODO|DONE)\s+)?(.*?)(?:\s+(:[\w@#%:]+:))?\s*$").unwrap();
+    static ref KEYWORD_RE: Regex = Regex::new(r"^#\+(\w+):\s*(.*)$").unwrap();
+    static ref BLOCK_START_RE: Regex = Regex::new(r"(?i)^#\+begin_(\w+)\s*(.*)$").unwrap();
+    static ref LIST_ITEM_RE: Regex =
+        Regex::new(r"^(\s*)([-+]|\d+[.)])\s+(?:\[([ xX-])\]\s+)?(.*)$").unwrap();
+    static ref PROPERTY_RE: Regex = Regex::new(r"^\s*:(\w+):\s*(.*)$").unwrap();
+    static ref RULE_RE: Regex = Regex::new(r"^\s*-{5,}\s*$").unwrap();
+    static ref TABLE_SEPARATOR_RE: Regex = Regex::new(r"^\s*\|[-+|]*$").unwrap();
+}
+
+/// Link types that are URLs rather than roles.
+const URL_SCHEMES: &[&str] = &["http", "https", "mailto", "ftp", "file", "news"];
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_inline_boundary(c: Option<char>) -> bool {
+    match c {
+        None => true,
+        Some(c) => c.is_whitespace() || "-.,:;!?'\")}[<>/".contains(c),
+    }
+}
+
+struct Converter {
+    events: Vec<AnnotatedEvent<'static>>,
+    front_matter: BTreeMap<String, Value>,
+}
+
+impl Converter {
+    fn push<E: Into<Event<'static>>>(&mut self, event: E) {
+        self.events.push(AnnotatedEvent::new(event, None));
+    }
+
+    fn start(&mut self, tag: Tag, attrs: Attrs<'static>) {
+        self.push(tag.start_tag(attrs));
+    }
+
+    fn end(&mut self, tag: Tag) {
+        self.push(tag.end_tag());
+    }
+
+    fn text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if let Some(AnnotatedEvent {
+            event: Event::Text(TextEvent { text: last }),
+            ..
+        }) = self.events.last_mut()
+        {
+            *last = format!("{}{}", last, text).into();
+        } else {
+            self.push(TextEvent {
+                text: text.to_string().into(),
+            });
+        }
+    }
+
+    /// Emits a link (`[[target][description]]` or `[[target]]`).
+    fn link(&mut self, target: &str, description: Option<&str>) {
+        if let Some((kind, value)) = target.split_once(':') {
+            if !URL_SCHEMES.contains(&kind) && kind.chars().all(|c| c.is_ascii_alphanumeric()) {
+                // other link types map to roles like `{ref}`intro``
+                return self.push(InterpretedTextEvent {
+                    role: kind.to_string().into(),
+                    text: description.unwrap_or(value).to_string().into(),
+                    role_span: None,
+                });
+            }
+        }
+        let attrs = Attrs {
+            target: Some(target.to_string().into()),
+            ..Attrs::default()
+        };
+        self.start(Tag::Link, attrs);
+        self.inline(description.unwrap_or(target));
+        self.end(Tag::Link);
+    }
+
+    /// Finds the closing marker of inline markup starting at `start`.
+    fn find_closing(text: &str, start: usize, marker: char) -> Option<usize> {
+        let inner = &text[start + 1..];
+        if inner.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let mut prev = None;
+        for (idx, c) in inner.char_indices() {
+            if c == marker && idx > 0 && !matches!(prev, Some(p) if char::is_whitespace(p)) {
+                let end = start + 1 + idx;
+                if is_inline_boundary(text[end + 1..].chars().next()) {
+                    return Some(end);
+                }
+            }
+            prev = Some(c);
+        }
+        None
+    }
+
+    fn inline(&mut self, text: &str) {
+        let mut pos = 0;
+        let mut plain_start = 0;
+        while pos < text.len() {
+            let rest = &text[pos..];
+            let c = rest.chars().next().unwrap();
+            let before = text[..pos].chars().next_back();
+
+            if rest.starts_with("[[") {
+                if let Some(end) = rest.find("]]") {
+                    let link = &rest[2..end];
+                    self.text(&text[plain_start..pos]);
+                    match link.split_once("][") {
+                        Some((target, description)) => self.link(target, Some(description)),
+                        None => self.link(link, None),
+                    }
+                    pos += end + 2;
+                    plain_start = pos;
+                    continue;
+                }
+            }
+
+            if "*/_+=~".contains(c) && is_inline_boundary(before) {
+                if let Some(end) = Converter::find_closing(text, pos, c) {
+                    let inner = &text[pos + 1..end];
+                    self.text(&text[plain_start..pos]);
+                    let tag = match c {
+                        '*' => Some(Tag::Strong),
+                        '/' => Some(Tag::Emphasis),
+                        '_' => Some(Tag::EmphasisAlt),
+                        '+' => Some(Tag::Strikethrough),
+                        _ => None,
+                    };
+                    match tag {
+                        Some(tag) => {
+                            self.start(tag, Attrs::default());
+                            self.inline(inner);
+                            self.end(tag);
+                        }
+                        None => self.push(InlineCodeEvent {
+                            code: inner.to_string().into(),
+                        }),
+                    }
+                    pos = end + 1;
+                    plain_start = pos;
+                    continue;
+                }
+            }
+
+            pos += c.len_utf8();
+        }
+        self.text(&text[plain_start..]);
+    }
+
+    /// Emits the lines of a paragraph.
+    fn paragraph_contents(&mut self, lines: &[&str]) {
+        for (idx, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            match line.strip_suffix("\\\\") {
+                Some(line) => {
+                    self.inline(line.trim_end());
+                    self.push(Event::HardBreak);
+                }
+                None => {
+                    self.inline(line);
+                    if idx + 1 < lines.len() {
+                        self.push(Event::SoftBreak);
+                    }
+                }
+            }
+        }
+    }
+
+    fn heading(&mut self, level: usize, title: &str, id: Option<String>) {
+        let tag = Tag::from_header_level(level.min(6)).unwrap();
+        let attrs = Attrs {
+            id: id.map(Into::into),
+            ..Attrs::default()
+        };
+        self.start(tag, attrs);
+        self.inline(title);
+        self.end(tag);
+    }
+
+    fn table(&mut self, lines: &[&str]) {
+        let cells = |line: &str| -> Vec<String> {
+            let line = line.trim();
+            let line = line.strip_prefix('|').unwrap_or(line);
+            let line = line.strip_suffix('|').unwrap_or(line);
+            line.split('|').map(|x| x.trim().to_string()).collect()
+        };
+        let mut rows: Vec<&str> = vec![];
+        let mut header_rows = 0;
+        for line in lines {
+            if TABLE_SEPARATOR_RE.is_match(line) {
+                if header_rows == 0 && !rows.is_empty() {
+                    header_rows = rows.len();
+                }
+            } else {
+                rows.push(line);
+            }
+        }
+
+        self.start(Tag::Table, Attrs::default());
+        self.start(Tag::TableHeader, Attrs::default());
+        if header_rows > 0 {
+            for cell in cells(rows[0]) {
+                self.start(Tag::TableHead, Attrs::default());
+                self.inline(&cell);
+                self.end(Tag::TableHead);
+            }
+        }
+        self.end(Tag::TableHeader);
+        self.start(Tag::TableBody, Attrs::default());
+        for row in rows.iter().skip(header_rows.min(1)) {
+            self.start(Tag::TableRow, Attrs::default());
+            for cell in cells(row) {
+                self.start(Tag::TableCell, Attrs::default());
+                self.inline(&cell);
+                self.end(Tag::TableCell);
+            }
+            self.end(Tag::TableRow);
+        }
+        self.end(Tag::TableBody);
+        self.end(Tag::Table);
+    }
+
+    /// Emits a list starting at the first line and returns the number of
+    /// lines it spans.
+    fn list(&mut self, lines: &[&str]) -> usize {
+        let first = LIST_ITEM_RE.captures(lines[0]).unwrap();
+        let indent = first[1].len();
+        let ordered = first[2].starts_with(|c: char| c.is_ascii_digit());
+
+        // split into items: an item continues while lines are indented
+        // deeper than its bullet or blank and followed by such lines
+        let mut items: Vec<(usize, usize)> = vec![];
+        let mut idx = 0;
+        let mut tight = true;
+        while idx < lines.len() {
+            let is_item = matches!(
+                LIST_ITEM_RE.captures(lines[idx]),
+                Some(caps) if caps[1].len() == indent
+                    && caps[2].starts_with(|c: char| c.is_ascii_digit()) == ordered
+            );
+            if !is_item {
+                break;
+            }
+            let start = idx;
+            idx += 1;
+            while idx < lines.len() {
+                let line = lines[idx];
+                if line.trim().is_empty() {
+                    let next = lines[idx..].iter().find(|x| !x.trim().is_empty());
+                    match next {
+                        Some(next) if indentation(next) > indent => {}
+                        Some(next)
+                            if indentation(next) == indent && LIST_ITEM_RE.is_match(next) =>
+                        {
+                            tight = false;
+                            break;
+                        }
+                        _ => break,
+                    }
+                } else if indentation(line) <= indent {
+                    break;
+                }
+                idx += 1;
+            }
+            let mut end = idx;
+            while end > start + 1 && lines[end - 1].trim().is_empty() {
+                end -= 1;
+            }
+            items.push((start, end));
+            while idx < lines.len() && lines[idx].trim().is_empty() {
+                idx += 1;
+            }
+        }
+
+        let tag = if ordered {
+            Tag::OrderedList
+        } else {
+            Tag::UnorderedList
+        };
+        let start = if ordered {
+            first[2][..first[2].len() - 1].parse().ok()
+        } else {
+            None
+        };
+        self.start(
+            tag,
+            Attrs {
+                start,
+                tight: Some(tight),
+                ..Attrs::default()
+            },
+        );
+        for (start, end) in items {
+            let caps = LIST_ITEM_RE.captures(lines[start]).unwrap();
+            let content_indent = indent + caps[2].len() + 1;
+            let mut content = vec![caps.get(4).unwrap().as_str()];
+            for line in &lines[start + 1..end] {
+                content.push(
+                    line.get(content_indent.min(indentation(line))..)
+                        .unwrap_or(""),
+                );
+            }
+            self.start(Tag::ListItem, Attrs::default());
+            if let Some(checkbox) = caps.get(3) {
+                self.push(CheckboxEvent {
+                    checked: checkbox.as_str() != " ",
+                });
+            }
+            let item_start = self.events.len();
+            self.blocks(&content);
+            if tight {
+                // tight items hold their contents directly
+                let mut depth = 0;
+                let mut idx = item_start;
+                while idx < self.events.len() {
+                    match self.events[idx].event {
+                        Event::StartTag(..) => depth += 1,
+                        Event::EndTag(..) => depth -= 1,
+                        _ => {}
+                    }
+                    let paragraph = match self.events[idx].event {
+                        Event::StartTag(ref tag) => tag.tag == Tag::Paragraph,
+                        Event::EndTag(ref tag) => tag.tag == Tag::Paragraph,
+                        _ => false,
+                    };
+                    let top_level = depth == 1
+                        && matches!(self.events[idx].event, Event::StartTag(..))
+                        || depth == 0 && matches!(self.events[idx].event, Event::EndTag(..));
+                    if paragraph && top_level {
+                        self.events.remove(idx);
+                    } else {
+                        idx += 1;
+                    }
+                }
+            }
+            self.end(Tag::ListItem);
+        }
+        self.end(tag);
+        idx
+    }
+
+    /// Emits block level contents.
+    fn blocks(&mut self, lines: &[&str]) {
+        let mut idx = 0;
+        while idx < lines.len() {
+            let line = lines[idx];
+            let trimmed = line.trim();
+
+            // blank lines and comments
+            if trimmed.is_empty() || trimmed == "#" || trimmed.starts_with("# ") {
+                idx += 1;
+                continue;
+            }
+
+            // headings with an optional property drawer
+            if let Some(caps) = HEADING_RE.captures(line) {
+                idx += 1;
+                let mut id = None;
+                if lines.get(idx).map(|x| x.trim()) == Some(":PROPERTIES:") {
+                    idx += 1;
+                    while idx < lines.len() && lines[idx].trim() != ":END:" {
+                        if let Some(prop) = PROPERTY_RE.captures(lines[idx]) {
+                            if prop[1].eq_ignore_ascii_case("custom_id") {
+                                id = Some(prop[2].trim().to_string());
+                            }
+                        }
+                        idx += 1;
+                    }
+                    idx += 1;
+                }
+                self.heading(caps[1].len(), &caps[2], id);
+                continue;
+            }
+
+            // blocks
+            if let Some(caps) = BLOCK_START_RE.captures(trimmed) {
+                let kind = caps[1].to_lowercase();
+                let argument = caps[2].trim().to_string();
+                let end_marker = format!("#+end_{}", kind);
+                let body_start = idx + 1;
+                let mut end = body_start;
+                while end < lines.len() && !lines[end].trim().eq_ignore_ascii_case(&end_marker) {
+                    end += 1;
+                }
+                let body_indent = indentation(line);
+                let body: Vec<&str> = lines[body_start..end]
+                    .iter()
+                    .map(|x| x.get(body_indent.min(indentation(x))..).unwrap_or(""))
+                    .collect();
+                let mut code = body.join("\n");
+                code.push('\n');
+                match kind.as_str() {
+                    "src" | "example" => self.push(CodeBlockEvent {
+                        language: argument
+                            .split_whitespace()
+                            .next()
+                            .filter(|_| kind == "src")
+                            .map(|x| Str::from(x.to_string())),
+                        args: None,
+                        code: code.into(),
+                    }),
+                    "quote" => {
+                        self.start(Tag::BlockQuote, Attrs::default());
+                        self.blocks(&body);
+                        self.end(Tag::BlockQuote);
+                    }
+                    _ => self.push(DirectiveEvent {
+                        name: kind.into(),
+                        argument: if argument.is_empty() {
+                            None
+                        } else {
+                            Some(argument.into())
+                        },
+                        front_matter: None,
+                        body: code.into(),
+                        body_location: None,
+                    }),
+                }
+                idx = end + 1;
+                continue;
+            }
+
+            // keywords
+            if let Some(caps) = KEYWORD_RE.captures(trimmed) {
+                self.front_matter
+                    .insert(caps[1].to_lowercase(), Value::String(caps[2].to_string()));
+                idx += 1;
+                continue;
+            }
+
+            if RULE_RE.is_match(line) {
+                self.push(Event::Rule);
+                idx += 1;
+                continue;
+            }
+
+            if trimmed.starts_with('|') {
+                let start = idx;
+                while idx < lines.len() && lines[idx].trim().starts_with('|') {
+                    idx += 1;
+                }
+                self.table(&lines[start..idx]);
+                continue;
+            }
+
+            if LIST_ITEM_RE.is_match(line) && !HEADING_RE.is_match(line) {
+                idx += self.list(&lines[idx..]);
+                continue;
+            }
+
+            // paragraphs run until a blank line or another block
+            let start = idx;
+            idx += 1;
+            while idx < lines.len() {
+                let next = lines[idx];
+                let next_trimmed = next.trim();
+                if next_trimmed.is_empty()
+                    || HEADING_RE.is_match(next)
+                    || BLOCK_START_RE.is_match(next_trimmed)
+                    || KEYWORD_RE.is_match(next_trimmed)
+                    || LIST_ITEM_RE.is_match(next)
+                    || RULE_RE.is_match(next)
+                    || next_trimmed.starts_with('|')
+                {
+                    break;
+                }
+                idx += 1;
+            }
+            self.start(Tag::Paragraph, Attrs::default());
+            self.paragraph_contents(&lines[start..idx]);
+            self.end(Tag::Paragraph);
+        }
+    }
+}
+
+/// Converts an Org document into an event stream.
+///
+/// This is only available with the `org-import` feature.  `#+KEY: value`
+/// keywords (like `#+TITLE:`) become front matter with lowercased keys.
+/// Links with a type that is not a URL scheme (`[[ref:intro]]`) become
+/// interpreted text with the link type as role so that they can be handled
+/// by role processors.  Special blocks (`#+BEGIN_NOTE`) become directives.
+///
+/// The events carry no locations.
+///
+/// ```
+/// use struckdown::markdown::to_markdown;
+/// use struckdown::parser::from_org;
+///
+/// let markdown = to_markdown(from_org("* Hello /World/"), &Default::default());
+/// assert_eq!(markdown, "# Hello *World*\n");
+/// ```
+pub fn from_org(source: &str) -> impl Iterator<Item = AnnotatedEvent<'static>> {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut converter = Converter {
+        events: vec![],
+        front_matter: BTreeMap::new(),
+    };
+    converter.blocks(&lines);
+    let front_matter = if converter.front_matter.is_empty() {
+        None
+    } else {
+        Some(Value::Object(converter.front_matter.into_iter().collect()))
+    };
+    let mut events = vec![DocumentStartEvent { front_matter }.into()];
+    events.extend(converter.events);
+    events.into_iter()
+}
+
+#[test]
+fn test_from_org() {
+    use crate::markdown::to_markdown;
+
+    let org = r#"#+TITLE: Notes
+#+AUTHOR: Jane
+
+* TODO Introduction                                      :draft:
+  :PROPERTIES:
+  :CUSTOM_ID: intro
+  :END:
+Some *bold*, /italic/, _underlined_, +struck+, =verbatim= and ~code~ text
+with a [[https://example.com][link]], a [[#intro]] reference\\
+and a role [[ref:setup]].
+
+** Lists
+- [X] done
+- [ ] open
+  - nested item
+1. first
+
+2. second
+   continued
+
+| Name | Value |
+|------+-------|
+| a    | *1*   |
+
+#+BEGIN_SRC rust
+fn main() {}
+#+END_SRC
+
+#+begin_quote
+Quoted /text/.
+#+end_quote
+
+#+BEGIN_NOTE Careful
+Body
+#+END_NOTE
+
+-----
+# a comment
+"#;
+    insta::assert_snapshot!(to_markdown(from_org(org), &Default::default()));
+}
@@ -0,0 +1,339 @@
+//! Compares the outlines of two versions of a document.
+//!
+//! Reviewing a change to documentation is easier if the structural changes
+//! are known up front.  [`collect_outline`] collects the headings of a
+//! processed event stream and [`compare_outlines`] reports the sections that
+//! were added, removed, moved or renamed.  [`OutlineReport::to_markdown`]
+//! renders the report as a summary that can be posted on pull requests.
+//!
+//! ```
+//! use struckdown::outline::{collect_outline, compare_outlines};
+//! use struckdown::parser::parse;
+//!
+//! let old = collect_outline(parse("# Intro\n## Setup\n## Usage\n", &Default::default()));
+//! let new = collect_outline(parse("# Intro\n## Usage\n## Setup\n## FAQ\n", &Default::default()));
+//! let report = compare_outlines(&old, &new);
+//! assert_eq!(report.added[0].title, "FAQ");
+//! assert_eq!(report.moved.len(), 1);
+//! ```
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, Location, StartTagEvent};
+use crate::plain::to_plain_text;
+
+/// A section of a document as started by a heading.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Section {
+    /// The level of the heading (1 to 6).
+    pub level: usize,
+    /// The text of the heading.
+    pub title: String,
+    /// The id of the heading.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The location of the heading.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
+
+/// A section that exists in both versions but changed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChangedSection {
+    /// The section in the old version.
+    pub old: Section,
+    /// The section in the new version.
+    pub new: Section,
+    /// The title of the section the new version is nested in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+/// The structural changes between two versions of a document.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct OutlineReport {
+    /// Sections that did not exist before.
+    pub added: Vec<Section>,
+    /// Sections that no longer exist.
+    pub removed: Vec<Section>,
+    /// Sections that changed their level, parent or position relative to
+    /// the other sections.
+    pub moved: Vec<ChangedSection>,
+    /// Sections that changed their title or id.
+    pub renamed: Vec<ChangedSection>,
+}
+
+impl OutlineReport {
+    /// Returns `true` if the structure did not change.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.moved.is_empty()
+            && self.renamed.is_empty()
+    }
+
+    /// Renders the report as a markdown summary.
+    ///
+    /// An empty report renders to a single line stating that the outline
+    /// did not change.
+    pub fn to_markdown(&self) -> String {
+        if self.is_empty() {
+            return "The outline did not change.\n".into();
+        }
+        let mut rv = String::new();
+        let mut list = |heading: &str, items: Vec<String>| {
+            if !items.is_empty() {
+                if !rv.is_empty() {
+                    rv.push('\n');
+                }
+                writeln!(rv, "**{}**\n", heading).unwrap();
+                for item in items {
+                    writeln!(rv, "- {}", item).unwrap();
+                }
+            }
+        };
+        list("Added", self.added.iter().map(describe_section).collect());
+        list(
+            "Removed",
+            self.removed.iter().map(describe_section).collect(),
+        );
+        list(
+            "Moved",
+            self.moved
+                .iter()
+                .map(|x| {
+                    let mut position = match x.parent {
+                        Some(ref parent) => format!("under {}", parent),
+                        None => "at the top level".into(),
+                    };
+                    if x.old.level != x.new.level {
+                        position = format!("level {} {}", x.new.level, position);
+                    }
+                    format!("{} (now {})", describe_section(&x.new), position)
+                })
+                .collect(),
+        );
+        list(
+            "Renamed",
+            self.renamed
+                .iter()
+                .map(|x| {
+                    format!(
+                        "{} → {}",
+                        describe_section(&x.old),
+                        describe_section(&x.new)
+                    )
+                })
+                .collect(),
+        );
+        rv
+    }
+}
+
+fn describe_section(section: &Section) -> String {
+    match section.id {
+        Some(ref id) => format!("{} (`#{}`)", section.title, id),
+        None => section.title.clone(),
+    }
+}
+
+/// Collects the sections of an event stream in document order.
+///
+/// To include generated ids the stream should already have been processed
+/// by processors such as [`AutoAnchors`](crate::processors::AutoAnchors).
+pub fn collect_outline<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(iter: I) -> Vec<Section> {
+    let mut rv = vec![];
+    let mut heading: Option<(Section, Vec<AnnotatedEvent<'data>>)> = None;
+    let mut depth = 0;
+
+    for annotated_event in iter {
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, ref attrs }) => {
+                if heading.is_some() {
+                    depth += 1;
+                } else if let Some(level) = tag.header_level() {
+                    heading = Some((
+                        Section {
+                            level,
+                            title: String::new(),
+                            id: attrs.id.as_ref().map(|x| x.as_str().to_string()),
+                            location: annotated_event.location.clone(),
+                        },
+                        vec![],
+                    ));
+                    continue;
+                }
+            }
+            Event::EndTag(_) if heading.is_some() => {
+                if depth == 0 {
+                    let (mut section, events) = heading.take().unwrap();
+                    section.title = to_plain_text(events.iter()).as_str().trim().to_string();
+                    rv.push(section);
+                    continue;
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        if let Some((_, ref mut events)) = heading {
+            events.push(annotated_event);
+        }
+    }
+
+    rv
+}
+
+/// Returns the index of the closest preceding section of a lower level.
+fn parents(sections: &[Section]) -> Vec<Option<usize>> {
+    let mut stack: Vec<usize> = vec![];
+    sections
+        .iter()
+        .enumerate()
+        .map(|(idx, section)| {
+            while let Some(&x) = stack.last() {
+                if sections[x].level < section.level {
+                    break;
+                }
+                stack.pop();
+            }
+            let parent = stack.last().copied();
+            stack.push(idx);
+            parent
+        })
+        .collect()
+}
+
+/// Returns the positions of a longest increasing subsequence.
+fn longest_increasing(values: &[usize]) -> Vec<bool> {
+    let mut lengths = vec![1; values.len()];
+    let mut previous: Vec<Option<usize>> = vec![None; values.len()];
+    for i in 0..values.len() {
+        for j in 0..i {
+            if values[j] < values[i] && lengths[j] + 1 > lengths[i] {
+                lengths[i] = lengths[j] + 1;
+                previous[i] = Some(j);
+            }
+        }
+    }
+    let mut rv = vec![false; values.len()];
+    let mut cursor = (0..values.len()).max_by_key(|&x| (lengths[x], std::cmp::Reverse(x)));
+    while let Some(idx) = cursor {
+        rv[idx] = true;
+        cursor = previous[idx];
+    }
+    rv
+}
+
+/// Compares the outlines of two versions of a document.
+///
+/// Sections are matched by id first and by title second.  A remaining
+/// section that keeps its level and follows the same matched section in
+/// both versions is matched as well, so a heading that changed both its
+/// title and generated id is reported as renamed rather than as removed
+/// and added.
+///
+/// Matched sections are renamed if their title or id changed and moved if
+/// their level or parent changed or if they changed their order relative
+/// to the other matched sections.  The fewest sections that explain a new
+/// order are reported as moved.
+pub fn compare_outlines(old: &[Section], new: &[Section]) -> OutlineReport {
+    let mut matches: Vec<Option<usize>> = vec![None; old.len()];
+    let mut taken = vec![false; new.len()];
+    let mut pass = |matches: &mut Vec<Option<usize>>, f: &dyn Fn(usize, usize) -> bool| {
+        for (i, matched) in matches.iter_mut().enumerate() {
+            if matched.is_some() {
+                continue;
+            }
+            if let Some(j) = (0..new.len()).find(|&j| !taken[j] && f(i, j)) {
+                *matched = Some(j);
+                taken[j] = true;
+            }
+        }
+    };
+    pass(&mut matches, &|i, j| {
+        old[i].id.is_some() && old[i].id == new[j].id
+    });
+    pass(&mut matches, &|i, j| {
+        !old[i].title.is_empty() && old[i].title == new[j].title
+    });
+    // sections are placed relative to the sections matched by id or title
+    let anchored = matches.clone();
+    let mut new_anchored = vec![false; new.len()];
+    for &j in anchored.iter().flatten() {
+        new_anchored[j] = true;
+    }
+    pass(&mut matches, &|i, j| {
+        let old_before = (0..i).rev().find_map(|x| anchored[x]);
+        let new_before = (0..j).rev().find(|&x| new_anchored[x]);
+        old[i].level == new[j].level && old_before == new_before
+    });
+
+    let old_parents = parents(old);
+    let new_parents = parents(new);
+    let mut pairs: Vec<(usize, usize)> = matches
+        .iter()
+        .enumerate()
+        .filter_map(|(i, j)| j.map(|j| (i, j)))
+        .collect();
+    let in_order = longest_increasing(&pairs.iter().map(|x| x.1).collect::<Vec<_>>());
+    let mut moved: Vec<bool> = pairs
+        .iter()
+        .zip(in_order)
+        .map(|(&(i, j), in_order)| {
+            !in_order
+                || old[i].level != new[j].level
+                || old_parents[i].and_then(|x| matches[x]) != new_parents[j]
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..pairs.len()).collect();
+    order.sort_by_key(|&x| pairs[x].1);
+    pairs = order.iter().map(|&x| pairs[x]).collect();
+    moved = order.iter().map(|&x| moved[x]).collect();
+
+    let mut report = OutlineReport::default();
+    for (&(i, j), moved) in pairs.iter().zip(moved) {
+        let changed = || ChangedSection {
+            old: old[i].clone(),
+            new: new[j].clone(),
+            parent: new_parents[j].map(|x| new[x].title.clone()),
+        };
+        if moved {
+            report.moved.push(changed());
+        }
+        if old[i].title != new[j].title || old[i].id != new[j].id {
+            report.renamed.push(changed());
+        }
+    }
+    report.removed = (0..old.len())
+        .filter(|&i| matches[i].is_none())
+        .map(|i| old[i].clone())
+        .collect();
+    report.added = (0..new.len())
+        .filter(|&j| !taken[j])
+        .map(|j| new[j].clone())
+        .collect();
+    report
+}
+
+#[test]
+fn test_compare_outlines() {
+    use crate::parser::parse;
+    use crate::processors::{AutoAnchors, Processor};
+
+    let outline = |source| {
+        collect_outline(
+            Box::new(AutoAnchors::default()).apply(Box::new(parse(source, &Default::default()))),
+        )
+    };
+    let old = outline(
+        "# Intro\n\n## Setup {#setup}\n\n## Usage\n\n### Options\n\n## Legacy\n\n## Contributing\n\n## License\n",
+    );
+    let new = outline(
+        "# Intro\n\n## FAQ\n\n## Installation {#setup}\n\n## Contributing\n\n## Licensing\n\n## Usage\n\n## Options\n",
+    );
+    let report = compare_outlines(&old, &new);
+    insta::assert_yaml_snapshot!(&report);
+    insta::assert_snapshot!(report.to_markdown());
+    assert!(compare_outlines(&old, &old).is_empty());
+}
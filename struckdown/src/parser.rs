@@ -1,20 +1,35 @@
 //! Gives access to the stream parser.
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
 use std::iter;
 use std::ops::Range;
+use std::sync::Arc;
 
 use itertools::Either;
 use lazy_static::lazy_static;
 use pulldown_cmark as cm;
 use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
+use crate::codes::Code;
 use crate::event::{
-    Alignment, AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, DirectiveEvent,
-    DocumentStartEvent, EndTagEvent, Event, FootnoteReferenceEvent, ImageEvent, InlineCodeEvent,
-    InterpretedTextEvent, Location, RawHtmlEvent, StartTagEvent, Str, Tag, TextEvent,
+    fingerprint, Alignment, AnnotatedEvent, Attrs, CheckboxEvent, CodeBlockEvent, DiagnosticEvent,
+    DirectiveEvent, DocumentStartEvent, EndTagEvent, Event, FootnoteReferenceEvent, ImageEvent,
+    InlineCodeEvent, InlineMathEvent, InterpretedTextEvent, Location, MathBlockEvent, RawHtmlEvent,
+    Severity, StartTagEvent, Str, Tag, TextEvent, Trivia,
 };
+use crate::limits::{Limits, LimitsIter};
 use crate::value::Value;
 
+#[cfg(feature = "html-import")]
+pub use crate::html_import::from_html;
+#[cfg(feature = "notebook-import")]
+pub use crate::notebook_import::from_notebook;
+#[cfg(feature = "org-import")]
+pub use crate::org_import::from_org;
+
 lazy_static! {
     static ref TEXT_ROLE_RE: Regex = Regex::new(r"\{([^\r\n\}]+)\}$").unwrap();
     static ref DIRECTIVE_RE: Regex = Regex::new(r"^\{([^\r\n\}]+)\}(?:\s+(.*?))?$").unwrap();
@@ -22,16 +37,156 @@ lazy_static! {
     static ref FRONTMATTER_RE: Regex = Regex::new(r"(?sm)\A---\s*$(.*?)^---\s*$\r?\n?").unwrap();
     static ref FRONTMATTER_FULL_RE: Regex = Regex::new(r"(?sm)\A---\s*$(.*)").unwrap();
     static ref CODE_LANG_RE: Regex = Regex::new(r#"(\S+)\s+"#).unwrap();
-    static ref CODE_ARG_RE: Regex = Regex::new(r#"([^=\s]+)(?:="([^"]*)"|\S+)?"#).unwrap();
+    static ref CODE_ARG_RE: Regex = Regex::new(r#"([^=\s]+)(?:="([^"]*)"|=(\S+))?"#).unwrap();
+    static ref DEFINITION_MARKER_RE: Regex = Regex::new(r"^:[ \t]+").unwrap();
+    static ref LIST_MARKER_RE: Regex = Regex::new(r"^(?:[-+*]|\d{1,9}[.)])(?: {1,4})?").unwrap();
+    static ref FENCE_RE: Regex = Regex::new(r"^ {0,3}(`{3,}|~{3,})").unwrap();
+}
+
+/// Where front matter is accepted in a document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrontMatterPosition {
+    /// Front matter must be at the start of the document.
+    Start,
+    /// Front matter must be at the end of the document.
+    End,
+    /// Front matter may be at the start or the end of the document.
+    StartOrEnd,
+}
+
+/// How columns of locations are counted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnMode {
+    /// Columns are UTF-8 byte offsets into the line.
+    Bytes,
+    /// Columns are UTF-16 code units as expected by the language server
+    /// protocol.
+    Utf16,
+    /// Columns are the display width of the line up to the location.
+    ///
+    /// Grapheme clusters are counted with their terminal width (so wide
+    /// characters count twice and combining marks not at all) and tabs
+    /// advance to the next multiple of `tab_width`.
+    DisplayWidth {
+        /// The distance between tab stops.
+        tab_width: usize,
+    },
+}
+
+/// Resolves references that are not defined in a document.
+///
+/// The resolver is called with the label of every reference link without a
+/// matching definition, like `Page Title` for `[Page Title]`, and returns
+/// the target and title (empty for none) of the link.  This lets shortcut
+/// references point to a site-wide link database:
+///
+/// ```
+/// use std::collections::HashMap;
+/// use struckdown::html::to_html;
+/// use struckdown::parser::{parse, ParserOptions, ReferenceResolver};
+///
+/// let mut pages = HashMap::new();
+/// pages.insert("Installation".to_string(), "/install/".to_string());
+/// let options = ParserOptions {
+///     reference_resolver: Some(ReferenceResolver::new(move |reference| {
+///         pages.get(reference).map(|target| (target.clone().into(), "".into()))
+///     })),
+///     ..ParserOptions::default()
+/// };
+/// let html = to_html(parse("See [Installation].", &options), &Default::default());
+/// assert!(html.contains(">Installation</a>"));
+/// ```
+#[derive(Clone)]
+pub struct ReferenceResolver(Arc<ResolveFn>);
+
+type ResolveFn = dyn Fn(&str) -> Option<(Str<'static>, Str<'static>)> + Send + Sync + 'static;
+
+impl ReferenceResolver {
+    /// Creates a resolver from a function.
+    pub fn new<F>(f: F) -> ReferenceResolver
+    where
+        F: Fn(&str) -> Option<(Str<'static>, Str<'static>)> + Send + Sync + 'static,
+    {
+        ReferenceResolver(Arc::new(f))
+    }
+
+    /// Resolves a reference into its target and title.
+    pub fn resolve(&self, reference: &str) -> Option<(Str<'static>, Str<'static>)> {
+        (self.0)(reference)
+    }
+}
+
+impl fmt::Debug for ReferenceResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ReferenceResolver")
+    }
+}
+
+/// Maps byte offsets to lines and columns.
+///
+/// The start of every line is recorded once so that lookups are a binary
+/// search instead of a scan from the start of the document.
+struct LineIndex<'data> {
+    source: &'data str,
+    line_starts: Vec<usize>,
+    column_mode: ColumnMode,
+}
+
+impl<'data> LineIndex<'data> {
+    fn new(source: &'data str, column_mode: ColumnMode) -> LineIndex<'data> {
+        let line_starts = iter::once(0)
+            .chain(source.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+        LineIndex {
+            source,
+            line_starts,
+            column_mode,
+        }
+    }
+
+    /// Returns the line (1 indexed) and column (0 indexed) of an offset.
+    fn locate(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(idx) => idx,
+            Err(idx) => idx - 1,
+        };
+        let prefix = &self.source[self.line_starts[line]..offset];
+        let column = match self.column_mode {
+            ColumnMode::Bytes => prefix.len(),
+            ColumnMode::Utf16 => prefix.encode_utf16().count(),
+            ColumnMode::DisplayWidth { tab_width } => {
+                prefix.graphemes(true).fold(0, |column, grapheme| {
+                    if grapheme == "\t" {
+                        let tab_width = tab_width.max(1);
+                        (column / tab_width + 1) * tab_width
+                    } else {
+                        column + grapheme.width()
+                    }
+                })
+            }
+        };
+        (line + 1, column)
+    }
 }
 
 /// Configures the parser.
 ///
-/// By default all features are enabled.
+/// By default all features except smart punctuation are enabled.
 #[derive(Debug, Clone)]
 pub struct ParserOptions {
     /// Enables or disables front matter.
     pub enable_frontmatter: bool,
+    /// The line opening front matter.
+    pub frontmatter_delimiter: String,
+    /// The line closing front matter.  Defaults to the opening delimiter.
+    pub frontmatter_end_delimiter: Option<String>,
+    /// Where front matter is accepted.
+    ///
+    /// Front matter at the end of the document must be a mapping so that
+    /// text between two thematic breaks is not mistaken for front matter.
+    pub frontmatter_position: FrontMatterPosition,
+    /// When disabled front matter at the start may follow blank lines.
+    pub frontmatter_at_first_line: bool,
     /// Enables or disables directives.
     pub enable_directives: bool,
     /// Enables or disables roles.
@@ -46,12 +201,70 @@ pub struct ParserOptions {
     pub enable_footnotes: bool,
     /// Enables or disables explicit anchors.
     pub enable_anchors: bool,
+    /// Enables or disables definition lists.
+    ///
+    /// Lines starting with a colon followed by whitespace define the term
+    /// in the line before.  Terms and definitions can also be separated by
+    /// a blank line.
+    pub enable_definition_lists: bool,
+    /// Enables or disables math.
+    ///
+    /// `$...$` is inline math and `$$...$$` display math.  The opening `$`
+    /// of inline math must not be followed by whitespace and the closing
+    /// `$` must neither follow whitespace nor be followed by a digit so
+    /// that prices stay text.  The `math` role, `math` code blocks and the
+    /// `math` directive produce math events as well.
+    pub enable_math: bool,
+    /// Enables or disables smart punctuation.
+    ///
+    /// When enabled straight quotes become curly quotes, `--` and `---`
+    /// become en and em dashes and `...` becomes an ellipsis.  This is off
+    /// by default.
+    pub enable_smart_punctuation: bool,
+    /// How the columns of locations are counted.
+    pub column_mode: ColumnMode,
+    /// Attaches the source range to block level tags.
+    ///
+    /// Every block level start tag gets a `data-sourcepos` attribute like
+    /// `3:1-5:12` (start line and column to the inclusive end line and
+    /// column, both 1 indexed) which editor previews can use for scroll
+    /// synchronization and click to edit.  This is off by default.
+    pub enable_source_positions: bool,
+    /// Limits for the size and complexity of documents.
+    ///
+    /// Documents over the limits are cut short with an error, see
+    /// [`limits`](crate::limits).  Nothing is limited by default.
+    pub limits: Limits,
+    /// Parses untrusted input defensively.
+    ///
+    /// In hardened mode the stream is guaranteed to be well formed, missing
+    /// end tags are synthesized and stray end tags dropped.  The document is
+    /// parsed eagerly in this mode.  This is off by default.
+    pub hardened: bool,
+    /// Records source trivia on the events.
+    ///
+    /// The exact source of blocks, list markers and fences are attached as
+    /// [`Trivia`] so that rendering back to markdown reproduces the source
+    /// byte for byte where the events were not changed.  Front matter at
+    /// the end of a document is not preserved.  The document is parsed
+    /// eagerly in this mode.  This is off by default.
+    pub lossless: bool,
+    /// Resolves reference links without a definition in the document.
+    ///
+    /// With a resolver the document is parsed eagerly.  Directive bodies
+    /// and other nested documents are parsed without it.  There is no
+    /// resolver by default.
+    pub reference_resolver: Option<ReferenceResolver>,
 }
 
 impl Default for ParserOptions {
     fn default() -> Self {
         ParserOptions {
             enable_frontmatter: true,
+            frontmatter_delimiter: "---".into(),
+            frontmatter_end_delimiter: None,
+            frontmatter_position: FrontMatterPosition::Start,
+            frontmatter_at_first_line: true,
             enable_directives: true,
             enable_roles: true,
             enable_tables: true,
@@ -59,6 +272,40 @@ impl Default for ParserOptions {
             enable_tasklists: true,
             enable_footnotes: true,
             enable_anchors: true,
+            enable_definition_lists: true,
+            enable_math: true,
+            enable_smart_punctuation: false,
+            column_mode: ColumnMode::Bytes,
+            enable_source_positions: false,
+            limits: Limits::default(),
+            hardened: false,
+            lossless: false,
+            reference_resolver: None,
+        }
+    }
+}
+
+impl ParserOptions {
+    /// Returns options for plain CommonMark.
+    ///
+    /// All extensions as well as the struckdown specific syntax (front
+    /// matter, directives, roles, explicit anchors and math) are disabled so that
+    /// documents are parsed exactly as a strict CommonMark implementation
+    /// would.
+    pub fn commonmark() -> ParserOptions {
+        ParserOptions {
+            enable_frontmatter: false,
+            enable_directives: false,
+            enable_roles: false,
+            enable_tables: false,
+            enable_strikethrough: false,
+            enable_tasklists: false,
+            enable_footnotes: false,
+            enable_anchors: false,
+            enable_definition_lists: false,
+            enable_math: false,
+            enable_smart_punctuation: false,
+            ..ParserOptions::default()
         }
     }
 }
@@ -94,7 +341,36 @@ impl Parser {
     /// does not exist as such in the source.  Both of those will not have a location
     /// attached.
     pub fn parse<'data>(&self, s: &'data str) -> impl Iterator<Item = AnnotatedEvent<'data>> {
-        parse_internal(s, self.options.clone())
+        let limits = &self.options.limits;
+        if let Err(error) = limits.check_input(s) {
+            return Either::Left(
+                vec![
+                    DocumentStartEvent { front_matter: None }.into(),
+                    error.into(),
+                ]
+                .into_iter(),
+            );
+        }
+        let iter = if self.options.hardened {
+            Either::Left(parse_hardened(s, self.options.clone()))
+        } else {
+            Either::Right(parse_internal(s, self.options.clone()))
+        };
+        let iter = if self.options.lossless {
+            Either::Left(record_trivia(s, iter.collect()).into_iter())
+        } else {
+            Either::Right(iter)
+        };
+        Either::Right(if limits.is_unlimited() {
+            Either::Left(iter)
+        } else {
+            Either::Right(LimitsIter::new(iter, Cow::Owned(limits.clone())))
+        })
+    }
+
+    /// Returns the options of the parser.
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
     }
 }
 
@@ -108,7 +384,15 @@ impl Parser {
 fn read_raw<'a, 'data, I: Iterator<Item = (cm::Event<'data>, Range<usize>)>>(
     iter: &'a mut I,
 ) -> Str<'data> {
+    read_raw_with_range(iter).0
+}
+
+/// Like [`read_raw`] but also returns the range the text was read from.
+fn read_raw_with_range<'a, 'data, I: Iterator<Item = (cm::Event<'data>, Range<usize>)>>(
+    iter: &'a mut I,
+) -> (Str<'data>, Option<Range<usize>>) {
     let mut depth = 1;
+    let mut range: Option<Range<usize>> = None;
     let mut buffer = None;
     let mut last_event = None;
 
@@ -121,8 +405,7 @@ fn read_raw<'a, 'data, I: Iterator<Item = (cm::Event<'data>, Range<usize>)>>(
                     if let Some(last_event) = last_event.take() {
                         buf.push_str(&last_event);
                     }
-                    buffer = Some(buf);
-                    buffer.as_mut().unwrap()
+                    buffer.get_or_insert(buf)
                 }
             }
         };
@@ -138,7 +421,13 @@ fn read_raw<'a, 'data, I: Iterator<Item = (cm::Event<'data>, Range<usize>)>>(
         };
     }
 
-    for (event, _) in iter {
+    for (event, event_range) in iter {
+        if let cm::Event::Text(..) | cm::Event::Code(..) = event {
+            range = Some(match range {
+                Some(range) => range.start..event_range.end,
+                None => event_range,
+            });
+        }
         match event {
             cm::Event::Start(..) => depth += 1,
             cm::Event::End(..) => depth -= 1,
@@ -152,11 +441,12 @@ fn read_raw<'a, 'data, I: Iterator<Item = (cm::Event<'data>, Range<usize>)>>(
         }
     }
 
-    match (buffer, last_event) {
+    let text = match (buffer, last_event) {
         (Some(buf), _) => buf.into(),
         (None, Some(event)) => Str::from_cm_str(event),
         (None, None) => "".into(),
-    }
+    };
+    (text, range)
 }
 
 /// parse front matter in some text
@@ -178,14 +468,99 @@ fn split_and_parse_front_matter(source: Str<'_>) -> (Option<Value>, Str<'_>) {
     (None, source)
 }
 
+/// Returns the offset and error of front matter that fails to parse.
+fn front_matter_error(source: &str) -> Option<(usize, serde_yaml::Error)> {
+    let m = FRONTMATTER_RE
+        .captures(source)
+        .or_else(|| FRONTMATTER_FULL_RE.captures(source))?;
+    let yaml = m.get(1).unwrap();
+    let err = serde_yaml::from_str::<Value>(yaml.as_str()).err()?;
+    // the error location counts characters
+    let offset = err.location().map_or(0, |location| {
+        yaml.as_str()
+            .char_indices()
+            .nth(location.index())
+            .map_or(yaml.as_str().len(), |x| x.0)
+    });
+    let offset = yaml.start() + offset;
+    Some((offset, err))
+}
+
+fn is_fence(line: &str, delimiter: &str) -> bool {
+    line.trim_end() == delimiter
+}
+
+/// Locates front matter according to the parser options.
+///
+/// Returns the range of the entire front matter block and the contained
+/// YAML source.
+fn find_front_matter<'a>(s: &'a str, options: &ParserOptions) -> Option<(Range<usize>, &'a str)> {
+    let start = options.frontmatter_delimiter.as_str();
+    let end = options
+        .frontmatter_end_delimiter
+        .as_deref()
+        .unwrap_or(start);
+    let mut offset = 0;
+    let lines: Vec<(usize, &str)> = s
+        .split_inclusive('\n')
+        .map(|line| {
+            offset += line.len();
+            (offset - line.len(), line)
+        })
+        .collect();
+    let content_start = |idx: usize| lines[idx].0 + lines[idx].1.len();
+
+    if options.frontmatter_position != FrontMatterPosition::End {
+        let first = if options.frontmatter_at_first_line {
+            Some(0)
+        } else {
+            lines.iter().position(|(_, line)| !line.trim().is_empty())
+        };
+        if let Some(first) = first.filter(|&x| x < lines.len() && is_fence(lines[x].1, start)) {
+            if let Some(close) = lines[first + 1..]
+                .iter()
+                .position(|(_, line)| is_fence(line, end))
+            {
+                let close = first + 1 + close;
+                // blank lines after the front matter are part of it
+                let block_end = lines[close + 1..]
+                    .iter()
+                    .take_while(|(_, line)| line.trim().is_empty())
+                    .last()
+                    .map_or(content_start(close), |(offset, line)| offset + line.len());
+                return Some((0..block_end, &s[content_start(first)..lines[close].0]));
+            }
+        }
+    }
+
+    if options.frontmatter_position != FrontMatterPosition::Start {
+        let last = lines
+            .iter()
+            .rposition(|(_, line)| !line.trim().is_empty())?;
+        if is_fence(lines[last].1, end) {
+            if let Some(open) = lines[..last]
+                .iter()
+                .rposition(|(_, line)| is_fence(line, start))
+            {
+                return Some((
+                    lines[open].0..s.len(),
+                    &s[content_start(open)..lines[last].0],
+                ));
+            }
+        }
+    }
+
+    None
+}
+
 /// A trailer is information that gets attached to the start tag when the end
 /// tag is emitted.
 ///
 /// Trailers are supported internally on all tags for which [`tag_supports_trailers`]
 /// returns `true`.
 enum Trailer<'data> {
-    /// Defines the id attribute via trailer.
-    Id(Str<'data>),
+    /// Defines the id attribute via trailer together with its span.
+    Id(Str<'data>, Option<Location>),
 }
 
 /// Checks if a tag supports trailers.
@@ -203,6 +578,14 @@ fn tag_supports_trailers(tag: Tag) -> bool {
     }
 }
 
+/// Trims leading and trailing whitespace off a string.
+fn trim_str(s: Str<'_>) -> Str<'_> {
+    let trimmed_start = s.as_str().trim_start();
+    let start = s.as_str().len() - trimmed_start.len();
+    let end = start + trimmed_start.trim_end().len();
+    s.slice(start, end)
+}
+
 fn split_code_block_args<'data>(
     info: Str<'data>,
 ) -> (Option<Str<'data>>, Option<BTreeMap<Str<'data>, Str<'data>>>) {
@@ -238,7 +621,100 @@ fn split_code_block_args<'data>(
     (Some(code), if args.is_empty() { None } else { Some(args) })
 }
 
+/// Borrows a cmark string from the source if it is a slice of it.
+fn rebind_cm_str<'data>(source: &'data str, value: cm::CowStr<'_>) -> cm::CowStr<'data> {
+    match value {
+        cm::CowStr::Borrowed(value) => {
+            let start = (value.as_ptr() as usize).wrapping_sub(source.as_ptr() as usize);
+            match source.get(start..start.wrapping_add(value.len())) {
+                Some(slice) if slice.as_ptr() == value.as_ptr() => cm::CowStr::Borrowed(slice),
+                _ => value.to_string().into(),
+            }
+        }
+        cm::CowStr::Boxed(value) => cm::CowStr::Boxed(value),
+        cm::CowStr::Inlined(value) => cm::CowStr::Inlined(value),
+    }
+}
+
+fn rebind_cm_tag<'data>(source: &'data str, tag: cm::Tag<'_>) -> cm::Tag<'data> {
+    match tag {
+        cm::Tag::Paragraph => cm::Tag::Paragraph,
+        cm::Tag::Heading(level) => cm::Tag::Heading(level),
+        cm::Tag::BlockQuote => cm::Tag::BlockQuote,
+        cm::Tag::CodeBlock(cm::CodeBlockKind::Indented) => {
+            cm::Tag::CodeBlock(cm::CodeBlockKind::Indented)
+        }
+        cm::Tag::CodeBlock(cm::CodeBlockKind::Fenced(lang)) => {
+            cm::Tag::CodeBlock(cm::CodeBlockKind::Fenced(rebind_cm_str(source, lang)))
+        }
+        cm::Tag::List(start) => cm::Tag::List(start),
+        cm::Tag::Item => cm::Tag::Item,
+        cm::Tag::FootnoteDefinition(label) => {
+            cm::Tag::FootnoteDefinition(rebind_cm_str(source, label))
+        }
+        cm::Tag::Table(alignments) => cm::Tag::Table(alignments),
+        cm::Tag::TableHead => cm::Tag::TableHead,
+        cm::Tag::TableRow => cm::Tag::TableRow,
+        cm::Tag::TableCell => cm::Tag::TableCell,
+        cm::Tag::Emphasis => cm::Tag::Emphasis,
+        cm::Tag::Strong => cm::Tag::Strong,
+        cm::Tag::Strikethrough => cm::Tag::Strikethrough,
+        cm::Tag::Link(link_type, target, title) => cm::Tag::Link(
+            link_type,
+            rebind_cm_str(source, target),
+            rebind_cm_str(source, title),
+        ),
+        cm::Tag::Image(link_type, target, title) => cm::Tag::Image(
+            link_type,
+            rebind_cm_str(source, target),
+            rebind_cm_str(source, title),
+        ),
+    }
+}
+
+fn rebind_cm_event<'data>(source: &'data str, event: cm::Event<'_>) -> cm::Event<'data> {
+    match event {
+        cm::Event::Start(tag) => cm::Event::Start(rebind_cm_tag(source, tag)),
+        cm::Event::End(tag) => cm::Event::End(rebind_cm_tag(source, tag)),
+        cm::Event::Text(text) => cm::Event::Text(rebind_cm_str(source, text)),
+        cm::Event::Code(code) => cm::Event::Code(rebind_cm_str(source, code)),
+        cm::Event::Html(html) => cm::Event::Html(rebind_cm_str(source, html)),
+        cm::Event::FootnoteReference(label) => {
+            cm::Event::FootnoteReference(rebind_cm_str(source, label))
+        }
+        cm::Event::SoftBreak => cm::Event::SoftBreak,
+        cm::Event::HardBreak => cm::Event::HardBreak,
+        cm::Event::Rule => cm::Event::Rule,
+        cm::Event::TaskListMarker(checked) => cm::Event::TaskListMarker(checked),
+    }
+}
+
+/// Parses cmark with broken references resolved by a resolver.
+///
+/// The cmark parser requires the callback to live as long as the source.
+/// As the resolver is only borrowed the source is parsed to completion and
+/// the strings are borrowed from the source again.
+fn parse_cm_with_resolver<'data>(
+    s: &'data str,
+    opts: cm::Options,
+    resolver: &ReferenceResolver,
+) -> Vec<(cm::Event<'data>, Range<usize>)> {
+    let mut callback = |link: cm::BrokenLink<'_>| {
+        resolver.resolve(link.reference).map(|(target, title)| {
+            (
+                target.as_str().to_string().into(),
+                title.as_str().to_string().into(),
+            )
+        })
+    };
+    cm::Parser::new_with_broken_link_callback(s, opts, Some(&mut callback))
+        .into_offset_iter()
+        .map(|(event, range)| (rebind_cm_event(s, event), range))
+        .collect()
+}
+
 // helper for table state
+#[derive(Default)]
 struct TableState {
     alignments: Vec<Alignment>,
     cell_is_head: bool,
@@ -254,10 +730,15 @@ struct TableState {
 /// stream in structure though some elements are already resolved.  The
 /// main parse function however will attach some virtual elements such as
 /// table bodies which are not there in regular cmark.
+///
+/// Only the `body` range of the `source` is parsed but locations are
+/// reported relative to the entire source.
 fn preliminary_parse_with_trailers<'data>(
-    s: &'data str,
+    source: &'data str,
+    body: Range<usize>,
     options: ParserOptions,
 ) -> impl Iterator<Item = (AnnotatedEvent, Option<Trailer<'data>>)> {
+    let s = &source[body.clone()];
     let mut opts = cm::Options::empty();
     if options.enable_tables {
         opts.insert(cm::Options::ENABLE_TABLES);
@@ -271,27 +752,34 @@ fn preliminary_parse_with_trailers<'data>(
     if options.enable_footnotes {
         opts.insert(cm::Options::ENABLE_FOOTNOTES);
     }
+    if options.enable_smart_punctuation {
+        opts.insert(cm::Options::ENABLE_SMART_PUNCTUATION);
+    }
 
-    let parser = cm::Parser::new_with_broken_link_callback(s, opts, None);
-    let mut iter = parser.into_offset_iter().peekable();
+    let mut iter = match options.reference_resolver {
+        Some(ref resolver) => Either::Left(parse_cm_with_resolver(s, opts, resolver).into_iter()),
+        None => Either::Right(
+            cm::Parser::new_with_broken_link_callback(s, opts, None).into_offset_iter(),
+        ),
+    }
+    .peekable();
     let mut tag_stack = vec![];
     let mut pending_role = None;
     let mut pending_trailer = None;
     let mut table_state = None;
+    let line_index = LineIndex::new(source, options.column_mode);
 
-    iter::from_fn(move || {
+    iter::from_fn(move || loop {
         let mut trailer = None;
 
         if let Some((event, range)) = iter.next() {
-            // inefficient way to find the location
+            let offset = body.start + range.start;
+            let (line, column) = line_index.locate(offset);
             let mut location = Some(Location {
-                offset: range.start,
+                offset,
                 len: range.end - range.start,
-                line: s[..range.start].chars().filter(|&c| c == '\n').count() + 1,
-                column: match s[..range.start].rfind('\n') {
-                    Some(nl) => range.start - nl - 1,
-                    None => range.start,
-                },
+                line,
+                column,
             });
 
             // simple events
@@ -306,7 +794,7 @@ fn preliminary_parse_with_trailers<'data>(
                         cm::Tag::Heading(4) => Tag::Heading4,
                         cm::Tag::Heading(5) => Tag::Heading5,
                         cm::Tag::Heading(6) => Tag::Heading6,
-                        cm::Tag::Heading(_) => unreachable!(),
+                        cm::Tag::Heading(_) => Tag::Heading6,
                         cm::Tag::BlockQuote => Tag::BlockQuote,
                         cm::Tag::CodeBlock(kind) => match kind {
                             cm::CodeBlockKind::Fenced(lang) => {
@@ -314,14 +802,41 @@ fn preliminary_parse_with_trailers<'data>(
                                 if options.enable_directives {
                                     if let Some(m) = DIRECTIVE_RE.captures(lang.as_str()) {
                                         let g1 = m.get(1).unwrap();
+                                        if options.enable_math && g1.as_str() == "math" {
+                                            let math = trim_str(read_raw(&mut iter));
+                                            let (_, args) = split_code_block_args(lang);
+                                            return Some((
+                                                AnnotatedEvent::new(
+                                                    MathBlockEvent { args, math },
+                                                    location,
+                                                ),
+                                                None,
+                                            ));
+                                        }
                                         let arg = if let Some(g2) = m.get(2) {
                                             lang.slice(g2.start(), g2.end())
                                         } else {
                                             "".into()
                                         };
-                                        let body = read_raw(&mut iter);
+                                        let (raw, raw_range) = read_raw_with_range(&mut iter);
+                                        // only verbatim bodies can be located
+                                        let body_end = raw_range
+                                            .filter(|range| {
+                                                s.get(range.clone()) == Some(raw.as_str())
+                                            })
+                                            .map(|range| body.start + range.end);
                                         let (front_matter, body) =
-                                            split_and_parse_front_matter(body);
+                                            split_and_parse_front_matter(raw);
+                                        let body_location = body_end.map(|end| {
+                                            let offset = end - body.as_str().len();
+                                            let (line, column) = line_index.locate(offset);
+                                            Location {
+                                                offset,
+                                                len: body.as_str().len(),
+                                                line,
+                                                column,
+                                            }
+                                        });
                                         return Some((
                                             AnnotatedEvent::new(
                                                 DirectiveEvent {
@@ -333,6 +848,7 @@ fn preliminary_parse_with_trailers<'data>(
                                                     },
                                                     front_matter,
                                                     body,
+                                                    body_location,
                                                 },
                                                 location,
                                             ),
@@ -342,6 +858,20 @@ fn preliminary_parse_with_trailers<'data>(
                                 }
                                 let code = read_raw(&mut iter);
                                 let (language, args) = split_code_block_args(lang);
+                                if options.enable_math
+                                    && language.as_ref().map(|x| x.as_str()) == Some("math")
+                                {
+                                    return Some((
+                                        AnnotatedEvent::new(
+                                            MathBlockEvent {
+                                                args,
+                                                math: trim_str(code),
+                                            },
+                                            location,
+                                        ),
+                                        None,
+                                    ));
+                                }
                                 return Some((
                                     AnnotatedEvent::new(
                                         CodeBlockEvent {
@@ -396,7 +926,9 @@ fn preliminary_parse_with_trailers<'data>(
                             Tag::Table
                         }
                         cm::Tag::TableHead => {
-                            let state = table_state.as_mut().expect("not in table");
+                            // table parts outside of a table are recovered as
+                            // parts of a table without alignments
+                            let state = table_state.get_or_insert_with(TableState::default);
                             state.cell_index = 0;
                             state.cell_is_head = true;
                             // do not emit location information for table headers.  We consider
@@ -406,13 +938,13 @@ fn preliminary_parse_with_trailers<'data>(
                             Tag::TableHeader
                         }
                         cm::Tag::TableRow => {
-                            let state = table_state.as_mut().expect("not in table");
+                            let state = table_state.get_or_insert_with(TableState::default);
                             state.cell_index = 0;
                             state.cell_is_head = false;
                             Tag::TableRow
                         }
                         cm::Tag::TableCell => {
-                            let state = table_state.as_mut().expect("not in table");
+                            let state = table_state.get_or_insert_with(TableState::default);
                             attrs.alignment = state
                                 .alignments
                                 .get(state.cell_index)
@@ -426,7 +958,7 @@ fn preliminary_parse_with_trailers<'data>(
                             }
                         }
                         cm::Tag::Emphasis => {
-                            if &s[range.start..range.start + 1] == "_" {
+                            if s[range.start..].starts_with('_') {
                                 Tag::EmphasisAlt
                             } else {
                                 Tag::Emphasis
@@ -460,6 +992,8 @@ fn preliminary_parse_with_trailers<'data>(
                                         } else {
                                             Some(Str::from_cm_str(title))
                                         },
+                                        width: None,
+                                        height: None,
                                     },
                                     location,
                                 ),
@@ -472,13 +1006,29 @@ fn preliminary_parse_with_trailers<'data>(
                 }
                 cm::Event::End(_) => {
                     trailer = pending_trailer.take();
-                    EndTagEvent {
-                        tag: tag_stack.pop().unwrap(),
+                    match tag_stack.pop() {
+                        Some(tag) => EndTagEvent { tag }.into(),
+                        // stray end tags are dropped
+                        None => continue,
                     }
-                    .into()
                 }
                 cm::Event::Text(text) => {
                     let mut text = Str::from_cm_str(text);
+                    // sub-spans can only be given if the text is verbatim
+                    let verbatim = s.get(range.clone()) == Some(text.as_str());
+                    let span = |start: usize, end: usize| {
+                        if !verbatim {
+                            return None;
+                        }
+                        let offset = body.start + range.start + start;
+                        let (line, column) = line_index.locate(offset);
+                        Some(Location {
+                            offset,
+                            len: end - start,
+                            line,
+                            column,
+                        })
+                    };
 
                     // handle roles
                     if options.enable_roles {
@@ -492,8 +1042,11 @@ fn preliminary_parse_with_trailers<'data>(
                                 if let Some(ref mut location) = location {
                                     location.len -= column_adjustment;
                                 }
-                                pending_role =
-                                    Some((text.slice(g1.start(), g1.end()), column_adjustment));
+                                pending_role = Some((
+                                    text.slice(g1.start(), g1.end()),
+                                    column_adjustment,
+                                    span(g0.start(), g0.end()),
+                                ));
                                 text = text.slice(0, g0.start());
                             }
                         }
@@ -511,8 +1064,11 @@ fn preliminary_parse_with_trailers<'data>(
                                 if let Some(ref mut location) = location {
                                     location.len -= column_adjustment;
                                 }
-                                pending_trailer =
-                                    Some(Trailer::Id(text.slice(g1.start(), g1.end())));
+                                // the span covers the braces but not the whitespace
+                                pending_trailer = Some(Trailer::Id(
+                                    text.slice(g1.start(), g1.end()),
+                                    span(g1.start() - 2, g1.end() + 1),
+                                ));
                                 text = text.slice(0, g0.start());
                             }
                         }
@@ -523,17 +1079,26 @@ fn preliminary_parse_with_trailers<'data>(
                 cm::Event::Code(value) => {
                     // if there is a pending role then we're not working with a
                     // code block, but an interpreted text one.
-                    if let Some((role, column_adjustment)) = pending_role.take() {
+                    if let Some((role, column_adjustment, role_span)) = pending_role.take() {
                         if let Some(ref mut location) = location {
-                            location.column -= column_adjustment;
                             location.offset -= column_adjustment;
+                            location.column = line_index.locate(location.offset).1;
                             location.len += column_adjustment;
                         }
-                        InterpretedTextEvent {
-                            text: Str::from_cm_str(value),
-                            role,
+                        if options.enable_math && role.as_str() == "math" {
+                            InlineMathEvent {
+                                math: Str::from_cm_str(value),
+                                display: false,
+                            }
+                            .into()
+                        } else {
+                            InterpretedTextEvent {
+                                text: Str::from_cm_str(value),
+                                role,
+                                role_span,
+                            }
+                            .into()
                         }
-                        .into()
                     } else {
                         InlineCodeEvent {
                             code: Str::from_cm_str(value),
@@ -555,9 +1120,9 @@ fn preliminary_parse_with_trailers<'data>(
                 cm::Event::TaskListMarker(checked) => CheckboxEvent { checked }.into(),
             };
 
-            Some((AnnotatedEvent::new(event, location), trailer))
+            return Some((AnnotatedEvent::new(event, location), trailer));
         } else {
-            None
+            return None;
         }
     })
 }
@@ -592,8 +1157,9 @@ where
         // attach an end tag trailer to the start tag if needed.
         if depth == 0 {
             if let Event::StartTag(StartTagEvent { ref mut attrs, .. }) = buffer[0].event {
-                if let Some(Trailer::Id(new_id)) = trailer {
+                if let Some(Trailer::Id(new_id, id_span)) = trailer {
                     attrs.id = Some(new_id);
+                    attrs.id_span = id_span;
                 }
             }
             break;
@@ -603,30 +1169,607 @@ where
     buffer
 }
 
+/// Buffers an entire list to record whether it and its sublists are tight.
+fn buffer_list<'data, I>(event: AnnotatedEvent<'data>, iter: &mut I) -> Vec<AnnotatedEvent<'data>>
+where
+    I: Iterator<Item = AnnotatedEvent<'data>>,
+{
+    let mut buffer = vec![event];
+    let mut tag_stack = vec![];
+    // index of the start event and tightness of all currently open lists
+    let mut lists = vec![(0, true)];
+
+    for annotated_event in iter {
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => {
+                match tag {
+                    Tag::OrderedList | Tag::UnorderedList => lists.push((buffer.len(), true)),
+                    Tag::Paragraph if tag_stack.last() == Some(&Tag::ListItem) => {
+                        if let Some(list) = lists.last_mut() {
+                            list.1 = false;
+                        }
+                    }
+                    _ => {}
+                }
+                tag_stack.push(tag);
+            }
+            Event::EndTag(EndTagEvent { tag }) => {
+                if tag_stack.pop().is_none() {
+                    // the end of the outermost list
+                    buffer.push(annotated_event);
+                    break;
+                }
+                if let Tag::OrderedList | Tag::UnorderedList = tag {
+                    if let Some((idx, tight)) = lists.pop() {
+                        if let Event::StartTag(StartTagEvent { ref mut attrs, .. }) =
+                            buffer[idx].event
+                        {
+                            attrs.tight = Some(tight);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        buffer.push(annotated_event);
+    }
+
+    if let Some((idx, tight)) = lists.pop() {
+        if let Event::StartTag(StartTagEvent { ref mut attrs, .. }) = buffer[idx].event {
+            attrs.tight = Some(tight);
+        }
+    }
+
+    buffer
+}
+
+/// Returns the length of the definition marker an event starts with.
+fn definition_marker_len(annotated_event: &AnnotatedEvent<'_>) -> Option<usize> {
+    match annotated_event.event {
+        Event::Text(TextEvent { ref text }) => DEFINITION_MARKER_RE
+            .find(text.as_str())
+            .filter(|m| m.end() < text.as_str().len())
+            .map(|m| m.end()),
+        _ => None,
+    }
+}
+
+/// Returns for every line of a paragraph if it starts a definition.
+fn definition_lines(paragraph: &[AnnotatedEvent<'_>]) -> Vec<bool> {
+    let mut rv = vec![];
+    let mut line_start = true;
+    let mut depth = 0;
+    let inner = paragraph.get(1..paragraph.len().saturating_sub(1));
+    for annotated_event in inner.unwrap_or_default() {
+        if line_start {
+            rv.push(definition_marker_len(annotated_event).is_some());
+            line_start = false;
+        }
+        match annotated_event.event {
+            Event::StartTag(..) => depth += 1,
+            Event::EndTag(..) => depth -= 1,
+            Event::SoftBreak if depth == 0 => line_start = true,
+            _ => {}
+        }
+    }
+    rv
+}
+
+/// Splits the contents of a paragraph into lines.
+fn split_lines(paragraph: Vec<AnnotatedEvent<'_>>) -> Vec<Vec<AnnotatedEvent<'_>>> {
+    let len = paragraph.len();
+    let mut lines = vec![vec![]];
+    let mut depth = 0;
+    for annotated_event in paragraph.into_iter().skip(1).take(len.saturating_sub(2)) {
+        match annotated_event.event {
+            Event::StartTag(..) => depth += 1,
+            Event::EndTag(..) => depth -= 1,
+            Event::SoftBreak if depth == 0 => {
+                lines.push(vec![]);
+                continue;
+            }
+            _ => {}
+        }
+        match lines.last_mut() {
+            Some(line) => line.push(annotated_event),
+            None => lines.push(vec![annotated_event]),
+        }
+    }
+    lines
+}
+
+fn is_plain_paragraph_start(annotated_event: &AnnotatedEvent<'_>) -> bool {
+    matches!(annotated_event.event, Event::StartTag(StartTagEvent {
+        tag: Tag::Paragraph,
+        ref attrs,
+    }) if attrs.is_empty())
+}
+
+/// Turns paragraphs with definition markers into definition lists.
+///
+/// The parser does not know about definition lists so they come in as
+/// paragraphs.  The lines of a paragraph before the first definition are
+/// terms, alternatively a paragraph starting with a definition defines the
+/// terms in the paragraph before it.  Adjacent paragraphs of that shape are
+/// merged into one list.
+struct DefinitionLists<'data, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    // events read ahead that still have to be looked at
+    pushback: VecDeque<AnnotatedEvent<'data>>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    in_list: bool,
+}
+
+impl<'data, I: Iterator<Item = AnnotatedEvent<'data>>> DefinitionLists<'data, I> {
+    fn new(source: I) -> Self {
+        DefinitionLists {
+            source,
+            pushback: VecDeque::new(),
+            buffer: VecDeque::new(),
+            in_list: false,
+        }
+    }
+
+    fn next_source(&mut self) -> Option<AnnotatedEvent<'data>> {
+        self.pushback.pop_front().or_else(|| self.source.next())
+    }
+
+    fn read_paragraph(&mut self, start: AnnotatedEvent<'data>) -> Vec<AnnotatedEvent<'data>> {
+        let mut paragraph = vec![start];
+        let mut depth = 1;
+        while let Some(annotated_event) = self.next_source() {
+            match annotated_event.event {
+                Event::StartTag(..) => depth += 1,
+                Event::EndTag(..) => depth -= 1,
+                _ => {}
+            }
+            paragraph.push(annotated_event);
+            if depth == 0 {
+                break;
+            }
+        }
+        paragraph
+    }
+
+    fn open_list(&mut self) {
+        if !self.in_list {
+            self.in_list = true;
+            self.buffer
+                .push_back(Tag::DefinitionList.start_tag(Attrs::default()).into());
+        }
+    }
+
+    fn close_list(&mut self) {
+        if self.in_list {
+            self.in_list = false;
+            self.buffer.push_back(Tag::DefinitionList.end_tag().into());
+        }
+    }
+
+    fn push_terms(&mut self, lines: Vec<Vec<AnnotatedEvent<'data>>>) {
+        for line in lines {
+            self.buffer
+                .push_back(Tag::DefinitionTerm.start_tag(Attrs::default()).into());
+            self.buffer.extend(line);
+            self.buffer.push_back(Tag::DefinitionTerm.end_tag().into());
+        }
+    }
+
+    fn push_definitions(&mut self, lines: Vec<Vec<AnnotatedEvent<'data>>>) {
+        let mut in_details = false;
+        for mut line in lines {
+            match line.first().and_then(definition_marker_len) {
+                Some(marker_len) => {
+                    if in_details {
+                        self.buffer
+                            .push_back(Tag::DefinitionDetails.end_tag().into());
+                    }
+                    in_details = true;
+                    self.buffer
+                        .push_back(Tag::DefinitionDetails.start_tag(Attrs::default()).into());
+                    let first = &mut line[0];
+                    if let Event::Text(TextEvent { ref mut text }) = first.event {
+                        if let Some(ref mut location) = first.location {
+                            if location.len == text.as_str().len() {
+                                location.offset += marker_len;
+                                location.len -= marker_len;
+                                location.column += marker_len;
+                            }
+                        }
+                        *text = text.as_str()[marker_len..].to_string().into();
+                    }
+                }
+                // lazy continuation of the definition
+                None => self.buffer.push_back(Event::SoftBreak.into()),
+            }
+            self.buffer.extend(line);
+        }
+        if in_details {
+            self.buffer
+                .push_back(Tag::DefinitionDetails.end_tag().into());
+        }
+    }
+
+    fn push_paragraph(&mut self, paragraph: Vec<AnnotatedEvent<'data>>) {
+        let markers = definition_lines(&paragraph);
+        match markers.iter().position(|&x| x) {
+            // more definitions for the last term
+            Some(0) if self.in_list => self.push_definitions(split_lines(paragraph)),
+            Some(idx) if idx > 0 => {
+                self.open_list();
+                let mut terms = split_lines(paragraph);
+                let definitions = terms.split_off(idx);
+                self.push_terms(terms);
+                self.push_definitions(definitions);
+            }
+            Some(_) => {
+                self.close_list();
+                self.buffer.extend(paragraph);
+            }
+            // the definitions might follow after a blank line
+            None => match self.next_source() {
+                Some(next) if is_plain_paragraph_start(&next) => {
+                    let next_paragraph = self.read_paragraph(next);
+                    if definition_lines(&next_paragraph).first() == Some(&true) {
+                        self.open_list();
+                        self.push_terms(split_lines(paragraph));
+                        self.push_definitions(split_lines(next_paragraph));
+                    } else {
+                        self.close_list();
+                        self.buffer.extend(paragraph);
+                        for annotated_event in next_paragraph.into_iter().rev() {
+                            self.pushback.push_front(annotated_event);
+                        }
+                    }
+                }
+                next => {
+                    self.close_list();
+                    self.buffer.extend(paragraph);
+                    if let Some(next) = next {
+                        self.pushback.push_front(next);
+                    }
+                }
+            },
+        }
+    }
+}
+
+impl<'data, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator for DefinitionLists<'data, I> {
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+            match self.next_source() {
+                Some(annotated_event) if is_plain_paragraph_start(&annotated_event) => {
+                    let paragraph = self.read_paragraph(annotated_event);
+                    self.push_paragraph(paragraph);
+                }
+                Some(annotated_event) => {
+                    self.close_list();
+                    self.buffer.push_back(annotated_event);
+                }
+                None if self.in_list => self.close_list(),
+                None => return None,
+            }
+        }
+    }
+}
+
+/// Checks if the character at an offset of the source is escaped.
+fn is_escaped(source: &str, offset: usize) -> bool {
+    source[..offset]
+        .bytes()
+        .rev()
+        .take_while(|&b| b == b'\\')
+        .count()
+        % 2
+        == 1
+}
+
+fn is_inline_container_start(annotated_event: &AnnotatedEvent<'_>) -> bool {
+    matches!(
+        annotated_event.event,
+        Event::StartTag(StartTagEvent {
+            tag: Tag::Paragraph
+                | Tag::Heading1
+                | Tag::Heading2
+                | Tag::Heading3
+                | Tag::Heading4
+                | Tag::Heading5
+                | Tag::Heading6
+                | Tag::TableHead
+                | Tag::TableCell,
+            ..
+        })
+    )
+}
+
+/// The location of math within the events of a block.
+struct MathMatch {
+    open_pos: usize,
+    close_index: usize,
+    close_pos: usize,
+    delimiter_len: usize,
+}
+
+/// Turns `$...$` and `$$...$$` in inline content into math events.
+///
+/// Math is located after parsing so markdown syntax within a formula
+/// (`$a*b*c$`) has already been interpreted by then.  The formula is
+/// therefore taken from the source between the delimiters and the events
+/// in between are dropped.  A paragraph holding nothing but display math
+/// becomes a math block.
+struct MathSpans<'data, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    document: &'data str,
+    line_index: LineIndex<'data>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+}
+
+impl<'data, I: Iterator<Item = AnnotatedEvent<'data>>> MathSpans<'data, I> {
+    fn new(source: I, document: &'data str, column_mode: ColumnMode) -> Self {
+        MathSpans {
+            source,
+            document,
+            line_index: LineIndex::new(document, column_mode),
+            buffer: VecDeque::new(),
+        }
+    }
+
+    fn location(&self, offset: usize, len: usize) -> Option<Location> {
+        let (line, column) = self.line_index.locate(offset);
+        Some(Location {
+            offset,
+            len,
+            line,
+            column,
+        })
+    }
+
+    /// Returns the text and offset of text events that are verbatim source.
+    fn verbatim_text<'a>(
+        &self,
+        annotated_event: &'a AnnotatedEvent<'data>,
+    ) -> Option<(&'a str, usize)> {
+        match (&annotated_event.event, &annotated_event.location) {
+            (Event::Text(TextEvent { text }), Some(location))
+                if self
+                    .document
+                    .get(location.offset..location.offset + location.len)
+                    == Some(text.as_str()) =>
+            {
+                Some((text.as_str(), location.offset))
+            }
+            _ => None,
+        }
+    }
+
+    /// Finds a closing delimiter at or after `pos` in the event at `index`.
+    fn find_closer(
+        &self,
+        events: &[AnnotatedEvent<'data>],
+        index: usize,
+        pos: usize,
+        delimiter_len: usize,
+    ) -> Option<(usize, usize)> {
+        let mut depth = 0;
+        for (idx, annotated_event) in events.iter().enumerate().skip(index) {
+            match annotated_event.event {
+                Event::StartTag(..) => depth += 1,
+                Event::EndTag(..) if depth == 0 => return None,
+                Event::EndTag(..) => depth -= 1,
+                _ => {}
+            }
+            let (text, offset) = match self.verbatim_text(annotated_event) {
+                Some(rv) => rv,
+                None => continue,
+            };
+            let start = if idx == index { pos } else { 0 };
+            for (candidate, _) in text[start..].match_indices('$') {
+                let candidate = start + candidate;
+                let abs = offset + candidate;
+                if (idx == index && candidate == pos) || is_escaped(self.document, abs) {
+                    continue;
+                }
+                // math must not overlap with other inline markup
+                if depth > 0 {
+                    return None;
+                }
+                if delimiter_len == 2 {
+                    if text[candidate..].starts_with("$$") {
+                        return Some((idx, candidate));
+                    }
+                } else if !self.document[..abs].ends_with(char::is_whitespace)
+                    && !self.document[abs + 1..].starts_with(|c: char| c.is_ascii_digit())
+                {
+                    return Some((idx, candidate));
+                }
+            }
+        }
+        None
+    }
+
+    /// Finds the first math in the text event at `index`.
+    fn find_math(&self, events: &[AnnotatedEvent<'data>], index: usize) -> Option<MathMatch> {
+        let (text, offset) = self.verbatim_text(&events[index])?;
+        for (open_pos, _) in text.match_indices('$') {
+            if is_escaped(self.document, offset + open_pos)
+                || (open_pos > 0 && text[..open_pos].ends_with('$'))
+            {
+                continue;
+            }
+            let delimiter_len = if text[open_pos..].starts_with("$$") {
+                2
+            } else if self.document[offset + open_pos + 1..]
+                .starts_with(|c: char| !c.is_whitespace())
+            {
+                1
+            } else {
+                continue;
+            };
+            if let Some((close_index, close_pos)) =
+                self.find_closer(events, index, open_pos + delimiter_len, delimiter_len)
+            {
+                return Some(MathMatch {
+                    open_pos,
+                    close_index,
+                    close_pos,
+                    delimiter_len,
+                });
+            }
+        }
+        None
+    }
+
+    fn process(&self, mut events: Vec<AnnotatedEvent<'data>>) -> Vec<AnnotatedEvent<'data>> {
+        let mut rv = Vec::with_capacity(events.len());
+        let mut index = 0;
+        while index < events.len() {
+            let m = match self.find_math(&events, index) {
+                Some(m) => m,
+                None => {
+                    rv.push(events[index].clone());
+                    index += 1;
+                    continue;
+                }
+            };
+            // math spans can only be cut out of text with a known source
+            let (open_offset, close_offset) = match (
+                events[index].location.as_ref(),
+                events[m.close_index].location.as_ref(),
+            ) {
+                (Some(open), Some(close)) => (open.offset, close.offset),
+                _ => {
+                    rv.push(events[index].clone());
+                    index += 1;
+                    continue;
+                }
+            };
+            let start = open_offset + m.open_pos;
+            let end = close_offset + m.close_pos + m.delimiter_len;
+
+            if let Event::Text(TextEvent { ref text }) = events[index].event {
+                if m.open_pos > 0 {
+                    rv.push(AnnotatedEvent::new(
+                        TextEvent {
+                            text: text.slice(0, m.open_pos),
+                        },
+                        self.location(open_offset, m.open_pos),
+                    ));
+                }
+            }
+
+            let display = m.delimiter_len == 2;
+            let math = &self.document[start + m.delimiter_len..end - m.delimiter_len];
+            rv.push(AnnotatedEvent::new(
+                InlineMathEvent {
+                    math: if display { math.trim() } else { math }.into(),
+                    display,
+                },
+                self.location(start, end - start),
+            ));
+
+            let rest = m.close_pos + m.delimiter_len;
+            index = m.close_index;
+            if let Event::Text(TextEvent { ref text }) = events[index].event {
+                if rest < text.as_str().len() {
+                    events[index] = AnnotatedEvent::new(
+                        TextEvent {
+                            text: text.slice(rest, text.as_str().len()),
+                        },
+                        self.location(close_offset + rest, text.as_str().len() - rest),
+                    );
+                    continue;
+                }
+            }
+            index += 1;
+        }
+
+        // a paragraph of display math is a math block
+        if rv.len() == 3 && is_plain_paragraph_start(&rv[0]) {
+            if let Event::InlineMath(InlineMathEvent {
+                ref math,
+                display: true,
+            }) = rv[1].event
+            {
+                return vec![AnnotatedEvent::new(
+                    MathBlockEvent {
+                        args: None,
+                        math: math.clone(),
+                    },
+                    rv[0].location.clone(),
+                )];
+            }
+        }
+        rv
+    }
+}
+
+impl<'data, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator for MathSpans<'data, I> {
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+        let annotated_event = self.source.next()?;
+        if !is_inline_container_start(&annotated_event) {
+            return Some(annotated_event);
+        }
+        let mut block = vec![annotated_event];
+        let mut depth = 1;
+        for annotated_event in self.source.by_ref() {
+            match annotated_event.event {
+                Event::StartTag(..) => depth += 1,
+                Event::EndTag(..) => depth -= 1,
+                _ => {}
+            }
+            block.push(annotated_event);
+            if depth == 0 {
+                break;
+            }
+        }
+        self.buffer.extend(self.process(block));
+        self.buffer.pop_front()
+    }
+}
+
 fn parse_internal(s: &str, options: ParserOptions) -> impl Iterator<Item = AnnotatedEvent> {
     let mut front_matter = None;
-    let mut s = s;
+    let mut body = 0..s.len();
     let mut front_matter_location = None;
 
     if options.enable_frontmatter {
-        if let Some(m) = FRONTMATTER_RE.captures(s) {
-            if let Ok(parsed_front_matter) = serde_yaml::from_str(&m[1]) {
-                let g0 = m.get(0).unwrap();
-                front_matter = Some(parsed_front_matter);
-                front_matter_location = Some(Location {
-                    offset: 0,
-                    len: g0.end(),
-                    line: 1,
-                    column: 0,
-                });
-                s = &s[g0.end()..];
+        if let Some((range, yaml)) = find_front_matter(s, &options) {
+            if let Ok(parsed_front_matter) = serde_yaml::from_str::<Value>(yaml) {
+                if range.start == 0 || parsed_front_matter.is_object() {
+                    front_matter = Some(parsed_front_matter);
+                    front_matter_location = Some(Location {
+                        offset: range.start,
+                        len: range.end - range.start,
+                        line: s[..range.start].matches('\n').count() + 1,
+                        column: 0,
+                    });
+                    body = if range.start == 0 {
+                        range.end..s.len()
+                    } else {
+                        0..range.start
+                    };
+                }
             }
         }
     }
 
-    let mut iter = preliminary_parse_with_trailers(s, options);
+    let enable_definition_lists = options.enable_definition_lists;
+    let enable_math = options.enable_math;
+    let enable_source_positions = options.enable_source_positions;
+    let column_mode = options.column_mode;
+    let mut iter = preliminary_parse_with_trailers(s, body, options);
 
-    iter::once(AnnotatedEvent::new(
+    let iter = iter::once(AnnotatedEvent::new(
         DocumentStartEvent { front_matter },
         front_matter_location,
     ))
@@ -646,7 +1789,7 @@ fn parse_internal(s: &str, options: ParserOptions) -> impl Iterator<Item = Annot
             }
         })
         .flatten()
-        .flat_map(|annotated_event| match annotated_event.event {
+        .flat_map(move |annotated_event| match annotated_event.event {
             // after a table header we inject an implied table body.
             Event::EndTag(EndTagEvent {
                 tag: Tag::TableHeader,
@@ -669,11 +1812,302 @@ fn parse_internal(s: &str, options: ParserOptions) -> impl Iterator<Item = Annot
                 )
                 .chain(iter::once(annotated_event)),
             ),
+            // directive front matter that fails to parse stays in the body
+            Event::Directive(DirectiveEvent {
+                front_matter: None,
+                ref body,
+                ref body_location,
+                ..
+            }) => match front_matter_error(body.as_str()) {
+                Some((offset, err)) => {
+                    let location = match body_location {
+                        Some(body_location) => {
+                            let offset = body_location.offset + offset;
+                            let (line, column) = LineIndex::new(s, column_mode).locate(offset);
+                            Some(Location {
+                                offset,
+                                len: 0,
+                                line,
+                                column,
+                            })
+                        }
+                        None => annotated_event.location.clone(),
+                    };
+                    let diagnostic = AnnotatedEvent::new(
+                        DiagnosticEvent {
+                            severity: Severity::Warning,
+                            message: "Invalid directive front matter".into(),
+                            description: Some(err.to_string().into()),
+                            code: Some(Code::InvalidDirectiveFrontMatter),
+                        },
+                        location,
+                    );
+                    Either::Left(iter::once(annotated_event).chain(iter::once(diagnostic)))
+                }
+                None => Either::Right(iter::once(annotated_event)),
+            },
             _ => Either::Right(iter::once(annotated_event)),
         }),
+    );
+    let iter = if enable_math {
+        Either::Left(MathSpans::new(iter, s, column_mode))
+    } else {
+        Either::Right(iter)
+    };
+    let mut iter = if enable_definition_lists {
+        Either::Left(DefinitionLists::new(iter))
+    } else {
+        Either::Right(iter)
+    };
+
+    // lists are buffered so that their start tags can tell if they are tight.
+    let iter = iter::from_fn(move || {
+        let annotated_event = iter.next()?;
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent {
+                tag: Tag::OrderedList,
+                ..
+            })
+            | Event::StartTag(StartTagEvent {
+                tag: Tag::UnorderedList,
+                ..
+            }) => Some(Either::Left(
+                buffer_list(annotated_event, &mut iter).into_iter(),
+            )),
+            _ => Some(Either::Right(iter::once(annotated_event))),
+        }
+    })
+    .flatten();
+
+    if !enable_source_positions {
+        return Either::Left(iter);
+    }
+    let line_index = LineIndex::new(s, column_mode);
+    Either::Right(iter.map(move |mut annotated_event| {
+        if let (Event::StartTag(StartTagEvent { tag, ref mut attrs }), Some(location)) =
+            (&mut annotated_event.event, &annotated_event.location)
+        {
+            if is_block_tag(*tag) {
+                let end = s[..location.offset + location.len]
+                    .trim_end_matches(['\n', '\r'])
+                    .len()
+                    .max(location.offset);
+                let (end_line, end_column) = line_index.locate(end);
+                attrs.custom.get_or_insert_with(Default::default).insert(
+                    "data-sourcepos".into(),
+                    format!(
+                        "{}:{}-{}:{}",
+                        location.line,
+                        location.column + 1,
+                        end_line,
+                        end_column
+                    )
+                    .into(),
+                );
+            }
+        }
+        annotated_event
+    }))
+}
+
+/// Returns `true` for tags the parser emits for blocks.
+fn is_block_tag(tag: Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Paragraph
+            | Tag::Heading1
+            | Tag::Heading2
+            | Tag::Heading3
+            | Tag::Heading4
+            | Tag::Heading5
+            | Tag::Heading6
+            | Tag::BlockQuote
+            | Tag::OrderedList
+            | Tag::UnorderedList
+            | Tag::ListItem
+            | Tag::FootnoteDefinition
+            | Tag::DefinitionList
+            | Tag::DefinitionTerm
+            | Tag::DefinitionDetails
+            | Tag::Table
+            | Tag::TableHeader
+            | Tag::TableBody
+            | Tag::TableRow
+            | Tag::TableHead
+            | Tag::TableCell
     )
 }
 
+/// Parses a document into a well formed stream.
+fn parse_hardened(s: &str, options: ParserOptions) -> std::vec::IntoIter<AnnotatedEvent<'_>> {
+    balance_tags(parse_internal(s, options).collect()).into_iter()
+}
+
+/// Makes a stream well formed.
+///
+/// End tags close the innermost open tag of their kind, closing all tags
+/// opened within it.  End tags without a matching start tag are dropped
+/// and tags left open are closed at the end.
+fn balance_tags(events: Vec<AnnotatedEvent<'_>>) -> Vec<AnnotatedEvent<'_>> {
+    let mut rv = Vec::with_capacity(events.len());
+    let mut open_tags = vec![];
+    for annotated_event in events {
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => open_tags.push(tag),
+            Event::EndTag(EndTagEvent { tag }) => match open_tags.iter().rposition(|x| *x == tag) {
+                Some(idx) => {
+                    for tag in open_tags.drain(idx + 1..).rev() {
+                        rv.push(tag.end_tag().into());
+                    }
+                    open_tags.pop();
+                }
+                None => continue,
+            },
+            _ => {}
+        }
+        rv.push(annotated_event);
+    }
+    rv.extend(open_tags.into_iter().rev().map(|tag| tag.end_tag().into()));
+    rv
+}
+
+/// Attaches source trivia to the events for the lossless mode.
+///
+/// The top level blocks partition the source: every block reaches from its
+/// first byte up to the first byte of the next block, which is split into
+/// the `raw` block and the `trailing` source after it.
+fn record_trivia<'data>(
+    s: &'data str,
+    mut events: Vec<AnnotatedEvent<'data>>,
+) -> Vec<AnnotatedEvent<'data>> {
+    for annotated_event in events.iter_mut() {
+        let rest = match annotated_event.location {
+            Some(ref location) => s.get(location.offset..).unwrap_or(""),
+            None => continue,
+        };
+        let (marker, fence) = match annotated_event.event {
+            Event::StartTag(StartTagEvent {
+                tag: Tag::ListItem, ..
+            }) => {
+                let marker = LIST_MARKER_RE.find(rest).map(|m| {
+                    // with five or more spaces the content is indented code
+                    if rest[m.end()..].starts_with(' ') {
+                        &rest[..m.as_str().trim_end().len() + 1]
+                    } else {
+                        m.as_str()
+                    }
+                });
+                (marker, None)
+            }
+            Event::CodeBlock(..) | Event::Directive(..) | Event::MathBlock(..) => (
+                None,
+                FENCE_RE.captures(rest).map(|c| c.get(1).unwrap().as_str()),
+            ),
+            _ => continue,
+        };
+        if marker.is_some() || fence.is_some() {
+            annotated_event.trivia = Some(Box::new(Trivia {
+                marker: marker.map(Str::from),
+                fence: fence.map(Str::from),
+                ..Trivia::default()
+            }));
+        }
+    }
+
+    // front matter at the end of the document is not part of any block
+    let (front_matter_end, end) = match events.first() {
+        Some(AnnotatedEvent {
+            event: Event::DocumentStart(..),
+            location: Some(ref location),
+            ..
+        }) if location.offset > 0 => (0, location.offset),
+        Some(AnnotatedEvent {
+            event: Event::DocumentStart(..),
+            location: Some(ref location),
+            ..
+        }) => (location.len, s.len()),
+        _ => (0, s.len()),
+    };
+
+    // the events and source ranges of top level blocks
+    let mut blocks: Vec<(Range<usize>, Range<usize>)> = vec![];
+    let mut current: Option<(usize, Option<Range<usize>>)> = None;
+    let mut depth = 0usize;
+    for (idx, annotated_event) in events.iter().enumerate() {
+        if depth == 0 {
+            if let Event::DocumentStart(..)
+            | Event::MetaData(..)
+            | Event::Error(..)
+            | Event::Diagnostic(..) = annotated_event.event
+            {
+                continue;
+            }
+            current = Some((idx, None));
+        }
+        if let (Some((_, ref mut range)), Some(ref location)) =
+            (&mut current, &annotated_event.location)
+        {
+            let (start, end) = (location.offset, location.offset + location.len);
+            *range = Some(match range.take() {
+                Some(range) => range.start.min(start)..range.end.max(end),
+                None => start..end,
+            });
+        }
+        match annotated_event.event {
+            Event::StartTag(..) => depth += 1,
+            Event::EndTag(..) => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        if depth == 0 {
+            if let Some((first, Some(range))) = current.take() {
+                blocks.push((first..idx + 1, range));
+            }
+        }
+    }
+
+    let first_block = blocks
+        .first()
+        .map_or(end, |x| x.1.start)
+        .clamp(front_matter_end, end);
+    if let Some(AnnotatedEvent {
+        event: Event::DocumentStart(..),
+        ..
+    }) = events.first()
+    {
+        let trivia = Trivia {
+            raw: s.get(..front_matter_end).map(Str::from),
+            trailing: s.get(front_matter_end..first_block).map(Str::from),
+            fingerprint: Some(fingerprint(&events[..1])),
+            ..Trivia::default()
+        };
+        events[0].trivia = Some(Box::new(trivia));
+    }
+
+    let mut pos = first_block;
+    for (idx, (indexes, range)) in blocks.iter().enumerate() {
+        let start = range.start.clamp(pos, end);
+        let next = blocks
+            .get(idx + 1)
+            .map_or(end, |x| x.1.start)
+            .clamp(start, end);
+        let block_end = range.end.clamp(start, next);
+        let (raw, trailing) = match (s.get(start..block_end), s.get(block_end..next)) {
+            (Some(raw), Some(trailing)) => (raw, trailing),
+            _ => continue,
+        };
+        pos = next;
+        let block_fingerprint = fingerprint(&events[indexes.clone()]);
+        let trivia = events[indexes.start]
+            .trivia
+            .get_or_insert_with(Default::default);
+        trivia.raw = Some(raw.into());
+        trivia.trailing = Some(trailing.into());
+        trivia.fingerprint = Some(block_fingerprint);
+    }
+
+    events
+}
+
 /// Parses structured cmark into an event stream.
 pub fn parse<'data, 'options>(
     s: &'data str,
@@ -681,3 +2115,249 @@ pub fn parse<'data, 'options>(
 ) -> impl Iterator<Item = AnnotatedEvent<'data>> {
     Parser::new(options).parse(s)
 }
+
+#[test]
+fn test_front_matter_options() {
+    let front_matter = |source: &str, options: &ParserOptions| {
+        let mut iter = parse(source, options);
+        match iter.next().map(|x| x.event) {
+            Some(Event::DocumentStart(DocumentStartEvent { front_matter })) => front_matter,
+            _ => None,
+        }
+    };
+
+    let custom = ParserOptions {
+        frontmatter_delimiter: "~~~meta".into(),
+        frontmatter_end_delimiter: Some("~~~".into()),
+        frontmatter_position: FrontMatterPosition::StartOrEnd,
+        frontmatter_at_first_line: false,
+        ..ParserOptions::default()
+    };
+    insta::assert_yaml_snapshot!(vec![
+        front_matter("---\ntitle: Default\n---\n# Hello", &Default::default()),
+        front_matter("\n---\ntitle: Not First\n---\n", &Default::default()),
+        front_matter("# Hello\n\n---\ntitle: End\n---\n", &Default::default()),
+        front_matter("\n\n~~~meta\ntitle: Custom\n~~~\n# Hello", &custom),
+        front_matter("# Hello\n\n~~~meta\ntitle: Trailing\n~~~\n\n", &custom),
+        front_matter("# Hello\n\n~~~meta\njust text\n~~~\n", &custom),
+    ]);
+}
+
+#[test]
+fn test_column_modes() {
+    let source = "# Größe\n\n\tcode\n\n- 漢字 `x` and {role}`y`\n";
+    let columns = |column_mode| {
+        let options = ParserOptions {
+            column_mode,
+            ..ParserOptions::default()
+        };
+        parse(source, &options)
+            .filter_map(|x| x.location)
+            .map(|x| (x.line, x.column))
+            .collect::<Vec<_>>()
+    };
+    insta::assert_debug_snapshot!(vec![
+        columns(ColumnMode::Bytes),
+        columns(ColumnMode::Utf16),
+        columns(ColumnMode::DisplayWidth { tab_width: 4 }),
+    ]);
+}
+
+#[test]
+fn test_smart_punctuation() {
+    let options = ParserOptions {
+        enable_smart_punctuation: true,
+        ..ParserOptions::default()
+    };
+    let source = "\"Quoted\" -- it's `\"code\"` and {role}`'x'`...";
+    let texts: Vec<_> = parse(source, &options)
+        .filter_map(|x| match x.event {
+            Event::Text(TextEvent { text }) => Some(text.as_str().to_string()),
+            Event::InlineCode(InlineCodeEvent { code }) => Some(code.as_str().to_string()),
+            Event::InterpretedText(InterpretedTextEvent { text, role, .. }) => {
+                Some(format!("{}:{}", role.as_str(), text.as_str()))
+            }
+            _ => None,
+        })
+        .collect();
+    insta::assert_debug_snapshot!(texts);
+}
+
+#[test]
+fn test_commonmark_options() {
+    let source =
+        "---\ntitle: x\n---\n# Hello {#hi}\n\n~~gone~~ {role}`x`\n\n```{note}\nbody\n```\n";
+    let events: Vec<_> = parse(source, &ParserOptions::commonmark())
+        .map(|x| x.event)
+        .collect();
+    insta::assert_debug_snapshot!(events);
+}
+
+#[test]
+fn test_source_positions() {
+    let options = ParserOptions {
+        enable_source_positions: true,
+        ..ParserOptions::default()
+    };
+    let source = "# Title\n\nSome *text*\nspanning lines.\n\n- one\n- two\n";
+    let positions: Vec<_> = parse(source, &options)
+        .filter_map(|x| match x.event {
+            Event::StartTag(StartTagEvent { tag, attrs }) => Some((
+                tag,
+                attrs
+                    .custom
+                    .and_then(|x| x.get("data-sourcepos").map(|x| x.as_str().to_string())),
+            )),
+            _ => None,
+        })
+        .collect();
+    insta::assert_debug_snapshot!(positions);
+}
+
+#[test]
+fn test_directive_body_location() {
+    let source =
+        "# Title\n\n```{note}\n---\nkind: x\n---\nA *body*.\n```\n\n> ```{note}\n> two\n> lines\n> ```\n";
+    let directives: Vec<_> = parse(source, &Default::default())
+        .filter_map(|x| match x.event {
+            Event::Directive(directive) => Some(directive),
+            _ => None,
+        })
+        .collect();
+
+    let body_location = directives[0].body_location.as_ref().unwrap();
+    assert_eq!(
+        &source[body_location.offset..body_location.offset + body_location.len],
+        directives[0].body.as_str()
+    );
+    assert_eq!((body_location.line, body_location.column), (7, 0));
+
+    let mapper = crate::event::LocationMapper::for_directive(&directives[0]).unwrap();
+    let lines: Vec<_> = parse(directives[0].body.as_str(), &Default::default())
+        .filter_map(|x| mapper.map_event(x).location)
+        .map(|x| (x.line, x.column))
+        .collect();
+    assert_eq!(
+        lines,
+        vec![(7, 0), (7, 0), (7, 2), (7, 3), (7, 2), (7, 8), (7, 0)]
+    );
+
+    // bodies not taken verbatim from the source cannot be located
+    assert!(directives[1].body_location.is_none());
+}
+
+#[test]
+fn test_sub_spans() {
+    let source =
+        "# Title {#custom}\n\nSee {ref}`intro` and *{kbd}`q`*.\n\n## \\*Escaped\\* {#other}\n";
+    let spans: Vec<_> = parse(source, &Default::default())
+        .filter_map(|x| match x.event {
+            Event::StartTag(StartTagEvent { attrs, .. }) if attrs.id.is_some() => {
+                Some(attrs.id_span)
+            }
+            Event::InterpretedText(InterpretedTextEvent { role_span, .. }) => Some(role_span),
+            _ => None,
+        })
+        .map(|span| span.map(|x| (&source[x.offset..x.offset + x.len], x.line, x.column)))
+        .collect();
+    assert_eq!(
+        spans,
+        vec![
+            Some(("{#custom}", 1, 8)),
+            Some(("{ref}", 3, 4)),
+            Some(("{kbd}", 3, 22)),
+            Some(("{#other}", 5, 15)),
+        ]
+    );
+}
+
+#[test]
+fn test_hardened() {
+    use crate::json::stream_to_json;
+
+    let stream = vec![
+        DocumentStartEvent { front_matter: None }.into(),
+        Tag::BlockQuote.start_tag(Attrs::default()).into(),
+        Tag::Paragraph.start_tag(Attrs::default()).into(),
+        TextEvent { text: "x".into() }.into(),
+        Tag::BlockQuote.end_tag().into(),
+        Tag::Emphasis.end_tag().into(),
+        Tag::ListItem.start_tag(Attrs::default()).into(),
+    ];
+    let tags: Vec<_> = balance_tags(stream)
+        .into_iter()
+        .filter_map(|x| match x.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => Some(format!("+{:?}", tag)),
+            Event::EndTag(EndTagEvent { tag }) => Some(format!("-{:?}", tag)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        tags,
+        vec![
+            "+BlockQuote",
+            "+Paragraph",
+            "-Paragraph",
+            "-BlockQuote",
+            "+ListItem",
+            "-ListItem"
+        ]
+    );
+
+    // well formed documents parse the same in hardened mode
+    let options = ParserOptions {
+        hardened: true,
+        ..ParserOptions::default()
+    };
+    for source in &[
+        "",
+        "# Title {#",
+        "| a |\n|---|\n| b | c | d |\n",
+        "````{note}\n```{tip}\n",
+        "> - [x] $a$ {role}`\n\n: *x",
+        "\u{0}\r\n***\t_\n[^1]\n\n[^1]: ![*a*](",
+    ] {
+        assert_eq!(
+            stream_to_json(parse(source, &options)),
+            stream_to_json(parse(source, &ParserOptions::default()))
+        );
+    }
+}
+
+#[test]
+fn test_reference_resolver() {
+    use crate::html::to_html;
+
+    let options = ParserOptions {
+        reference_resolver: Some(ReferenceResolver::new(|reference| match reference {
+            "Page Title" => Some(("/page/".into(), "The Page".into())),
+            "defined" => Some(("/resolved/".into(), "".into())),
+            _ => None,
+        })),
+        ..ParserOptions::default()
+    };
+    let source = "\
+See [Page Title], [Page Title][], [the page][Page Title], [defined] and [unknown].
+
+A ![Page Title] image and `[Page Title]` code.
+
+[defined]: /defined/
+";
+    insta::assert_snapshot!(to_html(parse(source, &options), &Default::default()));
+
+    // borrowed strings still point into the source
+    let events: Vec<_> = parse(source, &options).collect();
+    let plain: Vec<_> = parse(source, &ParserOptions::default()).collect();
+    let offsets = |events: &[AnnotatedEvent<'_>]| -> Vec<_> {
+        events
+            .iter()
+            .map(|x| x.location.as_ref().map(|x| (x.offset, x.len)))
+            .collect()
+    };
+    assert_eq!(offsets(&events[..3]), offsets(&plain[..3]));
+    let source_range = source.as_bytes().as_ptr_range();
+    assert!(events.iter().any(|x| match x.event {
+        Event::Text(TextEvent { ref text }) => source_range.contains(&text.as_str().as_ptr()),
+        _ => false,
+    }));
+}
@@ -0,0 +1,250 @@
+//! Renders parts of a document.
+//!
+//! Previews and fragments loaded on demand only need a part of a document
+//! but footnote numbers and links should be the same as in the full
+//! rendering.  [`anchor_range`] locates the events of a section or element
+//! by its anchor and [`extract`] takes a range of events out of a processed
+//! stream together with everything needed to render it on its own:
+//!
+//! * footnote definitions outside of the range that are referenced from
+//!   within the range are appended.
+//! * links to anchors outside of the range can be pointed to the full
+//!   document with `document_url`.
+//!
+//! [`render_html`] combines this with footnote numbers taken from the full
+//! document:
+//!
+//! ```
+//! use struckdown::parser::parse;
+//! use struckdown::partial::{anchor_range, render_html, PartialOptions};
+//!
+//! let source = "# One\n\nFirst[^a].\n\n# Two {#two}\n\nSecond[^b].\n\n[^a]: A\n\n[^b]: B\n";
+//! let events: Vec<_> = parse(source, &Default::default()).collect();
+//! let range = anchor_range(&events, "two").unwrap();
+//! let html = render_html(&events, range, &PartialOptions::default(), &Default::default());
+//! assert!(html.starts_with("<h1 id=\"two\">Two</h1>"));
+//! assert!(html.contains("<a href=\"#b\">2</a>"));
+//! assert!(html.contains("<div id=\"b\" class=\"footnote-definition\">"));
+//! ```
+use std::collections::HashSet;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, FootnoteReferenceEvent, StartTagEvent, Tag};
+use crate::html::{HtmlRenderer, HtmlRendererOptions};
+
+/// Configures how parts of a document are extracted.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PartialOptions {
+    /// Appends the footnote definitions referenced from the part.
+    pub footnotes: bool,
+    /// The URL of the full document that links to anchors outside of the
+    /// part are pointed to.
+    pub document_url: Option<String>,
+}
+
+impl Default for PartialOptions {
+    fn default() -> PartialOptions {
+        PartialOptions {
+            footnotes: true,
+            document_url: None,
+        }
+    }
+}
+
+/// Returns the index after the element starting at `start`.
+fn element_end(events: &[AnnotatedEvent<'_>], start: usize) -> usize {
+    let mut depth = 0;
+    for (idx, annotated_event) in events.iter().enumerate().skip(start) {
+        match annotated_event.event {
+            Event::StartTag(..) => depth += 1,
+            Event::EndTag(..) => depth -= 1,
+            _ => {}
+        }
+        if depth <= 0 {
+            return idx + 1;
+        }
+    }
+    events.len()
+}
+
+/// Locates the events of the element with the given anchor.
+///
+/// For headings the range covers the entire section up to the next heading
+/// of the same or a higher level, for all other elements just the element.
+pub fn anchor_range(events: &[AnnotatedEvent<'_>], anchor: &str) -> Option<Range<usize>> {
+    let (start, tag) = events
+        .iter()
+        .enumerate()
+        .find_map(|(idx, annotated_event)| match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, ref attrs })
+                if attrs.id.as_ref().map(|x| x.as_str()) == Some(anchor) =>
+            {
+                Some((idx, tag))
+            }
+            _ => None,
+        })?;
+
+    let level = match tag.header_level() {
+        Some(level) => level,
+        None => return Some(start..element_end(events, start)),
+    };
+    let end = events
+        .iter()
+        .enumerate()
+        .skip(start + 1)
+        .find_map(|(idx, annotated_event)| match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, .. })
+                if matches!(tag.header_level(), Some(x) if x <= level) =>
+            {
+                Some(idx)
+            }
+            _ => None,
+        })
+        .unwrap_or(events.len());
+    Some(start..end)
+}
+
+/// Extracts a range of events so that it can be rendered on its own.
+pub fn extract<'data>(
+    events: &[AnnotatedEvent<'data>],
+    range: Range<usize>,
+    options: &PartialOptions,
+) -> Vec<AnnotatedEvent<'data>> {
+    let mut rv = events[range.clone()].to_vec();
+
+    if options.footnotes {
+        let mut defined = HashSet::new();
+        let mut scanned = 0;
+        // appended definitions can reference further footnotes
+        while scanned < rv.len() {
+            let mut referenced = vec![];
+            for annotated_event in &rv[scanned..] {
+                match annotated_event.event {
+                    Event::StartTag(StartTagEvent {
+                        tag: Tag::FootnoteDefinition,
+                        ref attrs,
+                    }) => {
+                        if let Some(ref id) = attrs.id {
+                            defined.insert(id.as_str().to_string());
+                        }
+                    }
+                    Event::FootnoteReference(FootnoteReferenceEvent { ref target }) => {
+                        referenced.push(target.as_str().to_string());
+                    }
+                    _ => {}
+                }
+            }
+            scanned = rv.len();
+            for target in referenced {
+                if !defined.insert(target.clone()) {
+                    continue;
+                }
+                let definition = events.iter().position(|x| match x.event {
+                    Event::StartTag(StartTagEvent {
+                        tag: Tag::FootnoteDefinition,
+                        ref attrs,
+                    }) => attrs.id.as_ref().map(|x| x.as_str()) == Some(target.as_str()),
+                    _ => false,
+                });
+                if let Some(start) = definition {
+                    let end = element_end(events, start);
+                    rv.extend_from_slice(&events[start..end]);
+                }
+            }
+        }
+    }
+
+    if let Some(ref document_url) = options.document_url {
+        let anchors: HashSet<String> = rv
+            .iter()
+            .filter_map(|annotated_event| match annotated_event.event {
+                Event::StartTag(StartTagEvent { ref attrs, .. }) => {
+                    attrs.id.as_ref().map(|x| x.as_str().to_string())
+                }
+                _ => None,
+            })
+            .collect();
+        for annotated_event in rv.iter_mut() {
+            if let Event::StartTag(StartTagEvent {
+                tag: Tag::Link,
+                ref mut attrs,
+            }) = annotated_event.event
+            {
+                let target = match attrs.target {
+                    Some(ref target) => target.as_str(),
+                    None => continue,
+                };
+                if let Some(anchor) = target.strip_prefix('#') {
+                    if !anchors.contains(anchor) {
+                        attrs.target = Some(format!("{}{}", document_url, target).into());
+                    }
+                }
+            }
+        }
+    }
+
+    rv
+}
+
+/// Renders a range of events into HTML.
+///
+/// Footnotes are numbered as in the rendering of all events.
+pub fn render_html<'data>(
+    events: &[AnnotatedEvent<'data>],
+    range: Range<usize>,
+    options: &PartialOptions,
+    html_options: &HtmlRendererOptions,
+) -> String {
+    let mut renderer = HtmlRenderer::new_buffered(html_options);
+    renderer.number_footnotes(events);
+    for annotated_event in extract(events, range, options) {
+        renderer.feed_event(&annotated_event).unwrap();
+    }
+    renderer.finish().unwrap();
+    renderer.into_string()
+}
+
+#[test]
+fn test_render_html() {
+    use crate::parser::parse;
+    use crate::processors::{AutoAnchors, AutoAnchorsIter};
+    use std::borrow::Cow;
+
+    let source = "\
+# Intro
+
+Read the [usage](#usage) and the [notes](#notes)[^1].
+
+## Usage
+
+Call it[^2].
+
+### Details
+
+More[^3].
+
+## Notes
+
+Done.
+
+[^1]: First.
+
+[^2]: Second with a [link](#intro).
+
+[^3]: Third[^1].
+";
+    let events: Vec<_> = AutoAnchorsIter::new(
+        parse(source, &Default::default()),
+        Cow::Owned(AutoAnchors::default()),
+    )
+    .collect();
+    let range = anchor_range(&events, "usage").unwrap();
+    let options = PartialOptions {
+        document_url: Some("/docs/guide".into()),
+        ..Default::default()
+    };
+    insta::assert_snapshot!(render_html(&events, range, &options, &Default::default()));
+}
@@ -1,12 +1,35 @@
 //! Abstracts event stream modifications.
+//!
+//! A [`Pipeline`] can be built in code or deserialized from a config with
+//! a list of [built-in processors](crate::processors::BuiltinProcessor):
+//!
+//! ```
+//! use struckdown::pipeline::Pipeline;
+//!
+//! let pipeline: Pipeline = serde_yaml::from_str("
+//! processors:
+//!   - processor: auto_anchors
+//!   - processor: typography
+//! ").unwrap();
+//! let stream = pipeline.process("# Hello World\n\n(c) 2021");
+//! ```
+//!
+//! When multiple documents end up on one page an `id_namespace` keeps
+//! their anchors and footnotes apart.  It is applied after all processors
+//! (see [`IdNamespace`]).
+use serde::{Deserialize, Deserializer};
+
+use crate::dry_run::DryRunReport;
 use crate::event::AnnotatedEvent;
+use crate::markdown::{to_markdown, MarkdownRendererOptions};
 use crate::parser::{Parser, ParserOptions};
-use crate::processors::Processor;
+use crate::processors::{BuiltinProcessor, IdNamespace, Processor};
 
 /// Helper for applying preconfigured processors to an event stream.
 pub struct Pipeline {
     parser: Parser,
     processors: Vec<Box<dyn Processor>>,
+    id_namespace: Option<IdNamespace>,
 }
 
 impl Default for Pipeline {
@@ -15,20 +38,57 @@ impl Default for Pipeline {
     }
 }
 
+/// The serialized form of a [`Pipeline`].
+#[derive(Deserialize)]
+struct PipelineConfig {
+    #[serde(default)]
+    processors: Vec<BuiltinProcessor>,
+    #[serde(default)]
+    id_namespace: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for Pipeline {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let config = PipelineConfig::deserialize(deserializer)?;
+        let mut pipeline = Pipeline::new();
+        for processor in config.processors {
+            pipeline.add_processor(processor);
+        }
+        if let Some(prefix) = config.id_namespace {
+            pipeline.set_id_namespace(prefix);
+        }
+        Ok(pipeline)
+    }
+}
+
 impl Pipeline {
     /// Creates a new pipeline.
     pub fn new() -> Pipeline {
         Pipeline {
             parser: Parser::default(),
             processors: Vec::new(),
+            id_namespace: None,
         }
     }
 
+    /// Returns the parser of the pipeline.
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
     /// Changes the parsing options.
     pub fn set_parser_options(&mut self, parser_options: &ParserOptions) {
         self.parser = Parser::new(parser_options);
     }
 
+    /// Puts all ids of processed documents into a namespace.
+    pub fn set_id_namespace<S: Into<String>>(&mut self, prefix: S) {
+        self.id_namespace = Some(IdNamespace::new(prefix));
+    }
+
     /// Adds a processor to the pipeline
     pub fn add_processor<P: Processor + 'static>(&mut self, processor: P) {
         self.processors.push(Box::new(processor));
@@ -43,6 +103,9 @@ impl Pipeline {
         for processor in self.processors {
             iter = processor.apply(iter);
         }
+        if let Some(id_namespace) = self.id_namespace {
+            iter = Box::new(id_namespace).apply(iter);
+        }
         iter
     }
 
@@ -55,15 +118,64 @@ impl Pipeline {
         for processor in &self.processors {
             iter = processor.apply_ref(iter);
         }
+        if let Some(ref id_namespace) = self.id_namespace {
+            iter = id_namespace.apply_ref(iter);
+        }
         iter
     }
 
     /// Parses and processes a document returning an event stream.
+    ///
+    /// The [`Limits`](crate::limits::Limits) of the parser options are
+    /// enforced on the parsed as well as on the processed stream.
     pub fn process<'data, 'options: 'data>(
         &'options self,
         source: &'data str,
     ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data> {
-        self.apply_ref(Box::new(self.parser.parse(source)))
+        let iter = self.apply_ref(Box::new(self.parser.parse(source)));
+        let limits = &self.parser.options().limits;
+        if limits.is_unlimited() {
+            iter
+        } else {
+            Box::new(limits.apply(iter))
+        }
+    }
+
+    /// Parses and processes a document recording the changes of every
+    /// processor in a report instead of producing output.
+    ///
+    /// See [`dry_run`](crate::dry_run) for details.
+    pub fn dry_run<'data, 'options: 'data>(
+        &'options self,
+        document: &str,
+        source: &'data str,
+        report: &mut DryRunReport,
+    ) {
+        let mut events: Vec<_> = self.parser.parse(source).collect();
+        let stages = self
+            .processors
+            .iter()
+            .map(|x| &**x)
+            .chain(self.id_namespace.as_ref().map(|x| x as &dyn Processor));
+        for (stage, processor) in stages.enumerate() {
+            let processed: Vec<_> = processor
+                .apply_ref(Box::new(events.clone().into_iter()))
+                .collect();
+            report.record(stage, processor.name(), document, &events, &processed);
+            events = processed;
+        }
+        report.documents += 1;
+    }
+
+    /// Parses, processes and renders a document back into canonical markdown.
+    ///
+    /// The output always uses ATX headings, `-` bullets and fenced code
+    /// blocks.  Together with [`LinkStyle::Reference`](crate::markdown::LinkStyle)
+    /// this can be used to normalize documents to reduce style churn.  With a
+    /// [`lossless`](crate::parser::ParserOptions::lossless) parser blocks the
+    /// processors did not change keep their source instead.
+    pub fn normalize(&self, source: &str, options: &MarkdownRendererOptions) -> String {
+        to_markdown(self.process(source), options)
     }
 }
 
@@ -79,3 +191,48 @@ fn test_basic_pipeline() {
         &Default::default()
     ));
 }
+
+#[test]
+fn test_normalize() {
+    let pipeline = Pipeline::new();
+    insta::assert_snapshot!(pipeline.normalize(
+        "Hello World\n===========\n\n* one\n* two\n\nSubtitle\n--------\n\n    indented code\n",
+        &Default::default()
+    ));
+}
+
+#[test]
+fn test_deserialize_pipeline() {
+    use crate::html::to_html;
+
+    let pipeline: Pipeline = serde_yaml::from_str(
+        "processors:\n  - processor: auto_anchors\n  - processor: typography\n",
+    )
+    .unwrap();
+    insta::assert_snapshot!(to_html(
+        pipeline.process("# Hello World\n\n(c) 2021, about 1/2 done."),
+        &Default::default()
+    ));
+}
+
+#[test]
+fn test_id_namespace() {
+    use crate::event::{Event, MetaDataEvent};
+    use crate::html::to_html;
+
+    let pipeline: Pipeline = serde_yaml::from_str(
+        "id_namespace: intro\nprocessors:\n  - processor: auto_anchors\n  - processor: table_of_contents\n",
+    )
+    .unwrap();
+    let source =
+        "# Hello\n\n```{toc}\n```\n\nSee [usage](#usage)[^1].\n\n## Usage\n\n[^1]: A note.\n";
+    let events: Vec<_> = pipeline.process(source).collect();
+    let toc = events.iter().find_map(|x| match x.event {
+        Event::MetaData(MetaDataEvent { ref key, ref value }) if key.as_str() == "toc" => {
+            Some(value)
+        }
+        _ => None,
+    });
+    assert_eq!(toc.unwrap()[0]["anchor"], "intro--hello");
+    insta::assert_snapshot!(to_html(events.into_iter(), &Default::default()));
+}
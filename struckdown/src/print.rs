@@ -0,0 +1,381 @@
+//! Combines the documents of a docs tree into a single printable document.
+//!
+//! [`combine`] concatenates processed event streams in order and prepares
+//! the result for print or PDF output:
+//!
+//! * anchors are namespaced per document (`guide-install--usage`) so that
+//!   they stay unique and links between the documents are rewritten to
+//!   point into the combined document.  Every document additionally gets
+//!   its own anchor (`guide-install`).
+//! * headings and figures are numbered across the entire document.
+//! * `{pagebreak}` directives and the boundaries between documents become
+//!   page break hints.
+//! * footnotes are collected as endnotes at the end of the document.
+//!
+//! ```
+//! use struckdown::html::to_html;
+//! use struckdown::parser::parse;
+//! use struckdown::print::{combine, PrintOptions};
+//!
+//! let documents = vec![
+//!     ("index.md", "# Welcome\n\nSee [installing](guide/install.md#usage)."),
+//!     ("guide/install.md", "# Installing\n\n## Usage {#usage}"),
+//! ];
+//! let events = combine(
+//!     documents
+//!         .into_iter()
+//!         .map(|(path, source)| (path, parse(source, &Default::default()))),
+//!     &PrintOptions::default(),
+//! );
+//! let html = to_html(events.into_iter(), &Default::default());
+//! assert!(html.contains("<a href=\"#guide-install--usage\">"));
+//! ```
+//!
+//! Page breaks are empty containers with the `page_break_class`;
+//! [`PRINT_STYLESHEET`] has the matching rules for browsers and paged
+//! media tools.  Locations of events stay relative to their own document.
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, Attrs, DirectiveEvent, DocumentStartEvent, EndTagEvent, Event,
+    FootnoteReferenceEvent, StartTagEvent, Str, Tag, TextEvent,
+};
+
+/// CSS rules for the markup produced by [`combine`] with default options.
+pub const PRINT_STYLESHEET: &str = "\
+.page-break { break-after: page; }
+.heading-number, .figure-number { font-weight: bold; }
+@media print {
+  a[href^=\"#\"]::after { content: \" (p. \" target-counter(attr(href), page) \")\"; }
+}
+";
+
+/// Configures how documents are combined.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PrintOptions {
+    /// The deepest heading level that is numbered (0 disables numbering).
+    pub numbered_levels: usize,
+    /// Numbers figures.
+    pub number_figures: bool,
+    /// The label in front of figure numbers.
+    pub figure_label: String,
+    /// Inserts a page break before every document but the first.
+    pub break_between_documents: bool,
+    /// The class of page break hints.
+    pub page_break_class: String,
+    /// Moves footnotes to the end of the combined document.
+    pub endnotes: bool,
+    /// The title of the endnotes section.
+    pub endnotes_title: String,
+}
+
+impl Default for PrintOptions {
+    fn default() -> PrintOptions {
+        PrintOptions {
+            numbered_levels: 3,
+            number_figures: true,
+            figure_label: "Figure".into(),
+            break_between_documents: true,
+            page_break_class: "page-break".into(),
+            endnotes: true,
+            endnotes_title: "Notes".into(),
+        }
+    }
+}
+
+/// Returns the anchor of a document derived from its path.
+pub fn document_anchor(path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    let path = match path.rfind('.') {
+        Some(idx) if !path[idx..].contains('/') => &path[..idx],
+        _ => path,
+    };
+    slug::slugify(path)
+}
+
+/// Resolves a relative path against the folder of a document.
+fn resolve_path(base: &str, target: &str) -> String {
+    let mut segments: Vec<&str> = if target.starts_with('/') {
+        vec![]
+    } else {
+        let mut segments: Vec<&str> = base.trim_start_matches('/').split('/').collect();
+        segments.pop();
+        segments
+    };
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+struct Combiner<'data, 'options> {
+    options: &'options PrintOptions,
+    anchors: HashMap<String, String>,
+    events: Vec<AnnotatedEvent<'data>>,
+    endnotes: Vec<AnnotatedEvent<'data>>,
+    heading_counters: Vec<usize>,
+    figures: usize,
+}
+
+impl<'data, 'options> Combiner<'data, 'options> {
+    fn page_break(&mut self) {
+        let attrs = Attrs {
+            class: Some(self.options.page_break_class.clone().into()),
+            ..Attrs::default()
+        };
+        self.events.push(Tag::Container.start_tag(attrs).into());
+        self.events.push(Tag::Container.end_tag().into());
+    }
+
+    /// Rewrites a link target pointing into one of the documents.
+    fn rewrite_target(&self, path: &str, target: &str) -> Option<String> {
+        if target.contains("://") || target.starts_with("mailto:") {
+            return None;
+        }
+        let (target_path, fragment) = match target.find('#') {
+            Some(idx) => (&target[..idx], Some(&target[idx + 1..])),
+            None => (target, None),
+        };
+        let anchor = if target_path.is_empty() {
+            self.anchors.get(path.trim_start_matches('/'))?
+        } else {
+            self.anchors.get(&resolve_path(path, target_path))?
+        };
+        Some(match fragment {
+            Some(fragment) if !fragment.is_empty() => format!("#{}--{}", anchor, fragment),
+            _ => format!("#{}", anchor),
+        })
+    }
+
+    fn number_heading(&mut self, level: usize) -> Option<String> {
+        if level > self.options.numbered_levels {
+            return None;
+        }
+        self.heading_counters.resize(level, 0);
+        self.heading_counters[level - 1] += 1;
+        let numbers: Vec<String> = self
+            .heading_counters
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+        Some(numbers.join("."))
+    }
+
+    fn number(&mut self, class: &str, text: String) {
+        let attrs = Attrs {
+            class: Some(class.to_string().into()),
+            ..Attrs::default()
+        };
+        self.events.push(Tag::Span.start_tag(attrs).into());
+        self.events.push(TextEvent { text: text.into() }.into());
+        self.events.push(Tag::Span.end_tag().into());
+        self.events.push(TextEvent { text: " ".into() }.into());
+    }
+
+    fn add_document<I>(&mut self, path: &str, iter: I)
+    where
+        I: Iterator<Item = AnnotatedEvent<'data>>,
+    {
+        let anchor = self.anchors[path.trim_start_matches('/')].clone();
+        let namespaced = |id: &str| -> Str<'data> { format!("{}--{}", anchor, id).into() };
+
+        let attrs = Attrs {
+            id: Some(anchor.clone().into()),
+            ..Attrs::default()
+        };
+        self.events.push(Tag::Container.start_tag(attrs).into());
+        self.events.push(Tag::Container.end_tag().into());
+
+        // depth of an open footnote definition moved to the endnotes
+        let mut endnote_depth = 0;
+        let mut figure_captioned = false;
+
+        for mut annotated_event in iter {
+            match annotated_event.event {
+                Event::DocumentStart(..) => continue,
+                Event::StartTag(StartTagEvent { tag, ref mut attrs }) => {
+                    if let Some(ref id) = attrs.id {
+                        attrs.id = Some(namespaced(id.as_str()));
+                    }
+                    if tag == Tag::Link {
+                        let target = attrs.target.as_ref().map(|x| x.as_str().to_string());
+                        if let Some(target) = target.and_then(|x| self.rewrite_target(path, &x)) {
+                            attrs.target = Some(target.into());
+                        }
+                    }
+                    if endnote_depth > 0 || tag == Tag::FootnoteDefinition && self.options.endnotes
+                    {
+                        endnote_depth += 1;
+                    }
+                }
+                Event::EndTag(..) if endnote_depth > 0 => {
+                    endnote_depth -= 1;
+                    self.endnotes.push(annotated_event);
+                    continue;
+                }
+                Event::FootnoteReference(FootnoteReferenceEvent { ref mut target }) => {
+                    *target = namespaced(target.as_str());
+                }
+                Event::Directive(DirectiveEvent { ref name, .. })
+                    if name.as_str() == "pagebreak" =>
+                {
+                    self.page_break();
+                    continue;
+                }
+                _ => {}
+            }
+
+            if endnote_depth > 0 {
+                self.endnotes.push(annotated_event);
+                continue;
+            }
+
+            match annotated_event.event {
+                Event::StartTag(StartTagEvent { tag, .. }) => {
+                    let heading_number = tag
+                        .header_level()
+                        .and_then(|level| self.number_heading(level));
+                    self.events.push(annotated_event);
+                    if let Some(heading_number) = heading_number {
+                        self.number("heading-number", heading_number);
+                    }
+                    if self.options.number_figures {
+                        if tag == Tag::Figure {
+                            self.figures += 1;
+                            figure_captioned = false;
+                        } else if tag == Tag::FigureCaption {
+                            figure_captioned = true;
+                            let label = format!("{} {}:", self.options.figure_label, self.figures);
+                            self.number("figure-number", label);
+                        }
+                    }
+                }
+                Event::EndTag(EndTagEvent { tag: Tag::Figure })
+                    if self.options.number_figures && !figure_captioned =>
+                {
+                    let label = format!("{} {}", self.options.figure_label, self.figures);
+                    self.events
+                        .push(Tag::FigureCaption.start_tag(Attrs::default()).into());
+                    self.number("figure-number", label);
+                    self.events.pop();
+                    self.events.push(Tag::FigureCaption.end_tag().into());
+                    self.events.push(annotated_event);
+                }
+                _ => self.events.push(annotated_event),
+            }
+        }
+    }
+}
+
+/// Combines `(path, events)` pairs in order into a single event stream.
+///
+/// Paths are `/` separated and relative to the root of the docs tree.
+/// Links are resolved relative to the path of the document they are in.
+/// The front matter of the first document becomes the front matter of the
+/// combined document.
+pub fn combine<'data, I, P, E>(documents: I, options: &PrintOptions) -> Vec<AnnotatedEvent<'data>>
+where
+    I: IntoIterator<Item = (P, E)>,
+    P: AsRef<str>,
+    E: IntoIterator<Item = AnnotatedEvent<'data>>,
+{
+    let documents: Vec<(String, Vec<AnnotatedEvent<'data>>)> = documents
+        .into_iter()
+        .map(|(path, events)| (path.as_ref().to_string(), events.into_iter().collect()))
+        .collect();
+
+    let mut combiner = Combiner {
+        options,
+        anchors: documents
+            .iter()
+            .map(|(path, _)| {
+                let path = path.trim_start_matches('/');
+                (path.to_string(), document_anchor(path))
+            })
+            .collect(),
+        events: vec![],
+        endnotes: vec![],
+        heading_counters: vec![],
+        figures: 0,
+    };
+
+    let front_matter = documents
+        .first()
+        .and_then(|(_, events)| match events.first() {
+            Some(AnnotatedEvent {
+                event: Event::DocumentStart(DocumentStartEvent { front_matter }),
+                ..
+            }) => front_matter.clone(),
+            _ => None,
+        });
+    combiner
+        .events
+        .push(DocumentStartEvent { front_matter }.into());
+
+    for (idx, (path, events)) in documents.into_iter().enumerate() {
+        if idx > 0 && options.break_between_documents {
+            combiner.page_break();
+        }
+        combiner.add_document(&path, events.into_iter());
+    }
+
+    if !combiner.endnotes.is_empty() {
+        combiner.page_break();
+        let attrs = Attrs {
+            id: Some("endnotes".into()),
+            ..Attrs::default()
+        };
+        combiner.events.push(Tag::Heading1.start_tag(attrs).into());
+        combiner.events.push(
+            TextEvent {
+                text: options.endnotes_title.clone().into(),
+            }
+            .into(),
+        );
+        combiner.events.push(Tag::Heading1.end_tag().into());
+        let endnotes = std::mem::take(&mut combiner.endnotes);
+        combiner.events.extend(endnotes);
+    }
+
+    combiner.events
+}
+
+#[test]
+fn test_combine() {
+    use crate::html::to_html;
+    use crate::parser::parse;
+    use crate::processors::{AutoAnchorsIter, Figures, FiguresIter};
+    use std::borrow::Cow;
+
+    let documents = vec![
+        (
+            "index.md",
+            "---\ntitle: The Book\n---\n# Welcome\n\nRead [the guide](guide/install.md) first[^1].\n\n[^1]: Really.\n",
+        ),
+        (
+            "guide/install.md",
+            "# Installing\n\n## Usage {#usage}\n\nSee [usage](#usage) and [home](../index.md#welcome).\n\n```{pagebreak}\n```\n\n![A cat](cat.png)\n*A cat.*\n\n![A dog](dog.png)\n\nAlso[^1].\n\n[^1]: Another note.\n",
+        ),
+    ];
+    let events = combine(
+        documents.into_iter().map(|(path, source)| {
+            let events = parse(source, &Default::default());
+            let events = AutoAnchorsIter::new(events, Cow::Owned(Default::default()));
+            (
+                path,
+                FiguresIter::new(events, Cow::Owned(Figures::default())),
+            )
+        }),
+        &PrintOptions::default(),
+    );
+    insta::assert_snapshot!(to_html(events.into_iter(), &Default::default()));
+}
@@ -0,0 +1,202 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, Attrs, ErrorEvent, Event, StartTagEvent, Tag, TextEvent};
+use crate::processors::utils::whole_word_pattern;
+
+/// Wraps acronyms in text with their expansion.
+///
+/// Acronyms from `acronyms` and the optional YAML `glossary_file` (a
+/// mapping of acronyms to expansions) are matched case sensitively as whole
+/// words.  Matches are wrapped in a [`Tag::Abbreviation`] tag with the
+/// expansion as title.  By default only the first occurrence per section
+/// is wrapped.  Text in headings and code is never touched.
+///
+/// When applied this wraps the stream in a [`AcronymsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Acronyms {
+    /// Maps acronyms to their expansion.
+    pub acronyms: BTreeMap<String, String>,
+    /// An optional YAML file with additional acronyms.
+    pub glossary_file: Option<PathBuf>,
+    /// When enabled only the first occurrence per section is wrapped.
+    pub first_only: bool,
+}
+
+impl Default for Acronyms {
+    fn default() -> Acronyms {
+        Acronyms {
+            acronyms: BTreeMap::new(),
+            glossary_file: None,
+            first_only: true,
+        }
+    }
+}
+
+implement_processor!(Acronyms, AcronymsIter);
+
+/// Loads the acronyms configured for the processor.
+fn load_acronyms(options: &Acronyms) -> Result<BTreeMap<String, String>, ErrorEvent<'static>> {
+    let mut rv = BTreeMap::new();
+    if let Some(ref path) = options.glossary_file {
+        let error = |err: String| ErrorEvent {
+            title: format!("Failed to load glossary '{}'", path.display()).into(),
+            description: Some(err.into()),
+            code: Some(Code::GlossaryLoadFailure),
+        };
+        crate::sandbox::current().check_read(path).map_err(error)?;
+        let contents = fs::read_to_string(path).map_err(|err| error(err.to_string()))?;
+        let glossary: BTreeMap<String, String> =
+            serde_yaml::from_str(&contents).map_err(|err| error(err.to_string()))?;
+        rv.extend(glossary);
+    }
+    rv.extend(
+        options
+            .acronyms
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+    Ok(rv)
+}
+
+/// The iterator implementing [`Acronyms`].
+pub struct AcronymsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    regex: Option<Regex>,
+    acronyms: BTreeMap<String, String>,
+    seen: HashSet<String>,
+    skip_depth: usize,
+    options: Cow<'options, Acronyms>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> AcronymsIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Acronyms>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        let mut buffer = VecDeque::new();
+        let acronyms = load_acronyms(&options).unwrap_or_else(|err| {
+            buffer.push_back(err.into());
+            BTreeMap::new()
+        });
+
+        // longer acronyms first so that they win over their prefixes.
+        let mut terms = acronyms.keys().collect::<Vec<_>>();
+        terms.sort_by_key(|x| Reverse(x.len()));
+        let terms = terms
+            .into_iter()
+            .map(|x| whole_word_pattern(x))
+            .collect::<Vec<_>>();
+        let regex = if terms.is_empty() {
+            None
+        } else {
+            Some(Regex::new(&terms.join("|")).expect("bad acronyms"))
+        };
+
+        Self {
+            source: iterator,
+            buffer,
+            regex,
+            acronyms,
+            seen: HashSet::new(),
+            skip_depth: 0,
+            options,
+        }
+    }
+
+    /// Splits a text into text and abbreviation events.
+    fn process_text(&mut self, text: &str) -> Option<Vec<Event<'static>>> {
+        let regex = self.regex.as_ref()?;
+        let mut rv: Vec<Event<'static>> = vec![];
+        let mut last = 0;
+
+        for m in regex.find_iter(text) {
+            if self.options.first_only && !self.seen.insert(m.as_str().to_string()) {
+                continue;
+            }
+            if last < m.start() {
+                rv.push(
+                    TextEvent {
+                        text: text[last..m.start()].to_string().into(),
+                    }
+                    .into(),
+                );
+            }
+            rv.push(
+                Tag::Abbreviation
+                    .start_tag(Attrs {
+                        title: Some(self.acronyms[m.as_str()].clone().into()),
+                        ..Attrs::default()
+                    })
+                    .into(),
+            );
+            rv.push(
+                TextEvent {
+                    text: m.as_str().to_string().into(),
+                }
+                .into(),
+            );
+            rv.push(Tag::Abbreviation.end_tag().into());
+            last = m.end();
+        }
+
+        if rv.is_empty() {
+            return None;
+        }
+        if last < text.len() {
+            rv.push(
+                TextEvent {
+                    text: text[last..].to_string().into(),
+                }
+                .into(),
+            );
+        }
+        Some(rv)
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for AcronymsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => {
+                if tag.header_level().is_some() {
+                    self.seen.clear();
+                }
+                if self.skip_depth > 0 || tag.header_level().is_some() || tag == Tag::Abbreviation {
+                    self.skip_depth += 1;
+                }
+            }
+            Event::EndTag(..) => {
+                self.skip_depth = self.skip_depth.saturating_sub(1);
+            }
+            Event::Text(TextEvent { ref text }) if self.skip_depth == 0 => {
+                if let Some(events) = self.process_text(text.as_str()) {
+                    let mut events = events.into_iter();
+                    let first = events.next()?;
+                    self.buffer
+                        .extend(events.map(|event| AnnotatedEvent::new(event, None)));
+                    return Some(AnnotatedEvent::new(first, annotated_event.location));
+                }
+            }
+            _ => {}
+        }
+
+        Some(annotated_event)
+    }
+}
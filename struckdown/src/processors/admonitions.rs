@@ -0,0 +1,125 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, DirectiveEvent, Tag, TextEvent};
+use crate::processors::{DirectiveContext, DirectiveProcessor, DirectiveProcessorIter};
+use crate::theme::{ADMONITION, ADMONITION_TITLE};
+
+/// Renders admonition directives.
+///
+/// Directives named after one of the configured kinds (by default `note`,
+/// `warning`, `tip` and `danger`) are expanded into a [`Tag::Container`]
+/// with the classes `admonition` and the kind, followed by the parsed body:
+///
+/// ````markdown
+/// ```{warning} Mind the gap
+/// Do not *ever* step here.
+/// ```
+/// ````
+///
+/// Unless disabled, the container starts with a paragraph with the class
+/// `admonition-title` holding the argument of the directive or the
+/// capitalized kind.
+///
+/// When applied this wraps the stream in an [`AdmonitionsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Admonitions {
+    /// The names of the directives to render as admonitions.
+    pub kinds: Vec<String>,
+    /// Controls if a title is rendered.
+    pub titles: bool,
+}
+
+impl Default for Admonitions {
+    fn default() -> Admonitions {
+        Admonitions {
+            kinds: vec![
+                "note".into(),
+                "warning".into(),
+                "tip".into(),
+                "danger".into(),
+            ],
+            titles: true,
+        }
+    }
+}
+
+implement_processor!(Admonitions, AdmonitionsIter);
+
+fn expand_admonition(
+    directive: &DirectiveEvent<'_>,
+    ctx: &DirectiveContext<'_>,
+    titles: bool,
+) -> Vec<AnnotatedEvent<'static>> {
+    let kind = directive.name.as_str();
+    let mut rv = vec![Tag::Container
+        .start_tag(Attrs {
+            class: Some(format!("{} {}", ADMONITION, kind).into()),
+            ..Attrs::default()
+        })
+        .into()];
+    if titles {
+        let title = match directive.argument {
+            Some(ref argument) => argument.as_str().to_string(),
+            None => {
+                let mut chars = kind.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default()
+            }
+        };
+        rv.push(
+            Tag::Paragraph
+                .start_tag(Attrs {
+                    class: Some(ADMONITION_TITLE.into()),
+                    ..Attrs::default()
+                })
+                .into(),
+        );
+        rv.push(TextEvent { text: title.into() }.into());
+        rv.push(Tag::Paragraph.end_tag().into());
+    }
+    rv.extend(ctx.parse_directive_body(directive));
+    rv.push(Tag::Container.end_tag().into());
+    rv
+}
+
+impl DirectiveProcessor {
+    /// Registers a directive that renders as an admonition.
+    ///
+    /// See [`Admonitions`] for how admonitions are rendered.
+    pub fn register_admonition(&mut self, name: &str, titles: bool) -> &mut Self {
+        self.register_directive(name, move |directive, ctx| {
+            expand_admonition(directive, ctx, titles)
+        })
+    }
+}
+
+/// The iterator implementing [`Admonitions`].
+pub struct AdmonitionsIter<'data, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    inner: DirectiveProcessorIter<'data, 'static, I>,
+}
+
+impl<'data, I: Iterator<Item = AnnotatedEvent<'data>>> AdmonitionsIter<'data, I> {
+    pub fn new<'options, O: Into<Cow<'options, Admonitions>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        let mut directives = DirectiveProcessor::new();
+        for kind in &options.kinds {
+            directives.register_admonition(kind, options.titles);
+        }
+        Self {
+            inner: DirectiveProcessorIter::new(iterator, Cow::Owned(directives)),
+        }
+    }
+}
+
+impl<'data, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator for AdmonitionsIter<'data, I> {
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
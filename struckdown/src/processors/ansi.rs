@@ -0,0 +1,319 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, CodeBlockEvent, Event, Tag, TextEvent};
+
+lazy_static! {
+    static ref ESCAPE_RE: Regex =
+        Regex::new(r"\x1b\[([0-9;]*)([A-Za-z])|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b").unwrap();
+}
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+const COLOR_VALUES: [&str; 16] = [
+    "#000000", "#cd0000", "#00cd00", "#cdcd00", "#0000ee", "#cd00cd", "#00cdcd", "#e5e5e5",
+    "#7f7f7f", "#ff0000", "#00ff00", "#ffff00", "#5c5cff", "#ff00ff", "#00ffff", "#ffffff",
+];
+
+/// Renders ANSI escape sequences in code blocks.
+///
+/// Code blocks in one of the configured languages that contain ANSI escape
+/// codes are converted into a [`Tag::Container`] holding [`Tag::Span`] tags
+/// for each styled run of text.  Spans carry classes such as `ansi-red`,
+/// `ansi-bg-blue` or `ansi-bold` and, if `inline_styles` is enabled, the
+/// equivalent inline styles.  Colors from the 256 color and true color
+/// palettes are always emitted as inline styles.
+///
+/// As the output preserves newlines as text the container should be
+/// styled with `white-space: pre`.
+///
+/// When applied this wraps the stream in a [`AnsiIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Ansi {
+    /// The code block languages to process.
+    pub languages: Vec<String>,
+    /// The class to attach to the container.
+    pub class_name: Option<String>,
+    /// When enabled colors and text styles are also emitted as inline styles.
+    pub inline_styles: bool,
+}
+
+impl Default for Ansi {
+    fn default() -> Ansi {
+        Ansi {
+            languages: vec!["console".into(), "ansi".into()],
+            class_name: Some("ansi".into()),
+            inline_styles: false,
+        }
+    }
+}
+
+implement_processor!(Ansi, AnsiIter);
+
+/// A color set by an escape sequence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Color {
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn class(self) -> Option<String> {
+        match self {
+            Color::Indexed(idx) if idx < 8 => Some(COLOR_NAMES[idx as usize].to_string()),
+            Color::Indexed(idx) if idx < 16 => {
+                Some(format!("bright-{}", COLOR_NAMES[idx as usize - 8]))
+            }
+            _ => None,
+        }
+    }
+
+    fn css(self) -> String {
+        match self {
+            Color::Indexed(idx) if idx < 16 => COLOR_VALUES[idx as usize].to_string(),
+            Color::Indexed(idx) if idx >= 232 => {
+                let gray = 8 + (idx - 232) as u32 * 10;
+                format!("#{:02x}{:02x}{:02x}", gray, gray, gray)
+            }
+            Color::Indexed(idx) => {
+                let idx = idx - 16;
+                let scale = |x: u8| if x == 0 { 0 } else { 55 + x as u32 * 40 };
+                format!(
+                    "#{:02x}{:02x}{:02x}",
+                    scale(idx / 36),
+                    scale((idx / 6) % 6),
+                    scale(idx % 6)
+                )
+            }
+            Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+/// The current text style.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    dim: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl Style {
+    fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    fn apply(&mut self, params: &str) {
+        let mut codes = params
+            .split(';')
+            .map(|x| x.parse::<u32>().unwrap_or(0))
+            .collect::<VecDeque<_>>();
+        while let Some(code) = codes.pop_front() {
+            match code {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                2 => self.dim = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => {
+                    self.bold = false;
+                    self.dim = false;
+                }
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => self.fg = Some(Color::Indexed((code - 30) as u8)),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some(Color::Indexed((code - 40) as u8)),
+                49 => self.bg = None,
+                90..=97 => self.fg = Some(Color::Indexed((code - 90 + 8) as u8)),
+                100..=107 => self.bg = Some(Color::Indexed((code - 100 + 8) as u8)),
+                38 | 48 => {
+                    let color = match codes.pop_front() {
+                        Some(5) => codes.pop_front().map(|x| Color::Indexed(x as u8)),
+                        Some(2) => {
+                            let mut rgb = codes.drain(..codes.len().min(3)).map(|x| x as u8);
+                            match (rgb.next(), rgb.next(), rgb.next()) {
+                                (Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+                                _ => None,
+                            }
+                        }
+                        _ => None,
+                    };
+                    if code == 38 {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn to_attrs(&self, inline_styles: bool) -> Attrs<'static> {
+        let mut classes = vec![];
+        let mut styles = vec![];
+        if let Some(fg) = self.fg {
+            match fg.class() {
+                Some(class) => classes.push(format!("ansi-{}", class)),
+                None => styles.push(format!("color: {}", fg.css())),
+            }
+            if inline_styles && fg.class().is_some() {
+                styles.push(format!("color: {}", fg.css()));
+            }
+        }
+        if let Some(bg) = self.bg {
+            match bg.class() {
+                Some(class) => classes.push(format!("ansi-bg-{}", class)),
+                None => styles.push(format!("background-color: {}", bg.css())),
+            }
+            if inline_styles && bg.class().is_some() {
+                styles.push(format!("background-color: {}", bg.css()));
+            }
+        }
+        for &(enabled, class, style) in &[
+            (self.bold, "ansi-bold", "font-weight: bold"),
+            (self.dim, "ansi-dim", "opacity: 0.7"),
+            (self.italic, "ansi-italic", "font-style: italic"),
+            (
+                self.underline,
+                "ansi-underline",
+                "text-decoration: underline",
+            ),
+        ] {
+            if enabled {
+                classes.push(class.to_string());
+                if inline_styles {
+                    styles.push(style.to_string());
+                }
+            }
+        }
+
+        Attrs {
+            class: if classes.is_empty() {
+                None
+            } else {
+                Some(classes.join(" ").into())
+            },
+            custom: if styles.is_empty() {
+                None
+            } else {
+                let mut custom = BTreeMap::new();
+                custom.insert("style".into(), styles.join("; ").into());
+                Some(custom)
+            },
+            ..Attrs::default()
+        }
+    }
+}
+
+/// Converts ANSI formatted text into events.
+fn ansi_to_events(code: &str, options: &Ansi) -> Vec<AnnotatedEvent<'static>> {
+    let mut rv = vec![];
+    let mut style = Style::default();
+    let mut last = 0;
+
+    let emit = |rv: &mut Vec<AnnotatedEvent<'static>>, text: &str, style: &Style| {
+        if text.is_empty() {
+            return;
+        }
+        let text_event = TextEvent {
+            text: text.to_string().into(),
+        };
+        if style.is_plain() {
+            rv.push(text_event.into());
+        } else {
+            rv.push(
+                Tag::Span
+                    .start_tag(style.to_attrs(options.inline_styles))
+                    .into(),
+            );
+            rv.push(text_event.into());
+            rv.push(Tag::Span.end_tag().into());
+        }
+    };
+
+    for m in ESCAPE_RE.captures_iter(code) {
+        let g0 = m.get(0).unwrap();
+        emit(&mut rv, &code[last..g0.start()], &style);
+        last = g0.end();
+        if m.get(2).map(|x| x.as_str()) == Some("m") {
+            style.apply(&m[1]);
+        }
+    }
+    emit(&mut rv, &code[last..], &style);
+
+    rv
+}
+
+/// The iterator implementing [`Ansi`].
+pub struct AnsiIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Ansi>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> AnsiIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Ansi>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for AnsiIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        if let Event::CodeBlock(CodeBlockEvent {
+            language: Some(ref language),
+            ref code,
+            ..
+        }) = annotated_event.event
+        {
+            if self
+                .options
+                .languages
+                .iter()
+                .any(|x| x == language.as_str())
+                && code.as_str().contains('\x1b')
+            {
+                self.buffer
+                    .extend(ansi_to_events(code.as_str(), &self.options));
+                self.buffer.push_back(Tag::Container.end_tag().into());
+                return Some(AnnotatedEvent::new(
+                    Tag::Container.start_tag(Attrs {
+                        class: self
+                            .options
+                            .class_name
+                            .as_ref()
+                            .map(|x| x.to_string().into()),
+                        ..Attrs::default()
+                    }),
+                    annotated_event.location,
+                ));
+            }
+        }
+
+        Some(annotated_event)
+    }
+}
@@ -0,0 +1,210 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use slug::slugify;
+
+use crate::anchors::{AnchorRegistry, CollisionPolicy};
+use crate::event::{
+    AnnotatedEvent, Attrs, DirectiveEvent, Event, InlineCodeEvent, InterpretedTextEvent, Tag,
+};
+use crate::processors::utils::parse_body;
+
+lazy_static! {
+    static ref KEYWORD_RE: Regex = Regex::new(
+        r"^(?:(?:pub(?:\([^)]*\))?|async|const|unsafe|fn|struct|enum|trait|def|class)\s+)*"
+    )
+    .unwrap();
+    static ref NAME_RE: Regex = Regex::new(r"^-*[\w.:-]*\w").unwrap();
+}
+
+/// Expands API signature directives such as `{rust:fn}` or `{cli:option}`.
+///
+/// The argument of the directive is the signature and the body is parsed
+/// as markdown and used as description:
+///
+/// ````text
+/// ```{rust:fn} pub fn parse(s: &str) -> Events
+/// Parses a document.
+/// ```
+/// ````
+///
+/// The output is a container with the classes `api` and the directive name
+/// (with colons replaced by dashes) holding an `api-signature` and an
+/// `api-description` container.  The container gets an id derived from the
+/// directive name and the name in the signature (`rust-fn-parse`).  Roles
+/// with the same names as the directives (`` {rust:fn}`parse` ``) are turned
+/// into links to these ids.  Ids already in use are handled according to
+/// the [`CollisionPolicy`], links always point to the first item.
+///
+/// When applied this wraps the stream in a [`ApiSignaturesIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ApiSignatures {
+    /// The names of the directives to expand.
+    pub directives: Vec<String>,
+    /// When enabled roles named like the directives are turned into links.
+    pub resolve_roles: bool,
+    /// What to do with ids that are already in use.
+    pub duplicates: CollisionPolicy,
+}
+
+impl Default for ApiSignatures {
+    fn default() -> ApiSignatures {
+        ApiSignatures {
+            directives: [
+                "rust:fn",
+                "rust:struct",
+                "rust:enum",
+                "rust:trait",
+                "py:function",
+                "py:class",
+                "cli:option",
+            ]
+            .iter()
+            .map(|&x| x.into())
+            .collect(),
+            resolve_roles: true,
+            duplicates: CollisionPolicy::Overwrite,
+        }
+    }
+}
+
+implement_processor!(ApiSignatures, ApiSignaturesIter);
+
+/// Returns the name of the item described by a signature.
+fn signature_name(signature: &str) -> &str {
+    let rest = &signature.trim()[KEYWORD_RE.find(signature.trim()).map_or(0, |m| m.end())..];
+    NAME_RE.find(rest).map_or(rest, |m| m.as_str())
+}
+
+/// Returns the anchor for an API item.
+pub fn api_anchor(directive: &str, signature: &str) -> String {
+    slugify(format!("{} {}", directive, signature_name(signature)))
+}
+
+fn container(class: String) -> Attrs<'static> {
+    Attrs {
+        class: Some(class.into()),
+        ..Attrs::default()
+    }
+}
+
+/// The iterator implementing [`ApiSignatures`].
+pub struct ApiSignaturesIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    anchors: AnchorRegistry,
+    options: Cow<'options, ApiSignatures>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    ApiSignaturesIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, ApiSignatures>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            anchors: AnchorRegistry::new(options.duplicates),
+            options,
+        }
+    }
+
+    fn is_api_name(&self, name: &str) -> bool {
+        self.options.directives.iter().any(|x| x == name)
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for ApiSignaturesIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // buffered events are processed again as directive bodies can
+        // contain roles and directives themselves.
+        let annotated_event = match self.buffer.pop_front() {
+            Some(annotated_event) => annotated_event,
+            None => self.source.next()?,
+        };
+
+        let mut expanded = vec![];
+        let rv = match annotated_event.event {
+            Event::Directive(DirectiveEvent {
+                ref name,
+                ref argument,
+                ref body,
+                ref body_location,
+                ..
+            }) if self.is_api_name(name.as_str()) => {
+                let signature = argument.as_ref().map_or("", |x| x.as_str());
+                expanded.push(
+                    Tag::Container
+                        .start_tag(container("api-signature".into()))
+                        .into(),
+                );
+                expanded.push(
+                    InlineCodeEvent {
+                        code: signature.to_string().into(),
+                    }
+                    .into(),
+                );
+                expanded.push(Tag::Container.end_tag().into());
+                expanded.push(
+                    Tag::Container
+                        .start_tag(container("api-description".into()))
+                        .into(),
+                );
+                expanded.extend(parse_body(body.as_str(), body_location.as_ref()));
+                expanded.push(Tag::Container.end_tag().into());
+                expanded.push(Tag::Container.end_tag().into());
+
+                let mut attrs = container(format!("api {}", name.as_str().replace(':', "-")));
+                attrs.id = Some(
+                    match self.anchors.claim(&api_anchor(name.as_str(), signature)) {
+                        Ok(id) => id,
+                        Err(id) => {
+                            expanded.insert(
+                                0,
+                                AnnotatedEvent::new(
+                                    AnchorRegistry::collision_error(&id),
+                                    annotated_event.location.clone(),
+                                ),
+                            );
+                            id
+                        }
+                    }
+                    .into(),
+                );
+                AnnotatedEvent::new(Tag::Container.start_tag(attrs), annotated_event.location)
+            }
+            Event::InterpretedText(InterpretedTextEvent {
+                ref role, ref text, ..
+            }) if self.options.resolve_roles && self.is_api_name(role.as_str()) => {
+                expanded.push(InlineCodeEvent { code: text.clone() }.into());
+                expanded.push(Tag::Link.end_tag().into());
+                AnnotatedEvent::new(
+                    Tag::Link.start_tag(Attrs {
+                        target: Some(
+                            format!("#{}", api_anchor(role.as_str(), text.as_str())).into(),
+                        ),
+                        ..Attrs::default()
+                    }),
+                    annotated_event.location,
+                )
+            }
+            _ => {
+                self.anchors.observe_event(&annotated_event.event);
+                annotated_event
+            }
+        };
+
+        for annotated_event in expanded.into_iter().rev() {
+            self.buffer.push_front(annotated_event);
+        }
+        Some(rv)
+    }
+}
@@ -0,0 +1,255 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, Attrs, DirectiveEvent, DocumentStartEvent, Event, InterpretedTextEvent,
+    Location, StartTagEvent, Tag, TextEvent,
+};
+use crate::processors::utils::{parse_body, skip_element, MetaDataReport};
+use crate::value::Value;
+
+/// Filters content by the audience it is meant for.
+///
+/// Content is labelled with the audiences allowed to see it:
+///
+/// * the document by an `audience` key in its front matter,
+/// * the body of an `{audience}` directive by its argument
+///   (`` ```{audience} admin staff ``),
+/// * any other directive by an `audience` key in its front matter,
+/// * interpreted text by a role like `` {audience-admin}`text` ``,
+/// * a tag and its contents by a class like `audience-admin`.
+///
+/// Front matter values are a label or a list of labels.  Labelled content
+/// is kept if one of its labels is in `audience` and removed otherwise,
+/// unlabelled content is always kept.  Running the processor once per
+/// audience produces the stream for every audience from one source.  Kept
+/// bodies of `{audience}` directives are wrapped in a container with the
+/// class `audience` and a `data-audience` attribute.
+///
+/// A report of all labelled content and whether it is visible is emitted
+/// as `audience` meta data.
+///
+/// When applied this wraps the stream in a [`AudienceFilterIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AudienceFilter {
+    /// The labels of the audience the stream is produced for.
+    pub audience: Vec<String>,
+    /// The name of the directive labelling its body.
+    pub directive: String,
+    /// The front matter key holding labels.
+    pub front_matter_key: String,
+    /// The prefix of roles and classes holding a label.
+    pub prefix: String,
+    /// Controls if the report should be emitted as meta data.
+    pub emit_metadata: bool,
+}
+
+impl Default for AudienceFilter {
+    fn default() -> AudienceFilter {
+        AudienceFilter {
+            audience: vec![],
+            directive: "audience".into(),
+            front_matter_key: "audience".into(),
+            prefix: "audience-".into(),
+            emit_metadata: true,
+        }
+    }
+}
+
+implement_processor!(AudienceFilter, AudienceFilterIter);
+
+/// A single entry in the audience report.
+#[derive(Serialize, Debug)]
+pub struct Labelled {
+    /// How the content is labelled (`document`, `directive`,
+    /// `front_matter`, `role` or `class`).
+    kind: &'static str,
+    /// The audiences allowed to see the content.
+    labels: Vec<String>,
+    /// Whether the audience sees the content.
+    visible: bool,
+    /// The location of the content if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<Location>,
+}
+
+/// The audience report.
+#[derive(Serialize, Debug)]
+struct Report<'a> {
+    audience: &'a [String],
+    content: &'a [Labelled],
+}
+
+/// The iterator implementing [`AudienceFilter`].
+pub struct AudienceFilterIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    report: MetaDataReport<Labelled>,
+    options: Cow<'options, AudienceFilter>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    AudienceFilterIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, AudienceFilter>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            report: MetaDataReport::new(),
+            options: options.into(),
+        }
+    }
+
+    fn front_matter_labels(&self, front_matter: &Option<Value>) -> Option<Vec<String>> {
+        match front_matter.as_ref()?.get(&self.options.front_matter_key)? {
+            Value::String(label) => Some(vec![label.clone()]),
+            Value::Array(labels) => Some(
+                labels
+                    .iter()
+                    .filter_map(|x| x.as_str())
+                    .map(|x| x.to_string())
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn prefixed_label(&self, name: &str) -> Option<String> {
+        let prefix = self.options.prefix.as_str();
+        if !prefix.is_empty() && name.starts_with(prefix) && name.len() > prefix.len() {
+            Some(name[prefix.len()..].to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Records labelled content and returns `true` if it is visible.
+    fn check(
+        &mut self,
+        kind: &'static str,
+        labels: Vec<String>,
+        location: Option<Location>,
+    ) -> bool {
+        let visible = labels.iter().any(|x| self.options.audience.contains(x));
+        self.report.push(Labelled {
+            kind,
+            labels,
+            visible,
+            location,
+        });
+        visible
+    }
+
+    fn finish(&mut self) -> Option<AnnotatedEvent<'data>> {
+        let audience = &self.options.audience;
+        self.report
+            .finish("audience", self.options.emit_metadata, |content| Report {
+                audience,
+                content,
+            })
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for AudienceFilterIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+            let annotated_event = match self.source.next() {
+                Some(annotated_event) => annotated_event,
+                None => return self.finish(),
+            };
+
+            match annotated_event.event {
+                Event::DocumentStart(DocumentStartEvent { ref front_matter }) => {
+                    if let Some(labels) = self.front_matter_labels(front_matter) {
+                        if !self.check("document", labels, annotated_event.location.clone()) {
+                            self.source.by_ref().for_each(drop);
+                        }
+                    }
+                }
+                Event::Directive(DirectiveEvent {
+                    ref name,
+                    ref argument,
+                    ref body,
+                    ref body_location,
+                    ..
+                }) if name.as_str() == self.options.directive => {
+                    let labels: Vec<String> = argument
+                        .as_ref()
+                        .map_or("", |x| x.as_str())
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|x| !x.is_empty())
+                        .map(|x| x.to_string())
+                        .collect();
+                    let data_audience = labels.join(" ");
+                    if self.check("directive", labels, annotated_event.location.clone()) {
+                        self.buffer
+                            .extend(parse_body(body.as_str(), body_location.as_ref()));
+                        self.buffer.push_back(Tag::Container.end_tag().into());
+                        let mut custom = BTreeMap::new();
+                        custom.insert("data-audience".into(), data_audience.into());
+                        return Some(AnnotatedEvent::new(
+                            Tag::Container.start_tag(Attrs {
+                                class: Some("audience".into()),
+                                custom: Some(custom),
+                                ..Attrs::default()
+                            }),
+                            annotated_event.location,
+                        ));
+                    }
+                    continue;
+                }
+                Event::Directive(DirectiveEvent {
+                    ref front_matter, ..
+                }) => {
+                    if let Some(labels) = self.front_matter_labels(front_matter) {
+                        if !self.check("front_matter", labels, annotated_event.location.clone()) {
+                            continue;
+                        }
+                    }
+                }
+                Event::InterpretedText(InterpretedTextEvent {
+                    ref role, ref text, ..
+                }) => {
+                    if let Some(label) = self.prefixed_label(role.as_str()) {
+                        let location = annotated_event.location;
+                        if self.check("role", vec![label], location.clone()) {
+                            return Some(AnnotatedEvent::new(
+                                TextEvent { text: text.clone() },
+                                location,
+                            ));
+                        }
+                        continue;
+                    }
+                }
+                Event::StartTag(StartTagEvent { ref attrs, .. }) => {
+                    let labels: Vec<String> = attrs
+                        .class
+                        .as_ref()
+                        .map_or("", |x| x.as_str())
+                        .split_whitespace()
+                        .filter_map(|x| self.prefixed_label(x))
+                        .collect();
+                    if !labels.is_empty()
+                        && !self.check("class", labels, annotated_event.location.clone())
+                    {
+                        skip_element(&mut self.source);
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+
+            return Some(annotated_event);
+        }
+    }
+}
@@ -1,33 +1,113 @@
 use std::borrow::Cow;
 use std::collections::VecDeque;
 
+use deunicode::deunicode;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use slug::slugify;
 
-use crate::event::{AnnotatedEvent, Event, StartTagEvent};
+use crate::anchors::{AnchorRegistry, CollisionPolicy};
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, ErrorEvent, Event, StartTagEvent};
+
+/// How the text of a heading is turned into an anchor.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SlugStrategy {
+    /// Lowercase ASCII words joined by dashes with all other characters
+    /// transliterated (`Über uns` becomes `uber-uns`).
+    Ascii,
+    /// Like GitHub: lowercased, punctuation removed and every space
+    /// replaced by a dash while other letters are kept (`Über uns` becomes
+    /// `über-uns`).
+    Github,
+    /// Like Python's docutils: lowercase ASCII letters and digits joined by
+    /// dashes, leading digits and dashes removed, other characters dropped
+    /// (`1. Über uns` becomes `ber-uns`).
+    Docutils,
+    /// The lowercased text with all matches of `slug_pattern` replaced by
+    /// `slug_replacement` and leading and trailing replacements removed.
+    Custom,
+}
 
 /// Automatically add anchors to all headers when missing.
 ///
+/// The anchors are derived from the text of the headings according to the
+/// [`SlugStrategy`] so that they can match the conventions of the site the
+/// documents are published on.  With `transliterate` enabled non-ASCII
+/// text is transliterated before the strategy is applied which makes
+/// `docutils` keep accented letters (`café` becomes `cafe`).
+///
+/// Anchors set explicitly before a generated one are taken into account
+/// when handling duplicates according to the [`CollisionPolicy`].  If the
+/// custom `slug_pattern` is not a valid regular expression an error is
+/// emitted and the `ascii` strategy is used.
+///
 /// When applied this wraps the stream in a [`AutoAnchorsIter`].
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct AutoAnchors {
     /// The maximum level of headline that should get IDs.
     pub max_level: usize,
+    /// How anchors are derived from the text.
+    pub slug_strategy: SlugStrategy,
+    /// The regular expression for the `custom` strategy.
+    pub slug_pattern: String,
+    /// The replacement of matches for the `custom` strategy.
+    pub slug_replacement: String,
+    /// Transliterates non-ASCII text before the strategy is applied.
+    pub transliterate: bool,
+    /// What to do with duplicated anchors.
+    pub duplicates: CollisionPolicy,
 }
 
 impl Default for AutoAnchors {
     fn default() -> AutoAnchors {
-        AutoAnchors { max_level: 6 }
+        AutoAnchors {
+            max_level: 6,
+            slug_strategy: SlugStrategy::Ascii,
+            slug_pattern: r"[^\w]+".into(),
+            slug_replacement: "-".into(),
+            transliterate: false,
+            duplicates: CollisionPolicy::Overwrite,
+        }
     }
 }
 
+fn github_slug(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+fn docutils_slug(text: &str) -> String {
+    let text = text.to_lowercase();
+    let words: Vec<&str> = text
+        .split(|c: char| c.is_ascii() && !c.is_ascii_alphanumeric())
+        .map(|x| x.trim_matches(|c: char| !c.is_ascii()))
+        .filter(|x| !x.is_empty())
+        .collect();
+    let slug: String = words.join("-").chars().filter(|c| c.is_ascii()).collect();
+    slug.trim_start_matches(|c: char| c == '-' || c.is_ascii_digit())
+        .trim_end_matches('-')
+        .to_string()
+}
+
 implement_processor!(AutoAnchors, AutoAnchorsIter);
 
 /// The iterator implementing [`AutoAnchors`].
 pub struct AutoAnchorsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
     source: I,
     buffer: VecDeque<AnnotatedEvent<'data>>,
+    custom_pattern: Option<Regex>,
+    errors: Vec<ErrorEvent<'data>>,
+    anchors: AnchorRegistry,
     options: Cow<'options, AutoAnchors>,
 }
 
@@ -35,10 +115,54 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
     AutoAnchorsIter<'data, 'options, I>
 {
     pub fn new<O: Into<Cow<'options, AutoAnchors>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        let mut errors = vec![];
+        let mut custom_pattern = None;
+        if options.slug_strategy == SlugStrategy::Custom {
+            match Regex::new(&options.slug_pattern) {
+                Ok(regex) => custom_pattern = Some(regex),
+                Err(err) => errors.push(ErrorEvent {
+                    title: "Invalid slug pattern".into(),
+                    description: Some(err.to_string().into()),
+                    code: Some(Code::InvalidSlugPattern),
+                }),
+            }
+        }
         Self {
             source: iterator,
             buffer: VecDeque::new(),
-            options: options.into(),
+            custom_pattern,
+            errors,
+            anchors: AnchorRegistry::new(options.duplicates),
+            options,
+        }
+    }
+
+    /// Derives an anchor from text according to the slug strategy.
+    pub fn slugify(&self, text: &str) -> String {
+        let transliterated;
+        let text = if self.options.transliterate {
+            transliterated = deunicode(text);
+            transliterated.as_str()
+        } else {
+            text
+        };
+        match (self.options.slug_strategy, &self.custom_pattern) {
+            (SlugStrategy::Github, _) => github_slug(text),
+            (SlugStrategy::Docutils, _) => docutils_slug(text),
+            (SlugStrategy::Custom, Some(regex)) => {
+                let replacement = self.options.slug_replacement.as_str();
+                let text = text.trim().to_lowercase();
+                let slug = regex.replace_all(&text, replacement);
+                if replacement.is_empty() {
+                    slug.into_owned()
+                } else {
+                    slug.trim_start_matches(replacement)
+                        .trim_end_matches(replacement)
+                        .to_string()
+                }
+            }
+            _ => slug::slugify(text),
         }
     }
 }
@@ -55,7 +179,15 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
 
         self.source.next().map(|mut annotated_event| {
             let (header_level, attrs) = match annotated_event.event {
+                Event::DocumentStart(..) => {
+                    // configuration errors are reported right after the start
+                    self.buffer.extend(self.errors.drain(..).map(Into::into));
+                    return annotated_event;
+                }
                 Event::StartTag(StartTagEvent { tag, ref mut attrs }) => {
+                    if let Some(ref id) = attrs.id {
+                        self.anchors.observe(id.as_str());
+                    }
                     if let Some(header_level) = tag.header_level() {
                         (header_level, attrs)
                     } else {
@@ -88,7 +220,15 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
                 }
             }
 
-            attrs.id = Some(slugify(raw_text).into());
+            let slug = self.slugify(&raw_text);
+            attrs.id = Some(match self.anchors.claim(&slug) {
+                Ok(id) => id.into(),
+                Err(id) => {
+                    self.buffer
+                        .push_back(AnchorRegistry::collision_error(&id).into());
+                    id.into()
+                }
+            });
 
             annotated_event
         })
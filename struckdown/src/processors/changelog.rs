@@ -0,0 +1,280 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use slug::slugify;
+
+use crate::anchors::{AnchorRegistry, CollisionPolicy};
+use crate::event::{AnnotatedEvent, Attrs, DirectiveEvent, Event, MetaDataEvent, Tag};
+use crate::plain::to_plain_text;
+use crate::processors::utils::{block_tag, parse_body, split_blocks, split_sections, strip_outer};
+use crate::value::to_value;
+
+lazy_static! {
+    static ref RELEASE_RE: Regex =
+        Regex::new(r"^\[?v?([^\]\s]+)\]?(?:\s*[-–—(]\s*([^)]*?)\)?)?\s*$").unwrap();
+    static ref KIND_PREFIX_RE: Regex = Regex::new(r"^\s*([A-Za-z ]+?)\s*:").unwrap();
+}
+
+/// Expands `{changelog}` directives into structured releases.
+///
+/// The body of the directive is parsed as markdown.  Every heading at the
+/// first heading level starts a release (`## 1.0.0 - 2020-12-24`) and lists
+/// below it hold the changes.  The kind of a change is taken from the
+/// nearest heading above the list (`### Fixed`) or from a prefix of the
+/// item itself (`- Breaking: removed the foo`) and normalized into one of
+/// `added`, `changed`, `deprecated`, `removed`, `fixed`, `security` or
+/// `breaking`.  Other content is kept as release notes.
+///
+/// The output is a `changelog` container holding a `release` container per
+/// release.  Changes are grouped into `changes` containers with a
+/// `data-kind` attribute.  Additionally a `changelog` meta data event with
+/// the releases as plain text is emitted.
+///
+/// Release containers get an id derived from the version (`release-1-0-0`),
+/// ids already in use are handled according to the [`CollisionPolicy`].
+///
+/// When applied this wraps the stream in a [`ChangelogIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Changelog {
+    /// The name of the directive.
+    pub directive_name: String,
+    /// The kind for changes that are not otherwise classified.
+    pub default_kind: String,
+    /// Controls if the releases should be emitted as meta data.
+    pub emit_metadata: bool,
+    /// What to do with ids that are already in use.
+    pub duplicates: CollisionPolicy,
+}
+
+impl Default for Changelog {
+    fn default() -> Changelog {
+        Changelog {
+            directive_name: "changelog".into(),
+            default_kind: "changed".into(),
+            emit_metadata: true,
+            duplicates: CollisionPolicy::Overwrite,
+        }
+    }
+}
+
+implement_processor!(Changelog, ChangelogIter);
+
+/// A release as emitted in the meta data.
+#[derive(Serialize, Debug)]
+pub struct Release {
+    /// The version of the release.
+    pub version: String,
+    /// The release date if given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    /// The changes as plain text grouped by kind.
+    pub changes: BTreeMap<String, Vec<String>>,
+}
+
+/// Normalizes the kind of a change.
+fn normalize_kind(kind: &str) -> Option<&'static str> {
+    Some(match kind.trim().to_lowercase().as_str() {
+        "added" | "add" | "new" | "features" | "feature" => "added",
+        "changed" | "change" | "changes" | "improvements" => "changed",
+        "deprecated" | "deprecation" | "deprecations" => "deprecated",
+        "removed" | "remove" | "removals" => "removed",
+        "fixed" | "fix" | "fixes" | "bug fixes" | "bugfixes" => "fixed",
+        "security" => "security",
+        "breaking" | "breaking change" | "breaking changes" => "breaking",
+        _ => return None,
+    })
+}
+
+fn container(
+    class: &'static str,
+    custom: Option<(&'static str, String)>,
+) -> AnnotatedEvent<'static> {
+    Tag::Container
+        .start_tag(Attrs {
+            class: Some(class.into()),
+            custom: custom.map(|(key, value)| {
+                let mut map = BTreeMap::new();
+                map.insert(key.into(), value.into());
+                map
+            }),
+            ..Attrs::default()
+        })
+        .into()
+}
+
+/// The iterator implementing [`Changelog`].
+pub struct ChangelogIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    anchors: AnchorRegistry,
+    options: Cow<'options, Changelog>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> ChangelogIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Changelog>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            anchors: AnchorRegistry::new(options.duplicates),
+            options,
+        }
+    }
+
+    fn expand_release(
+        &mut self,
+        title: Vec<AnnotatedEvent<'static>>,
+        body: Vec<AnnotatedEvent<'static>>,
+    ) -> Release {
+        let title_text = to_plain_text(title.iter()).as_str().trim().to_string();
+        let (version, date) = match RELEASE_RE.captures(&title_text) {
+            Some(m) => (
+                m[1].to_string(),
+                m.get(2)
+                    .map(|x| x.as_str().to_string())
+                    .filter(|x| !x.is_empty()),
+            ),
+            None => (title_text.clone(), None),
+        };
+
+        let mut notes = vec![];
+        let mut groups: Vec<(String, Vec<Vec<AnnotatedEvent<'static>>>)> = vec![];
+        let mut kind = self.options.default_kind.clone();
+
+        for block in split_blocks(body) {
+            match block_tag(&block) {
+                Some(tag) if tag.header_level().is_some() => {
+                    let text = to_plain_text(block.iter());
+                    kind = normalize_kind(text.as_str())
+                        .map(|x| x.to_string())
+                        .unwrap_or_else(|| slugify(text.as_str()));
+                }
+                Some(Tag::UnorderedList) | Some(Tag::OrderedList) => {
+                    for item in split_blocks(strip_outer(block)) {
+                        let item_kind = KIND_PREFIX_RE
+                            .captures(to_plain_text(item.iter()).as_str())
+                            .and_then(|m| normalize_kind(&m[1]))
+                            .map_or_else(|| kind.clone(), |x| x.to_string());
+                        match groups.iter_mut().find(|(kind, _)| *kind == item_kind) {
+                            Some((_, items)) => items.push(item),
+                            None => groups.push((item_kind, vec![item])),
+                        }
+                    }
+                }
+                _ => notes.extend(block),
+            }
+        }
+
+        let mut release = Release {
+            version: version.clone(),
+            date: date.clone(),
+            changes: BTreeMap::new(),
+        };
+
+        let id = match self.anchors.claim(&slugify(format!("release {}", version))) {
+            Ok(id) => id,
+            Err(id) => {
+                self.buffer
+                    .push_back(AnchorRegistry::collision_error(&id).into());
+                id
+            }
+        };
+        self.buffer.push_back(
+            Tag::Container
+                .start_tag(Attrs {
+                    id: Some(id.into()),
+                    class: Some("release".into()),
+                    custom: Some({
+                        let mut custom = BTreeMap::new();
+                        custom.insert("data-version".into(), version.into());
+                        if let Some(date) = date {
+                            custom.insert("data-date".into(), date.into());
+                        }
+                        custom
+                    }),
+                    ..Attrs::default()
+                })
+                .into(),
+        );
+        self.buffer.push_back(container("release-title", None));
+        self.buffer.extend(title);
+        self.buffer.push_back(Tag::Container.end_tag().into());
+        if !notes.is_empty() {
+            self.buffer.push_back(container("release-notes", None));
+            self.buffer.extend(notes);
+            self.buffer.push_back(Tag::Container.end_tag().into());
+        }
+        for (kind, items) in groups {
+            let texts = release.changes.entry(kind.clone()).or_default();
+            self.buffer
+                .push_back(container("changes", Some(("data-kind", kind.clone()))));
+            self.buffer
+                .push_back(Tag::UnorderedList.start_tag(Attrs::default()).into());
+            for item in items {
+                texts.push(to_plain_text(item.iter()).as_str().trim().to_string());
+                self.buffer.extend(item);
+            }
+            self.buffer.push_back(Tag::UnorderedList.end_tag().into());
+            self.buffer.push_back(Tag::Container.end_tag().into());
+        }
+        self.buffer.push_back(Tag::Container.end_tag().into());
+
+        release
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for ChangelogIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        let body = match annotated_event.event {
+            Event::Directive(DirectiveEvent {
+                ref name,
+                ref body,
+                ref body_location,
+                ..
+            }) if name.as_str() == self.options.directive_name => {
+                parse_body(body.as_str(), body_location.as_ref())
+            }
+            _ => {
+                self.anchors.observe_event(&annotated_event.event);
+                return Some(annotated_event);
+            }
+        };
+
+        let mut releases = vec![];
+        for section in split_sections(body) {
+            if section.title.is_empty() {
+                self.buffer.extend(section.body);
+            } else {
+                releases.push(self.expand_release(section.title, section.body));
+            }
+        }
+        self.buffer.push_back(Tag::Container.end_tag().into());
+
+        if self.options.emit_metadata {
+            self.buffer.push_back(
+                MetaDataEvent {
+                    key: "changelog".into(),
+                    value: to_value(&releases).expect("bad changelog"),
+                }
+                .into(),
+            );
+        }
+
+        let mut start = container("changelog", None);
+        start.location = annotated_event.location;
+        Some(start)
+    }
+}
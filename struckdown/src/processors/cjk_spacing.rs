@@ -0,0 +1,104 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, TextEvent};
+
+/// Inserts spacing between CJK and latin characters.
+///
+/// This is commonly known as "pangu" spacing.  Only text events are
+/// modified so code and directive bodies are never touched.  Spacing is
+/// also inserted between two consecutive text events.
+///
+/// When applied this wraps the stream in a [`CjkSpacingIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CjkSpacing {
+    /// The space to insert.  Defaults to a thin space (U+2009).
+    pub space: String,
+}
+
+impl Default for CjkSpacing {
+    fn default() -> CjkSpacing {
+        CjkSpacing {
+            space: "\u{2009}".into(),
+        }
+    }
+}
+
+implement_processor!(CjkSpacing, CjkSpacingIter);
+
+/// Checks if a character is a CJK character.
+pub(crate) fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{2e80}'..='\u{2fdf}'
+        | '\u{3040}'..='\u{30ff}'
+        | '\u{3100}'..='\u{312f}'
+        | '\u{3200}'..='\u{32ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{20000}'..='\u{2fa1f}'
+    )
+}
+
+fn needs_space(a: char, b: char) -> bool {
+    (is_cjk(a) && b.is_ascii_alphanumeric()) || (a.is_ascii_alphanumeric() && is_cjk(b))
+}
+
+/// The iterator implementing [`CjkSpacing`].
+pub struct CjkSpacingIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    last_char: Option<char>,
+    options: Cow<'options, CjkSpacing>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    CjkSpacingIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, CjkSpacing>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            last_char: None,
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for CjkSpacingIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut annotated_event = self.source.next()?;
+
+        if let Event::Text(TextEvent { ref mut text }) = annotated_event.event {
+            let mut last_char = self.last_char;
+            let mut rv = None::<String>;
+            for (idx, c) in text.as_str().char_indices() {
+                if let Some(last_char) = last_char {
+                    if needs_space(last_char, c) {
+                        let buf = rv.get_or_insert_with(String::new);
+                        if buf.is_empty() {
+                            buf.push_str(&text.as_str()[..idx]);
+                        }
+                        buf.push_str(&self.options.space);
+                    }
+                }
+                if let Some(ref mut buf) = rv {
+                    buf.push(c);
+                }
+                last_char = Some(c);
+            }
+            self.last_char = last_char;
+            if let Some(rv) = rv {
+                *text = rv.into();
+            }
+        } else {
+            self.last_char = None;
+        }
+
+        Some(annotated_event)
+    }
+}
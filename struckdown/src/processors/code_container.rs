@@ -0,0 +1,127 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, CodeBlockEvent, Event, Str, Tag};
+use crate::theme::CODE_BLOCK;
+
+/// Wraps code blocks in a container carrying their meta data.
+///
+/// Every code block is wrapped in a [`Tag::Container`] with data attributes
+/// so that renderers and themes can uniformly attach copy buttons or source
+/// links.  The following attributes are emitted:
+///
+/// * `data-language`: the language of the code block
+/// * `data-title`: the `title` (or `filename`) argument of the code block
+/// * `data-copyable`: `true` unless disabled or the code block has a
+///   `copy=false` argument
+/// * `data-permalink`: the `permalink` argument of the code block
+///
+/// When applied this wraps the stream in a [`CodeContainerIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct CodeContainer {
+    /// The class that should be added to the container.
+    pub class_name: Option<String>,
+    /// Marks code blocks as copyable unless overridden per block.
+    pub copyable: bool,
+}
+
+impl Default for CodeContainer {
+    fn default() -> CodeContainer {
+        CodeContainer {
+            class_name: Some(CODE_BLOCK.into()),
+            copyable: true,
+        }
+    }
+}
+
+implement_processor!(CodeContainer, CodeContainerIter);
+
+fn get_arg<'a, 'data>(
+    args: &'a Option<BTreeMap<Str<'data>, Str<'data>>>,
+    key: &str,
+) -> Option<&'a Str<'data>> {
+    args.as_ref()?
+        .iter()
+        .find(|(k, _)| k.as_str() == key)
+        .map(|(_, v)| v)
+}
+
+/// The iterator implementing [`CodeContainer`].
+pub struct CodeContainerIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, CodeContainer>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    CodeContainerIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, CodeContainer>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for CodeContainerIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        let mut custom = BTreeMap::new();
+        if let Event::CodeBlock(CodeBlockEvent {
+            ref language,
+            ref args,
+            ..
+        }) = annotated_event.event
+        {
+            if let Some(language) = language {
+                custom.insert("data-language".into(), language.clone());
+            }
+            if let Some(title) = get_arg(args, "title").or_else(|| get_arg(args, "filename")) {
+                custom.insert("data-title".into(), title.clone());
+            }
+            let copyable = match get_arg(args, "copy").map(|x| x.as_str()) {
+                Some("false") | Some("no") | Some("0") => false,
+                Some(_) => true,
+                None => self.options.copyable,
+            };
+            custom.insert(
+                "data-copyable".into(),
+                Str::new(if copyable { "true" } else { "false" }),
+            );
+            if let Some(permalink) = get_arg(args, "permalink") {
+                custom.insert("data-permalink".into(), permalink.clone());
+            }
+        } else {
+            return Some(annotated_event);
+        }
+
+        let location = annotated_event.location.clone();
+        self.buffer.push_back(annotated_event);
+        self.buffer.push_back(Tag::Container.end_tag().into());
+        Some(AnnotatedEvent::new(
+            Tag::Container.start_tag(Attrs {
+                class: self
+                    .options
+                    .class_name
+                    .as_ref()
+                    .map(|x| x.to_string().into()),
+                custom: Some(custom),
+                ..Attrs::default()
+            }),
+            location,
+        ))
+    }
+}
@@ -0,0 +1,251 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, DirectiveEvent, ErrorEvent, Event, Location};
+use crate::parser::ParserOptions;
+use crate::processors::utils::parse_body_with_options;
+
+/// The maximum nesting of directives expanded from directive bodies.
+const MAX_DEPTH: usize = 32;
+
+/// A function expanding a directive into events.
+pub type DirectiveHandler = Arc<
+    dyn Fn(&DirectiveEvent<'_>, &DirectiveContext<'_>) -> Vec<AnnotatedEvent<'static>>
+        + Send
+        + Sync
+        + 'static,
+>;
+
+/// Gives directive handlers access to the processor expanding them.
+pub struct DirectiveContext<'a> {
+    processor: &'a DirectiveProcessor,
+    depth: usize,
+}
+
+impl<'a> DirectiveContext<'a> {
+    /// Parses a directive body as struckdown.
+    ///
+    /// Directives within the body are expanded recursively.  The returned
+    /// events carry no locations as they would point into the body rather
+    /// than the document.
+    pub fn parse_body(&self, body: &str) -> Vec<AnnotatedEvent<'static>> {
+        self.parse_mapped(body, None)
+    }
+
+    /// Parses the body of a directive as struckdown.
+    ///
+    /// Like [`parse_body`](Self::parse_body) but if the location of the
+    /// body is known the returned events carry locations in the document.
+    pub fn parse_directive_body(
+        &self,
+        directive: &DirectiveEvent<'_>,
+    ) -> Vec<AnnotatedEvent<'static>> {
+        self.parse_mapped(directive.body.as_str(), directive.body_location.as_ref())
+    }
+
+    fn parse_mapped(
+        &self,
+        body: &str,
+        body_location: Option<&Location>,
+    ) -> Vec<AnnotatedEvent<'static>> {
+        if self.depth >= MAX_DEPTH {
+            return vec![ErrorEvent {
+                title: "Directives nested too deeply".into(),
+                description: None,
+                code: Some(Code::DirectiveNestingTooDeep),
+            }
+            .into()];
+        }
+        let events = parse_body_with_options(body, body_location, &self.processor.parser_options);
+        DirectiveProcessorIter::new(events.into_iter(), Cow::Borrowed(self.processor))
+            .with_depth(self.depth + 1)
+            .collect()
+    }
+}
+
+/// Expands directives with registered directive handlers.
+///
+/// Every [`DirectiveEvent`] whose name has a handler registered with
+/// [`register_directive`](Self::register_directive) is replaced by the
+/// events the handler returns.  Handlers receive the directive with its
+/// argument, front matter and body and a [`DirectiveContext`] to parse the
+/// body.  Parsing it with
+/// [`parse_directive_body`](DirectiveContext::parse_directive_body) keeps
+/// the locations of its events pointing into the document:
+///
+/// ```
+/// use struckdown::event::{Attrs, Tag};
+/// use struckdown::processors::DirectiveProcessor;
+///
+/// let mut directives = DirectiveProcessor::new();
+/// directives.register_directive("note", |directive, ctx| {
+///     let attrs = Attrs {
+///         class: Some("note".into()),
+///         ..Default::default()
+///     };
+///     let mut events = vec![Tag::Container.start_tag(attrs).into()];
+///     events.extend(ctx.parse_directive_body(directive));
+///     events.push(Tag::Container.end_tag().into());
+///     events
+/// });
+/// ```
+///
+/// Unlike the built-in processors the handlers cannot be configured from
+/// front matter, so this processor is added to a pipeline in code.
+/// Directives without a handler are passed through unchanged.
+///
+/// When applied this wraps the stream in a [`DirectiveProcessorIter`].
+#[derive(Clone, Default)]
+pub struct DirectiveProcessor {
+    directives: BTreeMap<String, DirectiveHandler>,
+    parser_options: ParserOptions,
+}
+
+impl fmt::Debug for DirectiveProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DirectiveProcessor")
+            .field("directives", &self.directives.keys().collect::<Vec<_>>())
+            .field("parser_options", &self.parser_options)
+            .finish()
+    }
+}
+
+implement_processor!(DirectiveProcessor, DirectiveProcessorIter);
+
+impl DirectiveProcessor {
+    /// Creates a processor without any directives.
+    pub fn new() -> DirectiveProcessor {
+        DirectiveProcessor::default()
+    }
+
+    /// Registers a handler for a directive.
+    ///
+    /// A handler registered earlier for the same directive is replaced.
+    pub fn register_directive<F>(&mut self, name: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&DirectiveEvent<'_>, &DirectiveContext<'_>) -> Vec<AnnotatedEvent<'static>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.directives.insert(name.to_string(), Arc::new(handler));
+        self
+    }
+
+    /// Changes the options used to parse directive bodies.
+    ///
+    /// Front matter is always disabled for bodies.
+    pub fn set_parser_options(&mut self, parser_options: &ParserOptions) -> &mut Self {
+        self.parser_options = parser_options.clone();
+        self
+    }
+
+    /// Returns the handler of a directive.
+    pub fn get_directive(&self, name: &str) -> Option<&DirectiveHandler> {
+        self.directives.get(name)
+    }
+}
+
+/// The iterator implementing [`DirectiveProcessor`].
+pub struct DirectiveProcessorIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    depth: usize,
+    options: Cow<'options, DirectiveProcessor>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    DirectiveProcessorIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, DirectiveProcessor>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            depth: 0,
+            options: options.into(),
+        }
+    }
+
+    fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for DirectiveProcessorIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+
+            let annotated_event = self.source.next()?;
+            let directive = match annotated_event.event {
+                Event::Directive(ref directive) => directive,
+                _ => return Some(annotated_event),
+            };
+            let handler = match self.options.get_directive(directive.name.as_str()) {
+                Some(handler) => handler,
+                None => return Some(annotated_event),
+            };
+
+            let ctx = DirectiveContext {
+                processor: &self.options,
+                depth: self.depth,
+            };
+            let mut events = handler(directive, &ctx);
+            // the first event without a location points to the directive
+            if let Some(first) = events.first_mut() {
+                if first.location.is_none() {
+                    first.location = annotated_event.location.clone();
+                }
+            }
+            self.buffer.extend(events);
+        }
+    }
+}
+
+#[test]
+fn test_directive_processor() {
+    use crate::event::{Attrs, Tag, TextEvent};
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut directives = DirectiveProcessor::new();
+    directives.register_directive("note", |directive, ctx| {
+        let attrs = Attrs {
+            class: Some("note".into()),
+            ..Default::default()
+        };
+        let mut events = vec![Tag::Container.start_tag(attrs).into()];
+        if let Some(ref argument) = directive.argument {
+            events.push(Tag::Strong.start_tag(Default::default()).into());
+            events.push(
+                TextEvent {
+                    text: argument.clone().into_owned(),
+                }
+                .into(),
+            );
+            events.push(Tag::Strong.end_tag().into());
+        }
+        events.extend(ctx.parse_directive_body(directive));
+        events.push(Tag::Container.end_tag().into());
+        events
+    });
+
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(directives);
+    insta::assert_snapshot!(to_html(
+        pipeline.process(
+            "````{note} Outer\nSome *text*.\n\n```{note}\nNested\n```\n````\n\n```{other}\nkept\n```\n"
+        ),
+        &Default::default()
+    ));
+}
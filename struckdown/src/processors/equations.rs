@@ -0,0 +1,234 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::anchors::{AnchorRegistry, CollisionPolicy};
+use crate::codes::Code;
+use crate::event::{
+    AnnotatedEvent, Attrs, CodeBlockEvent, ErrorEvent, Event, InterpretedTextEvent, MathBlockEvent,
+    Tag, TextEvent,
+};
+
+/// Numbers display equations and resolves references to them.
+///
+/// Math blocks (`` ```math `` or a paragraph of `$$...$$`) are display
+/// equations, as are code blocks in the configured `language` if math is
+/// disabled in the parser.  They are numbered in document order and
+/// wrapped in a container with the class `equation` followed by a span
+/// with the class `equation-number` holding the number (`(3)`).  A
+/// `label` argument (`` ```math label=euler ``) gives the container the id
+/// `eq-euler` so it can be referenced with `` {eq}`euler` ``, which becomes
+/// a link with the number as text.
+/// Equations with a `nonumber` argument are left alone.
+///
+/// If `number_all` is disabled only labelled equations are numbered.
+/// References to unknown labels emit an error.  Ids already in use are
+/// handled according to the [`CollisionPolicy`], references to a label used
+/// more than once point to the last equation with the `overwrite` policy
+/// and to the first one otherwise.
+///
+/// When applied this wraps the stream in a [`EquationsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Equations {
+    /// The language of code blocks holding equations.
+    ///
+    /// Code blocks only hold equations if the parser does not turn them
+    /// into math blocks.
+    pub language: String,
+    /// The name of the role that references equations.
+    pub role_name: String,
+    /// The prefix for the ids of labelled equations.
+    pub id_prefix: String,
+    /// Controls if unlabelled equations are numbered.
+    pub number_all: bool,
+    /// What to do with ids that are already in use.
+    pub duplicates: CollisionPolicy,
+}
+
+impl Default for Equations {
+    fn default() -> Equations {
+        Equations {
+            language: "math".into(),
+            role_name: "eq".into(),
+            id_prefix: "eq-".into(),
+            number_all: true,
+            duplicates: CollisionPolicy::Overwrite,
+        }
+    }
+}
+
+implement_processor!(Equations, EquationsIter);
+
+/// The iterator implementing [`Equations`].
+pub struct EquationsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: Option<I>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Equations>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> EquationsIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Equations>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: Some(iterator),
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    /// Returns the label of an equation or `None` if it's not an equation.
+    fn equation_label(&self, event: &Event<'data>) -> Option<Option<String>> {
+        let args = match event {
+            Event::MathBlock(MathBlockEvent { args, .. }) => args,
+            Event::CodeBlock(CodeBlockEvent {
+                language: Some(language),
+                args,
+                ..
+            }) if language.as_str() == self.options.language => args,
+            _ => return None,
+        };
+        let arg = |name: &str| {
+            args.as_ref()?
+                .iter()
+                .find(|(k, _)| k.as_str() == name)
+                .map(|(_, v)| v)
+        };
+        if arg("nonumber").is_some() {
+            return None;
+        }
+        let label = arg("label").map(|x| x.as_str().to_string());
+        if label.is_none() && !self.options.number_all {
+            return None;
+        }
+        Some(label)
+    }
+
+    fn process(&mut self, iter: I) {
+        let events: Vec<_> = iter.collect();
+        let mut anchors = AnchorRegistry::new(self.options.duplicates);
+        for annotated_event in &events {
+            anchors.observe_event(&annotated_event.event);
+        }
+
+        // the ids of labelled equations in document order
+        let mut ids = VecDeque::new();
+        let mut labels = HashMap::new();
+        let mut number = 0;
+        for annotated_event in &events {
+            if let Some(label) = self.equation_label(&annotated_event.event) {
+                number += 1;
+                if let Some(label) = label {
+                    let id = anchors.claim(&format!("{}{}", self.options.id_prefix, label));
+                    let target = match id {
+                        Ok(ref id) | Err(ref id) => (id.clone(), number),
+                    };
+                    if self.options.duplicates == CollisionPolicy::Overwrite {
+                        labels.insert(label, target);
+                    } else {
+                        labels.entry(label).or_insert(target);
+                    }
+                    ids.push_back(id);
+                }
+            }
+        }
+
+        number = 0;
+        for annotated_event in events {
+            if let Some(label) = self.equation_label(&annotated_event.event) {
+                number += 1;
+                let id = match label.and_then(|_| ids.pop_front()) {
+                    Some(Ok(id)) => Some(id),
+                    Some(Err(id)) => {
+                        self.buffer.push_back(AnnotatedEvent::new(
+                            AnchorRegistry::collision_error(&id),
+                            annotated_event.location.clone(),
+                        ));
+                        Some(id)
+                    }
+                    None => None,
+                };
+                self.buffer.push_back(AnnotatedEvent::new(
+                    Tag::Container.start_tag(Attrs {
+                        id: id.map(Into::into),
+                        class: Some("equation".into()),
+                        ..Attrs::default()
+                    }),
+                    annotated_event.location.clone(),
+                ));
+                self.buffer.push_back(annotated_event);
+                self.buffer.push_back(
+                    Tag::Span
+                        .start_tag(Attrs {
+                            class: Some("equation-number".into()),
+                            ..Attrs::default()
+                        })
+                        .into(),
+                );
+                self.buffer.push_back(
+                    TextEvent {
+                        text: format!("({})", number).into(),
+                    }
+                    .into(),
+                );
+                self.buffer.push_back(Tag::Span.end_tag().into());
+                self.buffer.push_back(Tag::Container.end_tag().into());
+                continue;
+            }
+
+            let label = match annotated_event.event {
+                Event::InterpretedText(InterpretedTextEvent {
+                    ref role, ref text, ..
+                }) if role.as_str() == self.options.role_name => text.as_str().trim().to_string(),
+                _ => {
+                    self.buffer.push_back(annotated_event);
+                    continue;
+                }
+            };
+            match labels.get(&label) {
+                Some((id, number)) => {
+                    self.buffer.push_back(AnnotatedEvent::new(
+                        Tag::Link.start_tag(Attrs {
+                            target: Some(format!("#{}", id).into()),
+                            class: Some("equation-ref".into()),
+                            ..Attrs::default()
+                        }),
+                        annotated_event.location,
+                    ));
+                    self.buffer.push_back(
+                        TextEvent {
+                            text: format!("({})", number).into(),
+                        }
+                        .into(),
+                    );
+                    self.buffer.push_back(Tag::Link.end_tag().into());
+                }
+                None => {
+                    self.buffer.push_back(AnnotatedEvent::new(
+                        ErrorEvent {
+                            title: format!("Unknown equation '{}'", label).into(),
+                            description: None,
+                            code: Some(Code::UnknownEquation),
+                        },
+                        annotated_event.location,
+                    ));
+                    self.buffer
+                        .push_back(TextEvent { text: "(?)".into() }.into());
+                }
+            }
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for EquationsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(iter) = self.source.take() {
+            self.process(iter);
+        }
+        self.buffer.pop_front()
+    }
+}
@@ -1,18 +1,58 @@
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt::Display;
+use std::io;
 use std::path::PathBuf;
 use std::process::Stdio;
+use std::time::Duration;
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use tokio::io::BufReader;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
-use tokio::process::{ChildStdin, ChildStdout, Command};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
 use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, timeout, timeout_at, Instant};
 
+use crate::codes::Code;
 use crate::event::{AnnotatedEvent, ErrorEvent};
+use crate::protocol::{Hello, PROTOCOL_VERSION};
 
-/// Passes a JSON serialized stream through an external program.
+/// The encoding of events exchanged with an external program.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WireFormat {
+    /// One JSON value per line (see [`json`](crate::json)).
+    #[default]
+    Json,
+    /// A stream of MessagePack values with structs encoded as maps.
+    ///
+    /// This is considerably faster to encode and decode for large
+    /// documents.
+    MessagePack,
+}
+
+/// Passes a serialized stream through an external program.
+///
+/// Events are written to the program's stdin and read back from its stdout
+/// in the configured `format`.  Both directions are streamed: an event is
+/// only pulled from the source once the program accepted the previous one
+/// and events are passed on as soon as the program emits them.  Neither
+/// side has to hold the whole document in memory and the program can start
+/// working right away.
+///
+/// If the program fails (it cannot be started, exits with an error, emits
+/// invalid events or exceeds `timeout_ms`) an error is emitted which holds
+/// what the program wrote to stderr.  What happens with the document is
+/// controlled by `on_error`.  With `passthrough` and `drop` the output of
+/// the program is held back until it finished.
+///
+/// The messages exchanged with the program are specified by the
+/// [`protocol`](crate::protocol) module.  With `handshake` enabled the
+/// protocol version is negotiated before any events are sent.
+///
+/// The program is subject to the installed [`Sandbox`](crate::sandbox::Sandbox).
 ///
 /// When applied this wraps the stream in a [`ExternalIter`].
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -27,6 +67,31 @@ pub struct External {
     pub env: BTreeMap<String, String>,
     /// An optional working directory.
     pub cwd: Option<PathBuf>,
+    /// The encoding of the events.
+    #[serde(default)]
+    pub format: WireFormat,
+    /// The time in milliseconds after which the program is killed.
+    pub timeout_ms: Option<u64>,
+    /// What happens with the document when the program fails.
+    #[serde(default)]
+    pub on_error: OnError,
+    /// Exchanges protocol versions with the program before the events.
+    #[serde(default)]
+    pub handshake: bool,
+}
+
+/// What an [`External`] processor does when the program fails.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// The stream ends with the error.  Events the program emitted before
+    /// are kept.
+    #[default]
+    Fail,
+    /// The error is followed by the unprocessed events.
+    Passthrough,
+    /// The output of the program is dropped and only the error is emitted.
+    Drop,
 }
 
 implement_processor!(External, ExternalIter);
@@ -35,6 +100,7 @@ implement_processor!(External, ExternalIter);
 enum State {
     Initial,
     Communicating,
+    Passthrough,
     Done,
 }
 
@@ -42,9 +108,20 @@ enum State {
 pub struct ExternalIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
     source: I,
     state: State,
+    child: Option<Child>,
     stdin: Option<ChildStdin>,
     stdout: Option<BufReader<ChildStdout>>,
+    stderr: Option<JoinHandle<Vec<u8>>>,
+    deadline: Option<Instant>,
     buffered_event: Option<Vec<u8>>,
+    // bytes read from the program but not decoded yet
+    read_buffer: Vec<u8>,
+    // events sent to the program, kept for passing them through on errors
+    sent: Vec<AnnotatedEvent<'data>>,
+    // events the program emitted that are held back until it finished
+    held: Vec<AnnotatedEvent<'data>>,
+    // events that are emitted before continuing
+    pending: VecDeque<AnnotatedEvent<'data>>,
     options: Cow<'options, External>,
     rt: Option<Runtime>,
 }
@@ -58,23 +135,251 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> ExternalIter<'d
         Self {
             source: iterator,
             state: State::Initial,
+            child: None,
             stdin: None,
             stdout: None,
+            stderr: None,
+            deadline: None,
             buffered_event: None,
+            read_buffer: Vec::new(),
+            sent: Vec::new(),
+            held: Vec::new(),
+            pending: VecDeque::new(),
             options: options.into(),
             rt: Some(rt),
         }
     }
+
+    /// Returns `true` if the output of the program is held back.
+    fn holds_output(&self) -> bool {
+        self.options.on_error != OnError::Fail
+    }
+
+    /// Sends our protocol version and checks the one of the program.
+    fn handshake(&mut self) -> Result<(), String> {
+        let format = self.options.format;
+        let deadline = self.deadline;
+        let hello = encode_message(&Hello::default(), format);
+        let stdin = &mut self.stdin;
+        let stdout = &mut self.stdout;
+        let buffer = &mut self.read_buffer;
+        let reply = self.rt.as_ref().unwrap().block_on(async {
+            let exchange = async {
+                // a program that exited right away might still have replied,
+                // so failed writes show up as a missing reply
+                if let Some(stdin) = stdin {
+                    stdin.write_all(&hello).await.ok();
+                }
+                let stdout = stdout.as_mut().ok_or("stdout of the program is closed")?;
+                read_message::<Hello>(stdout, format, buffer)
+                    .await
+                    .map_err(|err| format!("invalid handshake: {}", err))?
+                    .ok_or_else(|| "program exited before the handshake".to_string())
+            };
+            match deadline {
+                Some(deadline) => timeout_at(deadline, exchange)
+                    .await
+                    .unwrap_or_else(|_| Err("timed out".into())),
+                None => exchange.await,
+            }
+        })?;
+        if reply.protocol.is_compatible_with(&PROTOCOL_VERSION) {
+            Ok(())
+        } else {
+            Err(format!(
+                "program speaks protocol version {}, expected {}.x",
+                reply.protocol, PROTOCOL_VERSION.major
+            ))
+        }
+    }
+
+    /// Stops the program and emits the error according to the policy.
+    fn fail<D: Display>(&mut self, err: &D) {
+        let rt = self.rt.as_ref().unwrap();
+        let mut child = self.child.take();
+        let stderr = self.stderr.take();
+        self.stdin.take();
+        self.stdout.take();
+        let stderr = rt.block_on(async {
+            if let Some(ref mut child) = child {
+                child.start_kill().ok();
+            }
+            match stderr {
+                Some(stderr) => timeout(Duration::from_millis(500), stderr)
+                    .await
+                    .ok()
+                    .and_then(Result::ok)
+                    .unwrap_or_default(),
+                None => Vec::new(),
+            }
+        });
+
+        let mut error = error_event(err, &self.options);
+        let stderr = String::from_utf8_lossy(&stderr);
+        if !stderr.trim().is_empty() {
+            error.description = Some(
+                format!(
+                    "{}\n\nstderr:\n{}",
+                    error.description.as_ref().map_or("", |x| x.as_str()),
+                    stderr.trim_end()
+                )
+                .into(),
+            );
+        }
+        self.held.clear();
+        self.pending.push_back(error.into());
+        if self.options.on_error == OnError::Passthrough {
+            self.pending.extend(self.sent.drain(..));
+            self.state = State::Passthrough;
+        } else {
+            self.state = State::Done;
+        }
+    }
+
+    /// Waits for the program to exit after it closed its stdout.
+    fn finish(&mut self) {
+        self.stdin.take();
+        self.stdout.take();
+        let mut child = match self.child.take() {
+            Some(child) => child,
+            None => return,
+        };
+        let deadline = self.deadline;
+        let status = self.rt.as_ref().unwrap().block_on(async {
+            match deadline {
+                Some(deadline) => timeout_at(deadline, child.wait()).await.ok(),
+                None => Some(child.wait().await),
+            }
+        });
+        match status {
+            None => {
+                self.child = Some(child);
+                self.fail(&"timed out");
+            }
+            Some(Err(err)) => self.fail(&err),
+            Some(Ok(status)) if !status.success() => {
+                self.fail(&format!("program exited with {}", status))
+            }
+            Some(Ok(_)) => {
+                self.sent.clear();
+                self.pending.extend(self.held.drain(..));
+                self.state = State::Done;
+            }
+        }
+    }
+}
+
+/// Returns the title of the errors emitted when the program fails.
+fn failure_title(options: &External) -> String {
+    format!(
+        "Failed to execute external processor '{}')",
+        options.cmd.display()
+    )
 }
 
 fn error_event<D: Display>(err: &D, options: &External) -> ErrorEvent<'static> {
     ErrorEvent {
-        title: format!(
-            "Failed to execute external processor '{}')",
-            options.cmd.display()
-        )
-        .into(),
+        title: failure_title(options).into(),
         description: Some(err.to_string().into()),
+        code: Some(Code::ExternalProcessorFailure),
+    }
+}
+
+fn encode_message<T: Serialize>(message: &T, format: WireFormat) -> Vec<u8> {
+    match format {
+        WireFormat::Json => {
+            let mut rv = serde_json::to_vec(message)
+                .expect("Serializing events to external processors should never fail");
+            rv.push(b'\n');
+            rv
+        }
+        WireFormat::MessagePack => rmp_serde::to_vec_named(message)
+            .expect("Serializing events to external processors should never fail"),
+    }
+}
+
+/// Decodes the first MessagePack value from the buffer if it is complete.
+fn decode_message_pack<T: DeserializeOwned>(buffer: &mut Vec<u8>) -> Result<Option<T>, String> {
+    if buffer.is_empty() {
+        return Ok(None);
+    }
+    let mut cursor = io::Cursor::new(&buffer[..]);
+    match rmp_serde::from_read(&mut cursor) {
+        Ok(event) => {
+            let consumed = cursor.position() as usize;
+            buffer.drain(..consumed);
+            Ok(Some(event))
+        }
+        Err(rmp_serde::decode::Error::InvalidMarkerRead(ref err))
+        | Err(rmp_serde::decode::Error::InvalidDataRead(ref err))
+            if err.kind() == io::ErrorKind::UnexpectedEof =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+/// Decodes the first JSON line from the buffer if it is complete.
+///
+/// At the end of the stream a last line without newline is decoded too.
+/// Blank lines are skipped.
+fn decode_json_line<T: DeserializeOwned>(
+    buffer: &mut Vec<u8>,
+    eof: bool,
+) -> Result<Option<T>, String> {
+    loop {
+        let end = match buffer.iter().position(|&b| b == b'\n') {
+            Some(pos) => pos + 1,
+            None if eof && !buffer.is_empty() => buffer.len(),
+            None => return Ok(None),
+        };
+        let line: Vec<u8> = buffer.drain(..end).collect();
+        if !line.iter().all(u8::is_ascii_whitespace) {
+            return serde_json::from_slice(&line)
+                .map(Some)
+                .map_err(|err| err.to_string());
+        }
+    }
+}
+
+/// Reads the next message from the program.
+///
+/// Returns `None` once the program closed its stdout.  Partially read
+/// messages are kept in the buffer so that this can be cancelled at any
+/// point without losing data.
+async fn read_message<T: DeserializeOwned>(
+    stdout: &mut BufReader<ChildStdout>,
+    format: WireFormat,
+    buffer: &mut Vec<u8>,
+) -> Result<Option<T>, String> {
+    // large JSON events span many chunks, only look for a line once a
+    // newline came in.
+    let mut complete = true;
+    loop {
+        if complete {
+            let event = match format {
+                WireFormat::Json => decode_json_line(buffer, false)?,
+                WireFormat::MessagePack => decode_message_pack(buffer)?,
+            };
+            if event.is_some() {
+                return Ok(event);
+            }
+        }
+        let mut chunk = [0; 8192];
+        match stdout.read(&mut chunk).await {
+            Ok(0) | Err(_) if buffer.is_empty() => return Ok(None),
+            Ok(0) | Err(_) => {
+                return match format {
+                    WireFormat::Json => decode_json_line(buffer, true),
+                    WireFormat::MessagePack => Err("unexpected end of MessagePack stream".into()),
+                }
+            }
+            Ok(read) => {
+                complete = format != WireFormat::Json || chunk[..read].contains(&b'\n');
+                buffer.extend_from_slice(&chunk[..read]);
+            }
+        }
     }
 }
 
@@ -85,48 +390,75 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
+            if let Some(annotated_event) = self.pending.pop_front() {
+                return Some(annotated_event);
+            }
             match self.state {
                 State::Done => return None,
+                State::Passthrough => return self.source.next(),
                 State::Initial => {
+                    self.state = State::Communicating;
+                    let sandbox = crate::sandbox::current();
+                    let prepared = sandbox
+                        .resolve_command(&self.options.cmd)
+                        .and_then(|program| Ok((program, sandbox.command_env(&self.options.env)?)));
+                    let (program, (clear_env, env)) = match prepared {
+                        Ok(prepared) => prepared,
+                        Err(err) => {
+                            self.fail(&err);
+                            continue;
+                        }
+                    };
                     let rt = self.rt.take().unwrap();
-                    let mut error = None;
-                    rt.block_on(async {
-                        let mut cmd = Command::new(&self.options.cmd);
+                    let spawned = rt.block_on(async {
+                        let mut cmd = Command::new(&program);
+                        if clear_env {
+                            cmd.env_clear();
+                        }
                         cmd.args(&self.options.args)
                             .stdin(Stdio::piped())
                             .stdout(Stdio::piped())
-                            .envs(&self.options.env);
+                            .stderr(Stdio::piped())
+                            .kill_on_drop(true)
+                            .envs(env);
                         if let Some(ref cwd) = self.options.cwd {
                             cmd.current_dir(cwd);
                         }
-                        match cmd.spawn() {
-                            Ok(mut process) => {
-                                self.stdin = process.stdin.take();
-                                self.stdout = process.stdout.take().map(BufReader::new);
-                            }
-                            Err(ref err) => {
-                                error = Some(error_event(err, &self.options));
-                            }
-                        };
+                        cmd.spawn().map(|mut process| {
+                            self.stdin = process.stdin.take();
+                            self.stdout = process.stdout.take().map(BufReader::new);
+                            // stderr is read in the background so the program
+                            // never blocks on it
+                            self.stderr = process.stderr.take().map(|mut stderr| {
+                                tokio::spawn(async move {
+                                    let mut rv = Vec::new();
+                                    stderr.read_to_end(&mut rv).await.ok();
+                                    rv
+                                })
+                            });
+                            self.deadline = self
+                                .options
+                                .timeout_ms
+                                .map(|ms| Instant::now() + Duration::from_millis(ms));
+                            self.child = Some(process);
+                        })
                     });
-
-                    if let Some(error) = error {
-                        self.state = State::Done;
-                        return Some(error.into());
-                    } else {
-                        self.state = State::Communicating;
-                        self.rt = Some(rt);
-                        continue;
+                    self.rt = Some(rt);
+                    if let Err(ref err) = spawned {
+                        self.fail(err);
+                    } else if self.options.handshake {
+                        if let Err(err) = self.handshake() {
+                            self.fail(&err);
+                        }
                     }
                 }
                 State::Communicating => {
                     if self.buffered_event.is_none() {
                         if let Some(event) = self.source.next() {
-                            let mut serialized_event = serde_json::to_vec(&event).expect(
-                                "Serializing events to external processors should never fail",
-                            );
-                            serialized_event.push(b'\n');
-                            self.buffered_event = Some(serialized_event);
+                            self.buffered_event = Some(encode_message(&event, self.options.format));
+                            if self.options.on_error == OnError::Passthrough {
+                                self.sent.push(event);
+                            }
                         } else {
                             // close stdin if we're done writing.
                             self.stdin.take();
@@ -136,66 +468,231 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
                     let mut stdout = self.stdout.take().unwrap();
                     let mut rv = None;
                     let mut done = false;
+                    let mut failure = None;
                     let rt = self.rt.take().unwrap();
+                    let mut read_buffer = std::mem::take(&mut self.read_buffer);
+                    let deadline = self.deadline;
 
                     rt.block_on(async {
-                        let mut line = String::new();
+                        let format = self.options.format;
                         let should_write = self.buffered_event.is_some();
                         let write_task = async {
                             if let (Some(ref mut stdin), Some(ref buffered_event)) =
                                 (&mut stdin, &self.buffered_event)
                             {
-                                stdin.write(buffered_event).await.is_err()
+                                stdin.write(buffered_event).await
                             } else {
-                                false
+                                Ok(0)
                             }
                         };
 
-                        let should_read = rv.is_none();
-                        if should_read {
-                            tokio::select! {
-                                read = stdout.read_line(&mut line) => {
-                                    match read {
-                                        Ok(0) | Err(_) => done = true,
-                                        Ok(_) => {
-                                            rv = Some(match serde_json::from_str(&line) {
-                                                Ok(event) => event,
-                                                Err(ref err) => {
-                                                    self.state = State::Done;
-                                                    error_event(err, &self.options).into()
-                                                }
-                                            })
+                        tokio::select! {
+                            read = read_message(&mut stdout, format, &mut read_buffer) => {
+                                match read {
+                                    Ok(None) => done = true,
+                                    Ok(Some(event)) => rv = Some(event),
+                                    Err(err) => failure = Some(err),
+                                }
+                            }
+                            written = write_task, if should_write => {
+                                match written {
+                                    // large events are written in multiple steps
+                                    Ok(written) if written > 0 => {
+                                        let buffered_event = self.buffered_event.as_mut().unwrap();
+                                        buffered_event.drain(..written);
+                                        if buffered_event.is_empty() {
+                                            self.buffered_event = None;
                                         }
                                     }
-                                }
-                                failed = write_task, if should_write => {
-                                    self.buffered_event.take();
-                                    if failed {
-                                        self.state = State::Done;
-                                        rv = Some(error_event(&"failed to write to subprocess", &self.options).into());
+                                    _ => {
+                                        self.buffered_event.take();
+                                        failure = Some("failed to write to subprocess".into());
                                     }
                                 }
                             }
+                            _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                                failure = Some("timed out".into());
+                            }
                         }
                     });
 
                     self.stdin = stdin;
                     self.stdout = Some(stdout);
+                    self.read_buffer = read_buffer;
+                    self.rt = Some(rt);
 
-                    if let Some(rv) = rv {
-                        self.rt = Some(rt);
-                        return Some(rv);
+                    if let Some(failure) = failure {
+                        self.fail(&failure);
+                    } else if let Some(rv) = rv {
+                        if !self.holds_output() {
+                            return Some(rv);
+                        }
+                        self.held.push(rv);
                     } else if done {
-                        // close stuff
-                        self.stdin.take();
-                        self.stdout.take();
-                        self.state = State::Done;
-                        return None;
-                    } else {
-                        self.rt = Some(rt);
+                        self.finish();
                     }
                 }
             }
         }
     }
 }
+
+#[cfg(unix)]
+#[test]
+fn test_decode_json_line() {
+    let mut buffer = b"1\n\n  \n2\n\n3".to_vec();
+    let mut decoded = vec![];
+    while let Some(value) = decode_json_line::<u32>(&mut buffer, false).unwrap() {
+        decoded.push(value);
+    }
+    assert_eq!(decoded, vec![1, 2]);
+    assert_eq!(decode_json_line::<u32>(&mut buffer, true), Ok(Some(3)));
+    assert_eq!(decode_json_line::<u32>(&mut buffer, true), Ok(None));
+
+    let mut buffer = b"\n\n".to_vec();
+    assert_eq!(decode_json_line::<u32>(&mut buffer, true), Ok(None));
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_stream_large_events() {
+    use crate::event::{Event, TextEvent};
+    use crate::processors::Processor;
+
+    // events larger than the pipe buffer are written in multiple steps
+    let text = "All work and no play makes Jack a dull boy. ".repeat(50_000);
+    let processor = External {
+        cmd: "cat".into(),
+        args: vec![],
+        env: BTreeMap::new(),
+        cwd: None,
+        format: WireFormat::Json,
+        timeout_ms: None,
+        on_error: OnError::Fail,
+        handshake: false,
+    };
+    let events: Vec<_> = Box::new(processor)
+        .apply(Box::new((0..3).map(|_| {
+            AnnotatedEvent::from(TextEvent {
+                text: text.as_str().into(),
+            })
+        })))
+        .collect();
+    assert_eq!(events.len(), 3);
+    for annotated_event in events {
+        match annotated_event.event {
+            Event::Text(TextEvent {
+                text: ref event_text,
+            }) => {
+                assert_eq!(event_text.as_str(), text)
+            }
+            ref event => panic!("unexpected event {:?}", event),
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_error_policies() {
+    use crate::event::{Event, TextEvent};
+    use crate::processors::Processor;
+
+    let run = |args: &[&str], timeout_ms, on_error| {
+        let processor = External {
+            cmd: "sh".into(),
+            args: args.iter().map(|x| x.to_string()).collect(),
+            env: BTreeMap::new(),
+            cwd: None,
+            format: WireFormat::Json,
+            timeout_ms,
+            on_error,
+            handshake: false,
+        };
+        Box::new(processor)
+            .apply(Box::new(
+                vec![AnnotatedEvent::from(TextEvent {
+                    text: "Hello".into(),
+                })]
+                .into_iter(),
+            ))
+            .map(|x| x.event)
+            .collect::<Vec<_>>()
+    };
+    let error = |event: &Event<'_>| match event {
+        Event::Error(ErrorEvent {
+            description: Some(description),
+            ..
+        }) => description.as_str().to_string(),
+        event => panic!("expected error, got {:?}", event),
+    };
+
+    let events = run(&["-c", "cat; echo broken >&2; exit 3"], None, OnError::Fail);
+    assert_eq!(events.len(), 2);
+    assert!(matches!(events[0], Event::Text(..)));
+    assert!(error(&events[1]).contains("exit status: 3"));
+    assert!(error(&events[1]).contains("stderr:\nbroken"));
+
+    let events = run(&["-c", "cat; exit 1"], None, OnError::Drop);
+    assert_eq!(events.len(), 1);
+    error(&events[0]);
+
+    let events = run(&["-c", "sleep 10"], Some(100), OnError::Passthrough);
+    assert_eq!(events.len(), 2);
+    assert!(error(&events[0]).contains("timed out"));
+    assert!(matches!(events[1], Event::Text(..)));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_handshake() {
+    use crate::event::{Event, TextEvent};
+    use crate::processors::Processor;
+
+    let run = |script: &str| {
+        let processor = External {
+            cmd: "sh".into(),
+            args: vec!["-c".into(), script.into()],
+            env: BTreeMap::new(),
+            cwd: None,
+            format: WireFormat::Json,
+            timeout_ms: Some(5000),
+            on_error: OnError::Fail,
+            handshake: true,
+        };
+        Box::new(processor)
+            .apply(Box::new(
+                vec![AnnotatedEvent::from(TextEvent {
+                    text: "Hello".into(),
+                })]
+                .into_iter(),
+            ))
+            .map(|x| x.event)
+            .collect::<Vec<_>>()
+    };
+
+    // cat replies with our own version
+    let events = run("cat");
+    assert_eq!(events.len(), 1);
+    assert!(matches!(events[0], Event::Text(..)));
+
+    let events = run(r#"echo '{"protocol":"1.7.2"}'; tail -n +2"#);
+    assert_eq!(events.len(), 1);
+    assert!(matches!(events[0], Event::Text(..)));
+
+    for (script, expected) in &[
+        (
+            r#"echo '{"protocol":"2.0.0"}'; cat"#,
+            "protocol version 2.0.0",
+        ),
+        (r#"echo '{"type":"soft_break"}'; cat"#, "invalid handshake"),
+        ("exit 0", "before the handshake"),
+    ] {
+        match run(script).last() {
+            Some(Event::Error(ErrorEvent {
+                description: Some(description),
+                ..
+            })) => assert!(description.as_str().contains(expected), "{}", description),
+            event => panic!("expected error, got {:?}", event),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, DirectiveEvent, Event, MetaDataEvent, Tag};
+use crate::plain::to_plain_text;
+use crate::processors::utils::{parse_body, split_sections};
+use crate::value::to_value;
+
+/// Expands `{faq}` directives into questions and answers.
+///
+/// The body of the directive is parsed as markdown.  Every heading starts a
+/// question and everything up to the next heading is its answer.  Each
+/// directive is emitted as containers with the classes `faq`, `faq-item`,
+/// `faq-question` and `faq-answer` followed by a `faq` meta data event
+/// holding the questions and answers as plain text.  The HTML renderer can
+/// turn the latter into FAQPage JSON-LD (see
+/// [`HtmlRendererOptions::emit_faq_json_ld`](crate::html::HtmlRendererOptions::emit_faq_json_ld)).
+///
+/// When applied this wraps the stream in a [`FaqIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Faq {
+    /// The name of the directive.
+    pub directive_name: String,
+    /// Controls if the questions should be emitted as meta data.
+    pub emit_metadata: bool,
+}
+
+impl Default for Faq {
+    fn default() -> Faq {
+        Faq {
+            directive_name: "faq".into(),
+            emit_metadata: true,
+        }
+    }
+}
+
+implement_processor!(Faq, FaqIter);
+
+/// A question and answer pair as emitted in the meta data.
+#[derive(Serialize, Debug)]
+pub struct FaqEntry {
+    /// The question as plain text.
+    pub question: String,
+    /// The answer as plain text.
+    pub answer: String,
+}
+
+fn container(class: &'static str) -> AnnotatedEvent<'static> {
+    Tag::Container
+        .start_tag(Attrs {
+            class: Some(class.into()),
+            ..Attrs::default()
+        })
+        .into()
+}
+
+/// The iterator implementing [`Faq`].
+pub struct FaqIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Faq>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> FaqIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Faq>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for FaqIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        let body = match annotated_event.event {
+            Event::Directive(DirectiveEvent {
+                ref name,
+                ref body,
+                ref body_location,
+                ..
+            }) if name.as_str() == self.options.directive_name => {
+                parse_body(body.as_str(), body_location.as_ref())
+            }
+            _ => return Some(annotated_event),
+        };
+
+        let mut entries = vec![];
+        for section in split_sections(body) {
+            if section.title.is_empty() {
+                self.buffer.extend(section.body);
+                continue;
+            }
+            entries.push(FaqEntry {
+                question: to_plain_text(section.title.iter())
+                    .as_str()
+                    .trim()
+                    .to_string(),
+                answer: to_plain_text(section.body.iter())
+                    .as_str()
+                    .trim()
+                    .to_string(),
+            });
+            self.buffer.push_back(container("faq-item"));
+            self.buffer.push_back(container("faq-question"));
+            self.buffer.extend(section.title);
+            self.buffer.push_back(Tag::Container.end_tag().into());
+            self.buffer.push_back(container("faq-answer"));
+            self.buffer.extend(section.body);
+            self.buffer.push_back(Tag::Container.end_tag().into());
+            self.buffer.push_back(Tag::Container.end_tag().into());
+        }
+        self.buffer.push_back(Tag::Container.end_tag().into());
+
+        if self.options.emit_metadata {
+            self.buffer.push_back(
+                MetaDataEvent {
+                    key: "faq".into(),
+                    value: to_value(&entries).expect("bad faq entries"),
+                }
+                .into(),
+            );
+        }
+
+        let mut start = container("faq");
+        start.location = annotated_event.location;
+        Some(start)
+    }
+}
@@ -0,0 +1,210 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, EndTagEvent, Event, ImageEvent, StartTagEvent, Tag, TextEvent};
+
+/// Promotes standalone images into figures.
+///
+/// A paragraph that only holds an image (optionally wrapped in a link) is
+/// turned into a [`Tag::Figure`].  If the image is followed by a line that is
+/// entirely emphasized that line becomes the [`Tag::FigureCaption`]:
+///
+/// ```markdown
+/// ![A cat](cat.png)
+/// *The cat in its natural habitat.*
+/// ```
+///
+/// When `alt_as_caption` is enabled images without an explicit caption use
+/// their alt text as caption.
+///
+/// When applied this wraps the stream in a [`FiguresIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Figures {
+    /// Controls if emphasized lines below images become captions.
+    pub captions: bool,
+    /// Controls if the alt text is used if there is no explicit caption.
+    pub alt_as_caption: bool,
+}
+
+impl Default for Figures {
+    fn default() -> Figures {
+        Figures {
+            captions: true,
+            alt_as_caption: false,
+        }
+    }
+}
+
+implement_processor!(Figures, FiguresIter);
+
+fn is_blank(annotated_event: &AnnotatedEvent<'_>) -> bool {
+    match annotated_event.event {
+        Event::Text(TextEvent { ref text }) => text.as_str().trim().is_empty(),
+        Event::SoftBreak => true,
+        _ => false,
+    }
+}
+
+/// Returns the number of events making up the image at the start.
+fn image_len(events: &[AnnotatedEvent<'_>]) -> Option<usize> {
+    match events {
+        [AnnotatedEvent {
+            event: Event::Image(..),
+            ..
+        }, ..] => Some(1),
+        [AnnotatedEvent {
+            event: Event::StartTag(StartTagEvent { tag: Tag::Link, .. }),
+            ..
+        }, AnnotatedEvent {
+            event: Event::Image(..),
+            ..
+        }, AnnotatedEvent {
+            event: Event::EndTag(EndTagEvent { tag: Tag::Link }),
+            ..
+        }, ..] => Some(3),
+        _ => None,
+    }
+}
+
+/// The iterator implementing [`Figures`].
+pub struct FiguresIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Figures>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> FiguresIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Figures>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    /// Converts the events of a paragraph into a figure if possible.
+    fn promote(&self, paragraph: Vec<AnnotatedEvent<'data>>) -> Vec<AnnotatedEvent<'data>> {
+        if paragraph.len() < 2 {
+            return paragraph;
+        }
+        let inner = &paragraph[1..paragraph.len() - 1];
+        let image_len = match image_len(inner) {
+            Some(image_len) => image_len,
+            None => return paragraph,
+        };
+        let (image, rest) = inner.split_at(image_len);
+
+        let caption = match rest {
+            [AnnotatedEvent {
+                event: Event::SoftBreak,
+                ..
+            }, AnnotatedEvent {
+                event:
+                    Event::StartTag(StartTagEvent {
+                        tag: Tag::Emphasis, ..
+                    }),
+                ..
+            }, caption @ .., AnnotatedEvent {
+                event:
+                    Event::EndTag(EndTagEvent {
+                        tag: Tag::Emphasis, ..
+                    }),
+                ..
+            }] if self.options.captions
+                && !caption.iter().any(|x| {
+                    matches!(
+                        x.event,
+                        Event::SoftBreak
+                            | Event::HardBreak
+                            | Event::EndTag(EndTagEvent { tag: Tag::Emphasis })
+                    )
+                }) =>
+            {
+                Some(caption.to_vec())
+            }
+            rest if rest.iter().all(is_blank) => None,
+            _ => return paragraph,
+        };
+        let caption = caption.or_else(|| {
+            if !self.options.alt_as_caption {
+                return None;
+            }
+            image.iter().find_map(|x| match x.event {
+                Event::Image(ImageEvent {
+                    alt: Some(ref alt), ..
+                }) if !alt.as_str().is_empty() => {
+                    Some(vec![TextEvent { text: alt.clone() }.into()])
+                }
+                _ => None,
+            })
+        });
+
+        let (start, end) = match (paragraph.first(), paragraph.last()) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return paragraph,
+        };
+        let attrs = match start.event {
+            Event::StartTag(StartTagEvent { ref attrs, .. }) => attrs.clone(),
+            _ => return paragraph,
+        };
+        let mut rv = vec![AnnotatedEvent::new(
+            Tag::Figure.start_tag(attrs),
+            start.location.clone(),
+        )];
+        rv.extend(image.iter().cloned());
+        if let Some(caption) = caption {
+            rv.push(Tag::FigureCaption.start_tag(Default::default()).into());
+            rv.extend(caption);
+            rv.push(Tag::FigureCaption.end_tag().into());
+        }
+        rv.push(AnnotatedEvent::new(
+            Tag::Figure.end_tag(),
+            end.location.clone(),
+        ));
+        rv
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for FiguresIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        if !matches!(
+            annotated_event.event,
+            Event::StartTag(StartTagEvent {
+                tag: Tag::Paragraph,
+                ..
+            })
+        ) {
+            return Some(annotated_event);
+        }
+
+        // paragraphs only hold inline elements so the end of the paragraph
+        // is the first paragraph end tag.
+        let mut paragraph = vec![annotated_event];
+        for annotated_event in self.source.by_ref() {
+            let is_end = matches!(
+                annotated_event.event,
+                Event::EndTag(EndTagEvent {
+                    tag: Tag::Paragraph
+                })
+            );
+            paragraph.push(annotated_event);
+            if is_end {
+                break;
+            }
+        }
+        self.buffer.extend(self.promote(paragraph));
+        self.buffer.pop_front()
+    }
+}
@@ -0,0 +1,127 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, EndTagEvent, Event, StartTagEvent, Tag};
+
+/// Shifts the levels of all headings.
+///
+/// This is useful when a document is embedded into a larger page where its
+/// headings have to be nested below the headings of the page.  Every
+/// heading is shifted by `offset` levels (negative offsets promote
+/// headings) and the result is clamped to `min_level` and `max_level`.
+///
+/// With `normalize` enabled the levels are first shifted so that the first
+/// heading of the document is a level 1 heading, then the offset is
+/// applied.  For instance a document starting with a level 3 heading that
+/// is embedded below a level 1 heading of the page is best configured with
+/// `normalize: true` and `offset: 1`.
+///
+/// When applied this wraps the stream in a [`HeadingAdjustIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct HeadingAdjust {
+    /// The number of levels headings are shifted by.
+    pub offset: i32,
+    /// Shifts the levels so that the first heading becomes level 1 before
+    /// the offset is applied.
+    pub normalize: bool,
+    /// The lowest level a heading can end up at.
+    pub min_level: usize,
+    /// The highest level a heading can end up at.
+    pub max_level: usize,
+}
+
+impl Default for HeadingAdjust {
+    fn default() -> HeadingAdjust {
+        HeadingAdjust {
+            offset: 0,
+            normalize: false,
+            min_level: 1,
+            max_level: 6,
+        }
+    }
+}
+
+implement_processor!(HeadingAdjust, HeadingAdjustIter);
+
+/// The iterator implementing [`HeadingAdjust`].
+pub struct HeadingAdjustIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    // the shift of all levels once known
+    shift: Option<i32>,
+    options: Cow<'options, HeadingAdjust>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    HeadingAdjustIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, HeadingAdjust>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            shift: if options.normalize {
+                None
+            } else {
+                Some(options.offset)
+            },
+            options,
+        }
+    }
+
+    fn adjust(&self, tag: Tag, shift: i32) -> Tag {
+        let level = match tag.header_level() {
+            Some(level) => level as i32 + shift,
+            None => return tag,
+        };
+        let min_level = self.options.min_level.max(1);
+        let max_level = self.options.max_level.min(6).max(min_level);
+        let level = (level.max(0) as usize).max(min_level).min(max_level);
+        Tag::from_header_level(level).unwrap_or(tag)
+    }
+
+    fn adjust_event(&self, annotated_event: &mut AnnotatedEvent<'data>, shift: i32) {
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { ref mut tag, .. })
+            | Event::EndTag(EndTagEvent { ref mut tag }) => {
+                *tag = self.adjust(*tag, shift);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for HeadingAdjustIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // with normalization everything up to the first heading is held
+        // back until the shift is known
+        while self.shift.is_none() {
+            match self.source.next() {
+                Some(annotated_event) => {
+                    if let Event::StartTag(StartTagEvent { tag, .. }) = annotated_event.event {
+                        if let Some(level) = tag.header_level() {
+                            self.shift = Some(self.options.offset + 1 - level as i32);
+                        }
+                    }
+                    self.buffer.push_back(annotated_event);
+                }
+                None => self.shift = Some(self.options.offset),
+            }
+        }
+
+        let shift = self.shift.unwrap_or(0);
+        let mut annotated_event = match self.buffer.pop_front() {
+            Some(annotated_event) => annotated_event,
+            None => self.source.next()?,
+        };
+        self.adjust_event(&mut annotated_event, shift);
+        Some(annotated_event)
+    }
+}
@@ -0,0 +1,132 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, FootnoteReferenceEvent, MetaDataEvent, StartTagEvent};
+use crate::value::Value;
+
+/// Namespaces all ids of a document.
+///
+/// When multiple documents are rendered onto one page their anchors and
+/// footnotes collide.  This prefixes every id with `prefix` and
+/// `separator` (`usage` becomes `intro--usage`) and rewrites footnote
+/// references, links to anchors (`#usage`) and the anchors of the
+/// `toc` meta data to match.
+///
+/// Ids are namespaced where they end up, so this has to run after all
+/// processors that add ids or links.  A [`Pipeline`](crate::pipeline::Pipeline)
+/// with an `id_namespace` takes care of this:
+///
+/// ```yaml
+/// id_namespace: intro
+/// processors:
+///   - processor: auto_anchors
+/// ```
+///
+/// When applied this wraps the stream in an [`IdNamespaceIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct IdNamespace {
+    /// The namespace ids are put into.  An empty prefix leaves ids alone.
+    pub prefix: String,
+    /// The separator between the namespace and the id.
+    pub separator: String,
+}
+
+impl Default for IdNamespace {
+    fn default() -> IdNamespace {
+        IdNamespace {
+            prefix: "".into(),
+            separator: "--".into(),
+        }
+    }
+}
+
+impl IdNamespace {
+    /// Creates a namespace with the default separator.
+    pub fn new<S: Into<String>>(prefix: S) -> IdNamespace {
+        IdNamespace {
+            prefix: prefix.into(),
+            ..IdNamespace::default()
+        }
+    }
+
+    /// Puts an id into the namespace.
+    pub fn namespaced(&self, id: &str) -> String {
+        if self.prefix.is_empty() {
+            id.to_string()
+        } else {
+            format!("{}{}{}", self.prefix, self.separator, id)
+        }
+    }
+
+    fn namespace_toc(&self, value: &mut Value) {
+        match value {
+            Value::Array(items) => items.iter_mut().for_each(|x| self.namespace_toc(x)),
+            Value::Object(item) => {
+                if let Some(Value::String(anchor)) = item.get_mut("anchor") {
+                    *anchor = self.namespaced(anchor);
+                }
+                if let Some(children) = item.get_mut("children") {
+                    self.namespace_toc(children);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn namespace_event(&self, event: &mut Event<'_>) {
+        match event {
+            Event::StartTag(StartTagEvent { attrs, .. }) => {
+                if let Some(ref id) = attrs.id {
+                    attrs.id = Some(self.namespaced(id.as_str()).into());
+                }
+                let target = match attrs.target {
+                    Some(ref target) => target.as_str(),
+                    None => return,
+                };
+                if let Some(anchor) = target.strip_prefix('#') {
+                    attrs.target = Some(format!("#{}", self.namespaced(anchor)).into());
+                }
+            }
+            Event::FootnoteReference(FootnoteReferenceEvent { target }) => {
+                *target = self.namespaced(target.as_str()).into();
+            }
+            Event::MetaData(MetaDataEvent { key, value }) if key.as_str() == "toc" => {
+                self.namespace_toc(value);
+            }
+            _ => {}
+        }
+    }
+}
+
+implement_processor!(IdNamespace, IdNamespaceIter);
+
+/// The iterator implementing [`IdNamespace`].
+pub struct IdNamespaceIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    options: Cow<'options, IdNamespace>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    IdNamespaceIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, IdNamespace>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for IdNamespaceIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut annotated_event = self.source.next()?;
+        self.options.namespace_event(&mut annotated_event.event);
+        Some(annotated_event)
+    }
+}
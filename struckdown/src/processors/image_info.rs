@@ -0,0 +1,235 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, ImageEvent};
+use crate::processors::utils::is_relative_target;
+
+lazy_static! {
+    static ref SVG_TAG_RE: Regex = Regex::new(r"<svg\b[^>]*>").unwrap();
+    static ref SVG_ATTR_RE: Regex =
+        Regex::new(r#"\s(width|height|viewBox)\s*=\s*["']([^"']*)["']"#).unwrap();
+}
+
+// the size of the header read from images
+const HEADER_LIMIT: u64 = 512 * 1024;
+
+/// Adds the dimensions of local images.
+///
+/// The file headers of images with relative targets are read to determine
+/// their width and height which then allows renderers to reserve the space
+/// of the images before they are loaded.  PNG, GIF, JPEG, WebP, BMP and SVG
+/// images are supported:
+///
+/// ```yaml
+/// processors:
+///   - processor: image_info
+///     root: docs
+/// ```
+///
+/// Targets are resolved relative to `root`, images outside of it are
+/// ignored.  Missing files, files in unknown formats and files not readable
+/// under the installed [`Sandbox`](crate::sandbox::Sandbox) are left alone
+/// as are images that already have a width and height.
+///
+/// When applied this wraps the stream in an [`ImageInfoIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ImageInfo {
+    /// The folder that image targets are resolved against.
+    pub root: PathBuf,
+}
+
+impl Default for ImageInfo {
+    fn default() -> ImageInfo {
+        ImageInfo { root: ".".into() }
+    }
+}
+
+implement_processor!(ImageInfo, ImageInfoIter);
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u32::from(u16::from_be_bytes([bytes[0], bytes[1]])))
+}
+
+fn le_u16(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u32::from(u16::from_le_bytes([bytes[0], bytes[1]])))
+}
+
+fn le_u24(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 3)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn le_i32(data: &[u8], offset: usize) -> Option<i32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn jpeg_size(data: &[u8]) -> Option<(u32, u32)> {
+    let mut offset = 2;
+    loop {
+        if *data.get(offset)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(offset + 1)?;
+        match marker {
+            // fill bytes
+            0xff => offset += 1,
+            // markers without a payload
+            0x01 | 0xd0..=0xd7 => offset += 2,
+            // start of frame (except for DHT, JPG and DAC)
+            0xc0..=0xcf if marker != 0xc4 && marker != 0xc8 && marker != 0xcc => {
+                return Some((be_u16(data, offset + 7)?, be_u16(data, offset + 5)?));
+            }
+            _ => offset += 2 + be_u16(data, offset + 2)? as usize,
+        }
+    }
+}
+
+fn webp_size(data: &[u8]) -> Option<(u32, u32)> {
+    match data.get(12..16)? {
+        b"VP8 " => Some((le_u16(data, 26)? & 0x3fff, le_u16(data, 28)? & 0x3fff)),
+        b"VP8L" => {
+            let bits = u32::from_le_bytes([
+                *data.get(21)?,
+                *data.get(22)?,
+                *data.get(23)?,
+                *data.get(24)?,
+            ]);
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        b"VP8X" => Some((le_u24(data, 24)? + 1, le_u24(data, 27)? + 1)),
+        _ => None,
+    }
+}
+
+fn svg_length(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let value = value.strip_suffix("px").unwrap_or(value);
+    let value: f64 = value.parse().ok()?;
+    if value > 0.0 {
+        Some(value.round() as u32)
+    } else {
+        None
+    }
+}
+
+fn svg_size(data: &[u8]) -> Option<(u32, u32)> {
+    let text = String::from_utf8_lossy(data);
+    let tag = SVG_TAG_RE.find(&text)?.as_str();
+    let (mut width, mut height, mut view_box) = (None, None, None);
+    for caps in SVG_ATTR_RE.captures_iter(tag) {
+        match &caps[1] {
+            "width" => width = svg_length(&caps[2]),
+            "height" => height = svg_length(&caps[2]),
+            _ => view_box = Some(caps[2].to_string()),
+        }
+    }
+    if let (Some(width), Some(height)) = (width, height) {
+        return Some((width, height));
+    }
+    let view_box: Vec<f64> = view_box?
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|x| !x.is_empty())
+        .filter_map(|x| x.parse().ok())
+        .collect();
+    match view_box[..] {
+        [_, _, width, height] if width > 0.0 && height > 0.0 => {
+            Some((width.round() as u32, height.round() as u32))
+        }
+        _ => None,
+    }
+}
+
+/// Determines the width and height of an image from its header.
+fn image_size(data: &[u8]) -> Option<(u32, u32)> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some((be_u32(data, 16)?, be_u32(data, 20)?))
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some((le_u16(data, 6)?, le_u16(data, 8)?))
+    } else if data.starts_with(b"\xff\xd8") {
+        jpeg_size(data)
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        webp_size(data)
+    } else if data.starts_with(b"BM") {
+        let width = le_i32(data, 18)?;
+        let height = le_i32(data, 22)?;
+        Some((width.unsigned_abs(), height.unsigned_abs()))
+    } else {
+        svg_size(data)
+    }
+}
+
+/// The iterator implementing [`ImageInfo`].
+pub struct ImageInfoIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    options: Cow<'options, ImageInfo>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> ImageInfoIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, ImageInfo>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            options: options.into(),
+        }
+    }
+
+    fn read_header(&self, target: &str) -> Option<Vec<u8>> {
+        let root = fs::canonicalize(&self.options.root).ok()?;
+        let end = target.find(['#', '?']).unwrap_or(target.len());
+        let path = fs::canonicalize(root.join(Path::new(&target[..end]))).ok()?;
+        if !path.starts_with(&root) {
+            return None;
+        }
+        crate::sandbox::current().check_read(&path).ok()?;
+        let mut header = vec![];
+        fs::File::open(&path)
+            .ok()?
+            .take(HEADER_LIMIT)
+            .read_to_end(&mut header)
+            .ok()?;
+        Some(header)
+    }
+
+    fn add_size(&self, image: &mut ImageEvent<'data>) {
+        if (image.width.is_some() && image.height.is_some())
+            || !is_relative_target(image.target.as_str())
+        {
+            return;
+        }
+        if let Some((width, height)) = self
+            .read_header(image.target.as_str())
+            .and_then(|header| image_size(&header))
+        {
+            image.width = Some(width);
+            image.height = Some(height);
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for ImageInfoIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut annotated_event = self.source.next()?;
+        if let Event::Image(ref mut image) = annotated_event.event {
+            self.add_size(image);
+        }
+        Some(annotated_event)
+    }
+}
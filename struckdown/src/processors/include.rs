@@ -0,0 +1,179 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, ErrorEvent, Event};
+use crate::parser::parse;
+
+/// Includes other documents.
+///
+/// Directives with the configured name (`include` by default) are replaced
+/// by the events of the referenced file:
+///
+/// ````markdown
+/// ```{include} chapters/intro.md
+/// ```
+/// ````
+///
+/// Paths are resolved relative to `root` for the document and relative to
+/// the including file for nested includes.  Files outside of `root` cannot
+/// be included.  The front matter of included files is dropped and their
+/// events carry no locations.  Missing files, include cycles and includes
+/// nested deeper than `max_depth` are reported as error events.
+///
+/// Files must also be readable under the installed
+/// [`Sandbox`](crate::sandbox::Sandbox).
+///
+/// When applied this wraps the stream in an [`IncludeIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Include {
+    /// The name of the directive.
+    pub directive: String,
+    /// The folder that includes are resolved against.
+    pub root: PathBuf,
+    /// The maximum nesting of includes.
+    pub max_depth: usize,
+}
+
+impl Default for Include {
+    fn default() -> Include {
+        Include {
+            directive: "include".into(),
+            root: ".".into(),
+            max_depth: 8,
+        }
+    }
+}
+
+implement_processor!(Include, IncludeIter);
+
+/// The iterator implementing [`Include`].
+pub struct IncludeIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    // the files currently being included, innermost last
+    stack: Vec<PathBuf>,
+    options: Cow<'options, Include>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> IncludeIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Include>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            stack: vec![],
+            options: options.into(),
+        }
+    }
+
+    fn include(&self, path: &str) -> Result<Vec<AnnotatedEvent<'static>>, ErrorEvent<'static>> {
+        let error = |code: Code, title: String, description: Option<String>| ErrorEvent {
+            title: title.into(),
+            description: description.map(Into::into),
+            code: Some(code),
+        };
+        if self.stack.len() >= self.options.max_depth {
+            return Err(error(
+                Code::IncludeNestingTooDeep,
+                "Includes nested too deeply".into(),
+                None,
+            ));
+        }
+        let root = fs::canonicalize(&self.options.root).map_err(|err| {
+            error(
+                Code::IncludeFailure,
+                "Invalid include root".into(),
+                Some(err.to_string()),
+            )
+        })?;
+        let base = match self.stack.last() {
+            Some(parent) => parent.parent().unwrap_or(&root),
+            None => &root,
+        };
+        let failed = |err: io::Error| {
+            error(
+                Code::IncludeFailure,
+                format!("Failed to include '{}'", path),
+                Some(err.to_string()),
+            )
+        };
+        let resolved = fs::canonicalize(base.join(Path::new(path))).map_err(failed)?;
+        if !resolved.starts_with(&root) {
+            return Err(error(
+                Code::IncludeOutsideRoot,
+                format!("Cannot include '{}' from outside the root", path),
+                None,
+            ));
+        }
+        if self.stack.contains(&resolved) {
+            return Err(error(
+                Code::IncludeCycle,
+                format!("Include cycle through '{}'", path),
+                None,
+            ));
+        }
+        crate::sandbox::current()
+            .check_read(&resolved)
+            .map_err(|err| error(Code::SandboxViolation, err, None))?;
+        let contents = fs::read_to_string(&resolved).map_err(failed)?;
+
+        // boxed as the nested iterator would otherwise be a new type per level
+        let events: Box<dyn Iterator<Item = AnnotatedEvent<'static>> + '_> = Box::new(
+            parse(&contents, &Default::default())
+                .skip(1)
+                .map(|annotated_event| {
+                    AnnotatedEvent::new(annotated_event.event.into_owned(), None)
+                }),
+        );
+        let mut iter = IncludeIter::new(events, Cow::Borrowed(self.options.as_ref()));
+        iter.stack = self.stack.clone();
+        iter.stack.push(resolved);
+        Ok(iter.collect())
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for IncludeIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+
+            let annotated_event = self.source.next()?;
+            let path = match annotated_event.event {
+                Event::Directive(ref directive)
+                    if directive.name.as_str() == self.options.directive =>
+                {
+                    directive
+                        .argument
+                        .as_ref()
+                        .map(|x| x.as_str().trim().to_string())
+                }
+                _ => return Some(annotated_event),
+            };
+
+            let result = match path {
+                Some(path) => self.include(&path),
+                None => Err(ErrorEvent {
+                    title: "Include without a path".into(),
+                    description: None,
+                    code: Some(Code::IncludeWithoutPath),
+                }),
+            };
+            match result {
+                Ok(events) => self.buffer.extend(events),
+                Err(err) => return Some(AnnotatedEvent::new(err, annotated_event.location)),
+            }
+        }
+    }
+}
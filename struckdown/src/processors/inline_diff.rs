@@ -0,0 +1,87 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, Event, InterpretedTextEvent, Tag, TextEvent};
+
+/// Turns insertion and deletion roles into inline diff markup.
+///
+/// `` {ins}`new text` `` becomes a [`Tag::Insertion`] and
+/// `` {del}`old text` `` a [`Tag::Deletion`] tag holding the text.  This
+/// allows showing before and after states inline without raw HTML.
+///
+/// When applied this wraps the stream in a [`InlineDiffIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct InlineDiff {
+    /// The name of the role for inserted text.
+    pub insert_role: String,
+    /// The name of the role for deleted text.
+    pub delete_role: String,
+}
+
+impl Default for InlineDiff {
+    fn default() -> InlineDiff {
+        InlineDiff {
+            insert_role: "ins".into(),
+            delete_role: "del".into(),
+        }
+    }
+}
+
+implement_processor!(InlineDiff, InlineDiffIter);
+
+/// The iterator implementing [`InlineDiff`].
+pub struct InlineDiffIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, InlineDiff>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    InlineDiffIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, InlineDiff>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for InlineDiffIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        if let Event::InterpretedText(InterpretedTextEvent {
+            ref role, ref text, ..
+        }) = annotated_event.event
+        {
+            let tag = if role.as_str() == self.options.insert_role {
+                Tag::Insertion
+            } else if role.as_str() == self.options.delete_role {
+                Tag::Deletion
+            } else {
+                return Some(annotated_event);
+            };
+            self.buffer
+                .push_back(TextEvent { text: text.clone() }.into());
+            self.buffer.push_back(tag.end_tag().into());
+            return Some(AnnotatedEvent::new(
+                tag.start_tag(Attrs::default()),
+                annotated_event.location,
+            ));
+        }
+
+        Some(annotated_event)
+    }
+}
@@ -0,0 +1,263 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, ImageEvent, MetaDataEvent, StartTagEvent};
+use crate::plain::to_plain_text;
+use crate::value::{value, Map, Value};
+
+/// Builds schema.org structured data as JSON-LD.
+///
+/// The processor looks at the front matter and the structure of the
+/// document and emits a `json_ld` meta data event at the end of the stream
+/// which templates can embed in a `<script type="application/ld+json">`
+/// tag.
+///
+/// The type of the document is taken from the `schema_type` front matter
+/// key and falls back to the configured `schema_type`:
+///
+/// * `Article`: the `headline` is the `title` from the front matter or the
+///   first level 1 heading.  `description`, `author`, `date` (published)
+///   and `updated` (modified) come from the front matter, images from the
+///   front matter `image` key and all images in the document.
+/// * `HowTo`: like an article but every level 2 heading becomes a
+///   `HowToStep`.
+///
+/// If the front matter has a `breadcrumbs` list (items with `title` and
+/// `url`) a `BreadcrumbList` is added and both are emitted in a `@graph`.
+///
+/// When applied this wraps the stream in a [`JsonLdIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct JsonLd {
+    /// The schema.org type used if the front matter does not define one.
+    pub schema_type: String,
+    /// The key of the emitted meta data.
+    pub metadata_key: String,
+}
+
+impl Default for JsonLd {
+    fn default() -> JsonLd {
+        JsonLd {
+            schema_type: "Article".into(),
+            metadata_key: "json_ld".into(),
+        }
+    }
+}
+
+implement_processor!(JsonLd, JsonLdIter);
+
+/// The iterator implementing [`JsonLd`].
+pub struct JsonLdIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    front_matter: Option<Value>,
+    headline: Option<String>,
+    steps: Vec<String>,
+    images: Vec<String>,
+    heading: Option<(usize, usize, Vec<AnnotatedEvent<'data>>)>,
+    depth: usize,
+    done: bool,
+    options: Cow<'options, JsonLd>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> JsonLdIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, JsonLd>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            front_matter: None,
+            headline: None,
+            steps: vec![],
+            images: vec![],
+            heading: None,
+            depth: 0,
+            done: false,
+            options: options.into(),
+        }
+    }
+
+    fn observe(&mut self, annotated_event: &AnnotatedEvent<'data>) {
+        match annotated_event.event {
+            Event::DocumentStart(ref document_start) => {
+                self.front_matter = document_start.front_matter.clone();
+            }
+            Event::Image(ImageEvent { ref target, .. }) => {
+                self.images.push(target.as_str().to_string());
+            }
+            Event::StartTag(StartTagEvent { tag, .. }) => {
+                self.depth += 1;
+                if self.heading.is_none() {
+                    if let Some(level) = tag.header_level() {
+                        self.heading = Some((level, self.depth, vec![]));
+                        return;
+                    }
+                }
+            }
+            Event::EndTag(_) => {
+                self.depth = self.depth.saturating_sub(1);
+                if let Some((level, depth, _)) = self.heading {
+                    if depth == self.depth + 1 {
+                        let events = self.heading.take().unwrap().2;
+                        let text = to_plain_text(events.iter()).as_str().trim().to_string();
+                        if level == 1 && self.headline.is_none() {
+                            self.headline = Some(text);
+                        } else if level == 2 {
+                            self.steps.push(text);
+                        }
+                        return;
+                    }
+                }
+            }
+            _ => {}
+        }
+        if let Some((_, _, ref mut events)) = self.heading {
+            events.push(annotated_event.clone());
+        }
+    }
+
+    fn front_matter_str(&self, key: &str) -> Option<&str> {
+        self.front_matter
+            .as_ref()
+            .and_then(|x| x.get(key))
+            .and_then(|x| x.as_str())
+    }
+
+    fn build(&self) -> Value {
+        let schema_type = self
+            .front_matter_str("schema_type")
+            .unwrap_or(&self.options.schema_type);
+        let mut rv = Map::new();
+        rv.insert("@type".into(), schema_type.into());
+
+        let title = self
+            .front_matter_str("title")
+            .map(|x| x.to_string())
+            .or_else(|| self.headline.clone());
+        if let Some(title) = title {
+            let key = if schema_type == "HowTo" {
+                "name"
+            } else {
+                "headline"
+            };
+            rv.insert(key.into(), title.into());
+        }
+        if let Some(description) = self.front_matter_str("description") {
+            rv.insert("description".into(), description.into());
+        }
+
+        let front_matter = self.front_matter.as_ref();
+        let authors: Vec<Value> = match front_matter.and_then(|x| x.get("author")) {
+            Some(Value::String(name)) => vec![name.as_str().into()],
+            Some(Value::Array(names)) => names.clone(),
+            _ => vec![],
+        };
+        let authors: Vec<Value> = authors
+            .iter()
+            .filter_map(|x| x.as_str())
+            .map(|name| value!({"@type": "Person", "name": name}))
+            .collect();
+        match authors.len() {
+            0 => {}
+            1 => {
+                rv.insert("author".into(), authors.into_iter().next().unwrap());
+            }
+            _ => {
+                rv.insert("author".into(), authors.into());
+            }
+        }
+
+        if let Some(date) = self.front_matter_str("date") {
+            rv.insert("datePublished".into(), date.into());
+        }
+        if let Some(updated) = self.front_matter_str("updated") {
+            rv.insert("dateModified".into(), updated.into());
+        }
+
+        let mut images = vec![];
+        if let Some(image) = self.front_matter_str("image") {
+            images.push(image.to_string());
+        }
+        for image in &self.images {
+            if !images.contains(image) {
+                images.push(image.clone());
+            }
+        }
+        if !images.is_empty() {
+            rv.insert("image".into(), images.into());
+        }
+
+        if schema_type == "HowTo" && !self.steps.is_empty() {
+            let steps: Vec<Value> = self
+                .steps
+                .iter()
+                .enumerate()
+                .map(
+                    |(idx, name)| value!({"@type": "HowToStep", "position": idx + 1, "name": name}),
+                )
+                .collect();
+            rv.insert("step".into(), steps.into());
+        }
+
+        let breadcrumbs: Vec<Value> = front_matter
+            .and_then(|x| x.get("breadcrumbs"))
+            .and_then(|x| x.as_array())
+            .map(|items| {
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, item)| {
+                        let mut crumb = Map::new();
+                        crumb.insert("@type".into(), "ListItem".into());
+                        crumb.insert("position".into(), (idx + 1).into());
+                        if let Some(title) = item.get("title") {
+                            crumb.insert("name".into(), title.clone());
+                        }
+                        if let Some(url) = item.get("url") {
+                            crumb.insert("item".into(), url.clone());
+                        }
+                        Value::Object(crumb)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if breadcrumbs.is_empty() {
+            let mut document = Map::new();
+            document.insert("@context".into(), "https://schema.org".into());
+            document.extend(rv);
+            return Value::Object(document);
+        }
+        value!({
+            "@context": "https://schema.org",
+            "@graph": [
+                rv,
+                {"@type": "BreadcrumbList", "itemListElement": breadcrumbs},
+            ],
+        })
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for JsonLdIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.source.next() {
+            Some(annotated_event) => {
+                self.observe(&annotated_event);
+                Some(annotated_event)
+            }
+            None if !self.done => {
+                self.done = true;
+                Some(
+                    MetaDataEvent {
+                        key: self.options.metadata_key.clone().into(),
+                        value: self.build(),
+                    }
+                    .into(),
+                )
+            }
+            None => None,
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::borrow::Cow;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, CodeBlockEvent, Event};
+
+lazy_static! {
+    // only captures the letters of the interpreter so that version suffixes
+    // such as the 3 in python3 are dropped.
+    static ref SHEBANG_RE: Regex =
+        Regex::new(r"\A#!\s*(?:\S*/)?(?:env\s+(?:-\S+\s+)*)?([A-Za-z]+)").unwrap();
+    static ref RULES: Vec<(Regex, &'static str)> = [
+        (r"\A<\?php", "php"),
+        (r"\A<\?xml", "xml"),
+        (r"(?i)\A<!doctype html|\A<html", "html"),
+        (
+            r"(?m)^\s*(?:pub\s+)?fn\s+\w+\s*(?:<[^>]*>)?\(|^use\s+(?:std|crate)::",
+            "rust"
+        ),
+        (r"(?m)\Apackage\s+\w+\s*$", "go"),
+        (r"(?m)^#include\s+[<\x22]", "c"),
+        (
+            r"(?m)^(?:def|class)\s+\w+.*:\s*$|^from\s+\S+\s+import\s",
+            "python"
+        ),
+        (r"(?m)\A\$ \S", "console"),
+        (r"(?m)\A---\s*$", "yaml"),
+    ]
+    .iter()
+    .map(|&(regex, language)| (Regex::new(regex).unwrap(), language))
+    .collect();
+}
+
+/// Guesses the language of unlabeled code blocks.
+///
+/// The language is detected from the first lines of the code with a few
+/// simple heuristics such as shebang lines (`#!/usr/bin/env python`),
+/// `<?php` or `fn main()`.  If nothing matches the `fallback` language is
+/// used if configured.  Code blocks that already have a language are left
+/// untouched.
+///
+/// Apply this before a highlighting processor such as `Syntect`.
+///
+/// When applied this wraps the stream in a [`LanguageDetectionIter`].
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LanguageDetection {
+    /// The language to use if the language cannot be detected.
+    pub fallback: Option<String>,
+}
+
+implement_processor!(LanguageDetection, LanguageDetectionIter);
+
+/// Guesses the language of a piece of code.
+pub fn detect_language(code: &str) -> Option<&'static str> {
+    if let Some(m) = SHEBANG_RE.captures(code) {
+        return Some(match &m[1] {
+            "python" => "python",
+            "bash" | "sh" | "zsh" => "bash",
+            "node" | "nodejs" => "javascript",
+            "ruby" => "ruby",
+            "perl" => "perl",
+            "php" => "php",
+            _ => return None,
+        });
+    }
+
+    RULES
+        .iter()
+        .find(|(regex, _)| regex.is_match(code))
+        .map(|&(_, language)| language)
+        .or_else(|| {
+            let trimmed = code.trim();
+            if (trimmed.starts_with('{') || trimmed.starts_with('['))
+                && serde_json::from_str::<serde::de::IgnoredAny>(trimmed).is_ok()
+            {
+                Some("json")
+            } else {
+                None
+            }
+        })
+}
+
+/// The iterator implementing [`LanguageDetection`].
+pub struct LanguageDetectionIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    options: Cow<'options, LanguageDetection>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    LanguageDetectionIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, LanguageDetection>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for LanguageDetectionIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut annotated_event = self.source.next()?;
+        if let Event::CodeBlock(CodeBlockEvent {
+            ref mut language,
+            ref code,
+            ..
+        }) = annotated_event.event
+        {
+            if language.is_none() {
+                *language = detect_language(code.as_str())
+                    .map(Into::into)
+                    .or_else(|| self.options.fallback.clone().map(Into::into));
+            }
+        }
+        Some(annotated_event)
+    }
+}
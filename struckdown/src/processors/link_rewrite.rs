@@ -0,0 +1,161 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, Attrs, ErrorEvent, Event, StartTagEvent, Tag};
+use crate::processors::utils::is_relative_target;
+
+/// A rule of the [`LinkRewrite`] processor.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LinkRule {
+    /// The regular expression link targets are matched against.
+    pub pattern: String,
+    /// Replaces the matched part of the target.  Groups can be referenced
+    /// as `$1` or `${name}`.
+    pub replace: Option<String>,
+    /// Attributes added to matching links (like `rel: nofollow`).
+    pub attrs: BTreeMap<String, String>,
+    /// Classes added to matching links.
+    pub class: Option<String>,
+}
+
+/// Rewrites and decorates links.
+///
+/// Relative links to `.md` files can be rewritten to the extension of the
+/// rendered files with `md_extension` (`guide.md#usage` becomes
+/// `guide.html#usage`).  Afterwards every rule whose `pattern` matches the
+/// target of a link is applied in order, later rules see the target as
+/// rewritten by earlier ones:
+///
+/// ```yaml
+/// processors:
+///   - processor: link_rewrite
+///     md_extension: html
+///     rules:
+///       - pattern: "^https?://"
+///         attrs: {rel: nofollow, target: _blank}
+///         class: external
+///       - pattern: "^/docs/"
+///         replace: /docs/v2/
+/// ```
+///
+/// Rules with invalid patterns are skipped and reported as errors.
+///
+/// When applied this wraps the stream in a [`LinkRewriteIter`].
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LinkRewrite {
+    /// The rules in the order they are applied.
+    pub rules: Vec<LinkRule>,
+    /// The extension relative `.md` links are rewritten to.
+    pub md_extension: Option<String>,
+}
+
+implement_processor!(LinkRewrite, LinkRewriteIter);
+
+fn rewrite_md_extension(target: &str, extension: &str) -> Option<String> {
+    if !is_relative_target(target) {
+        return None;
+    }
+    let end = target.find(['#', '?']).unwrap_or(target.len());
+    let path = target[..end].strip_suffix(".md")?;
+    Some(format!("{}.{}{}", path, extension, &target[end..]))
+}
+
+/// The iterator implementing [`LinkRewrite`].
+pub struct LinkRewriteIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    rules: Vec<(Regex, usize)>,
+    errors: Vec<ErrorEvent<'data>>,
+    options: Cow<'options, LinkRewrite>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    LinkRewriteIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, LinkRewrite>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        let mut errors = vec![];
+        let mut rules = vec![];
+        for (idx, rule) in options.rules.iter().enumerate() {
+            match Regex::new(&rule.pattern) {
+                Ok(regex) => rules.push((regex, idx)),
+                Err(err) => errors.push(ErrorEvent {
+                    title: format!("Invalid link pattern '{}'", rule.pattern).into(),
+                    description: Some(err.to_string().into()),
+                    code: Some(Code::InvalidLinkPattern),
+                }),
+            }
+        }
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            rules,
+            errors,
+            options,
+        }
+    }
+
+    fn rewrite(&self, attrs: &mut Attrs<'data>) {
+        let mut target = match attrs.target {
+            Some(ref target) => target.as_str().to_string(),
+            None => return,
+        };
+        if let Some(ref extension) = self.options.md_extension {
+            if let Some(rewritten) = rewrite_md_extension(&target, extension) {
+                target = rewritten;
+            }
+        }
+
+        for (regex, idx) in &self.rules {
+            if !regex.is_match(&target) {
+                continue;
+            }
+            let rule = &self.options.rules[*idx];
+            if let Some(ref replace) = rule.replace {
+                target = regex.replace(&target, replace.as_str()).into_owned();
+            }
+            if !rule.attrs.is_empty() {
+                let custom = attrs.custom.get_or_insert_with(Default::default);
+                for (key, value) in &rule.attrs {
+                    custom.insert(key.clone().into(), value.clone().into());
+                }
+            }
+            if let Some(ref class) = rule.class {
+                attrs.add_class(class);
+            }
+        }
+
+        attrs.target = Some(target.into());
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for LinkRewriteIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let mut annotated_event = self.source.next()?;
+        if let Event::DocumentStart(..) = annotated_event.event {
+            // configuration errors are reported right after the start
+            self.buffer.extend(self.errors.drain(..).map(Into::into));
+        } else if let Event::StartTag(StartTagEvent {
+            tag: Tag::Link,
+            ref mut attrs,
+        }) = annotated_event.event
+        {
+            self.rewrite(attrs);
+        }
+        Some(annotated_event)
+    }
+}
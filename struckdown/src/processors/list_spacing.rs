@@ -0,0 +1,229 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, EndTagEvent, Event, StartTagEvent, Tag};
+
+/// The spacing of a list.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Spacing {
+    /// The spacing from the source is kept.
+    Preserve,
+    /// Items hold their contents directly.
+    Tight,
+    /// The contents of items are wrapped in paragraphs.
+    Loose,
+}
+
+/// Normalizes lists to be tight or loose.
+///
+/// In markdown a single blank line between two items turns an entire list
+/// loose which wraps the contents of all of its items in paragraphs.  This
+/// processor forces a consistent spacing so that renderers produce the same
+/// output for all lists.  The spacing of top level lists and nested lists
+/// can be configured separately.
+///
+/// When a loose list is made tight, consecutive paragraphs in an item are
+/// separated by hard breaks.  The `tight` attribute of the list start tags
+/// is updated accordingly.
+///
+/// When applied this wraps the stream in a [`ListSpacingIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ListSpacing {
+    /// The spacing of top level lists.
+    pub spacing: Spacing,
+    /// The spacing of lists nested in other lists.
+    pub nested_spacing: Spacing,
+}
+
+impl Default for ListSpacing {
+    fn default() -> ListSpacing {
+        ListSpacing {
+            spacing: Spacing::Tight,
+            nested_spacing: Spacing::Tight,
+        }
+    }
+}
+
+implement_processor!(ListSpacing, ListSpacingIter);
+
+fn is_inline_tag(tag: Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis
+            | Tag::EmphasisAlt
+            | Tag::Strong
+            | Tag::Strikethrough
+            | Tag::Link
+            | Tag::Span
+            | Tag::Spoiler
+            | Tag::Abbreviation
+            | Tag::Insertion
+            | Tag::Deletion
+            | Tag::Sidenote
+    )
+}
+
+fn is_inline(event: &Event<'_>) -> bool {
+    match *event {
+        Event::StartTag(StartTagEvent { tag, .. }) => is_inline_tag(tag),
+        Event::Text(..)
+        | Event::InlineCode(..)
+        | Event::InlineMath(..)
+        | Event::InterpretedText(..)
+        | Event::Image(..)
+        | Event::FootnoteReference(..)
+        | Event::SoftBreak
+        | Event::HardBreak => true,
+        _ => false,
+    }
+}
+
+/// An open tag.
+struct OpenTag {
+    tag: Tag,
+    /// For lists if they are converted to tight or loose lists.
+    spacing: Option<bool>,
+    /// For paragraphs if the paragraph tags were removed.
+    removed: bool,
+}
+
+/// The iterator implementing [`ListSpacing`].
+pub struct ListSpacingIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    tag_stack: Vec<OpenTag>,
+    // a paragraph synthesized for a loose list item is open
+    in_paragraph: bool,
+    // a removed paragraph just ended in a tight list item
+    pending_break: bool,
+    options: Cow<'options, ListSpacing>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    ListSpacingIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, ListSpacing>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            tag_stack: vec![],
+            in_paragraph: false,
+            pending_break: false,
+            options: options.into(),
+        }
+    }
+
+    /// Returns the spacing of the list of the current item.
+    fn item_spacing(&self) -> Option<bool> {
+        let mut iter = self.tag_stack.iter().rev();
+        match (iter.next(), iter.next()) {
+            (Some(item), Some(list)) if item.tag == Tag::ListItem => list.spacing,
+            _ => None,
+        }
+    }
+
+    fn close_paragraph(&mut self) {
+        if self.in_paragraph {
+            self.buffer.push_back(Tag::Paragraph.end_tag().into());
+            self.in_paragraph = false;
+        }
+    }
+
+    fn process_event(&mut self, mut annotated_event: AnnotatedEvent<'data>) {
+        let item_spacing = self.item_spacing();
+
+        // loose items wrap runs of inline content in paragraphs
+        if item_spacing == Some(false) {
+            if is_inline(&annotated_event.event) {
+                if !self.in_paragraph {
+                    self.buffer
+                        .push_back(Tag::Paragraph.start_tag(Default::default()).into());
+                    self.in_paragraph = true;
+                }
+            } else if !matches!(annotated_event.event, Event::Checkbox(..)) {
+                self.close_paragraph();
+            }
+        }
+
+        // tight items drop their paragraphs
+        if item_spacing == Some(true) {
+            let pending_break = std::mem::replace(&mut self.pending_break, false);
+            if let Event::StartTag(StartTagEvent {
+                tag: Tag::Paragraph,
+                ..
+            }) = annotated_event.event
+            {
+                if pending_break {
+                    self.buffer.push_back(Event::HardBreak.into());
+                }
+                self.tag_stack.push(OpenTag {
+                    tag: Tag::Paragraph,
+                    spacing: None,
+                    removed: true,
+                });
+                return;
+            }
+        }
+
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, ref mut attrs }) => {
+                let mut spacing = None;
+                if let Tag::OrderedList | Tag::UnorderedList = tag {
+                    let nested = self
+                        .tag_stack
+                        .iter()
+                        .any(|x| matches!(x.tag, Tag::OrderedList | Tag::UnorderedList));
+                    let target = if nested {
+                        self.options.nested_spacing
+                    } else {
+                        self.options.spacing
+                    };
+                    spacing = match target {
+                        Spacing::Preserve => None,
+                        Spacing::Tight => Some(true),
+                        Spacing::Loose => Some(false),
+                    };
+                    if spacing.is_some() {
+                        attrs.tight = spacing;
+                    }
+                }
+                self.tag_stack.push(OpenTag {
+                    tag,
+                    spacing,
+                    removed: false,
+                });
+            }
+            Event::EndTag(EndTagEvent { .. }) => {
+                if let Some(open_tag) = self.tag_stack.pop() {
+                    if open_tag.removed {
+                        self.pending_break = true;
+                        return;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.buffer.push_back(annotated_event);
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for ListSpacingIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+            let annotated_event = self.source.next()?;
+            self.process_event(annotated_event);
+        }
+    }
+}
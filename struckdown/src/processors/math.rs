@@ -0,0 +1,238 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{
+    AnnotatedEvent, ErrorEvent, Event, InlineMathEvent, MathBlockEvent, RawHtmlEvent,
+};
+
+/// The program math is rendered with.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MathEngine {
+    /// Renders HTML with the `katex` command line tool.
+    ///
+    /// The HTML needs the KaTeX stylesheet and fonts.
+    Katex,
+    /// Renders MathML with the `latex2mathml` command line tool.
+    Latex2mathml,
+}
+
+impl Default for MathEngine {
+    fn default() -> MathEngine {
+        MathEngine::Katex
+    }
+}
+
+impl MathEngine {
+    fn default_cmd(self) -> &'static str {
+        match self {
+            MathEngine::Katex => "katex",
+            MathEngine::Latex2mathml => "latex2mathml",
+        }
+    }
+}
+
+/// Renders math to HTML or MathML.
+///
+/// Inline math and math blocks are passed to the command of the `engine`
+/// and replaced by the rendered output:
+///
+/// ```yaml
+/// processors:
+///   - processor: math
+///     engine: latex2mathml
+/// ```
+///
+/// `katex` receives the formula on stdin and `--display-mode` for display
+/// math, `latex2mathml` receives the formula with `-t` and its output is
+/// marked as a block for display math.  Math blocks are wrapped in a
+/// `div` with the class `math display`.  Formulas that fail to render are
+/// left alone and an error is emitted.
+///
+/// The command is subject to the installed [`Sandbox`](crate::sandbox::Sandbox).
+///
+/// This is only available with the `math-processor` feature.
+///
+/// When applied this wraps the stream in a [`MathIter`].
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Math {
+    /// The program math is rendered with.
+    pub engine: MathEngine,
+    /// Overrides the executable of the engine.
+    pub cmd: Option<PathBuf>,
+    /// Optional environment variables to pass.
+    pub env: BTreeMap<String, String>,
+}
+
+implement_processor!(Math, MathIter);
+
+/// The iterator implementing [`Math`].
+pub struct MathIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    options: Cow<'options, Math>,
+    // formulas rendered before keyed by display mode and source
+    cache: HashMap<(bool, String), String>,
+    pending_error: Option<AnnotatedEvent<'data>>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> MathIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Math>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            options: options.into(),
+            cache: HashMap::new(),
+            pending_error: None,
+        }
+    }
+
+    fn run(&self, math: &str, display: bool) -> Result<String, String> {
+        let cmd = match self.options.cmd {
+            Some(ref cmd) => cmd.clone(),
+            None => PathBuf::from(self.options.engine.default_cmd()),
+        };
+        let sandbox = crate::sandbox::current();
+        let program = sandbox.resolve_command(&cmd)?;
+        let (clear_env, env) = sandbox.command_env(&self.options.env)?;
+
+        let mut command = Command::new(&program);
+        if clear_env {
+            command.env_clear();
+        }
+        command
+            .envs(env)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        match self.options.engine {
+            MathEngine::Katex => {
+                if display {
+                    command.arg("--display-mode");
+                }
+                command.stdin(Stdio::piped());
+            }
+            MathEngine::Latex2mathml => {
+                command.arg("-t").arg(math).stdin(Stdio::null());
+            }
+        }
+
+        let mut child = command.spawn().map_err(|err| err.to_string())?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(math.as_bytes())
+                .map_err(|err| err.to_string())?;
+        }
+        let output = child.wait_with_output().map_err(|err| err.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        let rendered = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if display && self.options.engine == MathEngine::Latex2mathml {
+            if let Some(rest) = rendered.strip_prefix("<math") {
+                if !rest.starts_with(|c: char| c.is_alphanumeric()) {
+                    return Ok(format!("<math display=\"block\"{}", rest));
+                }
+            }
+        }
+        Ok(rendered)
+    }
+
+    fn render(&mut self, math: &str, display: bool) -> Result<String, String> {
+        let key = (display, math.to_string());
+        if let Some(rendered) = self.cache.get(&key) {
+            return Ok(rendered.clone());
+        }
+        let rendered = self.run(math, display)?;
+        self.cache.insert(key, rendered.clone());
+        Ok(rendered)
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for MathIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.pending_error.take() {
+            return Some(annotated_event);
+        }
+        let annotated_event = self.source.next()?;
+        let (math, display, block) = match annotated_event.event {
+            Event::InlineMath(InlineMathEvent { ref math, display }) => {
+                (math.as_str().to_string(), display, false)
+            }
+            Event::MathBlock(MathBlockEvent { ref math, .. }) => {
+                (math.as_str().to_string(), true, true)
+            }
+            _ => return Some(annotated_event),
+        };
+        match self.render(&math, display) {
+            Ok(html) => Some(AnnotatedEvent::new(
+                RawHtmlEvent {
+                    html: if block {
+                        format!("<div class=\"math display\">{}</div>\n", html)
+                    } else {
+                        html
+                    }
+                    .into(),
+                },
+                annotated_event.location,
+            )),
+            Err(err) => {
+                self.pending_error = Some(AnnotatedEvent::new(
+                    ErrorEvent {
+                        title: "Failed to render math".into(),
+                        description: Some(err.into()),
+                        code: Some(Code::MathRenderFailure),
+                    },
+                    annotated_event.location.clone(),
+                ));
+                Some(annotated_event)
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_math() {
+    use crate::parser::parse;
+    use crate::processors::Processor;
+
+    // cat echoes the formula in place of katex
+    let processor = Math {
+        cmd: Some("cat".into()),
+        ..Math::default()
+    };
+    let events: Vec<_> = Box::new(processor)
+        .apply(Box::new(parse(
+            "Euler: $e^{i\\pi} + 1 = 0$",
+            &Default::default(),
+        )))
+        .collect();
+    match events.iter().find_map(|x| match x.event {
+        Event::RawHtml(RawHtmlEvent { ref html }) => Some(html.as_str()),
+        _ => None,
+    }) {
+        Some(html) => assert_eq!(html, "e^{i\\pi} + 1 = 0"),
+        None => panic!("math was not rendered"),
+    }
+
+    let processor = Math {
+        cmd: Some("false".into()),
+        ..Math::default()
+    };
+    let events: Vec<_> = Box::new(processor)
+        .apply(Box::new(parse("$x$", &Default::default())))
+        .collect();
+    assert!(events
+        .iter()
+        .any(|x| matches!(x.event, Event::InlineMath(..))));
+    assert!(events.iter().any(|x| matches!(x.event, Event::Error(..))));
+}
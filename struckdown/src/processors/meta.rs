@@ -0,0 +1,154 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, DirectiveEvent, ErrorEvent, Event, MetaDataEvent};
+use crate::value::{Map, Value};
+
+/// Merges `{meta}` directives into the document metadata.
+///
+/// The YAML body (and front matter) of every `{meta}` directive is merged
+/// into the front matter of the document start event so processors further
+/// down the pipeline see it like regular front matter.  Mappings are merged
+/// recursively, later blocks win for all other values.
+///
+/// In place of the directive a `meta` meta data event with the block's
+/// values is emitted so processors can also pick up section specific
+/// metadata (for instance per-chapter authors) at the position it was
+/// declared.  Invalid YAML emits an error.
+///
+/// When applied this wraps the stream in a [`MetaIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Meta {
+    /// The name of the directive holding metadata.
+    pub directive_name: String,
+    /// Controls if the blocks are merged into the document front matter.
+    pub merge_into_front_matter: bool,
+    /// The key of the meta data events emitted for every block.
+    pub metadata_key: String,
+}
+
+impl Default for Meta {
+    fn default() -> Meta {
+        Meta {
+            directive_name: "meta".into(),
+            merge_into_front_matter: true,
+            metadata_key: "meta".into(),
+        }
+    }
+}
+
+implement_processor!(Meta, MetaIter);
+
+/// Recursively merges `other` into `target`.
+fn merge_values(target: &mut Value, other: Value) {
+    match (target, other) {
+        (Value::Object(target), Value::Object(other)) => {
+            for (key, value) in other {
+                match target.get_mut(&key) {
+                    Some(existing) => merge_values(existing, value),
+                    None => {
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        (target, other) => *target = other,
+    }
+}
+
+/// The iterator implementing [`Meta`].
+pub struct MetaIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: Option<I>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Meta>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> MetaIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Meta>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: Some(iterator),
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    fn process(&mut self, iter: I) {
+        let mut merged: Option<Value> = None;
+
+        for annotated_event in iter {
+            let (front_matter, body) = match annotated_event.event {
+                Event::Directive(DirectiveEvent {
+                    ref name,
+                    ref front_matter,
+                    ref body,
+                    ..
+                }) if name.as_str() == self.options.directive_name => (front_matter, body),
+                _ => {
+                    self.buffer.push_back(annotated_event);
+                    continue;
+                }
+            };
+
+            let mut value = front_matter
+                .clone()
+                .unwrap_or_else(|| Value::Object(Map::new()));
+            if !body.as_str().trim().is_empty() {
+                match serde_yaml::from_str::<Value>(body.as_str()) {
+                    Ok(body_value) => merge_values(&mut value, body_value),
+                    Err(err) => {
+                        self.buffer.push_back(AnnotatedEvent::new(
+                            ErrorEvent {
+                                title: "Invalid metadata".into(),
+                                description: Some(err.to_string().into()),
+                                code: Some(Code::InvalidMetadata),
+                            },
+                            annotated_event.location,
+                        ));
+                        continue;
+                    }
+                }
+            }
+
+            merge_values(
+                merged.get_or_insert_with(|| Value::Object(Map::new())),
+                value.clone(),
+            );
+            self.buffer.push_back(AnnotatedEvent::new(
+                MetaDataEvent {
+                    key: self.options.metadata_key.clone().into(),
+                    value,
+                },
+                annotated_event.location,
+            ));
+        }
+
+        if !self.options.merge_into_front_matter {
+            return;
+        }
+        if let (Some(merged), Some(annotated_event)) = (merged, self.buffer.front_mut()) {
+            if let Event::DocumentStart(ref mut document_start) = annotated_event.event {
+                match document_start.front_matter {
+                    Some(ref mut front_matter) => merge_values(front_matter, merged),
+                    None => document_start.front_matter = Some(merged),
+                }
+            }
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for MetaIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(iter) = self.source.take() {
+            self.process(iter);
+        }
+        self.buffer.pop_front()
+    }
+}
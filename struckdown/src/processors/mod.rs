@@ -7,8 +7,45 @@
 #[macro_use]
 mod utils;
 
+mod acronyms;
+mod admonitions;
+mod ansi;
+mod api_signatures;
+mod audience;
 mod autoanchors;
+mod changelog;
+mod cjk_spacing;
+mod code_container;
+mod directives;
+mod equations;
+mod faq;
+mod figures;
+mod heading_adjust;
+mod id_namespace;
+mod image_info;
+mod include;
+mod inline_diff;
+mod json_ld;
+mod language_detection;
+mod link_rewrite;
+mod list_spacing;
+mod meta;
+mod permalinks;
+mod quiz;
+mod redaction;
+mod roles;
+mod sidenotes;
+mod soft_breaks;
+mod spoiler;
+mod steps;
+mod terminology;
+mod theme_tokens;
+mod timed_content;
+mod title_heading;
 mod toc;
+mod typography;
+mod units;
+mod xref;
 
 #[cfg(feature = "external-processor")]
 mod external;
@@ -19,22 +56,88 @@ mod syntect;
 #[cfg(feature = "html-sanitizer-processor")]
 mod html_sanitizer;
 
+#[cfg(feature = "social-card-processor")]
+mod social_card;
+
+#[cfg(feature = "math-processor")]
+mod math;
+
+#[cfg(feature = "wasm-processor")]
+mod wasm;
+
+#[cfg(feature = "plugin")]
+mod plugin;
+
 use serde::Deserialize;
 
 use crate::event::AnnotatedEvent;
 
-pub use self::autoanchors::{AutoAnchors, AutoAnchorsIter};
+pub use self::acronyms::{Acronyms, AcronymsIter};
+pub use self::admonitions::{Admonitions, AdmonitionsIter};
+pub use self::ansi::{Ansi, AnsiIter};
+pub use self::api_signatures::{api_anchor, ApiSignatures, ApiSignaturesIter};
+pub use self::audience::{AudienceFilter, AudienceFilterIter};
+pub use self::autoanchors::{AutoAnchors, AutoAnchorsIter, SlugStrategy};
+pub use self::changelog::{Changelog, ChangelogIter, Release};
+pub use self::cjk_spacing::{CjkSpacing, CjkSpacingIter};
+pub use self::code_container::{CodeContainer, CodeContainerIter};
+pub use self::directives::{
+    DirectiveContext, DirectiveHandler, DirectiveProcessor, DirectiveProcessorIter,
+};
+pub use self::equations::{Equations, EquationsIter};
+pub use self::faq::{Faq, FaqEntry, FaqIter};
+pub use self::figures::{Figures, FiguresIter};
+pub use self::heading_adjust::{HeadingAdjust, HeadingAdjustIter};
+pub use self::id_namespace::{IdNamespace, IdNamespaceIter};
+pub use self::image_info::{ImageInfo, ImageInfoIter};
+pub use self::include::{Include, IncludeIter};
+pub use self::inline_diff::{InlineDiff, InlineDiffIter};
+pub use self::json_ld::{JsonLd, JsonLdIter};
+pub use self::language_detection::{detect_language, LanguageDetection, LanguageDetectionIter};
+pub use self::link_rewrite::{LinkRewrite, LinkRewriteIter, LinkRule};
+pub use self::list_spacing::{ListSpacing, ListSpacingIter, Spacing};
+pub use self::meta::{Meta, MetaIter};
+pub use self::permalinks::{Permalinks, PermalinksIter, SourceRef};
+pub use self::quiz::{Quiz, QuizIter};
+pub use self::redaction::{Redaction, RedactionIter};
+pub use self::roles::{RoleHandler, RoleProcessor, RoleProcessorIter, UnknownRolePolicy};
+pub use self::sidenotes::{SidenoteStyle, Sidenotes, SidenotesIter};
+pub use self::soft_breaks::{SoftBreakPolicy, SoftBreaks, SoftBreaksIter};
+pub use self::spoiler::{Spoiler, SpoilerIter};
+pub use self::steps::{Steps, StepsIter};
+pub use self::terminology::{Terminology, TerminologyAction, TerminologyIter};
+pub use self::theme_tokens::{ThemeTokens, ThemeTokensIter};
+pub use self::timed_content::{InactiveContent, TimedContent, TimedContentIter};
+pub use self::title_heading::{TitleHeading, TitleHeadingIter, TitlePolicy};
 pub use self::toc::{TableOfContents, TableOfContentsIter};
+pub use self::typography::{Typography, TypographyIter};
+pub use self::units::{format_quantity, Units, UnitsIter};
+pub use self::xref::{Reference, ReferenceDatabase, XRef, XRefIter};
 
 #[cfg(feature = "external-processor")]
-pub use self::external::{External, ExternalIter};
+pub use self::external::{External, ExternalIter, OnError, WireFormat};
 
 #[cfg(feature = "syntect-processor")]
-pub use self::syntect::{Syntect, SyntectIter};
+pub use self::syntect::{Syntect, SyntectIter, SyntectOutput};
 
 #[cfg(feature = "html-sanitizer-processor")]
 pub use self::html_sanitizer::{HtmlSanitizer, HtmlSanitizerIter};
 
+#[cfg(feature = "social-card-processor")]
+pub use self::social_card::{SocialCard, SocialCardIter};
+
+#[cfg(feature = "math-processor")]
+pub use self::math::{Math, MathEngine, MathIter};
+
+#[cfg(feature = "wasm-processor")]
+pub use self::wasm::{
+    CompiledModule, WasmProcessor, WasmProcessorIter, DEFAULT_WASM_FUEL, DEFAULT_WASM_MAX_MEMORY,
+    WASM_ABI_VERSION,
+};
+
+#[cfg(feature = "plugin")]
+pub use self::plugin::{LoadedLibrary, Plugin, PluginIter, PLUGIN_ABI_VERSION};
+
 /// Common trait for all stream processors.
 pub trait Processor {
     /// Applies the processor to an event stream.
@@ -52,6 +155,15 @@ pub trait Processor {
         &'options self,
         iter: Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>,
     ) -> Box<dyn Iterator<Item = AnnotatedEvent<'data>> + 'data>;
+
+    /// Returns the name of the processor for reports.
+    ///
+    /// This defaults to the name of the type without module path.
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        let path = name.split('<').next().unwrap_or(name);
+        &name[path.rfind("::").map_or(0, |x| x + 2)..]
+    }
 }
 
 macro_rules! builtin_processors {
@@ -65,6 +177,15 @@ macro_rules! builtin_processors {
             $($(#[$attr])* $name(Box<$name>),)*
         }
 
+        impl BuiltinProcessor {
+            /// Returns the name of the type of the configured processor.
+            pub fn type_name(&self) -> &'static str {
+                match self {
+                    $($(#[$attr])* Self::$name(..) => stringify!($name),)*
+                }
+            }
+        }
+
         impl Processor for BuiltinProcessor {
             fn apply<'data>(
                 self: Box<Self>,
@@ -83,6 +204,10 @@ macro_rules! builtin_processors {
                     $($(#[$attr])* Self::$name(options) => options.apply_ref(iter),)*
                 }
             }
+
+            fn name(&self) -> &'static str {
+                self.type_name()
+            }
         }
     };
 }
@@ -90,10 +215,53 @@ macro_rules! builtin_processors {
 builtin_processors! {
     type AutoAnchors;
     type TableOfContents;
+    type Redaction;
+    type Terminology;
+    type CjkSpacing;
+    type LanguageDetection;
+    type Ansi;
+    type CodeContainer;
+    type Permalinks;
+    type Steps;
+    type Faq;
+    type ApiSignatures;
+    type Changelog;
+    type Quiz;
+    type Spoiler;
+    type Acronyms;
+    type InlineDiff;
+    type Units;
+    type Typography;
+    type JsonLd;
+    type Equations;
+    type Sidenotes;
+    type Meta;
+    type SoftBreaks;
+    type Figures;
+    type TitleHeading;
+    type ListSpacing;
+    type Admonitions;
+    type Include;
+    type HeadingAdjust;
+    type LinkRewrite;
+    type ImageInfo;
+    type IdNamespace;
+    type TimedContent;
+    type AudienceFilter;
+    type XRef;
+    type ThemeTokens;
     #[cfg(feature = "external-processor")]
     type External;
     #[cfg(feature = "syntect-processor")]
     type Syntect;
     #[cfg(feature = "html-sanitizer-processor")]
     type HtmlSanitizer;
+    #[cfg(feature = "social-card-processor")]
+    type SocialCard;
+    #[cfg(feature = "math-processor")]
+    type Math;
+    #[cfg(feature = "wasm-processor")]
+    type WasmProcessor;
+    #[cfg(feature = "plugin")]
+    type Plugin;
 }
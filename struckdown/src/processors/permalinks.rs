@@ -0,0 +1,322 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+#[cfg(feature = "permalink-fetch")]
+use std::collections::{hash_map::DefaultHasher, HashMap};
+#[cfg(feature = "permalink-fetch")]
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "permalink-fetch")]
+use std::path::PathBuf;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, CodeBlockEvent, ErrorEvent, Event, Str};
+
+lazy_static! {
+    static ref SOURCE_RE: Regex = Regex::new(
+        r"^(github|gitlab):([^/\s]+)/([^/\s]+)/([^@#\s]+)(?:@([^#\s]+))?(?:#L(\d+)(?:-L?(\d+))?)?$"
+    )
+    .unwrap();
+}
+
+/// Resolves code block sources to permalinks.
+///
+/// Code blocks with a source argument such as
+/// `source="github:org/repo/path/to/file.rs@v1.0#L10-L20"` get a
+/// `permalink` argument with the canonical URL of the referenced lines
+/// attached.  The line range is optional.  The ref (`@v1.0`) can only be
+/// left out if `default_ref` is set as otherwise the link would not be
+/// stable.  Supported providers are `github` and `gitlab`.
+///
+/// With the `permalink-fetch` feature enabled and `fetch` turned on, empty
+/// code blocks are filled with the referenced lines.  Fetched files are
+/// cached in memory and optionally in `cache_dir`.  Fetching fails if the
+/// installed [`Sandbox`](crate::sandbox::Sandbox) disallows network access.
+///
+/// When applied this wraps the stream in a [`PermalinksIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Permalinks {
+    /// The code block argument holding the source reference.
+    pub arg: String,
+    /// The git ref to link to if the source does not specify one.
+    pub default_ref: Option<String>,
+    /// Enables fetching of the referenced lines into empty code blocks.
+    #[cfg(feature = "permalink-fetch")]
+    pub fetch: bool,
+    /// An optional folder to cache fetched files in.
+    #[cfg(feature = "permalink-fetch")]
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Default for Permalinks {
+    fn default() -> Permalinks {
+        Permalinks {
+            arg: "source".into(),
+            default_ref: None,
+            #[cfg(feature = "permalink-fetch")]
+            fetch: false,
+            #[cfg(feature = "permalink-fetch")]
+            cache_dir: None,
+        }
+    }
+}
+
+implement_processor!(Permalinks, PermalinksIter);
+
+/// A parsed code block source reference.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceRef {
+    /// The hosting provider (`github` or `gitlab`).
+    pub provider: String,
+    /// The owning organization or user.
+    pub org: String,
+    /// The repository name.
+    pub repo: String,
+    /// The path of the file within the repository.
+    pub path: String,
+    /// The git ref.
+    pub git_ref: String,
+    /// The optional line range (1 indexed, inclusive).
+    pub lines: Option<(usize, usize)>,
+}
+
+impl SourceRef {
+    /// Parses a source reference.
+    ///
+    /// On failure the reason is returned as error.
+    pub fn parse(source: &str, default_ref: Option<&str>) -> Result<SourceRef, String> {
+        let m = SOURCE_RE.captures(source.trim()).ok_or_else(|| {
+            "expected a reference like github:org/repo/path@ref#L1-L10".to_string()
+        })?;
+        let git_ref = m
+            .get(5)
+            .map(|x| x.as_str())
+            .or(default_ref)
+            .ok_or_else(|| "the reference is missing a git ref like @v1.0".to_string())?;
+        let lines = match m.get(6).and_then(|x| x.as_str().parse().ok()) {
+            Some(start) => {
+                let end = m.get(7).and_then(|x| x.as_str().parse().ok());
+                let end = end.unwrap_or(start);
+                if start > end {
+                    return Err(format!("line range L{}-L{} is reversed", start, end));
+                }
+                Some((start, end))
+            }
+            None => None,
+        };
+        Ok(SourceRef {
+            provider: m[1].to_string(),
+            org: m[2].to_string(),
+            repo: m[3].to_string(),
+            path: m[4].to_string(),
+            git_ref: git_ref.to_string(),
+            lines,
+        })
+    }
+
+    /// Returns the canonical URL to view the referenced lines.
+    pub fn permalink(&self) -> String {
+        let (base, fragment) = match self.provider.as_str() {
+            "gitlab" => (
+                format!(
+                    "https://gitlab.com/{}/{}/-/blob/{}/{}",
+                    self.org, self.repo, self.git_ref, self.path
+                ),
+                self.lines
+                    .map(|(start, end)| format!("#L{}-{}", start, end)),
+            ),
+            _ => (
+                format!(
+                    "https://github.com/{}/{}/blob/{}/{}",
+                    self.org, self.repo, self.git_ref, self.path
+                ),
+                self.lines
+                    .map(|(start, end)| format!("#L{}-L{}", start, end)),
+            ),
+        };
+        match fragment {
+            Some(fragment) => base + &fragment,
+            None => base,
+        }
+    }
+
+    /// Returns the URL of the raw file contents.
+    pub fn raw_url(&self) -> String {
+        match self.provider.as_str() {
+            "gitlab" => format!(
+                "https://gitlab.com/{}/{}/-/raw/{}/{}",
+                self.org, self.repo, self.git_ref, self.path
+            ),
+            _ => format!(
+                "https://raw.githubusercontent.com/{}/{}/{}/{}",
+                self.org, self.repo, self.git_ref, self.path
+            ),
+        }
+    }
+
+    /// Selects the referenced lines from the file contents.
+    pub fn select_lines(&self, contents: &str) -> String {
+        match self.lines {
+            Some((start, end)) => {
+                let mut rv = String::new();
+                for line in contents
+                    .lines()
+                    .skip(start.saturating_sub(1))
+                    .take(end.saturating_add(1).saturating_sub(start))
+                {
+                    rv.push_str(line);
+                    rv.push('\n');
+                }
+                rv
+            }
+            None => contents.to_string(),
+        }
+    }
+}
+
+/// The iterator implementing [`Permalinks`].
+pub struct PermalinksIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    #[cfg(feature = "permalink-fetch")]
+    cache: HashMap<String, Result<String, String>>,
+    options: Cow<'options, Permalinks>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    PermalinksIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, Permalinks>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            #[cfg(feature = "permalink-fetch")]
+            cache: HashMap::new(),
+            options: options.into(),
+        }
+    }
+
+    #[cfg(feature = "permalink-fetch")]
+    fn fetch(&mut self, url: &str) -> Result<String, String> {
+        if let Some(rv) = self.cache.get(url) {
+            return rv.clone();
+        }
+
+        let cache_path = self.options.cache_dir.as_ref().map(|dir| {
+            let mut hasher = DefaultHasher::new();
+            url.hash(&mut hasher);
+            dir.join(format!("{:016x}", hasher.finish()))
+        });
+        if let Some(contents) = cache_path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+        {
+            return Ok(contents);
+        }
+
+        crate::sandbox::current().check_network()?;
+        let response = ureq::get(url).call();
+        let rv = if response.ok() {
+            response.into_string().map_err(|err| err.to_string())
+        } else if let Some(err) = response.synthetic_error() {
+            Err(err.to_string())
+        } else {
+            Err(format!(
+                "server responded with status {}",
+                response.status()
+            ))
+        };
+
+        if let (Ok(contents), Some(path)) = (&rv, &cache_path) {
+            std::fs::create_dir_all(path.parent().unwrap()).ok();
+            std::fs::write(path, contents).ok();
+        }
+        self.cache.insert(url.to_string(), rv.clone());
+        rv
+    }
+
+    fn resolve(&mut self, code_block: &mut CodeBlockEvent<'data>) -> Option<ErrorEvent<'data>> {
+        let source = code_block
+            .args
+            .as_ref()?
+            .iter()
+            .find(|(key, _)| key.as_str() == self.options.arg)
+            .map(|(_, value)| value.as_str().to_string())?;
+
+        let source_ref = match SourceRef::parse(&source, self.options.default_ref.as_deref()) {
+            Ok(source_ref) => source_ref,
+            Err(err) => {
+                return Some(ErrorEvent {
+                    title: format!("Invalid code source '{}'", source).into(),
+                    description: Some(err.into()),
+                    code: Some(Code::InvalidCodeSource),
+                })
+            }
+        };
+
+        #[cfg(feature = "permalink-fetch")]
+        {
+            if self.options.fetch && code_block.code.as_str().trim().is_empty() {
+                match self.fetch(&source_ref.raw_url()) {
+                    Ok(contents) => code_block.code = source_ref.select_lines(&contents).into(),
+                    Err(err) => {
+                        return Some(ErrorEvent {
+                            title: format!("Failed to fetch code source '{}'", source).into(),
+                            description: Some(err.into()),
+                            code: Some(Code::CodeSourceFetchFailure),
+                        })
+                    }
+                }
+            }
+        }
+
+        code_block
+            .args
+            .get_or_insert_with(Default::default)
+            .insert(Str::new("permalink"), source_ref.permalink().into());
+        None
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for PermalinksIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let mut annotated_event = self.source.next()?;
+        if let Event::CodeBlock(ref mut code_block) = annotated_event.event {
+            if let Some(error) = self.resolve(code_block) {
+                self.buffer.push_back(annotated_event);
+                return Some(error.into());
+            }
+        }
+        Some(annotated_event)
+    }
+}
+
+#[test]
+fn test_select_lines() {
+    let source_ref = SourceRef::parse("github:org/repo/file.txt@v1#L2-L3", None).unwrap();
+    assert_eq!(source_ref.select_lines("a\nb\nc\nd\n"), "b\nc\n");
+
+    let source_ref =
+        SourceRef::parse("github:org/repo/file.txt@v1#L2-L18446744073709551615", None).unwrap();
+    assert_eq!(source_ref.select_lines("a\nb\nc\n"), "b\nc\n");
+
+    assert!(SourceRef::parse("github:org/repo/file.txt@v1#L3-L2", None).is_err());
+    assert!(SourceRef::parse("github:org/repo/file.txt", None).is_err());
+    assert_eq!(
+        SourceRef::parse("github:org/repo/file.txt", Some("v1"))
+            .unwrap()
+            .git_ref,
+        "v1"
+    );
+}
@@ -0,0 +1,319 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+use std::sync::{Arc, Mutex};
+
+use libloading::{Library, Symbol};
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, ErrorEvent, Event};
+use crate::json::{stream_from_json, stream_to_json};
+use crate::value::Value;
+
+/// The version of the ABI plugins have to implement.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type CreateFn = unsafe extern "C" fn(options: *const u8, len: usize) -> *mut c_void;
+type ProcessFn = unsafe extern "C" fn(
+    processor: *mut c_void,
+    input: *const u8,
+    len: usize,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int;
+type FreeBufferFn = unsafe extern "C" fn(buffer: *mut u8, len: usize);
+type DestroyFn = unsafe extern "C" fn(processor: *mut c_void);
+
+/// Passes a serialized stream through a native plugin.
+///
+/// This loads a dynamic library so that processors which cannot be
+/// published as source can be configured like the built-in ones:
+///
+/// ```yaml
+/// processors:
+///   - processor: plugin
+///     path: /opt/acme/libacme_glossary.so
+///     options:
+///       glossary: terms.yml
+/// ```
+///
+/// The library has to export these C functions:
+///
+/// ```c
+/// uint32_t struckdown_plugin_abi_version(void);
+/// void *struckdown_processor_create(const uint8_t *options, size_t len);
+/// int struckdown_processor_process(void *processor,
+///                                  const uint8_t *input, size_t len,
+///                                  uint8_t **output, size_t *output_len);
+/// void struckdown_processor_free_buffer(uint8_t *buffer, size_t len);
+/// void struckdown_processor_destroy(void *processor);
+/// ```
+///
+/// `struckdown_plugin_abi_version` returns [`PLUGIN_ABI_VERSION`].
+/// `struckdown_processor_create` receives `options` as JSON and returns
+/// the processor or null if the options are invalid.  The input of
+/// `struckdown_processor_process` is the event stream of the whole
+/// document as JSON lines (see [`json`](crate::json)).  It stores a buffer
+/// in `output` and returns `0` on success, the buffer then holds the
+/// processed stream as JSON lines, otherwise an error message.  Buffers are
+/// released with `struckdown_processor_free_buffer`.
+///
+/// If the plugin cannot be loaded or fails an error is emitted and the
+/// document is passed on unprocessed.  Plugins run in the process and are
+/// not sandboxed, only the library path is checked against the installed
+/// [`Sandbox`](crate::sandbox::Sandbox).  The library is loaded once per
+/// processor.
+///
+/// This is only available with the `plugin` feature.
+///
+/// When applied this wraps the stream in a [`PluginIter`].
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Plugin {
+    /// The path to the dynamic library.
+    pub path: PathBuf,
+    /// The options passed to the plugin.
+    pub options: Value,
+    /// The library loaded on first use.
+    #[serde(skip)]
+    pub library: LoadedLibrary,
+}
+
+/// Holds the loaded library of a [`Plugin`].
+///
+/// Clones start out empty so that every processor loads the library itself.
+#[derive(Default)]
+pub struct LoadedLibrary(Mutex<Option<Result<Arc<Library>, String>>>);
+
+impl LoadedLibrary {
+    fn get(&self, options: &Plugin) -> Result<Arc<Library>, String> {
+        let mut library = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        library
+            .get_or_insert_with(|| {
+                Library::new(&options.path)
+                    .map(Arc::new)
+                    .map_err(|err| err.to_string())
+            })
+            .clone()
+    }
+}
+
+impl Clone for LoadedLibrary {
+    fn clone(&self) -> LoadedLibrary {
+        LoadedLibrary::default()
+    }
+}
+
+impl fmt::Debug for LoadedLibrary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadedLibrary").finish()
+    }
+}
+
+implement_processor!(Plugin, PluginIter);
+
+fn symbol<'lib, T>(library: &'lib Library, name: &str) -> Result<Symbol<'lib, T>, String> {
+    let mut name = name.as_bytes().to_vec();
+    name.push(0);
+    unsafe { library.get(&name) }.map_err(|err| err.to_string())
+}
+
+/// Runs the plugin on a serialized document.
+fn run_plugin(options: &Plugin, input: &[u8]) -> Result<Vec<u8>, String> {
+    crate::sandbox::current().check_read(&options.path)?;
+    let library = options.library.get(options)?;
+
+    let abi_version = symbol::<AbiVersionFn>(&library, "struckdown_plugin_abi_version")?;
+    let create = symbol::<CreateFn>(&library, "struckdown_processor_create")?;
+    let process = symbol::<ProcessFn>(&library, "struckdown_processor_process")?;
+    let free_buffer = symbol::<FreeBufferFn>(&library, "struckdown_processor_free_buffer")?;
+    let destroy = symbol::<DestroyFn>(&library, "struckdown_processor_destroy")?;
+
+    let version = unsafe { abi_version() };
+    if version != PLUGIN_ABI_VERSION {
+        return Err(format!(
+            "plugin implements ABI version {}, expected {}",
+            version, PLUGIN_ABI_VERSION
+        ));
+    }
+
+    let plugin_options = serde_json::to_vec(&options.options).map_err(|err| err.to_string())?;
+    let processor = unsafe { create(plugin_options.as_ptr(), plugin_options.len()) };
+    if processor.is_null() {
+        return Err("plugin rejected the options".into());
+    }
+
+    let mut output = ptr::null_mut();
+    let mut output_len = 0;
+    let rv = unsafe {
+        process(
+            processor,
+            input.as_ptr(),
+            input.len(),
+            &mut output,
+            &mut output_len,
+        )
+    };
+    let output_buffer = if output.is_null() {
+        Vec::new()
+    } else {
+        let buffer = unsafe { slice::from_raw_parts(output, output_len) }.to_vec();
+        unsafe { free_buffer(output, output_len) };
+        buffer
+    };
+    unsafe { destroy(processor) };
+
+    if rv == 0 {
+        Ok(output_buffer)
+    } else {
+        Err(String::from_utf8_lossy(&output_buffer).into_owned())
+    }
+}
+
+/// The iterator implementing [`Plugin`].
+pub struct PluginIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: Option<I>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Plugin>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> PluginIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Plugin>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: Some(iterator),
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for PluginIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(source) = self.source.take() {
+            let events: Vec<_> = source.collect();
+            let input = stream_to_json(events.iter().cloned());
+            let output = run_plugin(&self.options, input.as_bytes()).and_then(|output| {
+                let output = String::from_utf8(output).map_err(|err| err.to_string())?;
+                stream_from_json(&output).map_err(|err| err.to_string())
+            });
+            match output {
+                Ok(output) => self.buffer.extend(output),
+                Err(err) => {
+                    self.buffer.extend(events);
+                    // the error goes right after the document start
+                    let idx = match self.buffer.front().map(|x| &x.event) {
+                        Some(Event::DocumentStart(..)) => 1,
+                        _ => 0,
+                    };
+                    self.buffer.insert(
+                        idx,
+                        ErrorEvent {
+                            title: format!(
+                                "Failed to run plugin '{}'",
+                                self.options.path.display()
+                            )
+                            .into(),
+                            description: Some(err.into()),
+                            code: Some(Code::PluginFailure),
+                        }
+                        .into(),
+                    );
+                }
+            }
+        }
+        self.buffer.pop_front()
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+fn compile_plugin(name: &str, version: u32, full: bool) -> PathBuf {
+    use std::process::Command;
+
+    let dir = std::env::temp_dir();
+    let source = dir.join(format!("struckdown-plugin-{}.c", name));
+    let path = dir.join(format!("struckdown-plugin-{}.so", name));
+    std::fs::write(
+        &source,
+        "#include <stddef.h>\n\
+         #include <stdint.h>\n\
+         uint32_t struckdown_plugin_abi_version(void) { return VERSION; }\n\
+         #ifdef FULL\n\
+         void *struckdown_processor_create(const uint8_t *o, size_t l) { return (void *)1; }\n\
+         int struckdown_processor_process(void *p, const uint8_t *i, size_t l,\n\
+                                          uint8_t **o, size_t *ol) { return 1; }\n\
+         void struckdown_processor_free_buffer(uint8_t *b, size_t l) {}\n\
+         void struckdown_processor_destroy(void *p) {}\n\
+         #endif\n",
+    )
+    .unwrap();
+    let mut cmd = Command::new("cc");
+    cmd.args(&["-shared", "-fPIC"])
+        .arg(format!("-DVERSION={}", version))
+        .arg("-o")
+        .arg(&path)
+        .arg(&source);
+    if full {
+        cmd.arg("-DFULL");
+    }
+    assert!(cmd.status().unwrap().success());
+    std::fs::remove_file(&source).ok();
+    path
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_plugin_failures() {
+    use crate::parser::parse;
+    use crate::processors::Processor;
+
+    let failure = |plugin: &Plugin| {
+        let events: Vec<_> = plugin
+            .apply_ref(Box::new(parse("Hello", &Default::default())))
+            .collect();
+        assert_eq!(events.len(), 5);
+        match events[1].event {
+            Event::Error(ErrorEvent {
+                ref description, ..
+            }) => description.as_ref().unwrap().as_str().to_string(),
+            ref other => panic!("unexpected event {:?}", other),
+        }
+    };
+
+    let missing = Plugin {
+        path: std::env::temp_dir().join("struckdown-plugin-missing.so"),
+        ..Plugin::default()
+    };
+    assert!(failure(&missing).contains("struckdown-plugin-missing.so"));
+
+    let incomplete = Plugin {
+        path: compile_plugin("incomplete", PLUGIN_ABI_VERSION, false),
+        ..Plugin::default()
+    };
+    assert!(failure(&incomplete).contains("struckdown_processor_create"));
+
+    let outdated = Plugin {
+        path: compile_plugin("outdated", PLUGIN_ABI_VERSION + 1, true),
+        ..Plugin::default()
+    };
+    let expected = format!(
+        "plugin implements ABI version {}, expected {}",
+        PLUGIN_ABI_VERSION + 1,
+        PLUGIN_ABI_VERSION
+    );
+    assert_eq!(failure(&outdated), expected);
+
+    // the library stays loaded for further documents
+    std::fs::remove_file(&outdated.path).unwrap();
+    assert_eq!(failure(&outdated), expected);
+    std::fs::remove_file(&incomplete.path).ok();
+}
@@ -0,0 +1,280 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{
+    AnnotatedEvent, Attrs, DirectiveEvent, ErrorEvent, Event, Location, Tag, TextEvent,
+};
+use crate::processors::utils::{block_tag, parse_body, split_blocks, strip_outer};
+
+/// Expands directives for educational content.
+///
+/// A `{quiz}` directive holds a question followed by a task list with the
+/// answer options.  Checked options are the correct answers:
+///
+/// ````text
+/// ```{quiz}
+/// What is 2 + 2?
+///
+/// - [ ] 3
+/// - [x] 4
+/// ```
+/// ````
+///
+/// The output is a `quiz` container with a `quiz-question` container, a
+/// `quiz-options` list without the checkboxes and a [`Tag::Details`] tag
+/// with the class `quiz-answer` revealing the correct options.
+///
+/// An `{exercise}` directive becomes an `exercise` container with the
+/// argument as `exercise-title` and a `{solution}` directive becomes a
+/// `solution` details tag.  Solutions can be nested into exercises.
+///
+/// When applied this wraps the stream in a [`QuizIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Quiz {
+    /// The name of the quiz directive.
+    pub quiz_name: String,
+    /// The name of the exercise directive.
+    pub exercise_name: String,
+    /// The name of the solution directive.
+    pub solution_name: String,
+    /// The summary of the revealed quiz answer.
+    pub answer_label: String,
+    /// The summary of solutions without an argument.
+    pub solution_label: String,
+}
+
+impl Default for Quiz {
+    fn default() -> Quiz {
+        Quiz {
+            quiz_name: "quiz".into(),
+            exercise_name: "exercise".into(),
+            solution_name: "solution".into(),
+            answer_label: "Answer".into(),
+            solution_label: "Solution".into(),
+        }
+    }
+}
+
+implement_processor!(Quiz, QuizIter);
+
+fn class_attrs(class: &str) -> Attrs<'static> {
+    Attrs {
+        class: Some(class.to_string().into()),
+        ..Attrs::default()
+    }
+}
+
+/// Pushes a details tag with a plain text summary.
+fn push_details(
+    rv: &mut Vec<AnnotatedEvent<'static>>,
+    class: &str,
+    summary: &str,
+    body: Vec<AnnotatedEvent<'static>>,
+) {
+    rv.push(Tag::Details.start_tag(class_attrs(class)).into());
+    rv.push(Tag::Summary.start_tag(Attrs::default()).into());
+    rv.push(
+        TextEvent {
+            text: summary.to_string().into(),
+        }
+        .into(),
+    );
+    rv.push(Tag::Summary.end_tag().into());
+    rv.extend(body);
+    rv.push(Tag::Details.end_tag().into());
+}
+
+/// Splits a list item into its checked state and the contents.
+fn split_option(
+    item: Vec<AnnotatedEvent<'static>>,
+) -> Option<(bool, Vec<AnnotatedEvent<'static>>)> {
+    let mut contents = strip_outer(item);
+    let idx = contents
+        .iter()
+        .take(2)
+        .position(|x| matches!(x.event, Event::Checkbox(..)))?;
+    match contents.remove(idx).event {
+        Event::Checkbox(checkbox) => Some((checkbox.checked, contents)),
+        _ => None,
+    }
+}
+
+/// The iterator implementing [`Quiz`].
+pub struct QuizIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Quiz>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> QuizIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Quiz>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    fn expand_quiz(
+        &self,
+        body: &str,
+        body_location: Option<&Location>,
+    ) -> Result<Vec<AnnotatedEvent<'static>>, ErrorEvent<'static>> {
+        let mut question = vec![];
+        let mut options = None;
+
+        for block in split_blocks(parse_body(body, body_location)) {
+            if options.is_none()
+                && matches!(
+                    block_tag(&block),
+                    Some(Tag::UnorderedList) | Some(Tag::OrderedList)
+                )
+            {
+                let parsed = split_blocks(strip_outer(block.clone()))
+                    .into_iter()
+                    .map(split_option)
+                    .collect::<Option<Vec<_>>>();
+                if let Some(parsed) = parsed {
+                    options = Some(parsed);
+                    continue;
+                }
+            }
+            question.extend(block);
+        }
+
+        let options = options.ok_or_else(|| ErrorEvent {
+            title: "Quiz without answer options".into(),
+            description: Some("expected a task list with the correct answers checked".into()),
+            code: Some(Code::QuizWithoutOptions),
+        })?;
+        let correct = options.iter().filter(|(checked, _)| *checked).count();
+
+        let mut attrs = class_attrs("quiz");
+        if correct > 1 {
+            let mut custom = BTreeMap::new();
+            custom.insert("data-multiple".into(), "true".into());
+            attrs.custom = Some(custom);
+        }
+
+        let mut rv = vec![Tag::Container.start_tag(attrs).into()];
+        rv.push(
+            Tag::Container
+                .start_tag(class_attrs("quiz-question"))
+                .into(),
+        );
+        rv.extend(question);
+        rv.push(Tag::Container.end_tag().into());
+
+        let mut answers = vec![Tag::UnorderedList.start_tag(Attrs::default()).into()];
+        rv.push(
+            Tag::UnorderedList
+                .start_tag(class_attrs("quiz-options"))
+                .into(),
+        );
+        for (checked, contents) in options {
+            if checked {
+                answers.push(Tag::ListItem.start_tag(Attrs::default()).into());
+                answers.extend(contents.iter().cloned());
+                answers.push(Tag::ListItem.end_tag().into());
+            }
+            rv.push(Tag::ListItem.start_tag(class_attrs("quiz-option")).into());
+            rv.extend(contents);
+            rv.push(Tag::ListItem.end_tag().into());
+        }
+        rv.push(Tag::UnorderedList.end_tag().into());
+        answers.push(Tag::UnorderedList.end_tag().into());
+
+        push_details(&mut rv, "quiz-answer", &self.options.answer_label, answers);
+        rv.push(Tag::Container.end_tag().into());
+        Ok(rv)
+    }
+
+    fn expand_exercise(
+        &self,
+        title: Option<&str>,
+        body: &str,
+        body_location: Option<&Location>,
+    ) -> Vec<AnnotatedEvent<'static>> {
+        let mut rv = vec![Tag::Container.start_tag(class_attrs("exercise")).into()];
+        if let Some(title) = title {
+            rv.push(
+                Tag::Container
+                    .start_tag(class_attrs("exercise-title"))
+                    .into(),
+            );
+            rv.push(
+                TextEvent {
+                    text: title.to_string().into(),
+                }
+                .into(),
+            );
+            rv.push(Tag::Container.end_tag().into());
+        }
+        rv.extend(parse_body(body, body_location));
+        rv.push(Tag::Container.end_tag().into());
+        rv
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for QuizIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // buffered events are processed again as exercises can contain
+        // solutions.
+        loop {
+            let annotated_event = match self.buffer.pop_front() {
+                Some(annotated_event) => annotated_event,
+                None => self.source.next()?,
+            };
+
+            let expanded = match annotated_event.event {
+                Event::Directive(DirectiveEvent {
+                    ref name,
+                    ref argument,
+                    ref body,
+                    ref body_location,
+                    ..
+                }) => {
+                    let title = argument.as_ref().map(|x| x.as_str().trim());
+                    if name.as_str() == self.options.quiz_name {
+                        match self.expand_quiz(body.as_str(), body_location.as_ref()) {
+                            Ok(events) => events,
+                            Err(error) => vec![error.into()],
+                        }
+                    } else if name.as_str() == self.options.exercise_name {
+                        self.expand_exercise(title, body.as_str(), body_location.as_ref())
+                    } else if name.as_str() == self.options.solution_name {
+                        let mut rv = vec![];
+                        push_details(
+                            &mut rv,
+                            "solution",
+                            title.unwrap_or(&self.options.solution_label),
+                            parse_body(body.as_str(), body_location.as_ref()),
+                        );
+                        rv
+                    } else {
+                        return Some(annotated_event);
+                    }
+                }
+                _ => return Some(annotated_event),
+            };
+
+            // an empty expansion removes the directive, continue with the
+            // next event.
+            let mut expanded = expanded.into_iter();
+            if let Some(first) = expanded.next() {
+                for annotated_event in expanded.rev() {
+                    self.buffer.push_front(annotated_event);
+                }
+                return Some(AnnotatedEvent::new(first.event, annotated_event.location));
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, DirectiveEvent, DocumentStartEvent, Event, InterpretedTextEvent, Location,
+    StartTagEvent, Tag, TextEvent,
+};
+use crate::processors::utils::{skip_element, MetaDataReport};
+use crate::value::Value;
+
+/// Removes content meant for a restricted audience.
+///
+/// Content is matched by role (`` {internal}`secret` ``), by directive name,
+/// by class on a start tag or by a truthy key in the front matter of a
+/// directive or the document.  Matched content is removed or replaced by
+/// the `replacement` text.  A report of everything that was removed is
+/// emitted as `redactions` meta data.
+///
+/// When applied this wraps the stream in a [`RedactionIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Redaction {
+    /// Roles whose interpreted text is removed.
+    pub roles: Vec<String>,
+    /// Directives that are removed.
+    pub directives: Vec<String>,
+    /// Classes that cause a tag to be removed with all of its contents.
+    pub classes: Vec<String>,
+    /// Front matter keys that cause a directive or the entire document to
+    /// be removed if set to a truthy value.
+    pub front_matter_keys: Vec<String>,
+    /// Optional text to emit in place of removed content.
+    pub replacement: Option<String>,
+    /// Controls if the report should be emitted as meta data.
+    pub emit_metadata: bool,
+}
+
+impl Default for Redaction {
+    fn default() -> Redaction {
+        Redaction {
+            roles: vec!["internal".into()],
+            directives: vec!["internal".into()],
+            classes: vec!["internal".into()],
+            front_matter_keys: vec!["draft".into()],
+            replacement: None,
+            emit_metadata: true,
+        }
+    }
+}
+
+implement_processor!(Redaction, RedactionIter);
+
+/// A single entry in the redaction report.
+#[derive(Serialize, Debug)]
+pub struct Redacted {
+    /// What caused the removal (`role`, `directive`, `front_matter`, `class`
+    /// or `document`).
+    kind: &'static str,
+    /// The name of the role, directive, class or front matter key.
+    name: String,
+    /// The location of the removed content if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<Location>,
+}
+
+/// The iterator implementing [`Redaction`].
+pub struct RedactionIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    report: MetaDataReport<Redacted>,
+    options: Cow<'options, Redaction>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+}
+
+/// Returns `true` for tags that can appear within a block of text.
+fn is_inline_tag(tag: Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis
+            | Tag::EmphasisAlt
+            | Tag::Strong
+            | Tag::Strikethrough
+            | Tag::Link
+            | Tag::Span
+            | Tag::Spoiler
+            | Tag::Abbreviation
+            | Tag::Insertion
+            | Tag::Deletion
+            | Tag::Sidenote
+    )
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> RedactionIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Redaction>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            report: MetaDataReport::new(),
+            options: options.into(),
+            buffer: VecDeque::new(),
+        }
+    }
+
+    fn flagged_key(&self, front_matter: &Option<Value>) -> Option<&str> {
+        let front_matter = front_matter.as_ref()?;
+        self.options
+            .front_matter_keys
+            .iter()
+            .find(|key| match front_matter.get(key.as_str()) {
+                None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+                Some(_) => true,
+            })
+            .map(|x| x.as_str())
+    }
+
+    fn record(&mut self, kind: &'static str, name: &str, location: Option<Location>) {
+        self.report.push(Redacted {
+            kind,
+            name: name.to_string(),
+            location,
+        });
+    }
+
+    /// Queues the replacement text if one is configured.  Removed blocks
+    /// are replaced by a paragraph so the text does not end up loose
+    /// between blocks.
+    fn replace(&mut self, location: Option<Location>, block: bool) {
+        let text = match self.options.replacement {
+            Some(ref text) => text.clone(),
+            None => return,
+        };
+        if block {
+            self.buffer.push_back(AnnotatedEvent::new(
+                Tag::Paragraph.start_tag(Default::default()),
+                location.clone(),
+            ));
+        }
+        self.buffer.push_back(AnnotatedEvent::new(
+            TextEvent { text: text.into() },
+            location,
+        ));
+        if block {
+            self.buffer.push_back(Tag::Paragraph.end_tag().into());
+        }
+    }
+
+    fn finish(&mut self) -> Option<AnnotatedEvent<'data>> {
+        self.report
+            .finish("redactions", self.options.emit_metadata, |entries| entries)
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for RedactionIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+
+            let annotated_event = match self.source.next() {
+                Some(annotated_event) => annotated_event,
+                None => return self.finish(),
+            };
+
+            match annotated_event.event {
+                Event::DocumentStart(DocumentStartEvent { ref front_matter }) => {
+                    if let Some(key) = self.flagged_key(front_matter).map(|x| x.to_string()) {
+                        self.record("document", &key, annotated_event.location.clone());
+                        self.source.by_ref().for_each(drop);
+                    }
+                }
+                Event::InterpretedText(InterpretedTextEvent { ref role, .. })
+                    if self.options.roles.iter().any(|x| x == role.as_str()) =>
+                {
+                    let location = annotated_event.location;
+                    self.record("role", role.as_str(), location.clone());
+                    self.replace(location, false);
+                    continue;
+                }
+                Event::Directive(DirectiveEvent {
+                    ref name,
+                    ref front_matter,
+                    ..
+                }) => {
+                    let reason = if self.options.directives.iter().any(|x| x == name.as_str()) {
+                        Some(("directive", name.as_str().to_string()))
+                    } else {
+                        self.flagged_key(front_matter)
+                            .map(|key| ("front_matter", key.to_string()))
+                    };
+                    if let Some((kind, name)) = reason {
+                        let location = annotated_event.location;
+                        self.record(kind, &name, location.clone());
+                        self.replace(location, true);
+                        continue;
+                    }
+                }
+                Event::StartTag(StartTagEvent { tag, ref attrs }) => {
+                    let class = attrs.class.as_ref().and_then(|class| {
+                        class
+                            .as_str()
+                            .split_whitespace()
+                            .find(|x| self.options.classes.iter().any(|c| c == x))
+                            .map(|x| x.to_string())
+                    });
+                    if let Some(class) = class {
+                        let location = annotated_event.location;
+                        self.record("class", &class, location.clone());
+                        skip_element(&mut self.source);
+                        self.replace(location, !is_inline_tag(tag));
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+
+            return Some(annotated_event);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{
+    AnnotatedEvent, DiagnosticEvent, ErrorEvent, Event, InterpretedTextEvent, Severity,
+};
+
+/// What happens to interpreted text with a role that is not registered.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownRolePolicy {
+    /// The interpreted text is passed through unchanged.
+    Keep,
+    /// The interpreted text is passed through unchanged and a warning is
+    /// emitted as diagnostic.
+    Warn,
+    /// The interpreted text is replaced by an error.
+    Error,
+    /// The interpreted text is removed.
+    Drop,
+}
+
+/// A function resolving interpreted text into events.
+pub type RoleHandler =
+    Arc<dyn Fn(&InterpretedTextEvent<'_>) -> Vec<Event<'static>> + Send + Sync + 'static>;
+
+/// Resolves interpreted text with registered role handlers.
+///
+/// Every [`InterpretedTextEvent`] whose role has a handler registered with
+/// [`register_role`](Self::register_role) is replaced by the events the
+/// handler returns.  The first of them inherits the location of the
+/// interpreted text.
+///
+/// ```
+/// use struckdown::event::{InlineCodeEvent, Event};
+/// use struckdown::processors::RoleProcessor;
+///
+/// let mut roles = RoleProcessor::new();
+/// roles.register_role("kbd", |text| {
+///     vec![Event::InlineCode(InlineCodeEvent { code: text.text.clone().into_owned() })]
+/// });
+/// ```
+///
+/// Unlike the built-in processors the handlers cannot be configured from
+/// front matter, so this processor is added to a pipeline in code.  When the
+/// unknown role policy is not `keep` it should come after all processors
+/// handling roles of their own (like [`Equations`](super::Equations)).
+///
+/// When applied this wraps the stream in a [`RoleProcessorIter`].
+#[derive(Clone)]
+pub struct RoleProcessor {
+    roles: BTreeMap<String, RoleHandler>,
+    unknown_role_policy: UnknownRolePolicy,
+}
+
+impl Default for RoleProcessor {
+    fn default() -> RoleProcessor {
+        RoleProcessor {
+            roles: BTreeMap::new(),
+            unknown_role_policy: UnknownRolePolicy::Keep,
+        }
+    }
+}
+
+impl fmt::Debug for RoleProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoleProcessor")
+            .field("roles", &self.roles.keys().collect::<Vec<_>>())
+            .field("unknown_role_policy", &self.unknown_role_policy)
+            .finish()
+    }
+}
+
+implement_processor!(RoleProcessor, RoleProcessorIter);
+
+impl RoleProcessor {
+    /// Creates a processor without any roles.
+    pub fn new() -> RoleProcessor {
+        RoleProcessor::default()
+    }
+
+    /// Registers a handler for a role.
+    ///
+    /// A handler registered earlier for the same role is replaced.
+    pub fn register_role<F>(&mut self, name: &str, handler: F) -> &mut Self
+    where
+        F: Fn(&InterpretedTextEvent<'_>) -> Vec<Event<'static>> + Send + Sync + 'static,
+    {
+        self.roles.insert(name.to_string(), Arc::new(handler));
+        self
+    }
+
+    /// Changes what happens to roles without a handler.
+    pub fn set_unknown_role_policy(&mut self, policy: UnknownRolePolicy) -> &mut Self {
+        self.unknown_role_policy = policy;
+        self
+    }
+
+    /// Returns the handler of a role.
+    pub fn get_role(&self, name: &str) -> Option<&RoleHandler> {
+        self.roles.get(name)
+    }
+}
+
+/// The iterator implementing [`RoleProcessor`].
+pub struct RoleProcessorIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, RoleProcessor>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    RoleProcessorIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, RoleProcessor>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for RoleProcessorIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+
+            let annotated_event = self.source.next()?;
+            let interpreted_text = match annotated_event.event {
+                Event::InterpretedText(ref interpreted_text) => interpreted_text,
+                _ => return Some(annotated_event),
+            };
+
+            match self.options.get_role(interpreted_text.role.as_str()) {
+                Some(handler) => {
+                    let mut location = annotated_event.location.clone();
+                    self.buffer.extend(
+                        handler(interpreted_text)
+                            .into_iter()
+                            .map(|event| AnnotatedEvent::new(event, location.take())),
+                    );
+                }
+                None => match self.options.unknown_role_policy {
+                    UnknownRolePolicy::Keep => return Some(annotated_event),
+                    UnknownRolePolicy::Warn => {
+                        self.buffer.push_back(AnnotatedEvent::new(
+                            DiagnosticEvent {
+                                severity: Severity::Warning,
+                                message: format!("Unknown role '{}'", interpreted_text.role).into(),
+                                description: None,
+                                code: Some(Code::UnknownRole),
+                            },
+                            annotated_event.location.clone(),
+                        ));
+                        return Some(annotated_event);
+                    }
+                    UnknownRolePolicy::Drop => {}
+                    UnknownRolePolicy::Error => {
+                        return Some(AnnotatedEvent::new(
+                            ErrorEvent {
+                                title: format!("Unknown role '{}'", interpreted_text.role).into(),
+                                description: None,
+                                code: Some(Code::UnknownRole),
+                            },
+                            annotated_event.location,
+                        ))
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[test]
+fn test_role_processor() {
+    use crate::event::{InlineCodeEvent, Tag, TextEvent};
+    use crate::html::to_html;
+    use crate::pipeline::Pipeline;
+
+    let mut roles = RoleProcessor::new();
+    roles
+        .register_role("ref", |text| {
+            let mut attrs = crate::event::Attrs::default();
+            attrs.target = Some(format!("#{}", text.text).into());
+            vec![
+                Tag::Link.start_tag(attrs).into(),
+                TextEvent {
+                    text: text.text.clone().into_owned(),
+                }
+                .into(),
+                Tag::Link.end_tag().into(),
+            ]
+        })
+        .register_role("kbd", |text| {
+            vec![InlineCodeEvent {
+                code: text.text.clone().into_owned(),
+            }
+            .into()]
+        });
+
+    let source = "See {ref}`intro`, press {kbd}`Ctrl+C` or {unknown}`x`.";
+    let mut output = String::new();
+    for &policy in &[
+        UnknownRolePolicy::Keep,
+        UnknownRolePolicy::Warn,
+        UnknownRolePolicy::Error,
+        UnknownRolePolicy::Drop,
+    ] {
+        let mut roles = roles.clone();
+        roles.set_unknown_role_policy(policy);
+        let mut pipeline = Pipeline::new();
+        pipeline.add_processor(roles);
+        output.push_str(&to_html(pipeline.process(source), &Default::default()));
+    }
+    insta::assert_snapshot!(output);
+}
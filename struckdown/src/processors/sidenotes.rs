@@ -0,0 +1,229 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{
+    AnnotatedEvent, Attrs, EndTagEvent, Event, FootnoteReferenceEvent, StartTagEvent, Str, Tag,
+    TextEvent,
+};
+use crate::value::Value;
+
+/// The style of the generated notes.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SidenoteStyle {
+    /// Footnotes are left alone.
+    Disabled,
+    /// Numbered sidenotes.
+    Numbered,
+    /// Unnumbered margin notes.
+    Margin,
+}
+
+/// Turns footnotes into sidenotes.
+///
+/// Every footnote reference is replaced by a [`Tag::Sidenote`] holding the
+/// contents of the footnote definition so themes can render Tufte-style
+/// margin notes.  Paragraphs in the definition are joined by hard breaks.
+/// Definitions that are referenced are removed from the stream.  Repeated
+/// references to the same footnote only emit the note once.
+///
+/// With the `numbered` style the sidenote is preceded by a span with the
+/// class `sidenote-number` holding the number of the note and the sidenote
+/// gets the class `numbered`.  The `margin` style emits unnumbered notes
+/// with the class `margin-note`.
+///
+/// The style can be changed per document with the `sidenotes` front matter
+/// key which takes a style name or a boolean.
+///
+/// When applied this wraps the stream in a [`SidenotesIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Sidenotes {
+    /// The default style of the notes.
+    pub style: SidenoteStyle,
+    /// The front matter key that overrides the style.
+    pub front_matter_key: String,
+}
+
+impl Default for Sidenotes {
+    fn default() -> Sidenotes {
+        Sidenotes {
+            style: SidenoteStyle::Numbered,
+            front_matter_key: "sidenotes".into(),
+        }
+    }
+}
+
+implement_processor!(Sidenotes, SidenotesIter);
+
+/// The iterator implementing [`Sidenotes`].
+pub struct SidenotesIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: Option<I>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Sidenotes>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> SidenotesIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Sidenotes>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: Some(iterator),
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    fn style(&self, front_matter: Option<&Value>) -> SidenoteStyle {
+        match front_matter.and_then(|x| x.get(&self.options.front_matter_key)) {
+            Some(Value::Bool(false)) => SidenoteStyle::Disabled,
+            Some(Value::Bool(true)) if self.options.style == SidenoteStyle::Disabled => {
+                SidenoteStyle::Numbered
+            }
+            Some(Value::String(style)) => match style.as_str() {
+                "numbered" => SidenoteStyle::Numbered,
+                "margin" => SidenoteStyle::Margin,
+                "disabled" => SidenoteStyle::Disabled,
+                _ => self.options.style,
+            },
+            _ => self.options.style,
+        }
+    }
+
+    fn process(&mut self, iter: I) {
+        let mut events = VecDeque::new();
+        let mut definitions: HashMap<Str<'data>, Vec<AnnotatedEvent<'data>>> = HashMap::new();
+        let mut definition: Option<(Str<'data>, Vec<AnnotatedEvent<'data>>)> = None;
+        let mut depth = 0;
+        let mut style = self.options.style;
+
+        for annotated_event in iter {
+            match annotated_event.event {
+                Event::DocumentStart(ref document_start) => {
+                    style = self.style(document_start.front_matter.as_ref());
+                }
+                Event::StartTag(StartTagEvent {
+                    tag: Tag::FootnoteDefinition,
+                    ref attrs,
+                }) if definition.is_none() && style != SidenoteStyle::Disabled => {
+                    let id = attrs.id.clone().unwrap_or_else(|| "".into());
+                    definition = Some((id, vec![annotated_event]));
+                    depth = 1;
+                    continue;
+                }
+                Event::StartTag(_) if definition.is_some() => depth += 1,
+                Event::EndTag(_) if definition.is_some() => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let (id, mut body) = definition.take().unwrap();
+                        body.push(annotated_event);
+                        definitions.insert(id, body);
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            match definition {
+                Some((_, ref mut body)) => body.push(annotated_event),
+                None => events.push_back(annotated_event),
+            }
+        }
+
+        if style == SidenoteStyle::Disabled {
+            self.buffer = events;
+            return;
+        }
+
+        let mut used = HashMap::new();
+        for annotated_event in events {
+            let target = match annotated_event.event {
+                Event::FootnoteReference(FootnoteReferenceEvent { ref target })
+                    if definitions.contains_key(target) =>
+                {
+                    target.clone()
+                }
+                _ => {
+                    self.buffer.push_back(annotated_event);
+                    continue;
+                }
+            };
+            let next_number = used.len() + 1;
+            let number = *used.entry(target.clone()).or_insert(next_number);
+            let repeated = number != next_number;
+
+            let class = if style == SidenoteStyle::Numbered {
+                self.buffer.push_back(AnnotatedEvent::new(
+                    Tag::Span.start_tag(Attrs {
+                        class: Some("sidenote-number".into()),
+                        ..Attrs::default()
+                    }),
+                    annotated_event.location.clone(),
+                ));
+                self.buffer.push_back(
+                    TextEvent {
+                        text: number.to_string().into(),
+                    }
+                    .into(),
+                );
+                self.buffer.push_back(Tag::Span.end_tag().into());
+                "numbered"
+            } else {
+                "margin-note"
+            };
+            // repeated references only point to the note already shown
+            if repeated {
+                continue;
+            }
+            self.buffer.push_back(AnnotatedEvent::new(
+                Tag::Sidenote.start_tag(Attrs {
+                    class: Some(class.into()),
+                    ..Attrs::default()
+                }),
+                annotated_event.location,
+            ));
+            let body = &definitions[&target];
+            let mut paragraphs = 0;
+            for event in &body[1..body.len() - 1] {
+                match event.event {
+                    Event::StartTag(StartTagEvent {
+                        tag: Tag::Paragraph,
+                        ..
+                    }) => {
+                        if paragraphs > 0 {
+                            self.buffer.push_back(Event::HardBreak.into());
+                        }
+                        paragraphs += 1;
+                    }
+                    Event::EndTag(EndTagEvent {
+                        tag: Tag::Paragraph,
+                    }) => {}
+                    _ => self.buffer.push_back(event.clone()),
+                }
+            }
+            self.buffer.push_back(Tag::Sidenote.end_tag().into());
+        }
+
+        // definitions that were never referenced are kept at the end
+        let mut unused: Vec<_> = definitions
+            .into_iter()
+            .filter(|(id, _)| !used.contains_key(id))
+            .collect();
+        unused.sort_by(|a, b| a.0.cmp(&b.0));
+        for (_, body) in unused {
+            self.buffer.extend(body);
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for SidenotesIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(iter) = self.source.take() {
+            self.process(iter);
+        }
+        self.buffer.pop_front()
+    }
+}
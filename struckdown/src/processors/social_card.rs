@@ -0,0 +1,345 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use slug::slugify;
+
+use crate::assets::AssetStore;
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, ErrorEvent, Event, MetaDataEvent, StartTagEvent};
+use crate::plain::to_plain_text;
+use crate::value::{value, Value};
+
+/// Renders a social preview (Open Graph) image per document.
+///
+/// At the end of the stream the configured command is invoked to render
+/// the image into `output_dir`.  The following placeholders in `args` are
+/// replaced:
+///
+/// * `{title}`: the `title` from the front matter or the first level 1
+///   heading.
+/// * `{author}`: the `author` from the front matter (joined with commas if
+///   it is a list).
+/// * `{template}`: the configured `template`.
+/// * `{output}`: the path of the image to write.
+///
+/// The image is named after the slugified `slug` key of the front matter or
+/// title.  Documents without a title do not get a card.  On
+/// success a `social_card` meta data event with the `url` (`url_prefix`
+/// joined with the file name) and `path` of the image is emitted; failures
+/// are reported as error events.
+///
+/// With `cache` enabled the file name also carries a hash of the inputs of
+/// the command and existing images are not rendered again (see
+/// [`AssetStore`](crate::assets::AssetStore)).
+///
+/// The command is subject to the installed [`Sandbox`](crate::sandbox::Sandbox).
+///
+/// This is only available with the `social-card-processor` feature.
+///
+/// When applied this wraps the stream in a [`SocialCardIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SocialCard {
+    /// The executable rendering the image.
+    pub cmd: PathBuf,
+    /// The arguments to pass to the command.
+    pub args: Vec<String>,
+    /// Optional environment variables to pass.
+    pub env: BTreeMap<String, String>,
+    /// An optional template passed to the command.
+    pub template: Option<PathBuf>,
+    /// The folder the images are written to.
+    pub output_dir: PathBuf,
+    /// The URL the output folder is served under.
+    pub url_prefix: String,
+    /// The file extension of the generated images.
+    pub extension: String,
+    /// The key of the emitted meta data.
+    pub metadata_key: String,
+    /// Enables content hashed file names and skips rendering existing images.
+    pub cache: bool,
+}
+
+impl Default for SocialCard {
+    fn default() -> SocialCard {
+        SocialCard {
+            cmd: "resvg".into(),
+            args: vec!["{template}".into(), "{output}".into()],
+            env: BTreeMap::new(),
+            template: None,
+            output_dir: "social".into(),
+            url_prefix: "/social/".into(),
+            extension: "png".into(),
+            metadata_key: "social_card".into(),
+            cache: false,
+        }
+    }
+}
+
+implement_processor!(SocialCard, SocialCardIter);
+
+/// The iterator implementing [`SocialCard`].
+pub struct SocialCardIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    front_matter: Option<Value>,
+    headline: Option<Vec<AnnotatedEvent<'data>>>,
+    headline_depth: usize,
+    depth: usize,
+    done: bool,
+    options: Cow<'options, SocialCard>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    SocialCardIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, SocialCard>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            front_matter: None,
+            headline: None,
+            headline_depth: 0,
+            depth: 0,
+            done: false,
+            options: options.into(),
+        }
+    }
+
+    fn observe(&mut self, annotated_event: &AnnotatedEvent<'data>) {
+        match annotated_event.event {
+            Event::DocumentStart(ref document_start) => {
+                self.front_matter = document_start.front_matter.clone();
+                return;
+            }
+            Event::StartTag(StartTagEvent { tag, .. }) => {
+                self.depth += 1;
+                if self.headline.is_none() && tag.header_level() == Some(1) {
+                    self.headline = Some(vec![]);
+                    self.headline_depth = self.depth;
+                    return;
+                }
+            }
+            Event::EndTag(_) => {
+                self.depth -= 1;
+                if self.depth + 1 == self.headline_depth {
+                    self.headline_depth = 0;
+                }
+            }
+            _ => {}
+        }
+        if self.headline_depth > 0 {
+            if let Some(ref mut events) = self.headline {
+                events.push(annotated_event.clone());
+            }
+        }
+    }
+
+    fn front_matter_str(&self, key: &str) -> Option<String> {
+        match self.front_matter.as_ref().and_then(|x| x.get(key)) {
+            Some(Value::String(value)) => Some(value.clone()),
+            Some(Value::Array(values)) => Some(
+                values
+                    .iter()
+                    .filter_map(|x| x.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            _ => None,
+        }
+    }
+
+    fn render(&self) -> Option<AnnotatedEvent<'data>> {
+        let title = self.front_matter_str("title").or_else(|| {
+            self.headline
+                .as_ref()
+                .map(|events| to_plain_text(events.iter()).as_str().trim().to_string())
+        })?;
+        // the slug ends up in a path so it is slugified as well
+        let stem = self
+            .front_matter_str("slug")
+            .map(|slug| slugify(&slug))
+            .filter(|slug| !slug.is_empty())
+            .unwrap_or_else(|| slugify(&title));
+        let author = self.front_matter_str("author").unwrap_or_default();
+        let template = self
+            .options
+            .template
+            .as_ref()
+            .map(|x| x.display().to_string())
+            .unwrap_or_default();
+
+        let sandbox = crate::sandbox::current();
+        let render = |output: &Path| -> Result<(), String> {
+            let program = sandbox.resolve_command(&self.options.cmd)?;
+            if let Some(ref template) = self.options.template {
+                sandbox.check_read(template)?;
+            }
+            let (clear_env, env) = sandbox.command_env(&self.options.env)?;
+            let output_str = output.display().to_string();
+            let args = self.options.args.iter().map(|arg| {
+                arg.replace("{title}", &title)
+                    .replace("{author}", &author)
+                    .replace("{template}", &template)
+                    .replace("{output}", &output_str)
+            });
+            let mut cmd = Command::new(&program);
+            if clear_env {
+                cmd.env_clear();
+            }
+            match cmd.args(args).envs(env).output() {
+                Ok(result) if result.status.success() => Ok(()),
+                Ok(result) => Err(String::from_utf8_lossy(&result.stderr).into_owned()),
+                Err(err) => Err(err.to_string()),
+            }
+        };
+
+        let result = if self.options.cache {
+            let template_contents = match self.options.template {
+                Some(ref path) => fs::read(path).unwrap_or_default(),
+                None => vec![],
+            };
+            let cmd = self.options.cmd.display().to_string();
+            let args = self.options.args.join("\0");
+            let env = serde_json::to_string(&self.options.env).unwrap_or_default();
+            let inputs: [&[u8]; 6] = [
+                cmd.as_bytes(),
+                args.as_bytes(),
+                env.as_bytes(),
+                &template_contents,
+                title.as_bytes(),
+                author.as_bytes(),
+            ];
+            AssetStore::new(&self.options.output_dir, &self.options.url_prefix)
+                .get_or_create(&stem, &inputs, &self.options.extension, render)
+                .map(|asset| (asset.url, asset.path))
+        } else {
+            let filename = format!("{}.{}", stem, self.options.extension);
+            let output = self.options.output_dir.join(&filename);
+            fs::create_dir_all(&self.options.output_dir)
+                .map_err(|err| err.to_string())
+                .and_then(|()| render(&output))
+                .map(|()| (format!("{}{}", self.options.url_prefix, filename), output))
+        };
+
+        let (url, path) = match result {
+            Ok(rv) => rv,
+            Err(err) => {
+                return Some(
+                    ErrorEvent {
+                        title: format!("Failed to render social card '{}'", stem).into(),
+                        description: Some(err.into()),
+                        code: Some(Code::SocialCardFailure),
+                    }
+                    .into(),
+                )
+            }
+        };
+
+        Some(
+            MetaDataEvent {
+                key: self.options.metadata_key.clone().into(),
+                value: value!({
+                    "url": url,
+                    "path": path.display().to_string(),
+                }),
+            }
+            .into(),
+        )
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for SocialCardIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.source.next() {
+            self.observe(&annotated_event);
+            return Some(annotated_event);
+        }
+        if self.done {
+            return None;
+        }
+        self.done = true;
+        self.render()
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_social_card() {
+    use crate::parser::parse;
+    use crate::processors::Processor;
+
+    let output_dir = std::env::temp_dir().join("struckdown-social-card-test");
+    let processor = SocialCard {
+        cmd: "touch".into(),
+        args: vec!["{output}".into()],
+        output_dir: output_dir.clone(),
+        ..SocialCard::default()
+    };
+    let events: Vec<_> = Box::new(processor)
+        .apply(Box::new(parse(
+            "---\nauthor: Jane Doe\n---\n# Hello *World*\n\nText",
+            &Default::default(),
+        )))
+        .collect();
+    match events.last().map(|x| &x.event) {
+        Some(Event::MetaData(MetaDataEvent { key, value })) => {
+            assert_eq!(key.as_str(), "social_card");
+            assert_eq!(value["url"], "/social/hello-world.png");
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+    assert!(output_dir.join("hello-world.png").is_file());
+    fs::remove_dir_all(&output_dir).ok();
+
+    let processor = SocialCard {
+        cmd: "touch".into(),
+        args: vec!["{output}".into()],
+        output_dir: output_dir.clone(),
+        cache: true,
+        ..SocialCard::default()
+    };
+    let events: Vec<_> = Box::new(processor)
+        .apply(Box::new(parse("# Cached", &Default::default())))
+        .collect();
+    match events.last().map(|x| &x.event) {
+        Some(Event::MetaData(MetaDataEvent { value, .. })) => {
+            assert!(value["url"]
+                .as_str()
+                .unwrap()
+                .starts_with("/social/cached-"));
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+    assert!(output_dir.join(crate::assets::MANIFEST_FILENAME).is_file());
+    fs::remove_dir_all(&output_dir).ok();
+
+    let processor = SocialCard {
+        cmd: "touch".into(),
+        args: vec!["{output}".into()],
+        output_dir: output_dir.join("nested"),
+        ..SocialCard::default()
+    };
+    let events: Vec<_> = Box::new(processor)
+        .apply(Box::new(parse(
+            "---\ntitle: Escape\nslug: ../../escaped\n---\nText",
+            &Default::default(),
+        )))
+        .collect();
+    match events.last().map(|x| &x.event) {
+        Some(Event::MetaData(MetaDataEvent { value, .. })) => {
+            assert_eq!(value["url"], "/social/escaped.png");
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+    assert!(output_dir.join("nested").join("escaped.png").is_file());
+    assert!(!output_dir.join("escaped.png").exists());
+    assert!(!std::env::temp_dir().join("escaped.png").exists());
+    fs::remove_dir_all(&output_dir).ok();
+}
@@ -0,0 +1,146 @@
+use std::borrow::Cow;
+use std::iter::Peekable;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, TextEvent};
+use crate::processors::cjk_spacing::is_cjk;
+use crate::value::Value;
+
+/// How soft breaks are handled.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SoftBreakPolicy {
+    /// Soft breaks are left alone for the renderer to handle.
+    Keep,
+    /// Soft breaks become a single space.
+    Space,
+    /// Soft breaks become hard breaks.
+    HardBreak,
+    /// Lines are joined.
+    ///
+    /// Between two CJK characters the break is removed entirely as these
+    /// scripts do not separate words with spaces.  Everywhere else it
+    /// becomes a single space.
+    Join,
+}
+
+/// Applies a policy to soft breaks.
+///
+/// A single newline within a paragraph means different things to different
+/// audiences: western prose expects a space, poetry and chat-style content
+/// expect a line break and East Asian text expects the lines to be joined
+/// without any whitespace.  This processor rewrites the
+/// [`Event::SoftBreak`] events so that all renderers agree.
+///
+/// The policy can be changed per document with the `soft_breaks` front
+/// matter key.
+///
+/// When applied this wraps the stream in a [`SoftBreaksIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct SoftBreaks {
+    /// The default policy.
+    pub policy: SoftBreakPolicy,
+    /// The front matter key that overrides the policy.
+    pub front_matter_key: String,
+}
+
+impl Default for SoftBreaks {
+    fn default() -> SoftBreaks {
+        SoftBreaks {
+            policy: SoftBreakPolicy::Join,
+            front_matter_key: "soft_breaks".into(),
+        }
+    }
+}
+
+implement_processor!(SoftBreaks, SoftBreaksIter);
+
+/// Checks if a character belongs to a script that does not use spaces.
+///
+/// Unlike for spacing, CJK punctuation and full width forms count here.
+fn is_east_asian(c: char) -> bool {
+    is_cjk(c) || matches!(c, '\u{3000}'..='\u{303f}' | '\u{ff00}'..='\u{ffef}')
+}
+
+/// The iterator implementing [`SoftBreaks`].
+pub struct SoftBreaksIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: Peekable<I>,
+    policy: SoftBreakPolicy,
+    last_char: Option<char>,
+    options: Cow<'options, SoftBreaks>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    SoftBreaksIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, SoftBreaks>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        Self {
+            source: iterator.peekable(),
+            policy: options.policy,
+            last_char: None,
+            options,
+        }
+    }
+
+    fn policy(&self, front_matter: Option<&Value>) -> SoftBreakPolicy {
+        match front_matter
+            .and_then(|x| x.get(&self.options.front_matter_key))
+            .and_then(|x| x.as_str())
+        {
+            Some("keep") => SoftBreakPolicy::Keep,
+            Some("space") => SoftBreakPolicy::Space,
+            Some("hard_break") => SoftBreakPolicy::HardBreak,
+            Some("join") => SoftBreakPolicy::Join,
+            _ => self.options.policy,
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        match self.source.peek()?.event {
+            Event::Text(TextEvent { ref text }) => text.as_str().chars().next(),
+            _ => None,
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for SoftBreaksIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut annotated_event = self.source.next()?;
+            match annotated_event.event {
+                Event::DocumentStart(ref document_start) => {
+                    self.policy = self.policy(document_start.front_matter.as_ref());
+                }
+                Event::Text(TextEvent { ref text }) => {
+                    self.last_char = text.as_str().chars().last().or(self.last_char);
+                }
+                Event::SoftBreak => {
+                    let last_char = self.last_char.take();
+                    let policy = self.policy;
+                    match policy {
+                        SoftBreakPolicy::Keep => {}
+                        SoftBreakPolicy::HardBreak => annotated_event.event = Event::HardBreak,
+                        SoftBreakPolicy::Join
+                            if matches!(last_char, Some(c) if is_east_asian(c))
+                                && matches!(self.next_char(), Some(c) if is_east_asian(c)) =>
+                        {
+                            continue;
+                        }
+                        SoftBreakPolicy::Space | SoftBreakPolicy::Join => {
+                            annotated_event.event = TextEvent { text: " ".into() }.into();
+                        }
+                    }
+                }
+                _ => self.last_char = None,
+            }
+            return Some(annotated_event);
+        }
+    }
+}
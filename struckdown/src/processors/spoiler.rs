@@ -0,0 +1,76 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, Event, InterpretedTextEvent, Tag, TextEvent};
+
+/// Turns spoiler roles into hidden inline text.
+///
+/// A role like `` {spoiler}`the butler did it` `` is replaced by a
+/// [`Tag::Spoiler`] tag holding the text.  The HTML renderer emits this as
+/// a focusable span with the `spoiler` class which can be styled to reveal
+/// its contents on hover, focus or click.
+///
+/// When applied this wraps the stream in a [`SpoilerIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Spoiler {
+    /// The name of the role.
+    pub role_name: String,
+}
+
+impl Default for Spoiler {
+    fn default() -> Spoiler {
+        Spoiler {
+            role_name: "spoiler".into(),
+        }
+    }
+}
+
+implement_processor!(Spoiler, SpoilerIter);
+
+/// The iterator implementing [`Spoiler`].
+pub struct SpoilerIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Spoiler>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> SpoilerIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Spoiler>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for SpoilerIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        match annotated_event.event {
+            Event::InterpretedText(InterpretedTextEvent {
+                ref role, ref text, ..
+            }) if role.as_str() == self.options.role_name => {
+                self.buffer
+                    .push_back(TextEvent { text: text.clone() }.into());
+                self.buffer.push_back(Tag::Spoiler.end_tag().into());
+                Some(AnnotatedEvent::new(
+                    Tag::Spoiler.start_tag(Attrs::default()),
+                    annotated_event.location,
+                ))
+            }
+            _ => Some(annotated_event),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, DirectiveEvent, Event, Tag};
+use crate::processors::utils::{parse_body, split_sections};
+
+/// Expands `{steps}` and `{timeline}` directives.
+///
+/// The body of the directive is parsed as markdown and split into entries
+/// at the first heading level used in it.  The heading becomes the title of
+/// the entry and everything up to the next heading its body.  The result is
+/// emitted as an ordered list:
+///
+/// ```text
+/// <ol class="steps">
+///   <li class="step">
+///     <div class="step-title">...</div>
+///     <div class="step-body">...</div>
+///   </li>
+/// </ol>
+/// ```
+///
+/// For timelines the classes are prefixed with `timeline` instead.  A
+/// `start` key in the directive front matter sets the first number.
+///
+/// When applied this wraps the stream in a [`StepsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Steps {
+    /// The name of the directive that emits steps.
+    pub steps_name: Option<String>,
+    /// The name of the directive that emits a timeline.
+    pub timeline_name: Option<String>,
+}
+
+impl Default for Steps {
+    fn default() -> Steps {
+        Steps {
+            steps_name: Some("steps".into()),
+            timeline_name: Some("timeline".into()),
+        }
+    }
+}
+
+implement_processor!(Steps, StepsIter);
+
+fn class_attrs(class: String) -> Attrs<'static> {
+    Attrs {
+        class: Some(class.into()),
+        ..Attrs::default()
+    }
+}
+
+/// The iterator implementing [`Steps`].
+pub struct StepsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, Steps>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> StepsIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Steps>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for StepsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        let prefix = match annotated_event.event {
+            Event::Directive(DirectiveEvent { ref name, .. }) => {
+                if Some(name.as_str()) == self.options.steps_name.as_deref() {
+                    "step"
+                } else if Some(name.as_str()) == self.options.timeline_name.as_deref() {
+                    "timeline"
+                } else {
+                    return Some(annotated_event);
+                }
+            }
+            _ => return Some(annotated_event),
+        };
+
+        let (body, start) = match annotated_event.event {
+            Event::Directive(DirectiveEvent {
+                ref body,
+                ref body_location,
+                ref front_matter,
+                ..
+            }) => (
+                parse_body(body.as_str(), body_location.as_ref()),
+                front_matter
+                    .as_ref()
+                    .and_then(|x| x.get("start"))
+                    .and_then(|x| x.as_u64())
+                    .map(|x| x as u32),
+            ),
+            _ => unreachable!(),
+        };
+
+        for entry in split_sections(body) {
+            self.buffer.push_back(
+                Tag::ListItem
+                    .start_tag(class_attrs(if prefix == "step" {
+                        "step".into()
+                    } else {
+                        format!("{}-entry", prefix)
+                    }))
+                    .into(),
+            );
+            if !entry.title.is_empty() {
+                self.buffer.push_back(
+                    Tag::Container
+                        .start_tag(class_attrs(format!("{}-title", prefix)))
+                        .into(),
+                );
+                self.buffer.extend(entry.title);
+                self.buffer.push_back(Tag::Container.end_tag().into());
+            }
+            self.buffer.push_back(
+                Tag::Container
+                    .start_tag(class_attrs(format!("{}-body", prefix)))
+                    .into(),
+            );
+            self.buffer.extend(entry.body);
+            self.buffer.push_back(Tag::Container.end_tag().into());
+            self.buffer.push_back(Tag::ListItem.end_tag().into());
+        }
+        self.buffer.push_back(Tag::OrderedList.end_tag().into());
+
+        Some(AnnotatedEvent::new(
+            Tag::OrderedList.start_tag(Attrs {
+                start,
+                class: Some(
+                    if prefix == "step" {
+                        "steps"
+                    } else {
+                        "timeline"
+                    }
+                    .into(),
+                ),
+                ..Attrs::default()
+            }),
+            annotated_event.location,
+        ))
+    }
+}
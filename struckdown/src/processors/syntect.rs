@@ -1,30 +1,91 @@
 use std::borrow::Cow;
-use std::marker::PhantomData;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 use syntect::easy::HighlightLines;
 use syntect::highlighting::{Theme, ThemeSet};
-use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
-use syntect::parsing::SyntaxSet;
+use syntect::html::{
+    css_for_theme_with_class_style, line_tokens_to_classed_spans, styled_line_to_highlighted_html,
+    ClassStyle, IncludeBackground,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxSet};
+use syntect::LoadingError;
 
-use crate::event::{AnnotatedEvent, CodeBlockEvent, Event, RawHtmlEvent};
+use crate::codes::Code;
+use crate::event::{
+    AnnotatedEvent, CodeBlockEvent, DirectiveEvent, ErrorEvent, Event, RawHtmlEvent, Str,
+};
+use crate::value::Value;
 
 const DEFAULT_THEME: &str = "InspiredGitHub";
+const CLASS_STYLE: ClassStyle = ClassStyle::Spaced;
+
+/// How the [`Syntect`] processor styles highlighted code.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyntectOutput {
+    /// The colors of the theme are emitted as inline styles.
+    InlineStyles,
+    /// The scopes of the syntax are emitted as CSS classes
+    /// (`<span class="keyword control python">`) which are independent of
+    /// the theme.  A stylesheet for these can be generated with
+    /// [`Syntect::stylesheet`].
+    Classes,
+}
 
 /// Implements syntax highlighting via [`syntect`].
 ///
+/// Code blocks with a language are highlighted, as are `code-block`
+/// directives which take the language as argument.  Line numbers and
+/// emphasized lines can be configured per code block, either as arguments
+/// of the code block or in the front matter of the directive:
+///
+/// ````markdown
+/// ```python linenos lineno-start=10 emphasize-lines="2,4-5"
+/// ...
+/// ```
+///
+/// ```{code-block} python
+/// ---
+/// linenos: true
+/// emphasize-lines: [3, 5]
+/// ---
+/// ...
+/// ```
+/// ````
+///
+/// If any of these is in effect every line is wrapped in a `span` with the
+/// class `line` (and `hll` for emphasized lines) and line numbers are
+/// emitted as a `span` with the class `lineno`.
+///
+/// With the `classes` output code is not styled inline but marked up with
+/// CSS classes so sites can theme code blocks with their own stylesheets,
+/// for instance to support a dark mode.
+///
+/// In-house languages can be highlighted by pointing `syntax_path` to a
+/// folder of `.sublime-syntax` definitions.  If themes or syntax
+/// definitions fail to load an error is emitted and the defaults are used.
+///
 /// When applied this wraps the stream in a [`SyntectIter`].
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct Syntect {
     /// The name of the theme to use.  If both this and `theme_path` is not
     /// set then a default theme is loaded.  If `theme_path` is set then the
-    /// theme in the `theme_path` folder is used.
+    /// themes in the `theme_path` folder are available in addition to the
+    /// bundled ones.
     pub theme: Option<String>,
     /// When `theme` is not set, then the path to the `.tmTheme` file to load
     /// otherwise the folder to a collection of theme files.
     pub theme_path: Option<PathBuf>,
+    /// A folder with `.sublime-syntax` files to load in addition to the
+    /// bundled syntax definitions.
+    pub syntax_path: Option<PathBuf>,
+    /// How highlighted code is styled.
+    pub output: SyntectOutput,
+    /// Emits line numbers for all code blocks unless disabled per block.
+    pub linenos: bool,
 }
 
 impl Default for Syntect {
@@ -32,8 +93,176 @@ impl Default for Syntect {
         Syntect {
             theme: None,
             theme_path: None,
+            syntax_path: None,
+            output: SyntectOutput::InlineStyles,
+            linenos: false,
+        }
+    }
+}
+
+fn loading_error(title: &str, err: LoadingError) -> ErrorEvent<'static> {
+    ErrorEvent {
+        title: title.to_string().into(),
+        description: Some(err.to_string().into()),
+        code: Some(Code::HighlightingLoadFailure),
+    }
+}
+
+impl Syntect {
+    /// Loads the configured theme.
+    ///
+    /// If the theme cannot be loaded the default theme is returned along
+    /// with the errors.
+    fn load_theme(&self) -> (Theme, Vec<ErrorEvent<'static>>) {
+        let mut errors = vec![];
+        let mut theme_set = ThemeSet::load_defaults();
+        let theme = match (&self.theme, &self.theme_path) {
+            (Some(theme), path) => {
+                if let Some(path) = path {
+                    if let Err(err) = theme_set.add_from_folder(path) {
+                        errors.push(loading_error("Failed to load themes", err));
+                    }
+                }
+                theme_set.themes.remove(theme)
+            }
+            (None, Some(path)) => match ThemeSet::get_theme(path) {
+                Ok(theme) => Some(theme),
+                Err(err) => {
+                    errors.push(loading_error("Failed to load theme", err));
+                    None
+                }
+            },
+            (None, None) => None,
+        };
+        let theme = theme.unwrap_or_else(|| theme_set.themes.remove(DEFAULT_THEME).unwrap());
+        (theme, errors)
+    }
+
+    /// Returns a stylesheet for the `classes` output of the configured theme.
+    ///
+    /// This can be used as a starting point for a custom stylesheet.
+    pub fn stylesheet(&self) -> Result<String, String> {
+        let (theme, errors) = self.load_theme();
+        if let Some(err) = errors.into_iter().next() {
+            return Err(match err.description {
+                Some(description) => format!("{}: {}", err.title.as_str(), description.as_str()),
+                None => err.title.as_str().to_string(),
+            });
+        }
+        Ok(css_for_theme_with_class_style(&theme, CLASS_STYLE))
+    }
+}
+
+/// The line options of a single code block.
+struct LineOptions {
+    linenos: bool,
+    lineno_start: usize,
+    emphasize_lines: BTreeSet<usize>,
+}
+
+impl LineOptions {
+    fn from_values<'a, F: Fn(&str) -> Option<Cow<'a, Value>>>(
+        get: F,
+        defaults: &Syntect,
+        line_count: usize,
+    ) -> LineOptions {
+        LineOptions {
+            linenos: get("linenos")
+                .and_then(|x| parse_bool(&x))
+                .unwrap_or(defaults.linenos),
+            lineno_start: get("lineno-start")
+                .and_then(|x| parse_number(&x))
+                .unwrap_or(1),
+            emphasize_lines: get("emphasize-lines")
+                .map(|x| parse_lines(&x, line_count))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn from_args(
+        args: &Option<BTreeMap<Str<'_>, Str<'_>>>,
+        defaults: &Syntect,
+        line_count: usize,
+    ) -> LineOptions {
+        LineOptions::from_values(
+            |key| {
+                args.as_ref()?
+                    .iter()
+                    .find(|(k, _)| k.as_str() == key)
+                    .map(|(_, v)| Cow::Owned(Value::String(v.as_str().into())))
+            },
+            defaults,
+            line_count,
+        )
+    }
+
+    fn from_front_matter(
+        front_matter: &Option<Value>,
+        defaults: &Syntect,
+        line_count: usize,
+    ) -> LineOptions {
+        LineOptions::from_values(
+            |key| front_matter.as_ref()?.get(key).map(Cow::Borrowed),
+            defaults,
+            line_count,
+        )
+    }
+
+    fn wraps_lines(&self) -> bool {
+        self.linenos || !self.emphasize_lines.is_empty()
+    }
+}
+
+fn parse_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(value) => Some(*value),
+        // a bare `linenos` argument has an empty value
+        Value::String(value) => match value.as_str() {
+            "" | "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_number(value: &Value) -> Option<usize> {
+    match value {
+        Value::Number(value) => value.as_u64().map(|x| x as usize),
+        Value::String(value) => value.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Parses line numbers given as list or as string like `1,3-5`.
+///
+/// Lines past `line_count` are ignored.
+fn parse_lines(value: &Value, line_count: usize) -> BTreeSet<usize> {
+    let mut rv = BTreeSet::new();
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                rv.extend(parse_lines(value, line_count));
+            }
+        }
+        Value::String(value) => {
+            for item in value.split(',') {
+                let mut bounds = item.splitn(2, '-').map(|x| x.trim().parse::<usize>());
+                match (bounds.next(), bounds.next()) {
+                    (Some(Ok(start)), None) => {
+                        rv.insert(start);
+                    }
+                    (Some(Ok(start)), Some(Ok(end))) => {
+                        rv.extend(start..=end.min(line_count));
+                    }
+                    _ => {}
+                }
+            }
         }
+        value => rv.extend(parse_number(value)),
     }
+    rv.retain(|&line| line <= line_count);
+    rv
 }
 
 implement_processor!(Syntect, SyntectIter);
@@ -43,42 +272,86 @@ pub struct SyntectIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>
     source: I,
     syntax_set: SyntaxSet,
     theme: Theme,
-    options: PhantomData<&'options Syntect>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    errors: Vec<ErrorEvent<'data>>,
+    options: Cow<'options, Syntect>,
 }
 
 impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> SyntectIter<'data, 'options, I> {
     pub fn new<O: Into<Cow<'options, Syntect>>>(iterator: I, options: O) -> Self {
         let options = options.into();
-        let theme = match (&options.theme, &options.theme_path) {
-            (Some(theme), None) => {
-                let mut theme_set = ThemeSet::load_defaults();
-                match theme_set.themes.remove(theme) {
-                    Some(theme) => theme,
-                    None => theme_set.themes.remove(DEFAULT_THEME).unwrap(),
+        let (theme, mut errors) = options.load_theme();
+        let syntax_set = match options.syntax_path {
+            Some(ref path) => {
+                let mut builder = SyntaxSet::load_defaults_nonewlines().into_builder();
+                if let Err(err) = builder.add_from_folder(path, false) {
+                    errors.push(loading_error("Failed to load syntax definitions", err));
                 }
+                builder.build()
             }
-            (Some(theme), Some(path)) => {
-                let mut theme_set =
-                    ThemeSet::load_from_folder(path).expect("failed to initialized theme folder");
-                match theme_set.themes.remove(theme) {
-                    Some(theme) => theme,
-                    None => theme_set.themes.remove(DEFAULT_THEME).unwrap(),
-                }
-            }
-            (None, Some(ref path)) => {
-                ThemeSet::get_theme(path).expect("failed to load theme by path")
-            }
-            (None, None) => {
-                let mut theme_set = ThemeSet::load_defaults();
-                theme_set.themes.remove(DEFAULT_THEME).unwrap()
-            }
+            None => SyntaxSet::load_defaults_nonewlines(),
         };
         Self {
             source: iterator,
-            syntax_set: SyntaxSet::load_defaults_nonewlines(),
+            syntax_set,
             theme,
-            options: PhantomData,
+            buffer: VecDeque::new(),
+            errors,
+            options,
+        }
+    }
+
+    fn highlight(&self, language: Option<&str>, code: &str, line_options: &LineOptions) -> String {
+        let syntax = language
+            .and_then(|language| self.syntax_set.find_syntax_by_token(language))
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        let mut h = HighlightLines::new(syntax, &self.theme);
+        let mut state = ParseState::new(syntax);
+        let mut stack = ScopeStack::new();
+        let mut rv = String::from("<pre><code>");
+        for (idx, line) in code.lines().enumerate() {
+            let html = match self.options.output {
+                SyntectOutput::InlineStyles => {
+                    let regions = h.highlight(line, &self.syntax_set);
+                    styled_line_to_highlighted_html(&regions[..], IncludeBackground::No)
+                }
+                SyntectOutput::Classes => {
+                    // spans are closed at the end of every line and the
+                    // scopes still open reopened on the next one.
+                    let mut html = String::new();
+                    for scope in stack.as_slice() {
+                        html.push_str(&format!(
+                            "<span class=\"{}\">",
+                            scope.build_string().replace('.', " ")
+                        ));
+                    }
+                    let ops = state.parse_line(line, &self.syntax_set);
+                    let (spans, _) =
+                        line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut stack);
+                    html.push_str(&spans);
+                    html.push_str(&"</span>".repeat(stack.len()));
+                    html
+                }
+            };
+            if line_options.wraps_lines() {
+                let lineno = line_options.lineno_start + idx;
+                if line_options.emphasize_lines.contains(&(idx + 1)) {
+                    rv.push_str("<span class=\"line hll\">");
+                } else {
+                    rv.push_str("<span class=\"line\">");
+                }
+                if line_options.linenos {
+                    rv.push_str(&format!("<span class=\"lineno\">{}</span>", lineno));
+                }
+                rv.push_str(&html);
+                rv.push_str("</span>\n");
+            } else {
+                rv.push_str(&html);
+                rv.push('\n');
+            }
         }
+        rv.push_str("</code></pre>");
+        rv
     }
 }
 
@@ -88,31 +361,111 @@ impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
     type Item = AnnotatedEvent<'data>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
         let annotated_event = self.source.next()?;
-        if let Event::CodeBlock(CodeBlockEvent {
-            language: Some(ref language),
-            ref code,
-            ..
-        }) = annotated_event.event
-        {
-            let language = language.as_str();
-            let syntax = self
-                .syntax_set
-                .find_syntax_by_token(language)
-                .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-            let mut h = HighlightLines::new(syntax, &self.theme);
-            let regions = h.highlight(code.as_str(), &self.syntax_set);
-            return Some(AnnotatedEvent::new(
-                RawHtmlEvent {
-                    html: format!(
-                        "<pre><code>{}</code></pre>",
-                        styled_line_to_highlighted_html(&regions[..], IncludeBackground::No)
-                    )
-                    .into(),
-                },
-                annotated_event.location,
-            ));
+        let html = match annotated_event.event {
+            Event::DocumentStart(..) => {
+                // loading errors are reported right after the start
+                self.buffer.extend(self.errors.drain(..).map(Into::into));
+                return Some(annotated_event);
+            }
+            Event::CodeBlock(CodeBlockEvent {
+                language: Some(ref language),
+                ref args,
+                ref code,
+            }) => self.highlight(
+                Some(language.as_str()),
+                code.as_str(),
+                &LineOptions::from_args(args, &self.options, code.as_str().lines().count()),
+            ),
+            Event::Directive(DirectiveEvent {
+                ref name,
+                ref argument,
+                ref front_matter,
+                ref body,
+                ..
+            }) if name.as_str() == "code-block" => self.highlight(
+                argument.as_ref().map(|x| x.as_str()),
+                body.as_str(),
+                &LineOptions::from_front_matter(
+                    front_matter,
+                    &self.options,
+                    body.as_str().lines().count(),
+                ),
+            ),
+            _ => return Some(annotated_event),
+        };
+        Some(AnnotatedEvent::new(
+            RawHtmlEvent { html: html.into() },
+            annotated_event.location,
+        ))
+    }
+}
+
+#[test]
+fn test_syntax_path() {
+    use crate::parser::parse;
+    use crate::processors::Processor;
+
+    let syntax_path = std::env::temp_dir().join("struckdown-syntect-test");
+    std::fs::create_dir_all(&syntax_path).unwrap();
+    std::fs::write(
+        syntax_path.join("Frob.sublime-syntax"),
+        "%YAML 1.2\n---\nname: Frob\nfile_extensions: [frob]\nscope: source.frob\ncontexts:\n  main:\n    - match: '\\bfrob\\b'\n      scope: keyword.control.frob\n",
+    )
+    .unwrap();
+
+    let highlight = |syntax_path| {
+        let processor = Syntect {
+            syntax_path: Some(syntax_path),
+            ..Syntect::default()
+        };
+        Box::new(processor)
+            .apply(Box::new(parse(
+                "```frob\nfrob it\n```",
+                &Default::default(),
+            )))
+            .map(|x| x.event)
+            .collect::<Vec<_>>()
+    };
+
+    let events = highlight(syntax_path);
+    match events.iter().find_map(|x| match x {
+        Event::RawHtml(RawHtmlEvent { ref html }) => Some(html.as_str()),
+        _ => None,
+    }) {
+        Some(html) => {
+            assert!(html.contains("<span style=\"font-weight:bold;color:#a71d5d;\">frob</span>"))
         }
-        Some(annotated_event)
+        None => panic!("code was not highlighted"),
     }
+
+    let events = highlight(std::env::temp_dir().join("struckdown-syntect-missing"));
+    assert!(matches!(events[1], Event::Error(..)));
+}
+
+#[test]
+fn test_stylesheet() {
+    let css = Syntect::default().stylesheet().unwrap();
+    assert!(css.contains(".keyword"));
+
+    let processor = Syntect {
+        theme_path: Some(std::env::temp_dir().join("struckdown-syntect-missing.tmTheme")),
+        ..Syntect::default()
+    };
+    assert!(processor.stylesheet().is_err());
+}
+
+#[test]
+fn test_parse_lines() {
+    let lines = |value: Value| parse_lines(&value, 5).into_iter().collect::<Vec<_>>();
+    assert_eq!(lines(Value::from("1,3-4")), vec![1, 3, 4]);
+    assert_eq!(lines(Value::from("4-18446744073709551615")), vec![4, 5]);
+    assert_eq!(lines(Value::from("2,7-9,12")), vec![2]);
+    assert_eq!(
+        lines(Value::from(vec![Value::from(5), Value::from(6)])),
+        vec![5]
+    );
 }
@@ -0,0 +1,199 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, Location, MetaDataEvent, TextEvent};
+use crate::processors::utils::whole_word_pattern;
+use crate::value::to_value;
+
+/// Controls what [`Terminology`] does when it finds a term.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminologyAction {
+    /// Replaces preferred terms in the text and reports denied terms.
+    Rewrite,
+    /// Only reports terms and leaves the text alone.
+    Report,
+}
+
+/// Enforces a terminology map on text.
+///
+/// Terms are matched as whole words and case insensitively.  A term in
+/// `replace` is rewritten to its preferred spelling which also covers case
+/// corrections (`javascript: JavaScript`).  Terms in `deny` can never be
+/// rewritten and are only reported.  All findings are emitted as
+/// `terminology` meta data at the end of the stream.
+///
+/// Only text events are inspected so code and directive bodies are left
+/// untouched.
+///
+/// When applied this wraps the stream in a [`TerminologyIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Terminology {
+    /// What to do with matched terms.
+    pub action: TerminologyAction,
+    /// Terms that must not be used.
+    pub deny: Vec<String>,
+    /// Maps terms to their preferred spelling.
+    pub replace: BTreeMap<String, String>,
+    /// Controls if findings should be emitted as meta data.
+    pub emit_metadata: bool,
+}
+
+impl Default for Terminology {
+    fn default() -> Terminology {
+        Terminology {
+            action: TerminologyAction::Rewrite,
+            deny: Vec::new(),
+            replace: BTreeMap::new(),
+            emit_metadata: true,
+        }
+    }
+}
+
+implement_processor!(Terminology, TerminologyIter);
+
+/// A term found by [`Terminology`].
+#[derive(Serialize, Debug)]
+pub struct TermFinding {
+    /// The term as it was found in the text.
+    term: String,
+    /// The preferred spelling if there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggestion: Option<String>,
+    /// Indicates if the text was rewritten.
+    replaced: bool,
+    /// The location of the text event containing the term.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    location: Option<Location>,
+}
+
+/// The iterator implementing [`Terminology`].
+pub struct TerminologyIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    regex: Option<Regex>,
+    lookup: BTreeMap<String, Option<String>>,
+    findings: Vec<TermFinding>,
+    done: bool,
+    options: Cow<'options, Terminology>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    TerminologyIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, Terminology>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        let mut lookup = BTreeMap::new();
+        for term in &options.deny {
+            lookup.insert(term.to_lowercase(), None);
+        }
+        for (term, preferred) in &options.replace {
+            lookup
+                .entry(term.to_lowercase())
+                .or_insert_with(|| Some(preferred.clone()));
+        }
+
+        // longer terms first so that multi word terms win over their parts.
+        let mut terms = lookup.keys().collect::<Vec<_>>();
+        terms.sort_by_key(|x| Reverse(x.len()));
+        let terms = terms
+            .into_iter()
+            .map(|x| whole_word_pattern(x))
+            .collect::<Vec<_>>();
+        let regex = if terms.is_empty() {
+            None
+        } else {
+            Some(
+                RegexBuilder::new(&terms.join("|"))
+                    .case_insensitive(true)
+                    .build()
+                    .expect("failed to compile terminology"),
+            )
+        };
+
+        Self {
+            source: iterator,
+            regex,
+            lookup,
+            findings: Vec::new(),
+            done: false,
+            options,
+        }
+    }
+
+    fn process_text(&mut self, text: &str, location: &Option<Location>) -> Option<String> {
+        let regex = self.regex.as_ref()?;
+        let rewrite = self.options.action == TerminologyAction::Rewrite;
+        let mut rv = String::new();
+        let mut last = 0;
+        let mut changed = false;
+
+        for m in regex.find_iter(text) {
+            let found = m.as_str();
+            let suggestion = match self.lookup.get(&found.to_lowercase()) {
+                Some(suggestion) => suggestion.clone(),
+                None => continue,
+            };
+            if suggestion.as_deref() == Some(found) {
+                continue;
+            }
+            let replaced = rewrite && suggestion.is_some();
+            if replaced {
+                rv.push_str(&text[last..m.start()]);
+                rv.push_str(suggestion.as_deref().unwrap());
+                last = m.end();
+                changed = true;
+            }
+            self.findings.push(TermFinding {
+                term: found.to_string(),
+                suggestion,
+                replaced,
+                location: location.clone(),
+            });
+        }
+
+        if changed {
+            rv.push_str(&text[last..]);
+            Some(rv)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for TerminologyIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut annotated_event = match self.source.next() {
+            Some(annotated_event) => annotated_event,
+            None => {
+                if self.done || !self.options.emit_metadata {
+                    return None;
+                }
+                self.done = true;
+                return Some(
+                    MetaDataEvent {
+                        key: "terminology".into(),
+                        value: to_value(&self.findings).expect("bad terminology report"),
+                    }
+                    .into(),
+                );
+            }
+        };
+
+        if let Event::Text(TextEvent { ref mut text }) = annotated_event.event {
+            if let Some(new_text) = self.process_text(text.as_str(), &annotated_event.location) {
+                *text = new_text.into();
+            }
+        }
+
+        Some(annotated_event)
+    }
+}
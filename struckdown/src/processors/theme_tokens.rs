@@ -0,0 +1,70 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, StartTagEvent, Tag};
+use crate::theme::{BLOCK_QUOTE, TABLE};
+
+/// Attaches theme tokens to markdown elements.
+///
+/// Elements generated by processors carry theme tokens already (see
+/// [`theme`](crate::theme)).  This processor adds tokens to the elements
+/// written in plain markdown so that profiles can style them as well.
+/// Tokens are added as classes and an empty token disables it.
+///
+/// When applied this wraps the stream in a [`ThemeTokensIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ThemeTokens {
+    /// The token for tables.
+    pub table: String,
+    /// The token for block quotes.
+    pub block_quote: String,
+}
+
+impl Default for ThemeTokens {
+    fn default() -> ThemeTokens {
+        ThemeTokens {
+            table: TABLE.into(),
+            block_quote: BLOCK_QUOTE.into(),
+        }
+    }
+}
+
+implement_processor!(ThemeTokens, ThemeTokensIter);
+
+/// The iterator implementing [`ThemeTokens`].
+pub struct ThemeTokensIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    options: Cow<'options, ThemeTokens>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    ThemeTokensIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, ThemeTokens>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for ThemeTokensIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut annotated_event = self.source.next()?;
+        if let Event::StartTag(StartTagEvent { tag, ref mut attrs }) = annotated_event.event {
+            let token = match tag {
+                Tag::Table => &self.options.table,
+                Tag::BlockQuote => &self.options.block_quote,
+                _ => return Some(annotated_event),
+            };
+            attrs.add_class(token);
+        }
+        Some(annotated_event)
+    }
+}
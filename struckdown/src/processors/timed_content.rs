@@ -0,0 +1,260 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, Attrs, DirectiveEvent, ErrorEvent, Event, Tag};
+use crate::processors::utils::parse_body;
+
+lazy_static! {
+    static ref DATE_RE: Regex = Regex::new(r"^\d{4}-\d{2}-\d{2}$").unwrap();
+}
+
+/// What happens with timed content outside of its time span.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InactiveContent {
+    /// The content is included as if it were active.
+    Include,
+    /// The content is removed.
+    Drop,
+    /// The content is included with the class `expired` or `scheduled`
+    /// and an error is emitted.
+    Flag,
+}
+
+/// Includes content depending on the build date.
+///
+/// The body of an `{until}` directive is only included before the given
+/// date, the body of a `{since}` directive only from the given date on:
+///
+/// ````markdown
+/// ```{until} 2025-01-01
+/// The service is down for maintenance on December 31st.
+/// ```
+/// ````
+///
+/// Active content is wrapped in a container with the class `timed-content`
+/// and a `data-until` or `data-since` attribute.  What happens with
+/// inactive content is controlled by `inactive`.  Dates are given as
+/// `YYYY-MM-DD`, directives with invalid dates are kept and emit an error.
+///
+/// The build date is `today` if set, otherwise the date of the
+/// `SOURCE_DATE_EPOCH` environment variable if set and the current UTC date
+/// otherwise.  An invalid `today` emits an error and is ignored.
+///
+/// When applied this wraps the stream in a [`TimedContentIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TimedContent {
+    /// The name of the directive for content that expires.
+    pub until_directive: String,
+    /// The name of the directive for content that starts at a date.
+    pub since_directive: String,
+    /// Overrides the build date (`YYYY-MM-DD`).
+    pub today: Option<String>,
+    /// What to do with content outside of its time span.
+    pub inactive: InactiveContent,
+}
+
+impl Default for TimedContent {
+    fn default() -> TimedContent {
+        TimedContent {
+            until_directive: "until".into(),
+            since_directive: "since".into(),
+            today: None,
+            inactive: InactiveContent::Drop,
+        }
+    }
+}
+
+implement_processor!(TimedContent, TimedContentIter);
+
+/// Converts days since the unix epoch into a `YYYY-MM-DD` date.
+fn date_from_days(days: i64) -> String {
+    // see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Returns the build date.
+fn build_date() -> String {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|x| x.trim().parse::<i64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |x| x.as_secs() as i64)
+        });
+    date_from_days(seconds.div_euclid(86_400))
+}
+
+/// The iterator implementing [`TimedContent`].
+pub struct TimedContentIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    errors: Vec<ErrorEvent<'data>>,
+    today: String,
+    options: Cow<'options, TimedContent>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    TimedContentIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, TimedContent>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        let mut errors = vec![];
+        let today = match options.today {
+            Some(ref today) if DATE_RE.is_match(today) => today.clone(),
+            Some(ref today) => {
+                errors.push(ErrorEvent {
+                    title: "Invalid date for the today option".into(),
+                    description: Some(format!("expected YYYY-MM-DD, got '{}'", today).into()),
+                    code: Some(Code::InvalidDate),
+                });
+                build_date()
+            }
+            None => build_date(),
+        };
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            errors,
+            today,
+            options,
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for TimedContentIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(annotated_event) = self.buffer.pop_front() {
+                return Some(annotated_event);
+            }
+
+            let annotated_event = self.source.next()?;
+            let (kind, date, body, body_location) = match annotated_event.event {
+                Event::DocumentStart(..) => {
+                    // configuration errors are reported right after the start
+                    self.buffer.extend(self.errors.drain(..).map(Into::into));
+                    return Some(annotated_event);
+                }
+                Event::Directive(DirectiveEvent {
+                    ref name,
+                    ref argument,
+                    ref body,
+                    ref body_location,
+                    ..
+                }) if name.as_str() == self.options.until_directive
+                    || name.as_str() == self.options.since_directive =>
+                {
+                    let kind = if name.as_str() == self.options.until_directive {
+                        "until"
+                    } else {
+                        "since"
+                    };
+                    let date = argument.as_ref().map_or("", |x| x.as_str().trim());
+                    if !DATE_RE.is_match(date) {
+                        self.buffer.push_back(annotated_event.clone());
+                        return Some(AnnotatedEvent::new(
+                            ErrorEvent {
+                                title: format!("Invalid date for {} directive", name.as_str())
+                                    .into(),
+                                description: Some(
+                                    format!("expected YYYY-MM-DD, got '{}'", date).into(),
+                                ),
+                                code: Some(Code::InvalidDate),
+                            },
+                            annotated_event.location,
+                        ));
+                    }
+                    (
+                        kind,
+                        date.to_string(),
+                        body.as_str().to_string(),
+                        body_location.clone(),
+                    )
+                }
+                _ => return Some(annotated_event),
+            };
+
+            let active = if kind == "until" {
+                self.today.as_str() < date.as_str()
+            } else {
+                self.today.as_str() >= date.as_str()
+            };
+            let mut class = "timed-content".to_string();
+            if !active {
+                match self.options.inactive {
+                    InactiveContent::Include => {}
+                    InactiveContent::Drop => continue,
+                    InactiveContent::Flag => {
+                        let (state, title, code) = if kind == "until" {
+                            (
+                                "expired",
+                                format!("Content expired on {}", date),
+                                Code::ContentExpired,
+                            )
+                        } else {
+                            (
+                                "scheduled",
+                                format!("Content is scheduled for {}", date),
+                                Code::ContentScheduled,
+                            )
+                        };
+                        class.push(' ');
+                        class.push_str(state);
+                        self.buffer.push_back(AnnotatedEvent::new(
+                            ErrorEvent {
+                                title: title.into(),
+                                description: None,
+                                code: Some(code),
+                            },
+                            annotated_event.location.clone(),
+                        ));
+                    }
+                }
+            }
+
+            self.buffer
+                .extend(parse_body(&body, body_location.as_ref()));
+            self.buffer.push_back(Tag::Container.end_tag().into());
+            let mut custom = BTreeMap::new();
+            custom.insert(format!("data-{}", kind).into(), date.into());
+            return Some(AnnotatedEvent::new(
+                Tag::Container.start_tag(Attrs {
+                    class: Some(class.into()),
+                    custom: Some(custom),
+                    ..Attrs::default()
+                }),
+                annotated_event.location,
+            ));
+        }
+    }
+}
+
+#[test]
+fn test_date_from_days() {
+    assert_eq!(date_from_days(0), "1970-01-01");
+    assert_eq!(date_from_days(11016), "2000-02-29");
+    assert_eq!(date_from_days(20089), "2025-01-01");
+    assert_eq!(date_from_days(-1), "1969-12-31");
+}
@@ -0,0 +1,162 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, StartTagEvent, Tag, TextEvent};
+use crate::plain::to_plain_text;
+use crate::value::{Map, Value};
+
+/// How the document title and the first heading are kept in sync.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TitlePolicy {
+    /// A level 1 heading is synthesized from the front matter title if the
+    /// document does not have one.
+    Synthesize,
+    /// The first level 1 heading becomes the front matter title if the
+    /// document does not have one.
+    Extract,
+    /// Like `extract` but the heading is also removed from the document
+    /// so that the theme can render the title itself.
+    ExtractAndRemove,
+}
+
+/// Keeps the document title and the top level heading in sync.
+///
+/// Site generators disagree on whether the title of a page comes from the
+/// front matter or from the first level 1 heading.  This processor makes
+/// sure that after it ran both agree according to the configured policy:
+///
+/// * `synthesize`: documents with a `title` in the front matter but no
+///   level 1 heading get one inserted at the start.
+/// * `extract`: the text of the first level 1 heading is stored as `title`
+///   in the front matter unless it already has one.
+/// * `extract_and_remove`: like `extract` but the heading is removed.
+///
+/// Titles derived from file names can be supplied by setting the front
+/// matter title before the pipeline runs.
+///
+/// When applied this wraps the stream in a [`TitleHeadingIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TitleHeading {
+    /// The synchronization policy.
+    pub policy: TitlePolicy,
+    /// The front matter key holding the title.
+    pub front_matter_key: String,
+}
+
+impl Default for TitleHeading {
+    fn default() -> TitleHeading {
+        TitleHeading {
+            policy: TitlePolicy::Synthesize,
+            front_matter_key: "title".into(),
+        }
+    }
+}
+
+implement_processor!(TitleHeading, TitleHeadingIter);
+
+/// The iterator implementing [`TitleHeading`].
+pub struct TitleHeadingIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: Option<I>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, TitleHeading>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    TitleHeadingIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, TitleHeading>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: Some(iterator),
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    fn process(&mut self, iter: I) {
+        self.buffer = iter.collect();
+
+        // the range of events making up the first level 1 heading
+        let mut heading = None;
+        let mut depth = 0;
+        for (idx, annotated_event) in self.buffer.iter().enumerate() {
+            match annotated_event.event {
+                Event::StartTag(StartTagEvent {
+                    tag: Tag::Heading1, ..
+                }) if depth == 0 => {
+                    heading = Some((idx, idx));
+                    depth = 1;
+                }
+                Event::StartTag(_) if depth > 0 => depth += 1,
+                Event::EndTag(_) if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        heading = heading.map(|(start, _)| (start, idx));
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let key = self.options.front_matter_key.as_str();
+        let title = match self.buffer.front().map(|x| &x.event) {
+            Some(Event::DocumentStart(document_start)) => document_start
+                .front_matter
+                .as_ref()
+                .and_then(|x| x.get(key))
+                .and_then(|x| x.as_str())
+                .map(|x| x.to_string()),
+            _ => return,
+        };
+
+        match (self.options.policy, heading) {
+            (TitlePolicy::Synthesize, None) => {
+                if let Some(title) = title {
+                    self.buffer
+                        .insert(1, Tag::Heading1.start_tag(Default::default()).into());
+                    self.buffer
+                        .insert(2, TextEvent { text: title.into() }.into());
+                    self.buffer.insert(3, Tag::Heading1.end_tag().into());
+                }
+            }
+            (TitlePolicy::Extract, Some((start, end)))
+            | (TitlePolicy::ExtractAndRemove, Some((start, end))) => {
+                if title.is_none() {
+                    let text = to_plain_text(self.buffer.range(start + 1..end))
+                        .as_str()
+                        .trim()
+                        .to_string();
+                    if let Event::DocumentStart(ref mut document_start) = self.buffer[0].event {
+                        if let Value::Object(map) = document_start
+                            .front_matter
+                            .get_or_insert_with(|| Value::Object(Map::new()))
+                        {
+                            map.insert(key.to_string(), Value::String(text));
+                        }
+                    }
+                }
+                if self.options.policy == TitlePolicy::ExtractAndRemove {
+                    self.buffer.drain(start..=end);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for TitleHeadingIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(iter) = self.source.take() {
+            self.process(iter);
+        }
+        self.buffer.pop_front()
+    }
+}
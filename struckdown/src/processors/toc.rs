@@ -10,13 +10,18 @@ use crate::event::{
 use crate::plain::to_plain_text;
 use crate::value::to_value;
 
-/// Automatically add anchors to all headers when missing.
+/// Replaces `{toc}` directives with a nested table of contents.
+///
+/// The stream is buffered so that a directive can reference headings that
+/// come after it.  Headings link to their ids which means this processor
+/// should run after [`AutoAnchors`](crate::processors::AutoAnchors).  The
+/// `max_depth` key in the directive front matter limits the nesting.
 ///
 /// When applied this wraps the stream in a [`TableOfContentsIter`].
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(default)]
 pub struct TableOfContents {
-    /// The name of the role that inserts the TOC.
+    /// The name of the directive that inserts the TOC.
     pub role_name: Option<String>,
     /// Controls if the toc should be emitted as meta data.
     pub emit_metadata: bool,
@@ -168,7 +173,7 @@ fn extract_toc<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(
                     }
                 }
             }
-            Event::Error(..) | Event::MetaData(..) => {}
+            Event::Error(..) | Event::MetaData(..) | Event::Diagnostic(..) => {}
             _ => {
                 if headline.is_some() {
                     headline_buf.push(annotated_event.clone());
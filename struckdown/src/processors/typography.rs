@@ -0,0 +1,129 @@
+use std::borrow::Cow;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Event, TextEvent};
+
+lazy_static! {
+    static ref FRACTION_RE: Regex = Regex::new(r"\b\d+/\d+\b").unwrap();
+    static ref SYMBOL_RE: Regex = Regex::new(r"(?i)\((?:c|r|tm)\)|\+-").unwrap();
+}
+
+/// Converts plain text patterns into typographic characters.
+///
+/// The following rules exist and can be turned off by listing them in
+/// `disable`:
+///
+/// * `fractions`: `1/2`, `1/4`, `3/4` and other common fractions become
+///   `½`, `¼` and `¾`.  Dates like `1/2/2021` are left alone.
+/// * `copyright`: `(c)` becomes `©`.
+/// * `registered`: `(r)` becomes `®`.
+/// * `trademark`: `(tm)` becomes `™`.
+/// * `plus_minus`: `+-` becomes `±`.
+///
+/// Only text events are modified so code is never touched.
+///
+/// When applied this wraps the stream in a [`TypographyIter`].
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Typography {
+    /// The names of the rules to disable.
+    pub disable: Vec<String>,
+}
+
+implement_processor!(Typography, TypographyIter);
+
+fn fraction(s: &str) -> Option<&'static str> {
+    Some(match s {
+        "1/2" => "½",
+        "1/3" => "⅓",
+        "2/3" => "⅔",
+        "1/4" => "¼",
+        "3/4" => "¾",
+        "1/5" => "⅕",
+        "2/5" => "⅖",
+        "3/5" => "⅗",
+        "4/5" => "⅘",
+        "1/6" => "⅙",
+        "5/6" => "⅚",
+        "1/8" => "⅛",
+        "3/8" => "⅜",
+        "5/8" => "⅝",
+        "7/8" => "⅞",
+        _ => return None,
+    })
+}
+
+impl Typography {
+    fn is_enabled(&self, rule: &str) -> bool {
+        !self.disable.iter().any(|x| x == rule)
+    }
+
+    /// Applies the enabled rules to a text.
+    pub fn apply_to_str<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut rv = Cow::Borrowed(text);
+        if self.is_enabled("fractions") {
+            if let Cow::Owned(new) = FRACTION_RE.replace_all(&rv, |caps: &Captures| {
+                // a slash next to the match means it is part of a date or path
+                let m = caps.get(0).unwrap();
+                if rv[..m.start()].ends_with('/') || rv[m.end()..].starts_with('/') {
+                    return caps[0].to_string();
+                }
+                fraction(&caps[0]).map_or_else(|| caps[0].to_string(), |x| x.to_string())
+            }) {
+                rv = Cow::Owned(new);
+            }
+        }
+        if let Cow::Owned(new) = SYMBOL_RE.replace_all(&rv, |caps: &Captures| {
+            let (rule, replacement) = match caps[0].to_lowercase().as_str() {
+                "(c)" => ("copyright", "©"),
+                "(r)" => ("registered", "®"),
+                "(tm)" => ("trademark", "™"),
+                _ => ("plus_minus", "±"),
+            };
+            if self.is_enabled(rule) {
+                replacement.to_string()
+            } else {
+                caps[0].to_string()
+            }
+        }) {
+            rv = Cow::Owned(new);
+        }
+        rv
+    }
+}
+
+/// The iterator implementing [`Typography`].
+pub struct TypographyIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    options: Cow<'options, Typography>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    TypographyIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, Typography>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: iterator,
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for TypographyIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut annotated_event = self.source.next()?;
+        if let Event::Text(TextEvent { ref mut text }) = annotated_event.event {
+            if let Cow::Owned(new_text) = self.options.apply_to_str(text.as_str()) {
+                *text = new_text.into();
+            }
+        }
+        Some(annotated_event)
+    }
+}
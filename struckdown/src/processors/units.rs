@@ -0,0 +1,212 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, Attrs, Event, InterpretedTextEvent, Tag, TextEvent};
+
+lazy_static! {
+    static ref QUANTITY_RE: Regex =
+        Regex::new(r"^\s*([-+−]?)(\d+)(?:[.,](\d+))?(?:[eE]([-+]?\d+))?\s*(.*?)\s*$").unwrap();
+    static ref EXPONENT_RE: Regex = Regex::new(r"\^?\{?([-−]?\d+)\}?").unwrap();
+    static ref UNIT_RE: Regex =
+        Regex::new(r"([A-Za-zµμΩ°%]+)(\^\{?[-−]?\d+\}?|\^?[-−]\d+|\d+)?").unwrap();
+}
+
+/// The narrow no-break space used between numbers and units.
+const NARROW_NBSP: &str = "\u{202f}";
+
+/// Locales that use a decimal comma.
+const DECIMAL_COMMA_LOCALES: &[&str] = &[
+    "cs", "da", "de", "es", "fi", "fr", "id", "it", "nb", "nl", "pl", "pt", "ru", "sv", "tr", "uk",
+];
+
+/// Formats quantities with units.
+///
+/// A role like `` {unit}`9.81 m/s^2` `` is replaced by a span with the
+/// class `unit` holding the formatted quantity: the number and unit are
+/// separated by a narrow no-break space, exponents are turned into
+/// superscripts (`m²`, `s⁻¹`), `*` becomes a multiplication dot and
+/// scientific notation is written as `× 10ⁿ`.  Large numbers are grouped
+/// with narrow no-break spaces.
+///
+/// The decimal separator follows `locale` (`de` renders `9,81`).  If no
+/// locale is configured the `lang` key of the front matter is used.
+///
+/// When applied this wraps the stream in a [`UnitsIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Units {
+    /// The name of the role.
+    pub role_name: String,
+    /// The locale to format numbers for.
+    pub locale: Option<String>,
+}
+
+impl Default for Units {
+    fn default() -> Units {
+        Units {
+            role_name: "unit".into(),
+            locale: None,
+        }
+    }
+}
+
+implement_processor!(Units, UnitsIter);
+
+fn superscript(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '0' => '⁰',
+            '1' => '¹',
+            '2' => '²',
+            '3' => '³',
+            '4' => '⁴',
+            '5' => '⁵',
+            '6' => '⁶',
+            '7' => '⁷',
+            '8' => '⁸',
+            '9' => '⁹',
+            '-' | '−' => '⁻',
+            '+' => '⁺',
+            c => c,
+        })
+        .collect()
+}
+
+/// Groups the digits of an integer with narrow no-break spaces.
+fn group_digits(digits: &str) -> String {
+    if digits.len() < 5 {
+        return digits.to_string();
+    }
+    let offset = digits.len() % 3;
+    let mut rv = String::new();
+    for (idx, c) in digits.chars().enumerate() {
+        if idx > 0 && idx % 3 == offset {
+            rv.push_str(NARROW_NBSP);
+        }
+        rv.push(c);
+    }
+    rv
+}
+
+/// Formats a unit expression such as `m/s^2` or `kg*m2`.
+fn format_unit(unit: &str) -> String {
+    let unit = unit.replace('*', "⋅");
+    UNIT_RE
+        .replace_all(&unit, |caps: &regex::Captures| match caps.get(2) {
+            Some(exp) => {
+                let exp = EXPONENT_RE
+                    .captures(exp.as_str())
+                    .map_or("", |m| m.get(1).unwrap().as_str());
+                format!("{}{}", &caps[1], superscript(exp))
+            }
+            None => caps[1].to_string(),
+        })
+        .into_owned()
+}
+
+/// Formats a quantity for a locale.
+pub fn format_quantity(text: &str, locale: Option<&str>) -> String {
+    let caps = match QUANTITY_RE.captures(text) {
+        Some(caps) => caps,
+        None => return format_unit(text.trim()),
+    };
+    let decimal_comma = match locale {
+        Some(locale) => {
+            let lang = locale.split(&['-', '_'][..]).next().unwrap_or("");
+            DECIMAL_COMMA_LOCALES.contains(&lang.to_lowercase().as_str())
+        }
+        None => false,
+    };
+
+    let mut rv = String::new();
+    match &caps[1] {
+        "-" | "−" => rv.push('−'),
+        sign => rv.push_str(sign),
+    }
+    rv.push_str(&group_digits(&caps[2]));
+    if let Some(fraction) = caps.get(3) {
+        rv.push(if decimal_comma { ',' } else { '.' });
+        rv.push_str(fraction.as_str());
+    }
+    if let Some(exp) = caps.get(4) {
+        rv.push_str(" × 10");
+        rv.push_str(&superscript(exp.as_str().trim_start_matches('+')));
+    }
+    let unit = &caps[5];
+    if !unit.is_empty() {
+        if unit != "%" && unit != "°" {
+            rv.push_str(NARROW_NBSP);
+        }
+        rv.push_str(&format_unit(unit));
+    }
+    rv
+}
+
+/// The iterator implementing [`Units`].
+pub struct UnitsIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: I,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    locale: Option<String>,
+    options: Cow<'options, Units>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> UnitsIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, Units>>>(iterator: I, options: O) -> Self {
+        let options = options.into();
+        Self {
+            source: iterator,
+            buffer: VecDeque::new(),
+            locale: options.locale.clone(),
+            options,
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for UnitsIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(annotated_event) = self.buffer.pop_front() {
+            return Some(annotated_event);
+        }
+
+        let annotated_event = self.source.next()?;
+        match annotated_event.event {
+            Event::DocumentStart(ref document_start) if self.locale.is_none() => {
+                self.locale = document_start
+                    .front_matter
+                    .as_ref()
+                    .and_then(|x| x.get("lang"))
+                    .and_then(|x| x.as_str())
+                    .map(|x| x.to_string());
+            }
+            Event::InterpretedText(InterpretedTextEvent {
+                ref role, ref text, ..
+            }) if role.as_str() == self.options.role_name => {
+                self.buffer.push_back(
+                    TextEvent {
+                        text: format_quantity(text.as_str(), self.locale.as_deref()).into(),
+                    }
+                    .into(),
+                );
+                self.buffer.push_back(Tag::Span.end_tag().into());
+                return Some(AnnotatedEvent::new(
+                    Tag::Span.start_tag(Attrs {
+                        class: Some("unit".into()),
+                        ..Attrs::default()
+                    }),
+                    annotated_event.location,
+                ));
+            }
+            _ => {}
+        }
+
+        Some(annotated_event)
+    }
+}
@@ -17,3 +17,233 @@ macro_rules! implement_processor {
         }
     };
 }
+
+use serde::Serialize;
+
+use crate::event::{
+    AnnotatedEvent, Event, Location, LocationMapper, MetaDataEvent, StartTagEvent, Tag,
+};
+use crate::value::to_value;
+
+/// Parses a directive body into owned events.
+///
+/// The document start event is skipped.  If the location of the body is
+/// known the locations of the events are mapped into the document,
+/// otherwise they are dropped as they would point into the body.
+pub(crate) fn parse_body(
+    body: &str,
+    body_location: Option<&Location>,
+) -> Vec<AnnotatedEvent<'static>> {
+    parse_body_with_options(body, body_location, &Default::default())
+}
+
+/// Like [`parse_body`] but with the given parser options.
+///
+/// Front matter is always disabled as directive bodies cannot have any.
+pub(crate) fn parse_body_with_options(
+    body: &str,
+    body_location: Option<&Location>,
+    options: &crate::parser::ParserOptions,
+) -> Vec<AnnotatedEvent<'static>> {
+    let options = crate::parser::ParserOptions {
+        enable_frontmatter: false,
+        ..options.clone()
+    };
+    let mapper = body_location.cloned().map(LocationMapper::new);
+    crate::parser::parse(body, &options)
+        .skip(1)
+        .map(|annotated_event| match mapper {
+            Some(ref mapper) => mapper.map_event(annotated_event).into_owned(),
+            None => AnnotatedEvent::new(annotated_event.event.into_owned(), None),
+        })
+        .collect()
+}
+
+/// A section of a parsed directive body.
+pub(crate) struct Section {
+    /// The contents of the heading starting the section.
+    pub title: Vec<AnnotatedEvent<'static>>,
+    /// Everything up to the next heading.
+    pub body: Vec<AnnotatedEvent<'static>>,
+}
+
+/// Splits parsed events into sections at the first heading level used.
+///
+/// Events before the first heading end up in a section without a title.
+pub(crate) fn split_sections(events: Vec<AnnotatedEvent<'static>>) -> Vec<Section> {
+    let section_level = events
+        .iter()
+        .find_map(|annotated_event| match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => tag.header_level(),
+            _ => None,
+        });
+
+    let mut sections: Vec<Section> = vec![];
+    let mut depth = 0;
+    let mut in_title = false;
+
+    for annotated_event in events {
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent { tag, .. }) => {
+                if depth == 0 && tag.header_level().is_some() && tag.header_level() == section_level
+                {
+                    sections.push(Section {
+                        title: vec![],
+                        body: vec![],
+                    });
+                    in_title = true;
+                    depth += 1;
+                    continue;
+                }
+                depth += 1;
+            }
+            Event::EndTag(..) => {
+                depth -= 1;
+                if depth == 0 && in_title {
+                    in_title = false;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+
+        if sections.is_empty() {
+            sections.push(Section {
+                title: vec![],
+                body: vec![],
+            });
+        }
+        let section = sections.last_mut().unwrap();
+        if in_title {
+            section.title.push(annotated_event);
+        } else {
+            section.body.push(annotated_event);
+        }
+    }
+
+    sections
+}
+
+/// Splits parsed events into their top level elements.
+pub(crate) fn split_blocks(
+    events: Vec<AnnotatedEvent<'static>>,
+) -> Vec<Vec<AnnotatedEvent<'static>>> {
+    let mut rv = vec![];
+    let mut current = vec![];
+    let mut depth = 0;
+    for annotated_event in events {
+        match annotated_event.event {
+            Event::StartTag(..) => depth += 1,
+            Event::EndTag(..) => depth -= 1,
+            _ => {}
+        }
+        current.push(annotated_event);
+        if depth == 0 {
+            rv.push(std::mem::take(&mut current));
+        }
+    }
+    rv
+}
+
+/// Returns the tag of a block if it starts with one.
+pub(crate) fn block_tag(block: &[AnnotatedEvent<'static>]) -> Option<Tag> {
+    match block.first()?.event {
+        Event::StartTag(StartTagEvent { tag, .. }) => Some(tag),
+        _ => None,
+    }
+}
+
+/// Strips the outer start and end tag of a block.
+pub(crate) fn strip_outer(mut block: Vec<AnnotatedEvent<'static>>) -> Vec<AnnotatedEvent<'static>> {
+    block.pop();
+    block.remove(0);
+    block
+}
+
+/// Checks if a link target is a relative path.
+pub(crate) fn is_relative_target(target: &str) -> bool {
+    !target.starts_with('/')
+        && !target.starts_with('#')
+        && !target.starts_with("//")
+        && !matches!(target.find(':'), Some(idx) if !target[..idx].contains('/'))
+}
+
+/// Builds a regex pattern matching a literal term as a whole word.
+///
+/// Word boundaries are only required on the sides where the term starts or
+/// ends with a word character so that terms like `C++` can match too.
+pub(crate) fn whole_word_pattern(term: &str) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut rv = String::new();
+    if term.starts_with(is_word) {
+        rv.push_str(r"\b");
+    }
+    rv.push_str(&regex::escape(term));
+    if term.ends_with(is_word) {
+        rv.push_str(r"\b");
+    }
+    rv
+}
+
+/// Skips the contents of an element whose start tag was just consumed.
+///
+/// The matching end tag is consumed as well.
+pub(crate) fn skip_element<'data, I: Iterator<Item = AnnotatedEvent<'data>>>(source: &mut I) {
+    let mut depth = 1;
+    for annotated_event in source {
+        match annotated_event.event {
+            Event::StartTag(..) => depth += 1,
+            Event::EndTag(..) => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            break;
+        }
+    }
+}
+
+/// A report of a processor emitted as meta data at the end of the stream.
+pub(crate) struct MetaDataReport<T> {
+    entries: Vec<T>,
+    done: bool,
+}
+
+impl<T> MetaDataReport<T> {
+    pub fn new() -> MetaDataReport<T> {
+        MetaDataReport {
+            entries: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Adds an entry to the report.
+    pub fn push(&mut self, entry: T) {
+        self.entries.push(entry);
+    }
+
+    /// Returns the meta data event with the value built from the entries.
+    ///
+    /// The event is only returned once and only if `emit` is set.
+    pub fn finish<'data, 'a, V, F>(
+        &'a mut self,
+        key: &'static str,
+        emit: bool,
+        value: F,
+    ) -> Option<AnnotatedEvent<'data>>
+    where
+        V: Serialize,
+        F: FnOnce(&'a [T]) -> V,
+    {
+        if self.done || !emit {
+            return None;
+        }
+        self.done = true;
+        Some(
+            MetaDataEvent {
+                key: key.into(),
+                value: to_value(value(&self.entries)).expect("bad meta data report"),
+            }
+            .into(),
+        )
+    }
+}
@@ -0,0 +1,356 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use wasmtime::{Config, Engine, ExternType, Instance, Memory, Module, Store};
+
+use crate::codes::Code;
+use crate::event::{AnnotatedEvent, ErrorEvent, Event};
+use crate::json::{stream_from_json, stream_to_json};
+
+/// The version of the ABI modules have to implement.
+pub const WASM_ABI_VERSION: i32 = 1;
+
+/// The default for [`WasmProcessor::fuel`].
+pub const DEFAULT_WASM_FUEL: u64 = 1_000_000_000;
+
+/// The default for [`WasmProcessor::max_memory`] (128 MiB).
+pub const DEFAULT_WASM_MAX_MEMORY: u64 = 128 * 1024 * 1024;
+
+/// The size of a page of WebAssembly memory.
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+/// Passes a serialized stream through a WebAssembly module.
+///
+/// This runs third-party plugins without spawning a process.  Modules get
+/// no imports so they cannot access the host at all, `fuel` additionally
+/// bounds the instructions they may execute and `max_memory` the memory
+/// they may use.
+///
+/// ```yaml
+/// processors:
+///   - processor: wasm_processor
+///     path: plugins/glossary.wasm
+/// ```
+///
+/// The module has to export:
+///
+/// * `memory`: its linear memory.  It has to declare a maximum size of at
+///   most `max_memory` bytes (for Rust `-C link-arg=--max-memory=<bytes>`).
+/// * `struckdown_abi_version() -> i32`: returns [`WASM_ABI_VERSION`].
+/// * `struckdown_alloc(len: i32) -> i32`: returns a buffer of `len` bytes.
+/// * `struckdown_process(ptr: i32, len: i32) -> i64`: processes the
+///   document in the buffer and returns the location of the output with
+///   the pointer in the upper and the length in the lower 32 bits.
+///
+/// Input and output are the event stream of the whole document as JSON
+/// lines (see [`json`](crate::json)).  The module is compiled once per
+/// processor and instantiated for every document.
+///
+/// If the module cannot be loaded, traps or emits an invalid stream an
+/// error is emitted and the document is passed on unprocessed.
+///
+/// The module is read subject to the installed [`Sandbox`](crate::sandbox::Sandbox).
+///
+/// This is only available with the `wasm-processor` feature.
+///
+/// When applied this wraps the stream in a [`WasmProcessorIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct WasmProcessor {
+    /// The path to the module.
+    pub path: PathBuf,
+    /// The number of instructions after which the module is stopped.
+    pub fuel: u64,
+    /// The largest memory in bytes a module may declare.
+    pub max_memory: u64,
+    /// The module compiled on first use.
+    #[serde(skip)]
+    pub compiled: CompiledModule,
+}
+
+impl Default for WasmProcessor {
+    fn default() -> WasmProcessor {
+        WasmProcessor {
+            path: PathBuf::new(),
+            fuel: DEFAULT_WASM_FUEL,
+            max_memory: DEFAULT_WASM_MAX_MEMORY,
+            compiled: CompiledModule::default(),
+        }
+    }
+}
+
+/// Holds the compiled module of a [`WasmProcessor`].
+///
+/// Clones start out empty so that every processor compiles its own module.
+#[derive(Default)]
+pub struct CompiledModule(Mutex<Option<Result<(Engine, Module), String>>>);
+
+impl CompiledModule {
+    fn get(&self, options: &WasmProcessor) -> Result<(Engine, Module), String> {
+        let mut compiled = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        compiled
+            .get_or_insert_with(|| compile_module(options))
+            .clone()
+    }
+}
+
+impl Clone for CompiledModule {
+    fn clone(&self) -> CompiledModule {
+        CompiledModule::default()
+    }
+}
+
+impl fmt::Debug for CompiledModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompiledModule").finish()
+    }
+}
+
+implement_processor!(WasmProcessor, WasmProcessorIter);
+
+/// Returns the bytes of a module's memory in a range.
+fn memory_range(memory: &Memory, ptr: usize, len: usize) -> Result<std::ops::Range<usize>, String> {
+    match ptr.checked_add(len) {
+        Some(end) if end <= memory.data_size() => Ok(ptr..end),
+        _ => Err(format!(
+            "buffer at {} with {} bytes is out of bounds",
+            ptr, len
+        )),
+    }
+}
+
+/// Compiles the module and checks that its memory is bounded.
+fn compile_module(options: &WasmProcessor) -> Result<(Engine, Module), String> {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+    let module = Module::from_file(&engine, &options.path).map_err(|err| err.to_string())?;
+    for export in module.exports() {
+        if let ExternType::Memory(memory) = export.ty() {
+            match memory.limits().max() {
+                Some(max) if u64::from(max) * WASM_PAGE_SIZE <= options.max_memory => {}
+                _ => {
+                    return Err(format!(
+                        "module memory has to declare a maximum of at most {} bytes",
+                        options.max_memory
+                    ))
+                }
+            }
+        }
+    }
+    Ok((engine, module))
+}
+
+/// Runs the module on a serialized document.
+fn run_module(options: &WasmProcessor, input: &[u8]) -> Result<Vec<u8>, String> {
+    crate::sandbox::current().check_read(&options.path)?;
+
+    let (engine, module) = options.compiled.get(options)?;
+    let store = Store::new(&engine);
+    store
+        .add_fuel(options.fuel)
+        .map_err(|err| err.to_string())?;
+    let instance = Instance::new(&store, &module, &[]).map_err(|err| err.to_string())?;
+
+    let export = |name: &str| {
+        instance
+            .get_func(name)
+            .ok_or_else(|| format!("module does not export {}", name))
+    };
+    let memory = instance
+        .get_memory("memory")
+        .ok_or("module does not export memory")?;
+    let abi_version = export("struckdown_abi_version")?
+        .get0::<i32>()
+        .map_err(|err| err.to_string())?;
+    let alloc = export("struckdown_alloc")?
+        .get1::<i32, i32>()
+        .map_err(|err| err.to_string())?;
+    let process = export("struckdown_process")?
+        .get2::<i32, i32, i64>()
+        .map_err(|err| err.to_string())?;
+
+    let version = abi_version().map_err(|err| err.to_string())?;
+    if version != WASM_ABI_VERSION {
+        return Err(format!(
+            "module implements ABI version {}, expected {}",
+            version, WASM_ABI_VERSION
+        ));
+    }
+
+    let len = i32::try_from(input.len()).map_err(|_| {
+        format!(
+            "document with {} bytes does not fit into the module",
+            input.len()
+        )
+    })?;
+    let ptr = alloc(len).map_err(|err| err.to_string())?;
+    let range = memory_range(&memory, ptr as u32 as usize, input.len())?;
+    // the module is not running while we hold on to its memory
+    unsafe { memory.data_unchecked_mut()[range].copy_from_slice(input) };
+
+    let rv = process(ptr, len).map_err(|err| err.to_string())? as u64;
+    let range = memory_range(&memory, (rv >> 32) as usize, (rv & 0xffff_ffff) as usize)?;
+    Ok(unsafe { memory.data_unchecked()[range].to_vec() })
+}
+
+/// The iterator implementing [`WasmProcessor`].
+pub struct WasmProcessorIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: Option<I>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, WasmProcessor>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>>
+    WasmProcessorIter<'data, 'options, I>
+{
+    pub fn new<O: Into<Cow<'options, WasmProcessor>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: Some(iterator),
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for WasmProcessorIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(source) = self.source.take() {
+            let events: Vec<_> = source.collect();
+            let input = stream_to_json(events.iter().cloned());
+            let output = run_module(&self.options, input.as_bytes()).and_then(|output| {
+                let output = String::from_utf8(output).map_err(|err| err.to_string())?;
+                stream_from_json(&output).map_err(|err| err.to_string())
+            });
+            match output {
+                Ok(output) => self.buffer.extend(output),
+                Err(err) => {
+                    self.buffer.extend(events);
+                    // the error goes right after the document start
+                    let idx = match self.buffer.front().map(|x| &x.event) {
+                        Some(Event::DocumentStart(..)) => 1,
+                        _ => 0,
+                    };
+                    self.buffer.insert(
+                        idx,
+                        ErrorEvent {
+                            title: format!(
+                                "Failed to run wasm processor '{}'",
+                                self.options.path.display()
+                            )
+                            .into(),
+                            description: Some(err.into()),
+                            code: Some(Code::WasmProcessorFailure),
+                        }
+                        .into(),
+                    );
+                }
+            }
+        }
+        self.buffer.pop_front()
+    }
+}
+
+#[cfg(test)]
+fn run_wat(name: &str, wat: &str, fuel: u64) -> Vec<Event<'static>> {
+    use crate::parser::parse;
+    use crate::processors::Processor;
+
+    let path = std::env::temp_dir().join(format!("struckdown-wasm-{}.wat", name));
+    std::fs::write(&path, wat).unwrap();
+    let processor = WasmProcessor {
+        path: path.clone(),
+        fuel,
+        ..WasmProcessor::default()
+    };
+    let events = processor
+        .apply_ref(Box::new(parse("Hello *World*", &Default::default())))
+        .map(|x| x.event.into_owned())
+        .collect();
+    std::fs::remove_file(&path).ok();
+    events
+}
+
+#[cfg(test)]
+fn test_module(version: i32, memory: &str, process: &str) -> String {
+    format!(
+        r#"(module
+  (memory (export "memory") {})
+  (func (export "struckdown_abi_version") (result i32) (i32.const {}))
+  (func (export "struckdown_alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "struckdown_process") (param i32 i32) (result i64) {}))"#,
+        memory, version, process
+    )
+}
+
+#[test]
+fn test_wasm_processor() {
+    use crate::parser::parse;
+
+    // returns the input as output
+    let echo = "(i64.or (i64.shl (i64.extend_i32_u (local.get 0)) (i64.const 32)) \
+                (i64.extend_i32_u (local.get 1)))";
+    let expected: Vec<_> = parse("Hello *World*", &Default::default())
+        .map(|x| x.event.into_owned())
+        .collect();
+    let failure = |events: &[Event<'_>]| match events.get(1) {
+        Some(Event::Error(ErrorEvent { description, .. })) => {
+            description.as_ref().unwrap().as_str().to_string()
+        }
+        other => panic!("unexpected event {:?}", other),
+    };
+
+    assert_eq!(
+        run_wat("echo", &test_module(1, "2 2", echo), DEFAULT_WASM_FUEL),
+        expected
+    );
+
+    let events = run_wat("version", &test_module(2, "2 2", echo), DEFAULT_WASM_FUEL);
+    assert_eq!(
+        failure(&events),
+        "module implements ABI version 2, expected 1"
+    );
+    assert_eq!(&events[2..], &expected[1..]);
+
+    let events = run_wat(
+        "bounds",
+        &test_module(1, "2 2", "(i64.const 0x7fff000000000010)"),
+        DEFAULT_WASM_FUEL,
+    );
+    assert!(failure(&events).contains("out of bounds"));
+
+    let events = run_wat(
+        "fuel",
+        &test_module(1, "2 2", "(loop $l (br $l)) (i64.const 0)"),
+        10_000,
+    );
+    assert!(!failure(&events).is_empty());
+    assert_eq!(&events[2..], &expected[1..]);
+
+    let events = run_wat("memory", &test_module(1, "2", echo), DEFAULT_WASM_FUEL);
+    assert!(failure(&events).contains("maximum"));
+}
+
+#[test]
+fn test_compiled_once() {
+    let path = std::env::temp_dir().join("struckdown-wasm-compiled-once.wat");
+    std::fs::write(&path, test_module(1, "1 1", "(i64.const 0)")).unwrap();
+    let processor = WasmProcessor {
+        path: path.clone(),
+        ..WasmProcessor::default()
+    };
+    assert!(processor.compiled.get(&processor).is_ok());
+    std::fs::remove_file(&path).unwrap();
+    // the module is not read again
+    assert!(processor.compiled.get(&processor).is_ok());
+    assert!(processor.clone().compiled.get(&processor).is_err());
+}
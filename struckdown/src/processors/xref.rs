@@ -0,0 +1,407 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::anchors::collect_anchors;
+use crate::codes::Code;
+use crate::event::{
+    AnnotatedEvent, Attrs, DiagnosticEvent, Event, InterpretedTextEvent, Location, Severity,
+    StartTagEvent, Str, Tag, TextEvent,
+};
+use crate::navigation::document_title;
+use crate::parser::ReferenceResolver;
+
+/// A target that cross-references can point to.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Reference {
+    /// The URL of the document.
+    pub document: String,
+    /// The id of the element or `None` for the document itself.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The title of the element.
+    pub title: String,
+}
+
+impl Reference {
+    /// Returns the link target (`document#id`).
+    pub fn href(&self) -> String {
+        match self.id {
+            Some(ref id) => format!("{}#{}", self.document, id),
+            None => self.document.clone(),
+        }
+    }
+}
+
+/// The anchors and titles of a set of documents.
+///
+/// The database is filled in a first pass over all documents and used by
+/// [`XRef`] to resolve references between them in a second pass.  It can be
+/// serialized to keep it between builds:
+///
+/// ```
+/// use struckdown::parser::parse;
+/// use struckdown::processors::{AutoAnchors, Processor, ReferenceDatabase};
+///
+/// let source = "# Guide\n\n## Setup\n";
+/// let events: Vec<_> = Box::new(AutoAnchors::default())
+///     .apply(Box::new(parse(source, &Default::default())))
+///     .collect();
+/// let mut database = ReferenceDatabase::new();
+/// database.add_document("/guide/", &events);
+/// assert_eq!(database.lookup("setup")[0].href(), "/guide/#setup");
+/// assert_eq!(database.lookup("Guide")[0].href(), "/guide/");
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReferenceDatabase {
+    references: Vec<Reference>,
+}
+
+impl ReferenceDatabase {
+    /// Creates an empty database.
+    pub fn new() -> ReferenceDatabase {
+        ReferenceDatabase::default()
+    }
+
+    /// Adds the title and anchors of a processed document.
+    ///
+    /// To include generated ids the stream should already have been
+    /// processed by processors such as [`AutoAnchors`](super::AutoAnchors).
+    pub fn add_document(&mut self, document: &str, events: &[AnnotatedEvent<'_>]) {
+        self.references.push(Reference {
+            document: document.to_string(),
+            id: None,
+            title: document_title(events).unwrap_or_default(),
+        });
+        for anchor in collect_anchors(events.iter().cloned()) {
+            self.references.push(Reference {
+                document: document.to_string(),
+                id: Some(anchor.id),
+                title: anchor.title,
+            });
+        }
+    }
+
+    /// Adds a single reference.
+    pub fn insert(&mut self, reference: Reference) {
+        self.references.push(reference);
+    }
+
+    /// Returns all references in the order they were added.
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    /// Finds the references matching a key.
+    ///
+    /// A key is a `document#id` pair, an id (optionally prefixed with
+    /// `#`), a document or a title.  Titles are compared case
+    /// insensitively and only used if nothing else matches.  A document
+    /// matching by title hides its elements with the same title.
+    pub fn lookup(&self, key: &str) -> Vec<&Reference> {
+        let matching = |f: &dyn Fn(&Reference) -> bool| -> Vec<&Reference> {
+            self.references.iter().filter(|x| f(x)).collect()
+        };
+        let rv = match key.rfind('#') {
+            Some(0) => matching(&|x| x.id.as_deref() == Some(&key[1..])),
+            Some(idx) => {
+                matching(&|x| x.document == key[..idx] && x.id.as_deref() == Some(&key[idx + 1..]))
+            }
+            None => {
+                matching(&|x| x.id.as_deref() == Some(key) || (x.id.is_none() && x.document == key))
+            }
+        };
+        if !rv.is_empty() || key.starts_with('#') {
+            return rv;
+        }
+        let key = key.to_lowercase();
+        let mut rv = matching(&|x| !x.title.is_empty() && x.title.to_lowercase() == key);
+        // the title of a document is usually the title of its first heading
+        let documents: Vec<String> = rv
+            .iter()
+            .filter(|x| x.id.is_none())
+            .map(|x| x.document.clone())
+            .collect();
+        rv.retain(|x| x.id.is_none() || !documents.contains(&x.document));
+        rv
+    }
+
+    /// Returns a parser hook resolving reference links against the database.
+    ///
+    /// This resolves links like `[Page Title]` that have no definition in
+    /// the document (see [`ReferenceResolver`]).  Only unambiguous keys are
+    /// resolved.
+    pub fn resolver(&self) -> ReferenceResolver {
+        let database = self.clone();
+        ReferenceResolver::new(move |key| match database.lookup(key)[..] {
+            [reference] => Some((reference.href().into(), reference.title.clone().into())),
+            _ => None,
+        })
+    }
+}
+
+/// Resolves cross-references between documents.
+///
+/// Interpreted text with the configured role (`` {ref}`setup` ``) becomes a
+/// link to the target with the key (see [`ReferenceDatabase::lookup`]) and
+/// the title of the target as text.  Explicit text is given in front of the
+/// key: `` {ref}`the setup <guide#setup>` ``.  Ids of the processed document
+/// take precedence over the database.
+///
+/// If `resolve_links` is enabled links to `#id` that is not an id of the
+/// processed document are resolved against the database as well.
+///
+/// Unresolved references and ambiguous keys emit a warning as diagnostic.
+/// Unresolved references are replaced by their text.  Links into the
+/// `document` the stream belongs to keep only the `#id` part.
+///
+/// When applied this wraps the stream in a [`XRefIter`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct XRef {
+    /// The references of all documents.
+    pub database: ReferenceDatabase,
+    /// The URL of the processed document.
+    pub document: Option<String>,
+    /// The name of the role that references targets.
+    pub role_name: String,
+    /// The class added to resolved references.
+    pub class: String,
+    /// Controls if links to unknown ids are resolved against the database.
+    pub resolve_links: bool,
+}
+
+impl Default for XRef {
+    fn default() -> XRef {
+        XRef {
+            database: ReferenceDatabase::default(),
+            document: None,
+            role_name: "ref".into(),
+            class: "xref".into(),
+            resolve_links: true,
+        }
+    }
+}
+
+implement_processor!(XRef, XRefIter);
+
+/// Splits role text into explicit text and key (`text <key>`).
+fn split_explicit_text(text: &str) -> (Option<&str>, &str) {
+    let text = text.trim();
+    if let (true, Some(idx)) = (text.ends_with('>'), text.rfind('<')) {
+        let title = text[..idx].trim();
+        if !title.is_empty() {
+            return (Some(title), text[idx + 1..text.len() - 1].trim());
+        }
+    }
+    (None, text)
+}
+
+/// The iterator implementing [`XRef`].
+pub struct XRefIter<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> {
+    source: Option<I>,
+    buffer: VecDeque<AnnotatedEvent<'data>>,
+    options: Cow<'options, XRef>,
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> XRefIter<'data, 'options, I> {
+    pub fn new<O: Into<Cow<'options, XRef>>>(iterator: I, options: O) -> Self {
+        Self {
+            source: Some(iterator),
+            buffer: VecDeque::new(),
+            options: options.into(),
+        }
+    }
+
+    /// Resolves a key to a link target and title.
+    ///
+    /// Ambiguous keys resolve to the first match and emit a warning.
+    fn resolve(
+        &mut self,
+        key: &str,
+        local: &HashMap<String, String>,
+        location: &Option<Location>,
+    ) -> Option<(String, String)> {
+        if let Some(title) = local.get(key.trim_start_matches('#')) {
+            return Some((format!("#{}", key.trim_start_matches('#')), title.clone()));
+        }
+        let references = self.options.database.lookup(key);
+        let reference = references.first()?;
+        if references.len() > 1 {
+            self.buffer.push_back(AnnotatedEvent::new(
+                DiagnosticEvent {
+                    severity: Severity::Warning,
+                    message: format!("Ambiguous reference '{}'", key).into(),
+                    description: Some(
+                        format!(
+                            "Matches {}",
+                            references
+                                .iter()
+                                .map(|x| x.href())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                        .into(),
+                    ),
+                    code: Some(Code::AmbiguousReference),
+                },
+                location.clone(),
+            ));
+        }
+        let href = match (reference.id.as_ref(), self.options.document.as_ref()) {
+            (Some(id), Some(document)) if *document == reference.document => format!("#{}", id),
+            _ => reference.href(),
+        };
+        Some((href, reference.title.clone()))
+    }
+
+    fn unresolved(&mut self, message: String, location: Option<Location>) {
+        self.buffer.push_back(AnnotatedEvent::new(
+            DiagnosticEvent {
+                severity: Severity::Warning,
+                message: message.into(),
+                description: None,
+                code: Some(Code::UnresolvedReference),
+            },
+            location,
+        ));
+    }
+
+    fn process(&mut self, iter: I) {
+        let events: Vec<_> = iter.collect();
+        let local: HashMap<String, String> = collect_anchors(events.iter().cloned())
+            .into_iter()
+            .map(|x| (x.id, x.title))
+            .collect();
+
+        for mut annotated_event in events {
+            match annotated_event.event {
+                Event::InterpretedText(InterpretedTextEvent {
+                    ref role, ref text, ..
+                }) if role.as_str() == self.options.role_name => {
+                    let (explicit_text, key) = split_explicit_text(text.as_str());
+                    let location = annotated_event.location.clone();
+                    match self.resolve(key, &local, &location) {
+                        Some((href, title)) => {
+                            let text = match explicit_text {
+                                Some(text) => text.to_string(),
+                                None if !title.is_empty() => title.clone(),
+                                None => key.to_string(),
+                            };
+                            self.buffer.push_back(AnnotatedEvent::new(
+                                Tag::Link.start_tag(Attrs {
+                                    target: Some(href.into()),
+                                    title: Some(title).filter(|x| !x.is_empty()).map(Into::into),
+                                    class: Some(self.options.class.clone().into()),
+                                    ..Attrs::default()
+                                }),
+                                location,
+                            ));
+                            self.buffer
+                                .push_back(TextEvent { text: text.into() }.into());
+                            self.buffer.push_back(Tag::Link.end_tag().into());
+                        }
+                        None => {
+                            let text: Str<'_> = explicit_text.unwrap_or(key).to_string().into();
+                            self.unresolved(
+                                format!("Unresolved reference '{}'", key),
+                                location.clone(),
+                            );
+                            self.buffer
+                                .push_back(AnnotatedEvent::new(TextEvent { text }, location));
+                        }
+                    }
+                    continue;
+                }
+                Event::StartTag(StartTagEvent {
+                    tag: Tag::Link,
+                    ref mut attrs,
+                }) if self.options.resolve_links => {
+                    let key = match attrs.target {
+                        Some(ref target)
+                            if target.as_str().starts_with('#')
+                                && !local.contains_key(&target.as_str()[1..]) =>
+                        {
+                            target.as_str().to_string()
+                        }
+                        _ => {
+                            self.buffer.push_back(annotated_event);
+                            continue;
+                        }
+                    };
+                    let location = annotated_event.location.clone();
+                    match self.resolve(&key, &local, &location) {
+                        Some((href, title)) => {
+                            attrs.target = Some(href.into());
+                            if attrs.title.is_none() && !title.is_empty() {
+                                attrs.title = Some(title.into());
+                            }
+                        }
+                        None => {
+                            self.unresolved(format!("Unresolved link target '{}'", key), location)
+                        }
+                    }
+                }
+                _ => {}
+            }
+            self.buffer.push_back(annotated_event);
+        }
+    }
+}
+
+impl<'data, 'options, I: Iterator<Item = AnnotatedEvent<'data>>> Iterator
+    for XRefIter<'data, 'options, I>
+{
+    type Item = AnnotatedEvent<'data>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(iter) = self.source.take() {
+            self.process(iter);
+        }
+        self.buffer.pop_front()
+    }
+}
+
+#[test]
+fn test_xref() {
+    use crate::diagnostics::collect_diagnostics;
+    use crate::html::to_html;
+    use crate::parser::parse;
+    use crate::processors::{AutoAnchors, Processor};
+
+    let process = |source| -> Vec<_> {
+        Box::new(AutoAnchors::default())
+            .apply(Box::new(parse(source, &Default::default())))
+            .collect()
+    };
+    let mut database = ReferenceDatabase::new();
+    database.add_document(
+        "/guide/",
+        &process("# User Guide\n\n## Setup\n\n## Usage {#usage}\n"),
+    );
+    database.add_document("/faq/", &process("# FAQ\n\n## Setup\n\n## Licensing\n"));
+    database.add_document("/api/", &process("# API\n\n## Client\n"));
+
+    let source = "\
+# API
+
+## Client
+
+See {ref}`User Guide`, {ref}`how to use it </guide/#usage>`, {ref}`licensing`,
+{ref}`client`, {ref}`setup`, [usage](#usage), [the client](#client) and
+{ref}`missing <nowhere>` or [nothing](#nothing).
+";
+    let events = process(source);
+    let xref = XRef {
+        database,
+        document: Some("/api/".into()),
+        ..XRef::default()
+    };
+    let events: Vec<_> = Box::new(xref).apply(Box::new(events.into_iter())).collect();
+    let mut output = to_html(events.iter().cloned(), &Default::default());
+    for diagnostic in collect_diagnostics(&events) {
+        output.push_str(&format!("\n{}", diagnostic));
+    }
+    insta::assert_snapshot!(output);
+}
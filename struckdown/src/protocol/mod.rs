@@ -0,0 +1,357 @@
+//! The protocol spoken with external processors.
+//!
+//! An [`External`] processor passes the event stream through a program.
+//! This module specifies what such a program has to do so that processors
+//! can be written in any language, and provides the means to check that a
+//! program does it.
+//!
+//! # Versioning
+//!
+//! The protocol is versioned following semantic versioning, the current
+//! version is [`PROTOCOL_VERSION`].  Programs speaking the same major
+//! version are compatible.  Minor versions add event types or optional
+//! fields, programs should pass on events they do not understand unchanged.
+//!
+//! # Messages
+//!
+//! The program reads messages from stdin and writes messages to stdout in
+//! the configured [`WireFormat`]:
+//!
+//! * `json`: every message is a JSON value on a line of its own (see
+//!   [`json`](crate::json)).
+//! * `message_pack`: messages are concatenated MessagePack values with
+//!   structs encoded as maps.
+//!
+//! Events are serialized as [`AnnotatedEvent`]s: an event with a location
+//! is a two element array of the event and its location, an event without
+//! one is just the event.  Events with trivia from a lossless parse are a
+//! three element array with the trivia last.  Every event is tagged with
+//! its `type`.
+//!
+//! # Handshake
+//!
+//! If the processor is configured with `handshake: true` the first message
+//! sent to the program is a [`Hello`] with our protocol version:
+//!
+//! ```json
+//! {"protocol":"1.5.0"}
+//! ```
+//!
+//! The program has to reply with a [`Hello`] holding its own version before
+//! it reads any events.  If the major versions differ the processor fails.
+//!
+//! # Stream
+//!
+//! The program receives a well formed stream starting with a document
+//! start event and has to emit one as well: it starts with a single
+//! document start event and every start tag is closed by the matching end
+//! tag.  Messages are streamed in both directions, a program must not wait
+//! for the end of its input before writing.  Once stdin is closed the
+//! program writes its remaining events, closes stdout and exits.
+//!
+//! # Errors
+//!
+//! Problems with the document are reported by emitting error events, or
+//! diagnostic events if they should not show up in the output.  Both can
+//! carry a `code` (see [`codes`](crate::codes)), programs should prefix
+//! their own codes with their name to keep them apart from the builtin
+//! ones.  If the program cannot continue it writes a description to stderr and exits
+//! with a non-zero status.
+//!
+//! # Conformance
+//!
+//! The golden [`FIXTURES`] hold documents together with their serialized
+//! event streams.  [`check_conformance`] runs a program against them and
+//! reports where it violates the protocol.  The `struck conformance`
+//! command exposes this on the command line.
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::codes::Code;
+use crate::event::{
+    AnnotatedEvent, DocumentStartEvent, EndTagEvent, ErrorEvent, Event, StartTagEvent, Tag,
+    TextEvent,
+};
+use crate::json::stream_from_json;
+use crate::processors::{External, ExternalIter, OnError};
+
+/// The version of the protocol.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion {
+    major: 1,
+    minor: 5,
+    patch: 0,
+};
+
+/// A semantic version of the protocol.
+///
+/// Serializes to a string like `1.0.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ProtocolVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl ProtocolVersion {
+    /// Returns `true` if both versions can talk to each other.
+    pub fn is_compatible_with(&self, other: &ProtocolVersion) -> bool {
+        self.major == other.major
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for ProtocolVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ProtocolVersion, String> {
+        let mut parts = s.split('.').map(|x| x.parse::<u64>());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => Ok(ProtocolVersion {
+                major,
+                minor,
+                patch,
+            }),
+            _ => Err(format!("invalid protocol version '{}'", s)),
+        }
+    }
+}
+
+impl Serialize for ProtocolVersion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ProtocolVersion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ProtocolVersion, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// The message exchanged in the handshake.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Hello {
+    /// The protocol version of the sender.
+    pub protocol: ProtocolVersion,
+}
+
+impl Default for Hello {
+    fn default() -> Hello {
+        Hello {
+            protocol: PROTOCOL_VERSION,
+        }
+    }
+}
+
+/// A golden fixture of the protocol.
+#[derive(Debug, Clone, Copy)]
+pub struct Fixture {
+    /// The name of the fixture.
+    pub name: &'static str,
+    /// The markdown source.
+    pub source: &'static str,
+    /// The parsed source serialized as JSON lines.
+    pub events: &'static str,
+}
+
+impl Fixture {
+    /// Returns the events of the fixture.
+    pub fn events(&self) -> Vec<AnnotatedEvent<'static>> {
+        stream_from_json(self.events).expect("fixtures are valid")
+    }
+}
+
+/// The golden fixtures.
+pub const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "basic",
+        source: include_str!("fixtures/basic.md"),
+        events: include_str!("fixtures/basic.jsonl"),
+    },
+    Fixture {
+        name: "extensions",
+        source: include_str!("fixtures/extensions.md"),
+        events: include_str!("fixtures/extensions.jsonl"),
+    },
+];
+
+/// The outcome of running a program against one fixture.
+#[derive(Debug, Serialize, Clone)]
+pub struct FixtureResult {
+    /// The name of the fixture.
+    pub fixture: String,
+    /// The violations of the protocol.
+    pub failures: Vec<String>,
+}
+
+/// The outcome of a conformance check.
+#[derive(Debug, Serialize, Clone)]
+pub struct ConformanceReport {
+    /// The protocol version checked against.
+    pub protocol: ProtocolVersion,
+    /// The results per fixture.
+    pub results: Vec<FixtureResult>,
+}
+
+impl ConformanceReport {
+    /// Returns `true` if no violations were found.
+    pub fn is_conformant(&self) -> bool {
+        self.results.iter().all(|x| x.failures.is_empty())
+    }
+}
+
+/// A document with an event larger than the pipe buffers.
+fn large_document() -> Vec<AnnotatedEvent<'static>> {
+    vec![
+        DocumentStartEvent { front_matter: None }.into(),
+        Tag::Paragraph.start_tag(Default::default()).into(),
+        TextEvent {
+            text: "All work and no play makes Jack a dull boy. "
+                .repeat(25_000)
+                .into(),
+        }
+        .into(),
+        Tag::Paragraph.end_tag().into(),
+    ]
+}
+
+/// Checks that an output stream follows the protocol.
+fn check_output(events: &[AnnotatedEvent<'_>]) -> Vec<String> {
+    let mut failures = vec![];
+    if !matches!(
+        events.first().map(|x| &x.event),
+        Some(Event::DocumentStart(..))
+    ) {
+        failures.push("the output does not start with a document start event".to_string());
+    }
+    let mut open_tags = vec![];
+    for (idx, annotated_event) in events.iter().enumerate() {
+        match annotated_event.event {
+            Event::DocumentStart(..) if idx > 0 => {
+                failures.push(format!("event {} is another document start event", idx));
+            }
+            Event::StartTag(StartTagEvent { tag, .. }) => open_tags.push(tag),
+            Event::EndTag(EndTagEvent { tag }) if open_tags.pop() != Some(tag) => {
+                failures.push(format!("event {} closes {:?} which is not open", idx, tag));
+            }
+            Event::Error(ErrorEvent {
+                ref description,
+                code: Some(Code::ExternalProcessorFailure),
+                ..
+            }) => {
+                failures.push(description.as_ref().map_or_else(
+                    || "the program failed".to_string(),
+                    |x| x.as_str().to_string(),
+                ));
+            }
+            _ => {}
+        }
+    }
+    for tag in open_tags {
+        failures.push(format!("{:?} is never closed", tag));
+    }
+    failures
+}
+
+/// Runs a program against the fixtures.
+///
+/// The program is run once per fixture and an additional time with an
+/// event larger than the pipe buffers.  `on_error` is ignored and a
+/// timeout of 30 seconds applies unless `timeout_ms` is set.
+pub fn check_conformance(processor: &External) -> ConformanceReport {
+    let processor = External {
+        on_error: OnError::Fail,
+        timeout_ms: processor.timeout_ms.or(Some(30_000)),
+        ..processor.clone()
+    };
+    let documents = FIXTURES
+        .iter()
+        .map(|fixture| (fixture.name, fixture.events()))
+        .chain(Some(("large", large_document())));
+    ConformanceReport {
+        protocol: PROTOCOL_VERSION,
+        results: documents
+            .map(|(name, events)| {
+                let output: Vec<_> =
+                    ExternalIter::new(events.into_iter(), Cow::Borrowed(&processor)).collect();
+                FixtureResult {
+                    fixture: name.to_string(),
+                    failures: check_output(&output),
+                }
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_golden_fixtures() {
+    use crate::json::stream_to_json;
+    use crate::parser::parse;
+
+    for fixture in FIXTURES {
+        assert_eq!(
+            stream_to_json(parse(fixture.source, &Default::default())),
+            fixture.events,
+            "wire format of fixture {} changed",
+            fixture.name
+        );
+    }
+}
+
+#[test]
+fn test_protocol_version() {
+    let version: ProtocolVersion = "1.2.3".parse().unwrap();
+    assert_eq!(version.to_string(), "1.2.3");
+    assert!(version.is_compatible_with(&PROTOCOL_VERSION));
+    assert!(!"2.0.0"
+        .parse::<ProtocolVersion>()
+        .unwrap()
+        .is_compatible_with(&PROTOCOL_VERSION));
+    assert!("1.0".parse::<ProtocolVersion>().is_err());
+    assert_eq!(
+        serde_json::to_string(&Hello::default()).unwrap(),
+        r#"{"protocol":"1.5.0"}"#
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_conformance() {
+    use crate::processors::WireFormat;
+
+    let processor = |args: &[&str]| External {
+        cmd: "sh".into(),
+        args: args.iter().map(|x| x.to_string()).collect(),
+        env: Default::default(),
+        cwd: None,
+        format: WireFormat::Json,
+        timeout_ms: None,
+        on_error: OnError::Fail,
+        handshake: true,
+    };
+
+    let report = check_conformance(&processor(&["-c", "cat"]));
+    assert!(report.is_conformant(), "{:?}", report);
+    assert_eq!(report.results.len(), FIXTURES.len() + 1);
+
+    // replies to the handshake but drops all events
+    let report = check_conformance(&processor(&["-c", "head -n 1; cat > /dev/null"]));
+    assert!(!report.is_conformant());
+    assert!(report.results[0].failures[0].contains("document start"));
+
+    let report = check_conformance(&processor(&["-c", r#"echo '{"protocol":"2.0.0"}'"#]));
+    assert!(report.results[0]
+        .failures
+        .iter()
+        .any(|x| x.contains("protocol version 2.0.0")));
+}
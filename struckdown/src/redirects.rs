@@ -0,0 +1,174 @@
+//! Generates redirects from front matter aliases.
+//!
+//! When documents move, the old paths can be listed in the `aliases` key of
+//! the front matter:
+//!
+//! ```markdown
+//! ---
+//! aliases:
+//!   - /old/path/
+//!   - /older/path/
+//! ---
+//! ```
+//!
+//! A [`RedirectMap`] collects these aliases for all documents of a site and
+//! maps each old path to the new path.  It can also generate small HTML stub
+//! pages for hosts that cannot be configured with redirects.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use v_htmlescape::escape;
+
+use crate::event::{AnnotatedEvent, DocumentStartEvent, Event};
+use crate::value::Value;
+
+/// An alias that was claimed by more than one document.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RedirectConflict {
+    /// The conflicting alias.
+    pub alias: String,
+    /// The documents claiming the alias.
+    pub targets: Vec<String>,
+}
+
+/// Maps old paths to the paths of the documents that replaced them.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RedirectMap {
+    redirects: BTreeMap<String, String>,
+    conflicts: Vec<RedirectConflict>,
+}
+
+/// Extracts the aliases from front matter.
+///
+/// The `aliases` key can hold a single string or a list of strings.
+pub fn aliases_from_front_matter(front_matter: &Value) -> Vec<String> {
+    match front_matter.get("aliases") {
+        Some(Value::String(alias)) => vec![alias.clone()],
+        Some(Value::Array(aliases)) => aliases
+            .iter()
+            .filter_map(|x| x.as_str())
+            .map(|x| x.to_string())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Renders an HTML page redirecting to the given target.
+pub fn render_redirect_stub(target: &str) -> String {
+    let target = escape(target);
+    format!(
+        "<!doctype html>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>Redirecting&hellip;</title>\n\
+         <link rel=\"canonical\" href=\"{0}\">\n\
+         <meta http-equiv=\"refresh\" content=\"0; url={0}\">\n\
+         <p>This page has moved to <a href=\"{0}\">{0}</a>.</p>\n",
+        target
+    )
+}
+
+impl RedirectMap {
+    /// Creates an empty redirect map.
+    pub fn new() -> RedirectMap {
+        RedirectMap::default()
+    }
+
+    /// Registers a single redirect from `alias` to `target`.
+    ///
+    /// If the alias already points to a different document a conflict is
+    /// recorded and the first target is kept.
+    pub fn add_redirect(&mut self, alias: &str, target: &str) {
+        match self.redirects.get(alias) {
+            None => {
+                self.redirects.insert(alias.to_string(), target.to_string());
+            }
+            Some(existing) if existing == target => {}
+            Some(existing) => {
+                let existing = existing.clone();
+                match self.conflicts.iter_mut().find(|x| x.alias == alias) {
+                    Some(conflict) => conflict.targets.push(target.to_string()),
+                    None => self.conflicts.push(RedirectConflict {
+                        alias: alias.to_string(),
+                        targets: vec![existing, target.to_string()],
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Registers the aliases of a document from its front matter.
+    pub fn add_document(&mut self, path: &str, front_matter: Option<&Value>) {
+        if let Some(front_matter) = front_matter {
+            for alias in aliases_from_front_matter(front_matter) {
+                self.add_redirect(&alias, path);
+            }
+        }
+    }
+
+    /// Registers the aliases of a document from its event stream.
+    ///
+    /// The front matter is taken from the document start event.
+    pub fn add_events<'a, 'data: 'a, I>(&mut self, path: &str, iter: I)
+    where
+        I: IntoIterator<Item = &'a AnnotatedEvent<'data>>,
+    {
+        for annotated_event in iter {
+            if let Event::DocumentStart(DocumentStartEvent { ref front_matter }) =
+                annotated_event.event
+            {
+                self.add_document(path, front_matter.as_ref());
+                break;
+            }
+        }
+    }
+
+    /// Looks up the target of an old path.
+    pub fn get(&self, alias: &str) -> Option<&str> {
+        self.redirects.get(alias).map(|x| x.as_str())
+    }
+
+    /// Returns `true` if no redirects were registered.
+    pub fn is_empty(&self) -> bool {
+        self.redirects.is_empty()
+    }
+
+    /// Iterates over all redirects as `(old, new)` pairs sorted by old path.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.redirects.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns the aliases claimed by more than one document.
+    pub fn conflicts(&self) -> &[RedirectConflict] {
+        &self.conflicts
+    }
+
+    /// Iterates over the HTML stub pages as `(old path, html)` pairs.
+    ///
+    /// Where the stubs are written is up to the caller; typically an alias
+    /// ending in a slash gets an `index.html` in that folder.
+    pub fn stub_pages(&self) -> impl Iterator<Item = (&str, String)> {
+        self.iter()
+            .map(|(alias, target)| (alias, render_redirect_stub(target)))
+    }
+}
+
+#[test]
+fn test_redirect_map() {
+    use crate::parser::parse;
+
+    let mut map = RedirectMap::new();
+    let docs = [
+        ("/guide/install/", "---\naliases: /setup/\n---\n# Install"),
+        (
+            "/guide/usage/",
+            "---\naliases:\n  - /usage/\n  - /setup/\n---\n# Usage",
+        ),
+        ("/about/", "# About"),
+    ];
+    for (path, source) in docs.iter() {
+        let events: Vec<_> = parse(source, &Default::default()).collect();
+        map.add_events(path, &events);
+    }
+    insta::assert_yaml_snapshot!(&map);
+    insta::assert_snapshot!(map.stub_pages().next().unwrap().1);
+}
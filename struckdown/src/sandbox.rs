@@ -0,0 +1,399 @@
+//! Restricts what processors may do on the host.
+//!
+//! Processors are configured from the front matter of documents, so a
+//! pipeline contributed by someone else can run arbitrary programs through
+//! the [`External`](crate::processors::External) processor or read arbitrary
+//! files through [`Include`](crate::processors::Include).  Before running
+//! such pipelines the host installs a [`Sandbox`] which all processors
+//! touching the system consult:
+//!
+//! ```
+//! use struckdown::sandbox::{install, Sandbox};
+//!
+//! install(Sandbox {
+//!     allowed_commands: Some(vec!["dot".into(), "/usr/bin/mmdc".into()]),
+//!     inherit_env: false,
+//!     read_roots: Some(vec!["docs".into()]),
+//!     allow_network: false,
+//!     ..Sandbox::default()
+//! });
+//! ```
+//!
+//! The sandbox is process wide rather than part of a processor's
+//! configuration as the latter is under the control of the document.  It
+//! cannot restrict what a spawned program does once it runs; the network
+//! flag is passed to spawned programs as the `STRUCKDOWN_NETWORK`
+//! environment variable (`on` or `off`) and only enforced for fetches done
+//! by struckdown itself.
+//!
+//! As every file read by a processor passes the sandbox, it also tells which
+//! files a document depends on: [`track_reads`] returns the files read
+//! while processing one.
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    static ref SANDBOX: RwLock<Arc<Sandbox>> = RwLock::new(Arc::new(Sandbox::default()));
+}
+
+thread_local! {
+    static READS: RefCell<Option<Vec<PathBuf>>> = RefCell::default();
+}
+
+/// The environment variable telling spawned programs if they may use the
+/// network.
+pub const NETWORK_ENV_VAR: &str = "STRUCKDOWN_NETWORK";
+
+/// Restrictions for processors.
+///
+/// The default sandbox does not restrict anything.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Sandbox {
+    /// The programs processors may spawn.
+    ///
+    /// Entries with a path separator must match the configured command
+    /// exactly, bare names only match commands looked up in `PATH`.  `None`
+    /// allows all programs.
+    pub allowed_commands: Option<Vec<PathBuf>>,
+    /// Whether spawned programs inherit the environment of this process.
+    pub inherit_env: bool,
+    /// The variables passed on if the environment is not inherited.
+    pub env_passthrough: Vec<String>,
+    /// The folders files may be read from.  `None` allows all files.
+    pub read_roots: Option<Vec<PathBuf>>,
+    /// Whether processors may access the network.
+    pub allow_network: bool,
+}
+
+impl Default for Sandbox {
+    fn default() -> Sandbox {
+        Sandbox {
+            allowed_commands: None,
+            inherit_env: true,
+            env_passthrough: vec!["PATH".into()],
+            read_roots: None,
+            allow_network: true,
+        }
+    }
+}
+
+impl Sandbox {
+    /// Checks if a program may be spawned.
+    pub fn check_command(&self, cmd: &Path) -> Result<(), String> {
+        let allowed = match self.allowed_commands {
+            Some(ref allowed) => allowed,
+            None => return Ok(()),
+        };
+        if allowed.iter().any(|x| x == cmd) {
+            Ok(())
+        } else {
+            Err(format!("Command '{}' is not allowed", cmd.display()))
+        }
+    }
+
+    /// Checks if a program may be spawned and returns the path to spawn.
+    ///
+    /// If commands are restricted, bare names are looked up in the `PATH`
+    /// of this process and relative paths are resolved against its working
+    /// directory, so that neither the environment nor the working directory
+    /// of the processor can swap the program.
+    pub fn resolve_command(&self, cmd: &Path) -> Result<PathBuf, String> {
+        self.check_command(cmd)?;
+        if self.allowed_commands.is_none() {
+            return Ok(cmd.to_path_buf());
+        }
+        let not_found = || format!("Command '{}' not found", cmd.display());
+        if cmd.components().count() > 1 || cmd.is_absolute() {
+            let cwd = std::env::current_dir().map_err(|_| not_found())?;
+            return Ok(cwd.join(cmd));
+        }
+        std::env::var_os("PATH")
+            .iter()
+            .flat_map(std::env::split_paths)
+            .filter(|dir| dir.is_absolute())
+            .map(|dir| dir.join(cmd))
+            .find(|path| path.is_file())
+            .ok_or_else(not_found)
+    }
+
+    /// Checks if a file may be read.
+    ///
+    /// Relative paths and roots are resolved against the working directory
+    /// and symlinks are followed before the check.
+    pub fn check_read(&self, path: &Path) -> Result<(), String> {
+        READS.with(|reads| {
+            if let Some(ref mut reads) = *reads.borrow_mut() {
+                reads.push(path.to_path_buf());
+            }
+        });
+        let roots = match self.read_roots {
+            Some(ref roots) => roots,
+            None => return Ok(()),
+        };
+        let denied = || format!("Reading '{}' is not allowed", path.display());
+        let path = path.canonicalize().map_err(|_| denied())?;
+        if roots
+            .iter()
+            .filter_map(|root| root.canonicalize().ok())
+            .any(|root| path.starts_with(root))
+        {
+            Ok(())
+        } else {
+            Err(denied())
+        }
+    }
+
+    /// Checks if the network may be accessed.
+    pub fn check_network(&self) -> Result<(), String> {
+        if self.allow_network {
+            Ok(())
+        } else {
+            Err("Network access is not allowed".into())
+        }
+    }
+
+    /// Returns the environment for a spawned program.
+    ///
+    /// `env` are the variables configured for the processor.  The returned
+    /// flag tells if the environment of the command must be cleared before
+    /// the returned variables are set.  If commands are restricted, `env`
+    /// must not set `PATH` or variables of the dynamic loader (`LD_*` and
+    /// `DYLD_*`) as they change which code a program runs.
+    pub fn command_env<'a, I>(&self, env: I) -> Result<(bool, Vec<(String, String)>), String>
+    where
+        I: IntoIterator<Item = (&'a String, &'a String)>,
+    {
+        self.command_env_from(env, |key| std::env::var(key).ok())
+    }
+
+    /// Like [`command_env`](Self::command_env) but looks up passed through
+    /// variables with the given function instead of the process environment.
+    fn command_env_from<'a, I, F>(
+        &self,
+        env: I,
+        lookup: F,
+    ) -> Result<(bool, Vec<(String, String)>), String>
+    where
+        I: IntoIterator<Item = (&'a String, &'a String)>,
+        F: Fn(&str) -> Option<String>,
+    {
+        let mut rv = vec![];
+        if !self.inherit_env {
+            for key in &self.env_passthrough {
+                if let Some(value) = lookup(key) {
+                    rv.push((key.clone(), value));
+                }
+            }
+        }
+        for (key, value) in env {
+            if self.allowed_commands.is_some()
+                && (key.eq_ignore_ascii_case("PATH")
+                    || key.starts_with("LD_")
+                    || key.starts_with("DYLD_"))
+            {
+                return Err(format!("Setting '{}' is not allowed", key));
+            }
+            rv.push((key.clone(), value.clone()));
+        }
+        rv.push((
+            NETWORK_ENV_VAR.into(),
+            if self.allow_network { "on" } else { "off" }.into(),
+        ));
+        Ok((!self.inherit_env, rv))
+    }
+}
+
+/// Installs the sandbox for all processors of this process.
+pub fn install(sandbox: Sandbox) {
+    *SANDBOX.write().unwrap() = Arc::new(sandbox);
+}
+
+/// Returns the installed sandbox.
+pub fn current() -> Arc<Sandbox> {
+    SANDBOX.read().unwrap().clone()
+}
+
+/// Runs a function and returns the files processors tried to read.
+///
+/// Only reads on the current thread are tracked, so the processed stream
+/// has to be consumed within the function.  Paths are returned as the
+/// processors passed them to [`Sandbox::check_read`], including files that
+/// did not exist or could not be read.
+///
+/// ```
+/// use struckdown::pipeline::Pipeline;
+/// use struckdown::processors::Include;
+/// use struckdown::sandbox::track_reads;
+///
+/// let mut pipeline = Pipeline::new();
+/// pipeline.add_processor(Include::default());
+/// let (events, reads) = track_reads(|| {
+///     pipeline.process("# Title").collect::<Vec<_>>()
+/// });
+/// assert!(!events.is_empty());
+/// assert!(reads.is_empty());
+/// ```
+pub fn track_reads<F: FnOnce() -> R, R>(f: F) -> (R, Vec<PathBuf>) {
+    let mut guard = ReadTracker {
+        outer: Some(READS.with(|reads| reads.borrow_mut().replace(vec![]))),
+    };
+    let rv = f();
+    let tracked = guard.finish();
+    (rv, tracked)
+}
+
+/// Restores the reads of an enclosing [`track_reads`] call, even if the
+/// tracked function panics.
+struct ReadTracker {
+    outer: Option<Option<Vec<PathBuf>>>,
+}
+
+impl ReadTracker {
+    fn finish(&mut self) -> Vec<PathBuf> {
+        let outer = match self.outer.take() {
+            Some(outer) => outer,
+            None => return vec![],
+        };
+        READS.with(|reads| {
+            let mut reads = reads.borrow_mut();
+            let tracked = reads.take().unwrap_or_default();
+            // nested tracking sees the reads of the inner function as well
+            *reads = outer.map(|mut outer| {
+                outer.extend(tracked.iter().cloned());
+                outer
+            });
+            tracked
+        })
+    }
+}
+
+impl Drop for ReadTracker {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[test]
+fn test_sandbox() {
+    use std::collections::BTreeMap;
+
+    let sandbox = Sandbox {
+        allowed_commands: Some(vec!["cat".into(), "/usr/bin/dot".into()]),
+        inherit_env: false,
+        env_passthrough: vec!["STRUCKDOWN_SANDBOX_TEST".into()],
+        read_roots: Some(vec!["src".into()]),
+        allow_network: false,
+    };
+    assert!(sandbox.check_command(Path::new("cat")).is_ok());
+    assert!(sandbox.check_command(Path::new("/usr/bin/dot")).is_ok());
+    assert!(sandbox.check_command(Path::new("./cat")).is_err());
+    assert!(sandbox.check_command(Path::new("dot")).is_err());
+
+    assert!(sandbox.check_read(Path::new("src/lib.rs")).is_ok());
+    assert!(sandbox.check_read(Path::new("src/../Cargo.toml")).is_err());
+    assert!(sandbox.check_read(Path::new("src/missing.rs")).is_err());
+    assert!(sandbox.check_network().is_err());
+
+    let mut env = BTreeMap::new();
+    env.insert("FOO".to_string(), "bar".to_string());
+    let (clear, vars) = sandbox
+        .command_env_from(&env, |key| match key {
+            "STRUCKDOWN_SANDBOX_TEST" => Some("1".into()),
+            _ => None,
+        })
+        .unwrap();
+    assert!(clear);
+    assert_eq!(
+        vars,
+        vec![
+            ("STRUCKDOWN_SANDBOX_TEST".into(), "1".into()),
+            ("FOO".into(), "bar".into()),
+            ("STRUCKDOWN_NETWORK".into(), "off".into()),
+        ]
+    );
+
+    assert!(Sandbox::default().check_command(Path::new("./cat")).is_ok());
+}
+
+#[test]
+fn test_command_hijacking() {
+    use std::collections::BTreeMap;
+
+    let sandbox = Sandbox {
+        allowed_commands: Some(vec!["cat".into(), "./bin/tool".into()]),
+        inherit_env: false,
+        ..Sandbox::default()
+    };
+
+    // the document cannot point the allowed command elsewhere
+    for key in &[
+        "PATH",
+        "Path",
+        "LD_PRELOAD",
+        "LD_LIBRARY_PATH",
+        "DYLD_INSERT_LIBRARIES",
+    ] {
+        let mut env = BTreeMap::new();
+        env.insert(key.to_string(), "/tmp/evil".to_string());
+        assert_eq!(
+            sandbox.command_env(&env).unwrap_err(),
+            format!("Setting '{}' is not allowed", key)
+        );
+        assert!(Sandbox::default().command_env(&env).is_ok());
+    }
+
+    // commands are spawned by absolute path so the working directory of
+    // the processor does not matter
+    if cfg!(unix) {
+        let cat = sandbox.resolve_command(Path::new("cat")).unwrap();
+        assert!(cat.is_absolute() && cat.ends_with("cat"));
+    }
+    let tool = sandbox.resolve_command(Path::new("./bin/tool")).unwrap();
+    assert_eq!(tool, std::env::current_dir().unwrap().join("./bin/tool"));
+    assert!(sandbox.resolve_command(Path::new("dot")).is_err());
+    assert_eq!(
+        Sandbox::default()
+            .resolve_command(Path::new("cat"))
+            .unwrap(),
+        PathBuf::from("cat")
+    );
+}
+
+#[test]
+fn test_track_reads() {
+    let sandbox = Sandbox::default();
+    let ((), reads) = track_reads(|| {
+        sandbox.check_read(Path::new("src/lib.rs")).unwrap();
+        let ((), inner) = track_reads(|| {
+            sandbox.check_read(Path::new("src/missing.rs")).unwrap();
+        });
+        assert_eq!(inner, vec![PathBuf::from("src/missing.rs")]);
+    });
+    assert_eq!(
+        reads,
+        vec![PathBuf::from("src/lib.rs"), PathBuf::from("src/missing.rs")]
+    );
+    sandbox.check_read(Path::new("src/lib.rs")).unwrap();
+    assert_eq!(track_reads(|| ()).1, Vec::<PathBuf>::new());
+
+    let ((), reads) = track_reads(|| {
+        let rv = std::panic::catch_unwind(|| {
+            track_reads(|| {
+                sandbox.check_read(Path::new("src/lib.rs")).unwrap();
+                panic!("processor failed");
+            })
+        });
+        assert!(rv.is_err());
+        sandbox.check_read(Path::new("src/missing.rs")).unwrap();
+    });
+    assert_eq!(
+        reads,
+        vec![PathBuf::from("src/lib.rs"), PathBuf::from("src/missing.rs")]
+    );
+    assert_eq!(track_reads(|| ()).1, Vec::<PathBuf>::new());
+}
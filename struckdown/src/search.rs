@@ -0,0 +1,303 @@
+//! Builds search indexes for client-side search.
+//!
+//! [`collect_search_records`] splits a processed document into one
+//! [`SearchRecord`] per section.  Optionally the records hold the titles of
+//! the sections they are nested in (for breadcrumbs) and the positions of
+//! their tokens (for highlighting matches).  A [`SearchIndex`] combines the
+//! records of many documents into a single file:
+//!
+//! ```
+//! use struckdown::parser::parse;
+//! use struckdown::search::{SearchIndex, SearchOptions};
+//!
+//! let options = SearchOptions {
+//!     hierarchy: true,
+//!     positions: true,
+//! };
+//! let events: Vec<_> = parse("# Guide\n\n## Setup\n\nRun it.", &Default::default()).collect();
+//! let mut index = SearchIndex::default();
+//! index.add_document("guide.html", &events, &options);
+//! let record = &index.records[1];
+//! assert_eq!(record.title, "Setup");
+//! assert_eq!(record.hierarchy, Some(vec!["Guide".to_string()]));
+//! assert_eq!(record.tokens.as_ref().unwrap()[0].term, "run");
+//! ```
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, DocumentStartEvent, EndTagEvent, Event, StartTagEvent, Tag};
+use crate::plain::to_plain_text;
+
+/// Configures what search records hold.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SearchOptions {
+    /// Records the titles of the sections a record is nested in.
+    pub hierarchy: bool,
+    /// Records the position of every token in the text.
+    pub positions: bool,
+}
+
+/// A token in the text of a record.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Token {
+    /// The lowercased token.
+    pub term: String,
+    /// Where the token starts in the text.
+    pub start: usize,
+    /// Where the token ends in the text.
+    pub end: usize,
+}
+
+/// The searchable text of a section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchRecord {
+    /// The document the section is in.
+    pub document: String,
+    /// The id of the heading of the section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The text of the heading.
+    ///
+    /// The record for the content before the first heading has the title
+    /// from the front matter or an empty title.
+    pub title: String,
+    /// The titles of the sections the section is nested in, outermost
+    /// first.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hierarchy: Option<Vec<String>>,
+    /// The text of the section without its heading.
+    pub text: String,
+    /// The tokens of the text.
+    ///
+    /// Positions count UTF-16 code units so that they can be used on
+    /// JavaScript strings directly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tokens: Option<Vec<Token>>,
+}
+
+/// The search records of a set of documents.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SearchIndex {
+    /// The records in the order the documents were added.
+    pub records: Vec<SearchRecord>,
+}
+
+impl SearchIndex {
+    /// Adds the records of a document.
+    pub fn add_document<'a, 'data: 'a, I>(
+        &mut self,
+        document: &str,
+        iter: I,
+        options: &SearchOptions,
+    ) where
+        I: IntoIterator<Item = &'a AnnotatedEvent<'data>>,
+    {
+        self.records
+            .extend(collect_search_records(document, iter, options));
+    }
+}
+
+/// Returns `true` for tags that do not end a block of text.
+fn is_inline_tag(tag: Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis
+            | Tag::EmphasisAlt
+            | Tag::Strong
+            | Tag::Strikethrough
+            | Tag::Link
+            | Tag::Span
+            | Tag::Spoiler
+            | Tag::Abbreviation
+            | Tag::Insertion
+            | Tag::Deletion
+    )
+}
+
+/// Splits text into lowercased alphanumeric tokens.
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut rv = vec![];
+    let mut current: Option<Token> = None;
+    let mut position = 0;
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            let token = current.get_or_insert_with(|| Token {
+                term: String::new(),
+                start: position,
+                end: position,
+            });
+            token.term.extend(c.to_lowercase());
+            token.end = position + c.len_utf16();
+        } else if let Some(token) = current.take() {
+            rv.push(token);
+        }
+        position += c.len_utf16();
+    }
+    rv.extend(current);
+    rv
+}
+
+/// Collects the search records of a document.
+///
+/// Every heading starts a new record.  Content before the first heading
+/// is a record of its own unless there is none.  To include generated
+/// heading ids the stream should already have been processed.
+pub fn collect_search_records<'a, 'data: 'a, I>(
+    document: &str,
+    iter: I,
+    options: &SearchOptions,
+) -> Vec<SearchRecord>
+where
+    I: IntoIterator<Item = &'a AnnotatedEvent<'data>>,
+{
+    let mut rv = vec![];
+    let mut current = SearchRecord {
+        document: document.to_string(),
+        id: None,
+        title: String::new(),
+        hierarchy: None,
+        text: String::new(),
+        tokens: None,
+    };
+    let mut parents: Vec<(usize, String)> = vec![];
+    let mut heading: Option<(usize, Vec<&'a AnnotatedEvent<'data>>)> = None;
+    let mut depth = 0;
+    let mut separate = false;
+    let mut in_section = false;
+
+    let mut finish = |record: SearchRecord| {
+        let mut record = SearchRecord {
+            text: record.text.trim_end().to_string(),
+            ..record
+        };
+        if options.hierarchy && record.hierarchy.is_none() {
+            record.hierarchy = Some(vec![]);
+        }
+        if options.positions {
+            record.tokens = Some(tokenize(&record.text));
+        }
+        rv.push(record);
+    };
+
+    for annotated_event in iter {
+        if let Some((level, ref mut events)) = heading {
+            match annotated_event.event {
+                Event::StartTag(..) => depth += 1,
+                Event::EndTag(..) if depth == 0 => {
+                    let title = to_plain_text(events.iter().copied())
+                        .as_str()
+                        .trim()
+                        .to_string();
+                    while let Some(&(parent_level, _)) = parents.last() {
+                        if parent_level < level {
+                            break;
+                        }
+                        parents.pop();
+                    }
+                    current.title = title.clone();
+                    if options.hierarchy {
+                        current.hierarchy = Some(parents.iter().map(|x| x.1.clone()).collect());
+                    }
+                    parents.push((level, title));
+                    heading = None;
+                    continue;
+                }
+                Event::EndTag(..) => depth -= 1,
+                _ => {}
+            }
+            events.push(annotated_event);
+            continue;
+        }
+
+        match annotated_event.event {
+            Event::DocumentStart(DocumentStartEvent {
+                front_matter: Some(ref front_matter),
+            }) => {
+                if let Some(title) = front_matter.get("title").and_then(|x| x.as_str()) {
+                    current.title = title.to_string();
+                }
+            }
+            Event::StartTag(StartTagEvent { tag, ref attrs }) => {
+                if let Some(level) = tag.header_level() {
+                    let next = SearchRecord {
+                        document: document.to_string(),
+                        id: attrs.id.as_ref().map(|x| x.as_str().to_string()),
+                        title: String::new(),
+                        hierarchy: None,
+                        text: String::new(),
+                        tokens: None,
+                    };
+                    let previous = std::mem::replace(&mut current, next);
+                    if in_section || !previous.text.trim().is_empty() {
+                        finish(previous);
+                    }
+                    in_section = true;
+                    heading = Some((level, vec![]));
+                    depth = 0;
+                    separate = false;
+                }
+            }
+            Event::EndTag(EndTagEvent { tag }) if !is_inline_tag(tag) => separate = true,
+            Event::CodeBlock(..) | Event::MathBlock(..) | Event::Directive(..) | Event::Rule => {
+                separate = true;
+            }
+            _ => {}
+        }
+        if let Some(text) = annotated_event.event.raw_text() {
+            if separate && !current.text.is_empty() {
+                current.text.push('\n');
+            }
+            separate = false;
+            current.text.push_str(text.as_str());
+        }
+    }
+
+    if in_section || !current.text.trim().is_empty() {
+        finish(current);
+    }
+    rv
+}
+
+#[test]
+fn test_collect_search_records() {
+    use crate::parser::parse;
+    use crate::processors::{AutoAnchors, Processor};
+
+    let source = "\
+---
+title: Guide
+---
+Welcome to the *guide*.
+
+# Installation
+
+Install with `cargo`.
+
+## Größe {#size}
+
+- 🦀 über
+- two
+
+## Usage
+
+```
+run --fast
+```
+";
+    let events: Vec<_> = Box::new(AutoAnchors::default())
+        .apply(Box::new(parse(source, &Default::default())))
+        .collect();
+    let options = SearchOptions {
+        hierarchy: true,
+        positions: true,
+    };
+    let mut index = SearchIndex::default();
+    index.add_document("guide.html", &events, &options);
+    insta::assert_yaml_snapshot!(&index);
+
+    let records = collect_search_records("guide.html", &events, &SearchOptions::default());
+    assert_eq!(records.len(), 4);
+    assert!(records
+        .iter()
+        .all(|x| x.hierarchy.is_none() && x.tokens.is_none()));
+}
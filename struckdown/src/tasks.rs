@@ -0,0 +1,154 @@
+//! Reads and edits task lists.
+//!
+//! Applications building interactive checklists on top of struckdown need
+//! to know which checkboxes a document has and to write a toggled checkbox
+//! back into the source without disturbing the rest of the document.
+//! [`collect_tasks`] lists all checkboxes with their text, their position in
+//! the nesting of lists and the byte range of the `[ ]` marker in the
+//! source which [`set_task`] and [`toggle_task`] then edit.
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::event::{AnnotatedEvent, CheckboxEvent, EndTagEvent, Event, StartTagEvent, Tag};
+use crate::parser::{parse, ParserOptions};
+use crate::plain::to_plain_text;
+
+/// A checkbox of a task list.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Task {
+    /// The text of the list item up to any nested list.
+    pub text: String,
+    /// Whether the checkbox is checked.
+    pub checked: bool,
+    /// The index of the list item within its list for every level of
+    /// nesting, starting with the outermost list.
+    pub path: Vec<usize>,
+    /// The byte range of the `[ ]` marker in the source.
+    pub range: Range<usize>,
+    /// The line of the checkbox in the source (1 indexed).
+    pub line: usize,
+}
+
+struct OpenItem<'data> {
+    task: Option<Task>,
+    events: Vec<AnnotatedEvent<'data>>,
+}
+
+/// Lists all checkboxes of a document in source order.
+pub fn collect_tasks(source: &str, options: &ParserOptions) -> Vec<Task> {
+    let mut rv = vec![];
+    // the number of items seen so far in every open list
+    let mut lists: Vec<usize> = vec![];
+    let mut items: Vec<OpenItem<'_>> = vec![];
+    // whether events belong to the text of the innermost item
+    let mut collecting = false;
+
+    for annotated_event in parse(source, options) {
+        match annotated_event.event {
+            Event::StartTag(StartTagEvent {
+                tag: Tag::OrderedList,
+                ..
+            })
+            | Event::StartTag(StartTagEvent {
+                tag: Tag::UnorderedList,
+                ..
+            }) => {
+                lists.push(0);
+                // text after a nested list does not belong to the item
+                collecting = false;
+            }
+            Event::EndTag(EndTagEvent {
+                tag: Tag::OrderedList,
+            })
+            | Event::EndTag(EndTagEvent {
+                tag: Tag::UnorderedList,
+            }) => {
+                lists.pop();
+            }
+            Event::StartTag(StartTagEvent {
+                tag: Tag::ListItem, ..
+            }) => {
+                if let Some(count) = lists.last_mut() {
+                    *count += 1;
+                }
+                items.push(OpenItem {
+                    task: None,
+                    events: vec![],
+                });
+                collecting = true;
+            }
+            Event::EndTag(EndTagEvent { tag: Tag::ListItem }) => {
+                if let Some(item) = items.pop() {
+                    if let Some(mut task) = item.task {
+                        task.text = to_plain_text(item.events.iter())
+                            .as_str()
+                            .trim()
+                            .to_string();
+                        rv.push(task);
+                    }
+                }
+                collecting = false;
+            }
+            Event::Checkbox(CheckboxEvent { checked }) => {
+                if let (Some(item), Some(location)) = (items.last_mut(), annotated_event.location) {
+                    item.task = Some(Task {
+                        text: String::new(),
+                        checked,
+                        path: lists.iter().map(|x| x - 1).collect(),
+                        range: location.offset..location.offset + location.len,
+                        line: location.line,
+                    });
+                }
+            }
+            _ => {
+                if collecting {
+                    if let Some(item) = items.last_mut() {
+                        item.events.push(annotated_event);
+                    }
+                }
+            }
+        }
+    }
+
+    rv.sort_by_key(|x| x.range.start);
+    rv
+}
+
+/// Returns the source with the checkbox of a task set to `checked`.
+///
+/// Returns `None` if the source no longer has a checkbox at the location of
+/// the task, for instance because it was edited in the meantime.
+pub fn set_task(source: &str, task: &Task, checked: bool) -> Option<String> {
+    match source.get(task.range.clone())? {
+        "[ ]" | "[x]" | "[X]" => {}
+        _ => return None,
+    }
+    let mut rv = String::with_capacity(source.len());
+    rv.push_str(&source[..task.range.start]);
+    rv.push_str(if checked { "[x]" } else { "[ ]" });
+    rv.push_str(&source[task.range.end..]);
+    Some(rv)
+}
+
+/// Returns the source with the checkbox of a task toggled.
+pub fn toggle_task(source: &str, task: &Task) -> Option<String> {
+    set_task(source, task, !task.checked)
+}
+
+#[test]
+fn test_tasks() {
+    let source = "\
+# Todo
+
+- [ ] write *docs*
+  - [x] outline
+  - [ ] examples
+- [X] release
+
+1. [ ] numbered
+";
+    let tasks = collect_tasks(source, &Default::default());
+    insta::assert_yaml_snapshot!(&tasks);
+    insta::assert_snapshot!(toggle_task(source, &tasks[2]).unwrap());
+}
@@ -0,0 +1,202 @@
+//! Maps theme tokens to the styling of an output profile.
+//!
+//! Processors mark the elements they generate with theme tokens: named
+//! classes such as `admonition` or `code-block` that describe what an
+//! element is rather than what it looks like.  A [`ThemeProfile`] decides at
+//! render time how a token is styled.  The default profile emits tokens as
+//! CSS classes, [`ThemeProfile::inline_styles`] replaces them by inline
+//! styles for HTML email where stylesheets are not available.  As the
+//! profile is an option of the renderer, switching profiles does not
+//! require processing the stream again:
+//!
+//! ```
+//! use struckdown::html::{to_html, HtmlRendererOptions};
+//! use struckdown::pipeline::Pipeline;
+//! use struckdown::processors::Admonitions;
+//! use struckdown::theme::ThemeProfile;
+//!
+//! let mut pipeline = Pipeline::new();
+//! pipeline.add_processor(Admonitions::default());
+//! let events: Vec<_> = pipeline.process("```{note}\nHello\n```").collect();
+//!
+//! let html = to_html(events.clone().into_iter(), &Default::default());
+//! assert!(html.starts_with("<div class=\"admonition note\">"));
+//!
+//! let options = HtmlRendererOptions {
+//!     theme: ThemeProfile::inline_styles(),
+//!     ..Default::default()
+//! };
+//! let html = to_html(events.into_iter(), &options);
+//! assert!(html.starts_with("<div class=\"note\" style=\"border-left: "));
+//! ```
+//!
+//! The builtin processors attach the tokens listed as constants in this
+//! module.  Classes that are not mapped by the profile are kept.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The token of admonition containers.
+pub const ADMONITION: &str = "admonition";
+/// The token of admonition titles.
+pub const ADMONITION_TITLE: &str = "admonition-title";
+/// The token of code block containers.
+pub const CODE_BLOCK: &str = "code-block";
+/// The token of tables.
+pub const TABLE: &str = "table";
+/// The token of block quotes.
+pub const BLOCK_QUOTE: &str = "blockquote";
+
+/// How a token is rendered.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct TokenStyle {
+    /// The classes emitted in place of the token.
+    ///
+    /// If not set the token is not emitted as class.
+    pub class: Option<String>,
+    /// Inline styles added to the element.
+    pub style: Option<String>,
+}
+
+/// Maps theme tokens to classes and inline styles.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ThemeProfile {
+    /// The styles of the tokens.
+    pub tokens: BTreeMap<String, TokenStyle>,
+}
+
+impl ThemeProfile {
+    /// Creates the profile emitting all tokens as classes.
+    pub fn classes() -> ThemeProfile {
+        ThemeProfile::default()
+    }
+
+    /// Creates a profile replacing the builtin tokens by inline styles.
+    pub fn inline_styles() -> ThemeProfile {
+        let styles = [
+            (
+                ADMONITION,
+                "border-left: 4px solid #888; padding: 8px 12px; margin: 16px 0",
+            ),
+            (ADMONITION_TITLE, "font-weight: bold; margin: 0 0 4px 0"),
+            (
+                CODE_BLOCK,
+                "background: #f6f8fa; padding: 8px 12px; overflow-x: auto",
+            ),
+            (TABLE, "border-collapse: collapse"),
+            (
+                BLOCK_QUOTE,
+                "border-left: 4px solid #ddd; margin: 0; padding-left: 12px; color: #555",
+            ),
+        ];
+        ThemeProfile {
+            tokens: styles
+                .iter()
+                .map(|(token, style)| {
+                    (
+                        token.to_string(),
+                        TokenStyle {
+                            class: None,
+                            style: Some(style.to_string()),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Looks up a builtin profile by name (`classes` or `inline_styles`).
+    pub fn builtin(name: &str) -> Option<ThemeProfile> {
+        match name {
+            "classes" => Some(ThemeProfile::classes()),
+            "inline_styles" => Some(ThemeProfile::inline_styles()),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the profile emits all tokens as classes.
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Maps whitespace separated classes to the classes and inline styles
+    /// to emit.
+    pub fn apply(&self, classes: &str) -> (String, String) {
+        let mut class_rv = String::new();
+        let mut style_rv = String::new();
+        for class in classes.split_whitespace() {
+            let (class, style) = match self.tokens.get(class) {
+                Some(token_style) => (token_style.class.as_deref(), token_style.style.as_deref()),
+                None => (Some(class), None),
+            };
+            for (rv, value, sep) in [(&mut class_rv, class, " "), (&mut style_rv, style, "; ")] {
+                if let Some(value) = value.filter(|x| !x.is_empty()) {
+                    if !rv.is_empty() {
+                        rv.push_str(sep);
+                    }
+                    rv.push_str(value);
+                }
+            }
+        }
+        (class_rv, style_rv)
+    }
+}
+
+#[test]
+fn test_theme_profiles() {
+    use crate::html::{to_html, HtmlRendererOptions};
+    use crate::pipeline::Pipeline;
+    use crate::processors::{Admonitions, CodeContainer, ThemeTokens};
+
+    let source = "\
+```{warning} Careful
+Mind the *gap*.
+```
+
+```rust
+fn main() {}
+```
+
+| a | b |
+|---|---|
+| 1 | 2 |
+
+> Quoted
+";
+    let mut pipeline = Pipeline::new();
+    pipeline.add_processor(Admonitions::default());
+    pipeline.add_processor(CodeContainer::default());
+    pipeline.add_processor(ThemeTokens::default());
+    let events: Vec<_> = pipeline.process(source).collect();
+
+    let mut rendered = String::new();
+    let mut custom = ThemeProfile::classes();
+    custom.tokens.insert(
+        ADMONITION.into(),
+        TokenStyle {
+            class: Some("alert alert-box".into()),
+            style: None,
+        },
+    );
+    for profile in [
+        ThemeProfile::classes(),
+        ThemeProfile::inline_styles(),
+        custom,
+    ] {
+        let options = HtmlRendererOptions {
+            theme: profile,
+            ..Default::default()
+        };
+        rendered.push_str(&to_html(events.clone().into_iter(), &options));
+        rendered.push_str("---\n");
+    }
+    insta::assert_snapshot!(rendered);
+
+    assert_eq!(
+        ThemeProfile::builtin("inline_styles"),
+        Some(ThemeProfile::inline_styles())
+    );
+    assert_eq!(ThemeProfile::builtin("latex"), None);
+}
@@ -0,0 +1,339 @@
+//! A tree representation of event streams.
+//!
+//! Most processing works on the flat event stream, but some transformations
+//! such as reordering sections or validating how elements nest are easier
+//! on a tree.  [`DocumentNode::from_events`] builds a tree from a stream in
+//! which every start tag and its end tag become an [`Element`] holding the
+//! events in between as children.  [`DocumentNode::to_events`] flattens the
+//! tree back into a stream:
+//!
+//! ```
+//! use struckdown::html::to_html;
+//! use struckdown::parser::parse;
+//! use struckdown::tree::DocumentNode;
+//!
+//! let events = parse("- one\n- two\n- three\n", &Default::default());
+//! let mut document = DocumentNode::from_events(events);
+//! let list = document.children[1].as_element_mut().unwrap();
+//! list.children.reverse();
+//! let html = to_html(document.to_events().into_iter(), &Default::default());
+//! assert_eq!(html, "<ul>\n<li>three</li>\n<li>two</li>\n<li>one</li>\n</ul>\n");
+//! ```
+//!
+//! For a balanced stream (as produced by the parser) the round trip keeps
+//! all events including their locations and trivia.
+use crate::event::{
+    AnnotatedEvent, Attrs, EndTagEvent, Event, Location, StartTagEvent, Tag, Trivia,
+};
+
+/// An element spanning from a start tag to its end tag.
+#[derive(Debug, Clone)]
+pub struct Element<'data> {
+    /// The tag of the element.
+    pub tag: Tag,
+    /// The attributes of the start tag.
+    pub attrs: Attrs<'data>,
+    /// The nodes between start and end tag.
+    pub children: Vec<Node<'data>>,
+    /// The location of the start tag.
+    pub location: Option<Location>,
+    /// The trivia of the start tag.
+    pub trivia: Option<Box<Trivia<'data>>>,
+    /// The location of the end tag.
+    pub end_location: Option<Location>,
+    /// The trivia of the end tag.
+    pub end_trivia: Option<Box<Trivia<'data>>>,
+}
+
+/// A node in the tree.
+#[derive(Debug, Clone)]
+pub enum Node<'data> {
+    /// An element with children.
+    Element(Element<'data>),
+    /// Any event other than start and end tags.
+    Event(AnnotatedEvent<'data>),
+}
+
+/// The root of a document tree.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentNode<'data> {
+    /// The top level nodes of the document.
+    pub children: Vec<Node<'data>>,
+}
+
+impl<'data> Element<'data> {
+    /// Creates an element without location.
+    pub fn new(tag: Tag, attrs: Attrs<'data>, children: Vec<Node<'data>>) -> Element<'data> {
+        Element {
+            tag,
+            attrs,
+            children,
+            location: None,
+            trivia: None,
+            end_location: None,
+            end_trivia: None,
+        }
+    }
+
+    /// Converts the element into an owned element.
+    pub fn into_owned(self) -> Element<'static> {
+        Element {
+            tag: self.tag,
+            attrs: self.attrs.into_owned(),
+            children: self.children.into_iter().map(Node::into_owned).collect(),
+            location: self.location,
+            trivia: self.trivia.map(|x| Box::new(x.into_owned())),
+            end_location: self.end_location,
+            end_trivia: self.end_trivia.map(|x| Box::new(x.into_owned())),
+        }
+    }
+
+    fn flatten_into(self, rv: &mut Vec<AnnotatedEvent<'data>>) {
+        rv.push(AnnotatedEvent {
+            event: Event::StartTag(StartTagEvent {
+                tag: self.tag,
+                attrs: self.attrs,
+            }),
+            location: self.location,
+            trivia: self.trivia,
+        });
+        for child in self.children {
+            child.flatten_into(rv);
+        }
+        rv.push(AnnotatedEvent {
+            event: Event::EndTag(EndTagEvent { tag: self.tag }),
+            location: self.end_location,
+            trivia: self.end_trivia,
+        });
+    }
+}
+
+impl<'data> Node<'data> {
+    /// Returns the element if the node is one.
+    pub fn as_element(&self) -> Option<&Element<'data>> {
+        match *self {
+            Node::Element(ref element) => Some(element),
+            Node::Event(..) => None,
+        }
+    }
+
+    /// Returns the element mutably if the node is one.
+    pub fn as_element_mut(&mut self) -> Option<&mut Element<'data>> {
+        match *self {
+            Node::Element(ref mut element) => Some(element),
+            Node::Event(..) => None,
+        }
+    }
+
+    /// Returns the event if the node is not an element.
+    pub fn as_event(&self) -> Option<&AnnotatedEvent<'data>> {
+        match *self {
+            Node::Element(..) => None,
+            Node::Event(ref event) => Some(event),
+        }
+    }
+
+    /// Returns the location of the node.
+    pub fn location(&self) -> Option<&Location> {
+        match *self {
+            Node::Element(ref element) => element.location.as_ref(),
+            Node::Event(ref event) => event.location.as_ref(),
+        }
+    }
+
+    /// Converts the node into an owned node.
+    pub fn into_owned(self) -> Node<'static> {
+        match self {
+            Node::Element(element) => Node::Element(element.into_owned()),
+            Node::Event(event) => Node::Event(event.into_owned()),
+        }
+    }
+
+    fn flatten_into(self, rv: &mut Vec<AnnotatedEvent<'data>>) {
+        match self {
+            Node::Element(element) => element.flatten_into(rv),
+            Node::Event(event) => rv.push(event),
+        }
+    }
+}
+
+impl<'data> From<Element<'data>> for Node<'data> {
+    fn from(value: Element<'data>) -> Node<'data> {
+        Node::Element(value)
+    }
+}
+
+impl<'data> From<AnnotatedEvent<'data>> for Node<'data> {
+    fn from(value: AnnotatedEvent<'data>) -> Node<'data> {
+        Node::Event(value)
+    }
+}
+
+/// Moves the innermost open element into its parent.
+fn close_element<'data>(stack: &mut Vec<Element<'data>>, children: &mut Vec<Node<'data>>) {
+    if let Some(element) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(element.into()),
+            None => children.push(element.into()),
+        }
+    }
+}
+
+impl<'data> DocumentNode<'data> {
+    /// Builds a tree from an event stream.
+    ///
+    /// The stream is expected to be balanced.  End tags close the innermost
+    /// open element of their tag, closing all elements opened within it.
+    /// End tags that close no element are dropped and elements that are
+    /// still open at the end of the stream are closed.
+    pub fn from_events<I>(iter: I) -> DocumentNode<'data>
+    where
+        I: IntoIterator<Item = AnnotatedEvent<'data>>,
+    {
+        let mut stack: Vec<Element<'data>> = vec![];
+        let mut children = vec![];
+        for annotated_event in iter {
+            let AnnotatedEvent {
+                event,
+                location,
+                trivia,
+            } = annotated_event;
+            match event {
+                Event::StartTag(StartTagEvent { tag, attrs }) => stack.push(Element {
+                    location,
+                    trivia,
+                    ..Element::new(tag, attrs, vec![])
+                }),
+                Event::EndTag(EndTagEvent { tag }) => {
+                    let idx = match stack.iter().rposition(|x| x.tag == tag) {
+                        Some(idx) => idx,
+                        None => continue,
+                    };
+                    while stack.len() > idx + 1 {
+                        close_element(&mut stack, &mut children);
+                    }
+                    if let Some(element) = stack.last_mut() {
+                        element.end_location = location;
+                        element.end_trivia = trivia;
+                    }
+                    close_element(&mut stack, &mut children);
+                }
+                event => {
+                    let node = Node::Event(AnnotatedEvent {
+                        event,
+                        location,
+                        trivia,
+                    });
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(node),
+                        None => children.push(node),
+                    }
+                }
+            }
+        }
+        while !stack.is_empty() {
+            close_element(&mut stack, &mut children);
+        }
+        DocumentNode { children }
+    }
+
+    /// Flattens the tree into an event stream.
+    pub fn to_events(self) -> Vec<AnnotatedEvent<'data>> {
+        let mut rv = vec![];
+        for child in self.children {
+            child.flatten_into(&mut rv);
+        }
+        rv
+    }
+
+    /// Converts the tree into an owned tree.
+    pub fn into_owned(self) -> DocumentNode<'static> {
+        DocumentNode {
+            children: self.children.into_iter().map(Node::into_owned).collect(),
+        }
+    }
+
+    /// Visits all nodes depth first in document order.
+    pub fn walk<F: FnMut(&Node<'data>)>(&self, mut f: F) {
+        fn walk_nodes<'data, F: FnMut(&Node<'data>)>(nodes: &[Node<'data>], f: &mut F) {
+            for node in nodes {
+                f(node);
+                if let Node::Element(ref element) = *node {
+                    walk_nodes(&element.children, f);
+                }
+            }
+        }
+        walk_nodes(&self.children, &mut f);
+    }
+}
+
+#[test]
+fn test_document_tree() {
+    use crate::event::TextEvent;
+    use crate::html::to_html;
+    use crate::parser::{parse, ParserOptions};
+
+    let source = "\
+# Title
+
+A *nested **paragraph***.
+
+> - one
+> - two
+
+```{note}
+Body
+```
+";
+    let options = ParserOptions {
+        lossless: true,
+        ..Default::default()
+    };
+    let events: Vec<_> = parse(source, &options).collect();
+    let document = DocumentNode::from_events(events.clone());
+    assert_eq!(
+        format!("{:?}", document.clone().to_events()),
+        format!("{:?}", events)
+    );
+
+    let mut tags = vec![];
+    document.walk(|node| {
+        if let Some(element) = node.as_element() {
+            tags.push(format!(
+                "{:?}@{}",
+                element.tag,
+                node.location().unwrap().line
+            ));
+        }
+    });
+    insta::assert_debug_snapshot!(&tags);
+
+    // stray end tags are dropped and open elements closed
+    let mut broken = events;
+    broken.remove(1);
+    broken.truncate(broken.len() - 5);
+    let html = to_html(
+        DocumentNode::from_events(broken).to_events().into_iter(),
+        &Default::default(),
+    );
+    insta::assert_snapshot!(html);
+
+    // end tags close the innermost element of their tag
+    let mismatched = vec![
+        Tag::BlockQuote.start_tag(Attrs::default()).into(),
+        Tag::Paragraph.start_tag(Attrs::default()).into(),
+        TextEvent { text: "x".into() }.into(),
+        Tag::BlockQuote.end_tag().into(),
+        Tag::Emphasis.end_tag().into(),
+        Tag::Paragraph.start_tag(Attrs::default()).into(),
+    ];
+    let document = DocumentNode::from_events(mismatched);
+    assert_eq!(document.children.len(), 2);
+    let quote = document.children[0].as_element().unwrap();
+    assert_eq!(quote.tag, Tag::BlockQuote);
+    assert_eq!(quote.children[0].as_element().unwrap().tag, Tag::Paragraph);
+    assert_eq!(
+        to_html(document.to_events().into_iter(), &Default::default()),
+        "<blockquote>\n<p>x</p>\n</blockquote>\n<p></p>\n"
+    );
+}
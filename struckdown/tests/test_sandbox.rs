@@ -0,0 +1,55 @@
+//! Runs processors under a restrictive sandbox.
+//!
+//! The sandbox is process wide, so these tests live in their own binary.
+#![cfg(all(unix, feature = "external-processor"))]
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use struckdown::json::stream_to_json;
+use struckdown::parser::parse;
+use struckdown::pipeline::Pipeline;
+use struckdown::processors::BuiltinProcessor;
+use struckdown::sandbox::{install, Sandbox};
+
+#[test]
+fn test_document_cannot_swap_commands() {
+    let evil = std::env::temp_dir().join("struckdown-sandbox-evil");
+    fs::create_dir_all(&evil).unwrap();
+    for name in &["cat", "tool"] {
+        let path = evil.join(name);
+        fs::write(&path, "#!/bin/sh\necho PWNED >&2\nexit 1\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    install(Sandbox {
+        allowed_commands: Some(vec!["cat".into(), "./tool".into()]),
+        inherit_env: false,
+        ..Sandbox::default()
+    });
+
+    let configs = vec![
+        serde_json::json!({
+            "processor": "external",
+            "cmd": "cat",
+            "env": {"PATH": evil.display().to_string()},
+        }),
+        serde_json::json!({
+            "processor": "external",
+            "cmd": "cat",
+            "env": {"LD_PRELOAD": evil.join("evil.so").display().to_string()},
+        }),
+        serde_json::json!({
+            "processor": "external",
+            "cmd": "./tool",
+            "cwd": evil.display().to_string(),
+        }),
+    ];
+    for config in configs {
+        let mut pipeline = Pipeline::new();
+        pipeline.add_processor(serde_json::from_value::<BuiltinProcessor>(config).unwrap());
+        let output = stream_to_json(pipeline.apply(Box::new(parse("Hello", &Default::default()))));
+        assert!(output.contains("\"error\""), "{}", output);
+        assert!(!output.contains("PWNED"), "{}", output);
+    }
+    fs::remove_dir_all(&evil).ok();
+}
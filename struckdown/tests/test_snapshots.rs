@@ -2,6 +2,7 @@ use std::fs;
 
 use struckdown::event::{AnnotatedEvent, DocumentStartEvent, Event};
 use struckdown::html::to_html;
+use struckdown::json::{stream_from_json, stream_to_json};
 use struckdown::parser::parse;
 use struckdown::pipeline::Pipeline;
 use struckdown::processors::BuiltinProcessor;
@@ -61,3 +62,13 @@ fn test_html() {
         insta::assert_snapshot!(html);
     });
 }
+
+#[test]
+fn test_json_roundtrip() {
+    insta::glob!("inputs/*.md", |file| {
+        let source = fs::read_to_string(file).unwrap();
+        let json = stream_to_json(parse(&source, &Default::default()));
+        let events = stream_from_json(&json).unwrap();
+        assert_eq!(stream_to_json(events.into_iter()), json);
+    });
+}